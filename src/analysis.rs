@@ -0,0 +1,1226 @@
+use std::collections::HashMap;
+
+use ast::*;
+use builtins::Builtins;
+use diagnostic::Diagnostic;
+use lexer::{Lexer, KEYWORDS};
+use parser;
+use resolver::{self, DeclId, DeclKind, Resolution};
+use token::{Span, Token, TokenType};
+use type_checker::{self, FunctionSignature, TypeCheckResult};
+use types::Type;
+use validation::{self, ValidationOptions};
+
+//Inserted where the cursor is when the code around it doesn't parse, so that
+//something like a. can be parsed as a member expression
+const PLACEHOLDER: &'static str = "boa_completion_placeholder";
+
+//The types that can be written without being declared
+pub const BUILTIN_TYPES: [&'static str; 5] = ["Number", "String", "Bool", "List", "Dictionary"];
+
+/*
+ * What a name in the code refers to
+ */
+#[derive(Clone,Eq,PartialEq,Debug)]
+pub enum Symbol
+{
+    Declaration(DeclId),
+    //Fields and methods by the name of their type and their own name
+    Field(String, String),
+    Method(String, String),
+    Type(String),
+}
+
+//A name in the code together with what it refers to
+#[derive(Clone,Eq,PartialEq,Debug)]
+pub struct Occurrence
+{
+    pub span: Span,
+    pub symbol: Symbol,
+}
+
+#[derive(Clone,Copy,Eq,PartialEq,Debug)]
+pub enum SymbolKind
+{
+    Function,
+    Type,
+    Field,
+    Implement,
+    Method,
+    Variable,
+}
+
+/*
+ * An entry in the outline of a file
+ */
+#[derive(Clone,PartialEq,Debug)]
+pub struct DocumentSymbol
+{
+    pub name: String,
+    pub detail: String,
+    pub kind: SymbolKind,
+    //All of the declaration and just its name
+    pub span: Span,
+    pub name_span: Span,
+    pub children: Vec<DocumentSymbol>,
+}
+
+#[derive(Clone,Copy,Eq,PartialEq,Debug)]
+pub enum CompletionKind
+{
+    Keyword,
+    Type,
+    Function,
+    Variable,
+    Field,
+    Method,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct Completion
+{
+    pub label: String,
+    pub kind: CompletionKind,
+    //The type of the completed name, empty for keywords
+    pub detail: String,
+}
+
+/*
+ * What a token is when highlighting code. The names are the ones the language
+ * server protocol uses for semantic tokens
+ */
+#[derive(Clone,Copy,Eq,PartialEq,Debug)]
+pub enum SemanticKind
+{
+    Keyword,
+    Comment,
+    String,
+    Number,
+    Operator,
+    Type,
+    Function,
+    Method,
+    Parameter,
+    Variable,
+    Property,
+}
+
+pub const SEMANTIC_KINDS: [SemanticKind; 11] = [
+    SemanticKind::Keyword,
+    SemanticKind::Comment,
+    SemanticKind::String,
+    SemanticKind::Number,
+    SemanticKind::Operator,
+    SemanticKind::Type,
+    SemanticKind::Function,
+    SemanticKind::Method,
+    SemanticKind::Parameter,
+    SemanticKind::Variable,
+    SemanticKind::Property,
+];
+
+impl SemanticKind
+{
+    pub fn name(&self) -> &'static str
+    {
+        match *self
+        {
+            SemanticKind::Keyword => "keyword",
+            SemanticKind::Comment => "comment",
+            SemanticKind::String => "string",
+            SemanticKind::Number => "number",
+            SemanticKind::Operator => "operator",
+            SemanticKind::Type => "type",
+            SemanticKind::Function => "function",
+            SemanticKind::Method => "method",
+            SemanticKind::Parameter => "parameter",
+            SemanticKind::Variable => "variable",
+            SemanticKind::Property => "property",
+        }
+    }
+}
+
+#[derive(Clone,Copy,Eq,PartialEq,Debug)]
+pub struct SemanticToken
+{
+    pub span: Span,
+    pub kind: SemanticKind,
+    //Whether this is the name in the declaration rather than a use of it
+    pub declaration: bool,
+}
+
+/*
+ * Everything that the checks found out about some code, kept even when the code
+ * has errors. Editor tools ask it questions about positions in the code
+ */
+pub struct Analysis
+{
+    pub code: String,
+    //Empty when the code could not be lexed
+    pub tokens: Vec<Token>,
+    //None when the code could not be lexed or parsed
+    pub program: Option<Program>,
+    pub resolution: Resolution,
+    pub types: TypeCheckResult,
+    pub diagnostics: Vec<Diagnostic>,
+    occurrences: Vec<Occurrence>,
+    //The types of the objects of member expressions by the span of the member
+    //name, and whether the object is the type itself instead of an instance
+    members: Vec<(Span, Type, bool)>,
+}
+
+//Runs every check on the code without stopping at the first kind of error
+pub fn analyse(code: &str, builtins: &Builtins, options: &ValidationOptions) -> Analysis
+{
+    let tokens = Lexer::new().try_tokenize(code);
+    let parsed = tokens.clone().and_then(parser::parse_token_list);
+    let tokens = tokens.unwrap_or_default();
+    let program = match parsed
+    {
+        Ok(program) => program,
+        Err(diagnostic) => {
+            return Analysis {
+                code: String::from(code),
+                tokens: tokens,
+                program: None,
+                resolution: Resolution::default(),
+                types: TypeCheckResult::default(),
+                diagnostics: vec!(*diagnostic),
+                occurrences: Vec::new(),
+                members: Vec::new(),
+            };
+        }
+    };
+
+    let resolution = resolver::resolve(&program, &builtins.names());
+    let types = type_checker::check(&program, &resolution, builtins);
+    let mut diagnostics = resolution.diagnostics.clone();
+    diagnostics.extend(types.diagnostics.iter().cloned());
+    diagnostics.extend(validation::validate(&program, options));
+
+    let (occurrences, members) = {
+        let mut collector = Collector {
+            resolution: &resolution,
+            types: &types,
+            occurrences: Vec::new(),
+            members: Vec::new(),
+        };
+        collector.program(&program);
+        (collector.occurrences, collector.members)
+    };
+
+    Analysis {
+        code: String::from(code),
+        tokens: tokens,
+        program: Some(program),
+        resolution: resolution,
+        types: types,
+        diagnostics: diagnostics,
+        occurrences: occurrences,
+        members: members,
+    }
+}
+
+impl Analysis
+{
+    pub fn occurrences(&self) -> &[Occurrence]
+    {
+        &self.occurrences
+    }
+
+    //The name at the offset. A cursor right after a name is still on it
+    pub fn symbol_at(&self, offset: usize) -> Option<&Occurrence>
+    {
+        self.occurrences.iter().find(|o| o.span.start <= offset && offset < o.span.end)
+            .or_else(|| self.occurrences.iter().find(|o| o.span.end == offset))
+    }
+
+    //Where the symbol was declared. Builtins are not declared in the code
+    pub fn definition(&self, symbol: &Symbol) -> Option<Span>
+    {
+        match *symbol
+        {
+            Symbol::Declaration(id) => {
+                let declaration = &self.resolution.declarations[id];
+                match declaration.kind
+                {
+                    DeclKind::Builtin => None,
+                    _ => Some(name_span(declaration.span, &declaration.name))
+                }
+            }
+            Symbol::Field(ref type_name, ref name) => self.types.types.get(type_name)
+                .and_then(|info| info.field(name))
+                .map(|field| name_span(field.span, name)),
+            Symbol::Method(ref type_name, ref name) => self.types.types.get(type_name)
+                .and_then(|info| info.methods.get(name))
+                .map(|method| method.span),
+            Symbol::Type(ref type_name) => self.types.types.get(type_name).map(|info| info.span),
+        }
+    }
+
+    //Every place where the symbol is named, including its declaration
+    pub fn references(&self, symbol: &Symbol) -> Vec<Span>
+    {
+        self.occurrences.iter()
+            .filter(|o| o.symbol == *symbol)
+            .map(|o| o.span)
+            .collect()
+    }
+
+    //A short description of the symbol, written like the code that declares it
+    pub fn describe(&self, symbol: &Symbol) -> String
+    {
+        match *symbol
+        {
+            Symbol::Declaration(id) => {
+                let declaration = &self.resolution.declarations[id];
+                match declaration.kind
+                {
+                    DeclKind::Builtin | DeclKind::Function => match self.types.functions.get(&declaration.name)
+                    {
+                        Some(signature) => function_description(&declaration.name, signature),
+                        None => format!("def {}", declaration.name)
+                    },
+                    DeclKind::Type => format!("type {}", declaration.name),
+                    DeclKind::Parameter | DeclKind::Variable | DeclKind::LoopVariable => {
+                        match self.types.decl_types.get(&id)
+                        {
+                            Some(declared_type) => format!("{} {}", declared_type, declaration.name),
+                            None => declaration.name.clone()
+                        }
+                    }
+                }
+            }
+            Symbol::Field(ref type_name, ref name) => {
+                match self.types.types.get(type_name).and_then(|info| info.field(name))
+                {
+                    Some(field) => format!("{}{} {}.{}", visibility_prefix(field.visibility), field.field_type, type_name, name),
+                    None => format!("{}.{}", type_name, name)
+                }
+            }
+            Symbol::Method(ref type_name, ref name) => {
+                match self.types.types.get(type_name).and_then(|info| info.methods.get(name))
+                {
+                    Some(method) => format!("{}{}", visibility_prefix(method.visibility),
+                        function_description(&format!("{}.{}", type_name, name), method)),
+                    None => format!("def {}.{}", type_name, name)
+                }
+            }
+            Symbol::Type(ref type_name) => format!("type {}", type_name),
+        }
+    }
+
+    //The description of the name at the offset and the span of the name
+    pub fn hover(&self, offset: usize) -> Option<(Span, String)>
+    {
+        self.symbol_at(offset).map(|o| (o.span, self.describe(&o.symbol)))
+    }
+
+    //What each token that is worth highlighting is, in the order of the code.
+    //Names are told apart by what they were resolved to
+    pub fn semantic_tokens(&self) -> Vec<SemanticToken>
+    {
+        let mut semantic_tokens = Vec::new();
+        for token in &self.tokens
+        {
+            let span = token.span();
+            let kind = match token.token_type()
+            {
+                TokenType::Keyword => SemanticKind::Keyword,
+                TokenType::LineComment | TokenType::BlockComment => SemanticKind::Comment,
+                TokenType::StringLiteral => SemanticKind::String,
+                TokenType::Number | TokenType::HexNumber => SemanticKind::Number,
+                TokenType::Operator | TokenType::Assignment => SemanticKind::Operator,
+                TokenType::Identifier => {
+                    match self.occurrences.iter().find(|o| o.span == span)
+                    {
+                        Some(occurrence) => {
+                            let declaration = self.definition(&occurrence.symbol) == Some(span);
+                            semantic_tokens.push(SemanticToken {
+                                span: span,
+                                kind: self.semantic_kind(&occurrence.symbol),
+                                declaration: declaration,
+                            });
+                            continue;
+                        }
+                        None if BUILTIN_TYPES.contains(&token.lexeme()) => SemanticKind::Type,
+                        None => SemanticKind::Variable,
+                    }
+                }
+                _ => continue
+            };
+            semantic_tokens.push(SemanticToken{span: span, kind: kind, declaration: false});
+        }
+        return semantic_tokens;
+    }
+
+    fn semantic_kind(&self, symbol: &Symbol) -> SemanticKind
+    {
+        match *symbol
+        {
+            Symbol::Declaration(id) => match self.resolution.declarations[id].kind
+            {
+                DeclKind::Builtin | DeclKind::Function => SemanticKind::Function,
+                DeclKind::Type => SemanticKind::Type,
+                DeclKind::Parameter => SemanticKind::Parameter,
+                DeclKind::Variable | DeclKind::LoopVariable => SemanticKind::Variable,
+            },
+            Symbol::Field(..) => SemanticKind::Property,
+            Symbol::Method(..) => SemanticKind::Method,
+            Symbol::Type(_) => SemanticKind::Type,
+        }
+    }
+
+    //The outline of the code: functions, types with their fields and implement
+    //blocks with their methods
+    pub fn document_symbols(&self) -> Vec<DocumentSymbol>
+    {
+        let program = match self.program
+        {
+            Some(ref program) => program,
+            None => return Vec::new()
+        };
+        let mut symbols = Vec::new();
+        for item in &program.items
+        {
+            match *item
+            {
+                Item::Function(ref function) => {
+                    let detail = self.types.functions.get(&function.name)
+                        .map(|signature| function_description(&function.name, signature))
+                        .unwrap_or_default();
+                    symbols.push(function_symbol(function, detail, SymbolKind::Function));
+                }
+                Item::Type(ref decl) => {
+                    let children = decl.fields.iter().map(|field| {
+                        let field_type = self.types.types.get(&decl.name)
+                            .and_then(|info| info.field(&field.name))
+                            .map(|info| info.field_type.to_string())
+                            .unwrap_or_default();
+                        DocumentSymbol {
+                            name: field.name.clone(),
+                            detail: field_type,
+                            kind: SymbolKind::Field,
+                            span: field.span,
+                            name_span: name_span(field.span, &field.name),
+                            children: Vec::new(),
+                        }
+                    }).collect();
+                    symbols.push(DocumentSymbol {
+                        name: decl.name.clone(),
+                        detail: String::from("type"),
+                        kind: SymbolKind::Type,
+                        span: decl.span,
+                        name_span: decl.name_span,
+                        children: children,
+                    });
+                }
+                Item::Implement(ref implement) => {
+                    let info = self.types.types.get(&implement.type_name);
+                    let children = implement.functions.iter().map(|function| {
+                        //Operators aren't methods, their functions are found by where they are declared
+                        let signature = match function.operator
+                        {
+                            Some(_) => info.and_then(|info| info.operators.iter()
+                                .find(|operator| operator.signature.span == function.name_span))
+                                .map(|operator| &operator.signature),
+                            None => info.and_then(|info| info.methods.get(&function.name))
+                        };
+                        let detail = signature.map(|signature| function_description(&function.name, signature))
+                            .unwrap_or_default();
+                        function_symbol(function, detail, SymbolKind::Method)
+                    }).collect();
+                    symbols.push(DocumentSymbol {
+                        name: format!("implement for {}", implement.type_name),
+                        detail: String::new(),
+                        kind: SymbolKind::Implement,
+                        span: implement.span,
+                        name_span: implement.type_span,
+                        children: children,
+                    });
+                }
+                Item::Statement(ref statement) => {
+                    if let StatementKind::Let{ref name, name_span, ..} = statement.kind
+                    {
+                        let detail = self.resolution.declared_at(name_span)
+                            .and_then(|id| self.types.decl_types.get(&id))
+                            .map(|t| t.to_string())
+                            .unwrap_or_default();
+                        symbols.push(DocumentSymbol {
+                            name: name.clone(),
+                            detail: detail,
+                            kind: SymbolKind::Variable,
+                            span: statement.span,
+                            name_span: name_span,
+                            children: Vec::new(),
+                        });
+                    }
+                }
+            }
+        }
+        return symbols;
+    }
+
+    //The names that can be written at the offset. After a . these are the members
+    //of the value before it, otherwise keywords, types, functions and the variables
+    //that are in scope
+    pub fn completions(&self, offset: usize, builtins: &Builtins) -> Vec<Completion>
+    {
+        let word_start = self.code[..offset].trim_end_matches(|c: char| c.is_alphanumeric() || c == '_').len();
+
+        //The name that is being typed may be what keeps the code from parsing
+        let patched;
+        let analysis = if self.program.is_some()
+        {
+            self
+        }
+        else
+        {
+            patched = self.with_placeholder(word_start, offset, builtins);
+            &patched
+        };
+
+        if self.code[..word_start].trim_end().ends_with('.')
+        {
+            return analysis.member_completions(word_start, builtins);
+        }
+        return analysis.scope_completions(offset);
+    }
+
+    //Analyses the code with a name in place of the one that is being typed,
+    //closing the statement and the blocks around it if that is what it takes to parse
+    fn with_placeholder(&self, word_start: usize, offset: usize, builtins: &Builtins) -> Analysis
+    {
+        let before = &self.code[..word_start];
+        let after = &self.code[offset..];
+        let attempts = [
+            format!("{}{}{}", before, PLACEHOLDER, after),
+            format!("{}{};{}", before, PLACEHOLDER, after),
+            format!("{}{};{}", before, PLACEHOLDER, closing_brackets(before)),
+        ];
+        let options = ValidationOptions::library();
+        let mut analysis = analyse(&attempts[0], builtins, &options);
+        for attempt in attempts[1..].iter()
+        {
+            if analysis.program.is_some()
+            {
+                break;
+            }
+            analysis = analyse(attempt, builtins, &options);
+        }
+        return analysis;
+    }
+
+    fn member_completions(&self, name_start: usize, builtins: &Builtins) -> Vec<Completion>
+    {
+        let (type_name, through_type) = match self.members.iter().find(|&&(span, _, _)| span.start == name_start)
+        {
+            Some(&(_, Type::User(ref type_name), through_type)) => (type_name, through_type),
+            Some(&(_, ref other, _)) => return builtin_member_completions(other, builtins),
+            None => return Vec::new()
+        };
+        let info = match self.types.types.get(type_name)
+        {
+            Some(info) => info,
+            None => return Vec::new()
+        };
+
+        //Private members can only be used in the implement blocks of their type
+        let inside_implement = self.program.iter().flat_map(|program| program.items.iter()).any(|item| {
+            match *item
+            {
+                Item::Implement(ref implement) => implement.type_name == *type_name && contains(implement.span, name_start),
+                _ => false
+            }
+        });
+        let visible = |visibility: Visibility| visibility == Visibility::Public || inside_implement;
+
+        //Instances have fields and methods, the type itself has static functions
+        let mut completions: Vec<Completion> = info.fields.iter()
+            .filter(|field| !through_type && visible(field.visibility))
+            .map(|field| Completion {
+                label: field.name.clone(),
+                kind: CompletionKind::Field,
+                detail: field.field_type.to_string(),
+            })
+            .collect();
+        let mut methods: Vec<(&String, &FunctionSignature)> = info.methods.iter()
+            .filter(|&(_, method)| method.receiver != through_type && visible(method.visibility))
+            .collect();
+        methods.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, method) in methods
+        {
+            completions.push(Completion {
+                label: name.clone(),
+                kind: CompletionKind::Method,
+                detail: function_description(name, method),
+            });
+        }
+        return completions;
+    }
+
+    fn scope_completions(&self, offset: usize) -> Vec<Completion>
+    {
+        let mut completions: Vec<Completion> = Vec::new();
+        {
+            let mut add = |label: &str, kind: CompletionKind, detail: String| {
+                if label != PLACEHOLDER && !completions.iter().any(|c| c.label == label)
+                {
+                    completions.push(Completion{label: String::from(label), kind: kind, detail: detail});
+                }
+            };
+
+            //Inner variables shadow outer ones so they are added first
+            if let Some(ref program) = self.program
+            {
+                for id in visible_declarations(program, &self.resolution, offset).into_iter().rev()
+                {
+                    let declaration = &self.resolution.declarations[id];
+                    let detail = self.types.decl_types.get(&id).map(|t| t.to_string()).unwrap_or_default();
+                    add(&declaration.name, CompletionKind::Variable, detail);
+                }
+            }
+
+            let mut functions: Vec<(&String, &FunctionSignature)> = self.types.functions.iter().collect();
+            functions.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, signature) in functions
+            {
+                add(name, CompletionKind::Function, function_description(name, signature));
+            }
+
+            let mut type_names: Vec<&str> = BUILTIN_TYPES.to_vec();
+            let mut user_types: Vec<&str> = self.types.types.keys().map(|name| name.as_str()).collect();
+            user_types.sort();
+            type_names.extend(user_types);
+            for name in type_names
+            {
+                add(name, CompletionKind::Type, String::new());
+            }
+
+            for keyword in KEYWORDS.iter()
+            {
+                add(keyword, CompletionKind::Keyword, String::new());
+            }
+        }
+        return completions;
+    }
+}
+
+//The brackets that close the ones left open in the code, innermost first
+fn closing_brackets(code: &str) -> String
+{
+    let mut open = Vec::new();
+    for c in code.chars()
+    {
+        match c
+        {
+            '{' | '(' | '[' => open.push(c),
+            '}' | ')' | ']' => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    open.iter().rev().map(|c| match *c
+    {
+        '{' => '}',
+        '(' => ')',
+        _ => ']',
+    }).collect()
+}
+
+fn contains(span: Span, offset: usize) -> bool
+{
+    span.start <= offset && offset <= span.end
+}
+
+//Parameters and fields have spans that include their type, this is the part with the name
+fn name_span(span: Span, name: &str) -> Span
+{
+    Span::new(span.end.saturating_sub(name.len()).max(span.start), span.end)
+}
+
+fn visibility_prefix(visibility: Visibility) -> &'static str
+{
+    match visibility
+    {
+        Visibility::Public => "",
+        Visibility::Private => "private ",
+    }
+}
+
+//Functions are described the way they are declared, def Number add(Number, Number)
+fn function_description(name: &str, signature: &FunctionSignature) -> String
+{
+    let params: Vec<String> = signature.params.iter().map(|p| p.to_string()).collect();
+    match signature.return_type
+    {
+        Type::Unit => format!("def {}({})", name, params.join(", ")),
+        ref return_type => format!("def {} {}({})", return_type, name, params.join(", ")),
+    }
+}
+
+//The methods of a built in type, with the type parameters of their signatures
+//filled in from the type of the value they are called on
+fn builtin_member_completions(object_type: &Type, builtins: &Builtins) -> Vec<Completion>
+{
+    let prefix = match object_type.builtin_name()
+    {
+        Some(type_name) => format!("{}.", type_name),
+        None => return Vec::new()
+    };
+    let mut completions: Vec<Completion> = builtins.functions().iter()
+        .filter(|function| function.name.starts_with(&prefix))
+        .map(|function| {
+            let name = &function.name[prefix.len()..];
+            let mut bindings = HashMap::new();
+            function.params[0].bind(object_type, &mut bindings);
+            let signature = FunctionSignature {
+                params: function.params[1..].iter().map(|param| param.substitute(&bindings)).collect(),
+                return_type: function.return_type.substitute(&bindings),
+                span: Span::default(),
+                visibility: Visibility::Public,
+                receiver: true,
+            };
+            Completion {
+                label: String::from(name),
+                kind: CompletionKind::Method,
+                detail: function_description(name, &signature),
+            }
+        })
+        .collect();
+    completions.sort_by(|a, b| a.label.cmp(&b.label));
+    return completions;
+}
+
+fn function_symbol(function: &FunctionDecl, detail: String, kind: SymbolKind) -> DocumentSymbol
+{
+    DocumentSymbol {
+        name: function.name.clone(),
+        detail: detail,
+        kind: kind,
+        span: function.span,
+        name_span: function.name_span,
+        children: Vec::new(),
+    }
+}
+
+//The variables and parameters that are in scope at the offset, outermost first.
+//Functions can't see the variables outside them
+fn visible_declarations(program: &Program, resolution: &Resolution, offset: usize) -> Vec<DeclId>
+{
+    let mut visible = Vec::new();
+    let functions = program.items.iter().flat_map(|item| match *item
+    {
+        Item::Function(ref function) => vec!(function),
+        Item::Implement(ref implement) => implement.functions.iter().collect(),
+        _ => Vec::new()
+    });
+    for function in functions
+    {
+        if contains(function.span, offset)
+        {
+            visible.extend(function.params.iter().filter_map(|param| resolution.declared_at(param.span)));
+            statements_visible(&function.body.statements, resolution, offset, &mut visible);
+            return visible;
+        }
+    }
+
+    let top_level: Vec<Statement> = program.items.iter().filter_map(|item| match *item
+    {
+        Item::Statement(ref statement) => Some(statement.clone()),
+        _ => None
+    }).collect();
+    statements_visible(&top_level, resolution, offset, &mut visible);
+    return visible;
+}
+
+fn statements_visible(statements: &[Statement], resolution: &Resolution, offset: usize, visible: &mut Vec<DeclId>)
+{
+    for statement in statements
+    {
+        if statement.span.end <= offset
+        {
+            if let StatementKind::Let{name_span, ..} = statement.kind
+            {
+                visible.extend(resolution.declared_at(name_span));
+            }
+            continue;
+        }
+        if !contains(statement.span, offset)
+        {
+            break;
+        }
+
+        let block_visible = |block: &Block, visible: &mut Vec<DeclId>| {
+            if contains(block.span, offset)
+            {
+                statements_visible(&block.statements, resolution, offset, visible);
+            }
+        };
+        match statement.kind
+        {
+            StatementKind::If{ref branches, ref else_block} => {
+                for &(_, ref block) in branches
+                {
+                    block_visible(block, visible);
+                }
+                if let Some(ref block) = *else_block
+                {
+                    block_visible(block, visible);
+                }
+            }
+            StatementKind::While{ref body, ..} => block_visible(body, visible),
+            StatementKind::For{var_span, ref body, ..} => {
+                if contains(body.span, offset)
+                {
+                    visible.extend(resolution.declared_at(var_span));
+                }
+                block_visible(body, visible);
+            }
+            StatementKind::Foreach{var_span, ref value, ref body, ..} => {
+                if contains(body.span, offset)
+                {
+                    visible.extend(resolution.declared_at(var_span));
+                    if let Some(ref value) = *value
+                    {
+                        visible.extend(resolution.declared_at(value.span));
+                    }
+                }
+                block_visible(body, visible);
+            }
+            StatementKind::Block(ref block) => block_visible(block, visible),
+            _ => {}
+        }
+    }
+}
+
+/*
+ * Walks the parse tree and records what every name refers to
+ */
+struct Collector<'a>
+{
+    resolution: &'a Resolution,
+    types: &'a TypeCheckResult,
+    occurrences: Vec<Occurrence>,
+    members: Vec<(Span, Type, bool)>,
+}
+
+impl<'a> Collector<'a>
+{
+    fn add(&mut self, span: Span, symbol: Symbol)
+    {
+        self.occurrences.push(Occurrence{span: span, symbol: symbol});
+    }
+
+    fn add_declaration(&mut self, declared_span: Span, span: Span)
+    {
+        if let Some(id) = self.resolution.declared_at(declared_span)
+        {
+            self.add(span, Symbol::Declaration(id));
+        }
+    }
+
+    //The name of the type when the expression is a type, as in Type.function
+    fn type_named(&self, expr: &Expr) -> Option<String>
+    {
+        match expr.kind
+        {
+            ExprKind::Identifier(ref name) => match self.resolution.bindings.get(&expr.id)
+            {
+                Some(&id) if self.resolution.declarations[id].kind == DeclKind::Type => Some(name.clone()),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    fn program(&mut self, program: &Program)
+    {
+        for item in &program.items
+        {
+            match *item
+            {
+                Item::Function(ref function) => {
+                    self.add_declaration(function.name_span, function.name_span);
+                    self.function(function);
+                }
+                Item::Type(ref decl) => {
+                    self.add(decl.name_span, Symbol::Type(decl.name.clone()));
+                    for field in &decl.fields
+                    {
+                        self.type_expr(&field.type_expr);
+                        self.add(name_span(field.span, &field.name), Symbol::Field(decl.name.clone(), field.name.clone()));
+                    }
+                }
+                Item::Implement(ref implement) => {
+                    self.add(implement.type_span, Symbol::Type(implement.type_name.clone()));
+                    for function in &implement.functions
+                    {
+                        if function.operator.is_none()
+                        {
+                            self.add(function.name_span, Symbol::Method(implement.type_name.clone(), function.name.clone()));
+                        }
+                        self.function(function);
+                    }
+                }
+                Item::Statement(ref statement) => self.statement(statement),
+            }
+        }
+    }
+
+    fn function(&mut self, function: &FunctionDecl)
+    {
+        if let Some(ref return_type) = function.return_type
+        {
+            self.type_expr(return_type);
+        }
+        for param in &function.params
+        {
+            self.type_expr(&param.type_expr);
+            self.add_declaration(param.span, name_span(param.span, &param.name));
+        }
+        self.block(&function.body);
+    }
+
+    fn type_expr(&mut self, type_expr: &TypeExpr)
+    {
+        if self.types.types.contains_key(&type_expr.name)
+        {
+            let span = Span::new(type_expr.span.start, type_expr.span.start + type_expr.name.len());
+            self.add(span, Symbol::Type(type_expr.name.clone()));
+        }
+        for arg in &type_expr.args
+        {
+            self.type_expr(arg);
+        }
+    }
+
+    fn block(&mut self, block: &Block)
+    {
+        for statement in &block.statements
+        {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement)
+    {
+        match statement.kind
+        {
+            StatementKind::Let{ref type_expr, name_span, ref value, ..} => {
+                if let Some(ref type_expr) = *type_expr
+                {
+                    self.type_expr(type_expr);
+                }
+                self.add_declaration(name_span, name_span);
+                if let Some(ref value) = *value
+                {
+                    self.expr(value);
+                }
+            }
+            StatementKind::Assign{ref target, ref value, ..} => {
+                self.expr(target);
+                self.expr(value);
+            }
+            StatementKind::Increment{ref target, ..} => self.expr(target),
+            StatementKind::Expr(ref expr) => self.expr(expr),
+            StatementKind::Return(ref value) => {
+                if let Some(ref value) = *value
+                {
+                    self.expr(value);
+                }
+            }
+            StatementKind::If{ref branches, ref else_block} => {
+                for &(ref condition, ref block) in branches
+                {
+                    self.expr(condition);
+                    self.block(block);
+                }
+                if let Some(ref block) = *else_block
+                {
+                    self.block(block);
+                }
+            }
+            StatementKind::While{ref condition, ref body} => {
+                self.expr(condition);
+                self.block(body);
+            }
+            StatementKind::For{var_span, ref start, ref end, ref step, ref body, ..} => {
+                self.add_declaration(var_span, var_span);
+                self.expr(start);
+                self.expr(end);
+                if let Some(ref step) = *step
+                {
+                    self.expr(step);
+                }
+                self.block(body);
+            }
+            StatementKind::Foreach{ref type_expr, var_span, ref value, ref container, ref body, ..} => {
+                if let Some(ref type_expr) = *type_expr
+                {
+                    self.type_expr(type_expr);
+                }
+                self.add_declaration(var_span, var_span);
+                if let Some(ref value) = *value
+                {
+                    if let Some(ref type_expr) = value.type_expr
+                    {
+                        self.type_expr(type_expr);
+                    }
+                    self.add_declaration(value.span, value.span);
+                }
+                self.expr(container);
+                self.block(body);
+            }
+            StatementKind::Block(ref block) => self.block(block),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr)
+    {
+        match expr.kind
+        {
+            ExprKind::Number(_) | ExprKind::String(_) | ExprKind::Bool(_) => {}
+            ExprKind::Identifier(ref name) => {
+                if let Some(&id) = self.resolution.bindings.get(&expr.id)
+                {
+                    //Type names are the same symbol wherever they are used
+                    match self.resolution.declarations[id].kind
+                    {
+                        DeclKind::Type => self.add(expr.span, Symbol::Type(name.clone())),
+                        _ => self.add(expr.span, Symbol::Declaration(id))
+                    }
+                }
+            }
+            ExprKind::List(ref elements) => {
+                for element in elements
+                {
+                    self.expr(element);
+                }
+            }
+            ExprKind::Dictionary(ref entries) => {
+                for &(ref key, ref value) in entries
+                {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            ExprKind::Unary{ref expr, ..} => self.expr(expr),
+            ExprKind::Binary{ref lhs, ref rhs, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ExprKind::Call{ref callee, ref args} => {
+                self.expr(callee);
+                for arg in args
+                {
+                    self.expr(arg);
+                }
+            }
+            ExprKind::Member{ref object, ref name, name_span} => {
+                self.expr(object);
+                let type_named = self.type_named(object);
+                let object_type = match type_named
+                {
+                    Some(ref type_name) => Type::User(type_name.clone()),
+                    None => self.types.expr_types.get(&object.id).cloned().unwrap_or(Type::Unknown)
+                };
+                if let Type::User(ref type_name) = object_type
+                {
+                    if let Some(info) = self.types.types.get(type_name)
+                    {
+                        if info.field(name).is_some()
+                        {
+                            self.add(name_span, Symbol::Field(type_name.clone(), name.clone()));
+                        }
+                        else if info.methods.contains_key(name)
+                        {
+                            self.add(name_span, Symbol::Method(type_name.clone(), name.clone()));
+                        }
+                    }
+                }
+                self.members.push((name_span, object_type, type_named.is_some()));
+            }
+            ExprKind::Index{ref object, ref index} => {
+                self.expr(object);
+                self.expr(index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod analysis_tests
+{
+    use analysis::*;
+
+    fn analyse_code(code: &str) -> Analysis
+    {
+        analyse(code, &Builtins::new(), &ValidationOptions::library())
+    }
+
+    fn offset_of(code: &str, text: &str) -> usize
+    {
+        code.find(text).unwrap()
+    }
+
+    const CODE: &'static str = "\
+type Point
+{
+    Number x;
+    private:
+    Number y;
+}
+implement for Point
+{
+    def Number sum(self, Point other)
+    {
+        return 0;
+    }
+}
+def Number add(Number a, Number b)
+{
+    let total = a + b;
+    return total;
+}
+def show(Point p)
+{
+    print(add(1, p.x));
+}";
+
+    #[test]
+    fn definitions_and_references()
+    {
+        let analysis = analyse_code(CODE);
+        assert!(analysis.diagnostics.is_empty(), "{:?}", analysis.diagnostics);
+
+        let occurrence = analysis.symbol_at(offset_of(CODE, "add(1") + 1).unwrap();
+        let definition = analysis.definition(&occurrence.symbol).unwrap();
+        assert_eq!(definition.start, offset_of(CODE, "add(Number"));
+        assert_eq!(analysis.references(&occurrence.symbol).len(), 2);
+
+        //A cursor right after a name is on the name
+        let total = analysis.symbol_at(offset_of(CODE, "total;") + 5).unwrap();
+        let references = analysis.references(&total.symbol);
+        assert_eq!(references, vec!(Span::new(offset_of(CODE, "total ="), offset_of(CODE, "total =") + 5),
+            Span::new(offset_of(CODE, "total;"), offset_of(CODE, "total;") + 5)));
+
+        let field = analysis.symbol_at(offset_of(CODE, "x)")).unwrap();
+        assert_eq!(field.symbol, Symbol::Field(String::from("Point"), String::from("x")));
+        assert_eq!(analysis.definition(&field.symbol).unwrap().start, offset_of(CODE, "x;"));
+
+        let print = analysis.symbol_at(offset_of(CODE, "print")).unwrap();
+        assert_eq!(analysis.definition(&print.symbol), None);
+    }
+
+    #[test]
+    fn hover()
+    {
+        let analysis = analyse_code(CODE);
+        let hover = |text: &str| analysis.hover(offset_of(CODE, text)).map(|(_, description)| description);
+        assert_eq!(hover("total =").unwrap(), "Number total");
+        assert_eq!(hover("b)").unwrap(), "Number b");
+        assert_eq!(hover("add(1").unwrap(), "def Number add(Number, Number)");
+        assert_eq!(hover("print").unwrap(), "def print(any type)");
+        assert_eq!(hover("Point p)").unwrap(), "type Point");
+        assert_eq!(hover("y;").unwrap(), "private Number Point.y");
+        assert_eq!(hover("sum").unwrap(), "def Number Point.sum(Point)");
+        assert_eq!(hover("{"), None);
+    }
+
+    #[test]
+    fn document_symbols()
+    {
+        let symbols = analyse_code(CODE).document_symbols();
+        let names: Vec<(&str, SymbolKind, usize)> = symbols.iter()
+            .map(|s| (s.name.as_str(), s.kind, s.children.len()))
+            .collect();
+        assert_eq!(names, vec!(
+            ("Point", SymbolKind::Type, 2),
+            ("implement for Point", SymbolKind::Implement, 1),
+            ("add", SymbolKind::Function, 0),
+            ("show", SymbolKind::Function, 0),
+        ));
+        assert_eq!(symbols[2].detail, "def Number add(Number, Number)");
+        assert_eq!(symbols[1].children[0].kind, SymbolKind::Method);
+    }
+
+    #[test]
+    fn semantic_tokens()
+    {
+        let code = "def Number add(Number a) { return a + len(a); } # done";
+        let analysis = analyse_code(code);
+        let kinds: Vec<(&str, SemanticKind, bool)> = analysis.semantic_tokens().iter()
+            .map(|t| (&code[t.span.start..t.span.end], t.kind, t.declaration))
+            .collect();
+        assert_eq!(kinds, vec!(
+            ("def", SemanticKind::Keyword, false),
+            ("Number", SemanticKind::Type, false),
+            ("add", SemanticKind::Function, true),
+            ("Number", SemanticKind::Type, false),
+            ("a", SemanticKind::Parameter, true),
+            ("return", SemanticKind::Keyword, false),
+            ("a", SemanticKind::Parameter, false),
+            ("+", SemanticKind::Operator, false),
+            //Names that are not declared are still highlighted
+            ("len", SemanticKind::Variable, false),
+            ("a", SemanticKind::Parameter, false),
+            ("# done", SemanticKind::Comment, false),
+        ));
+
+        let fields = analyse_code(CODE).semantic_tokens();
+        let x = fields.iter().find(|t| t.span.start == offset_of(CODE, "x)")).unwrap();
+        assert_eq!((x.kind, x.declaration), (SemanticKind::Property, false));
+        let point = fields.iter().find(|t| t.span.start == offset_of(CODE, "Point p")).unwrap();
+        assert_eq!(point.kind, SemanticKind::Type);
+
+        //The tokens are still there when the code doesn't parse
+        assert_eq!(analyse_code("def (").semantic_tokens().len(), 1);
+    }
+
+    #[test]
+    fn completions()
+    {
+        let builtins = Builtins::new();
+        let labels = |code: &str, offset: usize| -> Vec<String> {
+            analyse_code(code).completions(offset, &builtins).into_iter().map(|c| c.label).collect()
+        };
+
+        //Only the variables of the function around the cursor
+        let offset = offset_of(CODE, "return total") + 7;
+        let in_add = labels(CODE, offset);
+        assert!(in_add.contains(&String::from("total")));
+        assert!(in_add.contains(&String::from("a")));
+        assert!(!in_add.contains(&String::from("p")));
+        assert!(in_add.contains(&String::from("show")));
+        assert!(in_add.contains(&String::from("print")));
+        assert!(in_add.contains(&String::from("Point")));
+        assert!(in_add.contains(&String::from("foreach")));
+
+        //Members of the value before the dot, even though the code doesn't parse. The
+        //private field y can only be reached through its getter and setter
+        let code = CODE.replace("p.x", "p.");
+        let offset = offset_of(&code, "p.") + 2;
+        assert_eq!(labels(&code, offset), vec!("x", "get_y", "set_y", "sum"));
+
+        //Private members can be used in their implement block
+        let code = CODE.replace("return 0;", "other.");
+        let offset = offset_of(&code, "other.") + 6;
+        assert_eq!(labels(&code, offset), vec!("x", "y", "get_y", "set_y", "sum"));
+
+        //Only static functions are called through the type
+        let code = CODE.replace("p.x", "Point.").replace("sum(self, ", "sum(");
+        let offset = offset_of(&code, "Point.") + 6;
+        assert_eq!(labels(&code, offset), vec!("sum"));
+
+        //Methods of the standard library, with the element type filled in
+        let code = "def f(Dictionary<String, Number> d) { d. }";
+        let offset = offset_of(code, "d.") + 2;
+        assert_eq!(labels(code, offset), vec!("has", "keys", "length", "merge", "remove", "values"));
+        let code = "def f(List<String> words) { words. }";
+        let completions = analyse_code(code).completions(offset_of(code, "words.") + 6, &builtins);
+        let push = completions.iter().find(|c| c.label == "push").unwrap();
+        assert_eq!(push.detail, "def push(String)");
+    }
+}
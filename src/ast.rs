@@ -0,0 +1,390 @@
+use printer::type_expr_string;
+use token::Span;
+
+//Every expression gets a unique id from the parser which later passes use as keys
+//when they want to store information about it
+pub type NodeId = usize;
+
+/*
+ * The root of the parse tree. Contains everything that was found at the top level
+ * of a script
+ */
+#[derive(Clone,PartialEq,Debug)]
+pub struct Program
+{
+    pub items: Vec<Item>,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub enum Item
+{
+    Function(FunctionDecl),
+    Type(TypeDecl),
+    Implement(ImplementBlock),
+    //Code outside of functions. Parsed so that it can be reported nicely
+    Statement(Statement),
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct TypeExpr
+{
+    pub name: String,
+    pub args: Vec<TypeExpr>,
+    pub span: Span,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct Param
+{
+    pub type_expr: TypeExpr,
+    pub name: String,
+    pub span: Span,
+}
+
+//The value variable of foreach key, value in dictionary
+#[derive(Clone,PartialEq,Debug)]
+pub struct LoopValue
+{
+    pub type_expr: Option<TypeExpr>,
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct FunctionDecl
+{
+    pub name: String,
+    pub name_span: Span,
+    pub return_type: Option<TypeExpr>,
+    pub params: Vec<Param>,
+    pub body: Block,
+    //Only matters for functions in implement blocks, all other functions are public
+    pub visibility: Visibility,
+    //Where self is in the parameters of methods. Functions of implement blocks
+    //without it are static and called through the type
+    pub receiver: Option<Span>,
+    //Set for operator functions, which are named after their operator like operator +
+    pub operator: Option<Operator>,
+    pub span: Span,
+}
+
+//What an operator function in an implement block overloads
+#[derive(Clone,Copy,Eq,PartialEq,Hash,Debug)]
+pub enum Operator
+{
+    Binary(BinaryOp),
+    //-a, declared as operator - with one parameter
+    Negate,
+    //Whether a value counts as true in conditions, declared as operator if
+    Condition,
+}
+
+impl Operator
+{
+    pub fn symbol(&self) -> &'static str
+    {
+        match *self
+        {
+            Operator::Binary(op) => op.symbol(),
+            Operator::Negate => "-",
+            Operator::Condition => "if",
+        }
+    }
+
+    //The number of parameters that the operator function takes
+    pub fn operands(&self) -> usize
+    {
+        match *self
+        {
+            Operator::Binary(_) => 2,
+            Operator::Negate | Operator::Condition => 1,
+        }
+    }
+}
+
+#[derive(Clone,Copy,Eq,PartialEq,Debug)]
+pub enum Visibility
+{
+    Public,
+    Private,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct FieldDecl
+{
+    pub type_expr: TypeExpr,
+    pub name: String,
+    pub visibility: Visibility,
+    //Private fields get a getter and a setter unless they are declared hidden
+    pub hidden: bool,
+    pub span: Span,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct TypeDecl
+{
+    pub name: String,
+    pub name_span: Span,
+    pub fields: Vec<FieldDecl>,
+    pub span: Span,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct ImplementBlock
+{
+    pub type_name: String,
+    pub type_span: Span,
+    pub functions: Vec<FunctionDecl>,
+    pub span: Span,
+}
+
+impl ImplementBlock
+{
+    //The name that a function of the block is called by when the script runs.
+    //Operators can be implemented for different operands so their names include
+    //the types of the parameters, like Vector.operator *(Vector, Number)
+    pub fn function_name(&self, function: &FunctionDecl) -> String
+    {
+        if function.operator.is_none()
+        {
+            return format!("{}.{}", self.type_name, function.name);
+        }
+        let params: Vec<String> = function.params.iter().map(|param| type_expr_string(&param.type_expr)).collect();
+        format!("{}.{}({})", self.type_name, function.name, params.join(", "))
+    }
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct Block
+{
+    pub statements: Vec<Statement>,
+    pub span: Span,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct Statement
+{
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub enum StatementKind
+{
+    Let
+    {
+        type_expr: Option<TypeExpr>,
+        name: String,
+        name_span: Span,
+        value: Option<Expr>,
+    },
+    //name = value, name += value etc. op is None for plain assignment
+    Assign
+    {
+        target: Expr,
+        op: Option<BinaryOp>,
+        value: Expr,
+    },
+    //name++ and name--
+    Increment
+    {
+        target: Expr,
+        op: BinaryOp,
+    },
+    Expr(Expr),
+    Return(Option<Expr>),
+    If
+    {
+        //The if and all elseif branches in order
+        branches: Vec<(Expr, Block)>,
+        else_block: Option<Block>,
+    },
+    While
+    {
+        condition: Expr,
+        body: Block,
+    },
+    For
+    {
+        var: String,
+        var_span: Span,
+        start: Expr,
+        end: Expr,
+        step: Option<Expr>,
+        body: Block,
+    },
+    Foreach
+    {
+        type_expr: Option<TypeExpr>,
+        var: String,
+        var_span: Span,
+        //Only set when going through the keys and values of a dictionary
+        value: Option<LoopValue>,
+        container: Expr,
+        body: Block,
+    },
+    Block(Block),
+}
+
+#[derive(Clone,Copy,Eq,PartialEq,Hash,Debug)]
+pub enum BinaryOp
+{
+    Add,
+    Sub,
+    Mul,
+    Div,
+    //Division that rounds down to a whole number
+    IntDiv,
+    Rem,
+
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+
+    And,
+    Or,
+}
+
+impl BinaryOp
+{
+    pub fn from_operator(op: &str) -> Option<BinaryOp>
+    {
+        match op
+        {
+            "+" => Some(BinaryOp::Add),
+            "-" => Some(BinaryOp::Sub),
+            "*" => Some(BinaryOp::Mul),
+            "/" => Some(BinaryOp::Div),
+            "//" => Some(BinaryOp::IntDiv),
+            "%" => Some(BinaryOp::Rem),
+            "==" => Some(BinaryOp::Equal),
+            "!=" => Some(BinaryOp::NotEqual),
+            "<" => Some(BinaryOp::Less),
+            "<=" => Some(BinaryOp::LessEqual),
+            ">" => Some(BinaryOp::Greater),
+            ">=" => Some(BinaryOp::GreaterEqual),
+            "&" => Some(BinaryOp::And),
+            "|" => Some(BinaryOp::Or),
+            _ => None
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str
+    {
+        match *self
+        {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::IntDiv => "//",
+            BinaryOp::Rem => "%",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::And => "&",
+            BinaryOp::Or => "|",
+        }
+    }
+
+    //Returns true for the operators that compare their operands and give a Bool
+    pub fn is_comparison(&self) -> bool
+    {
+        matches!(*self, BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual)
+    }
+
+    //Higher binds tighter
+    pub fn precedence(&self) -> u8
+    {
+        match *self
+        {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Equal | BinaryOp::NotEqual => 3,
+            BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => 4,
+            BinaryOp::Add | BinaryOp::Sub => 5,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::IntDiv | BinaryOp::Rem => 6,
+        }
+    }
+}
+
+#[derive(Clone,Copy,Eq,PartialEq,Hash,Debug)]
+pub enum UnaryOp
+{
+    Negate,
+    Not,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct Expr
+{
+    pub id: NodeId,
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub enum ExprKind
+{
+    //The lexeme of the number as it was written in the code
+    Number(String),
+    String(String),
+    Bool(bool),
+    Identifier(String),
+    //[a, b, c]
+    List(Vec<Expr>),
+    //[key: value, ...] with [:] being the empty dictionary
+    Dictionary(Vec<(Expr, Expr)>),
+    Unary
+    {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    Binary
+    {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Call
+    {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
+    Member
+    {
+        object: Box<Expr>,
+        name: String,
+        name_span: Span,
+    },
+    Index
+    {
+        object: Box<Expr>,
+        index: Box<Expr>,
+    },
+}
+
+impl ExprKind
+{
+    //The expressions directly inside this one, in the order they were written
+    pub fn children(&self) -> Vec<&Expr>
+    {
+        match *self
+        {
+            ExprKind::Number(_) | ExprKind::String(_) | ExprKind::Bool(_) | ExprKind::Identifier(_) => Vec::new(),
+            ExprKind::List(ref elements) => elements.iter().collect(),
+            ExprKind::Dictionary(ref entries) => entries.iter().flat_map(|&(ref key, ref value)| vec!(key, value)).collect(),
+            ExprKind::Unary{ref expr, ..} => vec!(&**expr),
+            ExprKind::Binary{ref lhs, ref rhs, ..} => vec!(&**lhs, &**rhs),
+            ExprKind::Call{ref callee, ref args} => Some(&**callee).into_iter().chain(args.iter()).collect(),
+            ExprKind::Member{ref object, ..} => vec!(&**object),
+            ExprKind::Index{ref object, ref index} => vec!(&**object, &**index),
+        }
+    }
+}
@@ -0,0 +1,17 @@
+extern crate boa;
+
+use std::io;
+use std::process;
+
+use boa::limits::run_with_stack;
+
+//The language server for editors. It talks JSON-RPC over stdin and stdout
+pub fn main()
+{
+    //Code is checked on a thread with a big stack, like boa check does
+    let exit_code = run_with_stack(|| {
+        let stdin = io::stdin();
+        boa::lsp::run(&mut stdin.lock(), &mut io::stdout())
+    });
+    process::exit(exit_code);
+}
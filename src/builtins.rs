@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+use capabilities::{host_functions, Capabilities, Capability};
+use stdlib::{self, stdlib_functions};
+use types::Type;
+use variable::Value;
+
+//Rust code that runs when a script calls a function provided by the host. The
+//arguments have been checked against the signature of the function
+pub type NativeFunction = Rc<dyn Fn(Vec<Value>) -> Result<Value, String>>;
+
+/*
+ * A function that is available in every script without being declared
+ */
+#[derive(Clone)]
+pub struct BuiltinFunction
+{
+    pub name: String,
+    pub params: Vec<Type>,
+    pub return_type: Type,
+    //The implementation of functions registered by the host. Functions that are
+    //part of the language are implemented in call
+    pub native: Option<NativeFunction>,
+    //What the script has to be given to use the function. None for functions
+    //that can't reach outside of the script
+    pub capability: Option<Capability>,
+}
+
+impl BuiltinFunction
+{
+    pub fn native(name: &str, params: Vec<Type>, return_type: Type, native: NativeFunction) -> BuiltinFunction
+    {
+        BuiltinFunction {
+            name: String::from(name),
+            params: params,
+            return_type: return_type,
+            native: Some(native),
+            capability: None,
+        }
+    }
+
+    //Methods of the built in types are named Type.name. They are called on a value
+    //of the type, which is their first parameter
+    pub fn is_method(&self) -> bool
+    {
+        self.name.contains('.')
+    }
+
+    //A reference to the function that scripts can store and call
+    pub fn value(&self) -> Value
+    {
+        Value::function(self.name.clone(), self.params.clone(), self.return_type.clone())
+    }
+}
+
+/*
+ * The set of builtin functions that a script is checked and run with, and the
+ * capabilities that decide which of them it may use
+ */
+#[derive(Clone)]
+pub struct Builtins
+{
+    functions: Vec<BuiltinFunction>,
+    //The position of each function in functions, kept up to date by add
+    positions: HashMap<String, usize>,
+    capabilities: Capabilities,
+}
+
+impl Builtins
+{
+    //The functions that are part of the language, the standard library and the host
+    //functions of every capability. No capabilities are given, so only the language
+    //functions can be used
+    pub fn new() -> Builtins
+    {
+        let mut builtins = Builtins {
+            functions: Vec::new(),
+            positions: HashMap::new(),
+            capabilities: Capabilities::none(),
+        };
+        builtins.add(BuiltinFunction{name: String::from("print"), params: vec!(Type::Any), return_type: Type::Unit, native: None, capability: None});
+        for function in stdlib_functions().into_iter().chain(host_functions())
+        {
+            builtins.add(function);
+        }
+        return builtins;
+    }
+
+    pub fn set_capabilities(&mut self, capabilities: Capabilities)
+    {
+        self.capabilities = capabilities;
+    }
+
+    pub fn capabilities(&self) -> Capabilities
+    {
+        self.capabilities
+    }
+
+    //The capability that the function needs but was not given
+    pub fn missing_capability(&self, name: &str) -> Option<Capability>
+    {
+        self.get(name)
+            .and_then(|function| function.capability)
+            .filter(|capability| !self.capabilities.allows(*capability))
+    }
+
+    //Adds a function, replacing any earlier function with the same name
+    pub fn add(&mut self, function: BuiltinFunction)
+    {
+        match self.positions.get(&function.name)
+        {
+            Some(&position) => self.functions[position] = function,
+            None => {
+                self.positions.insert(function.name.clone(), self.functions.len());
+                self.functions.push(function);
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BuiltinFunction>
+    {
+        self.positions.get(name).map(|&position| &self.functions[position])
+    }
+
+    pub fn functions(&self) -> &[BuiltinFunction]
+    {
+        &self.functions
+    }
+
+    //The names of the functions that scripts can refer to. Methods are only found
+    //through the values they are called on
+    pub fn names(&self) -> Vec<&str>
+    {
+        self.functions.iter().filter(|f| !f.is_method()).map(|f| f.name.as_str()).collect()
+    }
+
+    //Runs a builtin function. Shared by everything that executes scripts so that
+    //they all behave the same
+    pub fn call(&self, name: &str, args: Vec<Value>, output: &mut dyn Write) -> Result<Value, String>
+    {
+        let function = match self.get(name)
+        {
+            Some(function) => function,
+            None => return Err(format!("There is no function called {}", name))
+        };
+        //The type checker rejects these calls, this makes sure that the checks
+        //can't be skipped
+        if let Some(capability) = self.missing_capability(name)
+        {
+            return Err(missing_capability_message(name, capability));
+        }
+        if let Some(ref native) = function.native
+        {
+            if args.len() != function.params.len()
+            {
+                return Err(format!("{} expects {} arguments but {} were given", name, function.params.len(), args.len()));
+            }
+            return native(args);
+        }
+
+        match name
+        {
+            "print" => {
+                for arg in &args
+                {
+                    writeln!(output, "{}", arg).map_err(|e| format!("Failed to print: {}", e))?;
+                }
+                Ok(Value::Unit)
+            }
+            _ if stdlib::takes_functions(name) => Err(format!("{} calls functions of the script, so only a running script can call it", name)),
+            _ => Err(format!("There is no function called {}", name))
+        }
+    }
+}
+
+pub fn missing_capability_message(name: &str, capability: Capability) -> String
+{
+    format!("{} needs the {} capability, which this script was not given", name, capability)
+}
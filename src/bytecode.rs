@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use ast::{BinaryOp, UnaryOp};
+use number::Number;
+use token::Span;
+use variable::Value;
+
+/*
+ * A single operation of the virtual machine. Operands refer to the constant pool or
+ * the local slots of the function, or are positions in the code for jumps
+ */
+#[derive(Clone,Copy,Eq,PartialEq,Debug)]
+pub enum Instruction
+{
+    //Pushes a value from the constant pool
+    Constant(usize),
+    LoadLocal(usize),
+    //Pops the top of the stack into a local slot
+    StoreLocal(usize),
+    Pop,
+    Dup,
+    //Duplicates the top two values, used when an element is both read and written
+    Dup2,
+
+    Unary(UnaryOp),
+    Binary(BinaryOp),
+    //Fails unless the top of the stack is a Number. Doesn't pop it
+    CheckNumber,
+
+    //Pops that many values and pushes a list of them
+    List(usize),
+    //Pops that many key value pairs and pushes a dictionary of them
+    Dictionary(usize),
+    //Pops an index and a container and pushes the element
+    Index,
+    //Pops a value, an index and a container and sets the element
+    SetIndex,
+    //Pops an object and pushes the field whose name is the constant
+    Field(usize),
+    //Pops a value and an object and sets the field whose name is the constant
+    SetField(usize),
+
+    //Fails unless the top of the stack is a function. Doesn't pop it
+    CheckCallable,
+    //Pops that many arguments and the function to call
+    Call(usize),
+    //Pops that many arguments and calls the script function whose name is the
+    //constant. Used for operator functions and methods, which aren't values
+    CallNamed{function: usize, args: usize},
+    //Pushes a new object with the type and fields of the constant object. The
+    //fields get values popped in the order they are declared if values is set,
+    //otherwise they are nothing until a constructor assigns them
+    Object{prototype: usize, values: bool},
+    Return,
+
+    Jump(usize),
+    //Pop a Bool and jump if it is false or true
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+
+    //Fails if the step of a for loop on the top of the stack is 0. Doesn't pop it
+    CheckStep,
+    //Pushes the value of the variable of a for loop in the pass that is in its
+    //slot and counts the pass, or jumps to exit if the value is past the end
+    ForNext{start: usize, end: usize, step: usize, pass: usize, exit: usize},
+    //Replaces the container on the top of the stack with a list of the values
+    //that foreach goes through, or of the keys and values one after the other
+    //if pairs is set. The container can't be changed until IterEnd
+    IterStart{pairs: bool},
+    //Pushes the next value of a foreach loop, or the next key and then its value,
+    //and advances the position. Jumps to exit if all values have been used
+    IterNext{values: usize, position: usize, exit: usize, pairs: bool},
+    //Allows the container of the innermost foreach loop to be changed again
+    IterEnd,
+
+    //Uses up a step of fuel. Emitted where the interpreter uses fuel, so both run
+    //out in the same place
+    Step,
+    //Stops with an error whose message is the constant
+    Fail(usize),
+}
+
+/*
+ * The code of a function together with its constants and the spans that errors in
+ * each instruction are reported at
+ */
+#[derive(Clone,Debug,Default)]
+pub struct Chunk
+{
+    pub code: Vec<Instruction>,
+    pub spans: Vec<Span>,
+    pub constants: Vec<Value>,
+}
+
+impl Chunk
+{
+    pub fn new() -> Chunk
+    {
+        Chunk::default()
+    }
+
+    //Adds an instruction and returns its position
+    pub fn emit(&mut self, instruction: Instruction, span: Span) -> usize
+    {
+        self.code.push(instruction);
+        self.spans.push(span);
+        return self.code.len() - 1;
+    }
+
+    //Adds a value to the constant pool unless an identical constant is already there
+    pub fn add_constant(&mut self, value: Value) -> usize
+    {
+        if let Some(index) = self.constants.iter().position(|c| same_constant(c, &value))
+        {
+            return index;
+        }
+        self.constants.push(value);
+        return self.constants.len() - 1;
+    }
+
+    //Points the jump at position to the end of the code
+    pub fn patch_jump(&mut self, position: usize)
+    {
+        let target = self.code.len();
+        self.code[position] = match self.code[position]
+        {
+            Instruction::Jump(_) => Instruction::Jump(target),
+            Instruction::JumpIfFalse(_) => Instruction::JumpIfFalse(target),
+            Instruction::JumpIfTrue(_) => Instruction::JumpIfTrue(target),
+            Instruction::ForNext{start, end, step, pass, ..} =>
+                Instruction::ForNext{start: start, end: end, step: step, pass: pass, exit: target},
+            Instruction::IterNext{values, position, pairs, ..} =>
+                Instruction::IterNext{values: values, position: position, exit: target, pairs: pairs},
+            other => panic!("{:?} is not a jump", other)
+        };
+    }
+}
+
+//Constants are only shared if they are exactly the same, 1 and 1.0 compare as equal
+//but are different constants
+fn same_constant(a: &Value, b: &Value) -> bool
+{
+    match (a, b)
+    {
+        (&Value::Number(Number::Integer(a)), &Value::Number(Number::Integer(b))) => a == b,
+        (&Value::Number(Number::Float(a)), &Value::Number(Number::Float(b))) => a.to_bits() == b.to_bits(),
+        (&Value::String(ref a), &Value::String(ref b)) => a == b,
+        (&Value::Bool(a), &Value::Bool(b)) => a == b,
+        (&Value::Function(ref a), &Value::Function(ref b)) => a.name == b.name,
+        (&Value::Unit, &Value::Unit) => true,
+        _ => false
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct CompiledFunction
+{
+    pub name: String,
+    pub params: usize,
+    //The number of local slots, including the parameters in the first slots
+    pub locals: usize,
+    pub chunk: Chunk,
+}
+
+#[derive(Clone,Debug,Default)]
+pub struct CompiledProgram
+{
+    pub functions: Vec<CompiledFunction>,
+    function_indices: HashMap<String, usize>,
+}
+
+impl CompiledProgram
+{
+    pub fn new() -> CompiledProgram
+    {
+        CompiledProgram::default()
+    }
+
+    pub fn add_function(&mut self, function: CompiledFunction)
+    {
+        self.function_indices.insert(function.name.clone(), self.functions.len());
+        self.functions.push(function);
+    }
+
+    pub fn function_index(&self, name: &str) -> Option<usize>
+    {
+        self.function_indices.get(name).cloned()
+    }
+
+    pub fn function(&self, name: &str) -> Option<&CompiledFunction>
+    {
+        self.function_index(name).map(|index| &self.functions[index])
+    }
+}
@@ -0,0 +1,252 @@
+use std::cell::Cell;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use builtins::BuiltinFunction;
+use convert::IntoNative;
+
+/*
+ * A group of host functions that reach outside of the script. Scripts can only
+ * use the functions of the capabilities they are given
+ */
+#[derive(Clone,Copy,Eq,PartialEq,Hash,Debug)]
+pub enum Capability
+{
+    Filesystem,
+    Clock,
+    Environment,
+    Random,
+}
+
+pub const ALL_CAPABILITIES: &'static [Capability] = &[
+    Capability::Filesystem,
+    Capability::Clock,
+    Capability::Environment,
+    Capability::Random,
+];
+
+impl Capability
+{
+    pub fn name(&self) -> &'static str
+    {
+        match *self
+        {
+            Capability::Filesystem => "filesystem",
+            Capability::Clock => "clock",
+            Capability::Environment => "environment",
+            Capability::Random => "random",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Capability>
+    {
+        ALL_CAPABILITIES.iter().cloned().find(|capability| capability.name() == name)
+    }
+
+    fn bit(&self) -> u8
+    {
+        match *self
+        {
+            Capability::Filesystem => 1,
+            Capability::Clock => 2,
+            Capability::Environment => 4,
+            Capability::Random => 8,
+        }
+    }
+}
+
+impl fmt::Display for Capability
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{}", self.name())
+    }
+}
+
+/*
+ * The capabilities that a script is given when it is checked and run
+ */
+#[derive(Clone,Copy,Eq,PartialEq,Debug)]
+pub struct Capabilities
+{
+    bits: u8,
+}
+
+impl Capabilities
+{
+    pub fn none() -> Capabilities
+    {
+        Capabilities {
+            bits: 0
+        }
+    }
+
+    pub fn all() -> Capabilities
+    {
+        Capabilities::from_list(ALL_CAPABILITIES)
+    }
+
+    pub fn from_list(capabilities: &[Capability]) -> Capabilities
+    {
+        capabilities.iter().fold(Capabilities::none(), |set, capability| set.with(*capability))
+    }
+
+    pub fn with(self, capability: Capability) -> Capabilities
+    {
+        Capabilities {
+            bits: self.bits | capability.bit()
+        }
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool
+    {
+        self.bits & capability.bit() != 0
+    }
+
+    pub fn list(&self) -> Vec<Capability>
+    {
+        ALL_CAPABILITIES.iter().cloned().filter(|capability| self.allows(*capability)).collect()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+//                      Host functions
+////////////////////////////////////////////////////////////////////////////////
+
+fn host<Args, F: IntoNative<Args>>(capability: Capability, name: &str, function: F) -> BuiltinFunction
+{
+    let mut builtin = BuiltinFunction::native(name, F::params(), F::return_type(), function.into_native());
+    builtin.capability = Some(capability);
+    return builtin;
+}
+
+fn io_error(path: &str, error: io::Error) -> String
+{
+    format!("{}: {}", path, error)
+}
+
+//The functions that every capability gives access to
+pub fn host_functions() -> Vec<BuiltinFunction>
+{
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+    let random_state = Rc::new(Cell::new(seed | 1));
+    let random_int_state = random_state.clone();
+
+    vec!(
+        host(Capability::Filesystem, "read_file",
+            |path: String| fs::read_to_string(&path).map_err(|e| io_error(&path, e))),
+        host(Capability::Filesystem, "write_file",
+            |path: String, contents: String| fs::write(&path, contents).map_err(|e| io_error(&path, e))),
+        host(Capability::Filesystem, "file_exists", |path: String| Path::new(&path).exists()),
+
+        //Seconds since the start of 1970
+        host(Capability::Clock, "clock",
+            || SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0)),
+
+        host(Capability::Environment, "env_var",
+            |name: String| env::var(&name).map_err(|_| format!("The environment variable {} is not set", name))),
+        host(Capability::Environment, "has_env_var", |name: String| env::var_os(name).is_some()),
+
+        //A number from 0 up to but not including 1
+        host(Capability::Random, "random", move || next_random(&random_state)),
+        //A whole number from low to high, both included
+        host(Capability::Random, "random_int", move |low: i64, high: i64| {
+            if low > high
+            {
+                return Err(format!("The range {} to {} is empty", low, high));
+            }
+            let range = (high as i128 - low as i128 + 1) as f64;
+            Ok(low + (next_random(&random_int_state) * range) as i64)
+        }),
+    )
+}
+
+//xorshift64*. Scripts don't need random numbers that are good enough for secrets
+fn next_random(state: &Cell<u64>) -> f64
+{
+    let mut x = state.get();
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    state.set(x);
+    let bits = x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11;
+    return bits as f64 / (1u64 << 53) as f64;
+}
+
+#[cfg(test)]
+mod capabilities_tests
+{
+    use std::env;
+    use std::fs;
+
+    use builtins::Builtins;
+    use capabilities::*;
+    use variable::Value;
+
+    fn call(builtins: &Builtins, name: &str, args: Vec<Value>) -> Result<Value, String>
+    {
+        builtins.call(name, args, &mut Vec::new())
+    }
+
+    fn string(s: &str) -> Value
+    {
+        Value::String(String::from(s))
+    }
+
+    #[test]
+    fn sets()
+    {
+        let set = Capabilities::none().with(Capability::Clock).with(Capability::Random);
+        assert!(set.allows(Capability::Clock));
+        assert!(!set.allows(Capability::Filesystem));
+        assert_eq!(set.list(), vec!(Capability::Clock, Capability::Random));
+        assert_eq!(Capabilities::all().list(), ALL_CAPABILITIES.to_vec());
+        assert_eq!(Capability::from_name("environment"), Some(Capability::Environment));
+        assert_eq!(Capability::from_name("network"), None);
+    }
+
+    #[test]
+    fn calls_need_the_capability()
+    {
+        let mut builtins = Builtins::new();
+        assert_eq!(call(&builtins, "clock", Vec::new()),
+            Err(String::from("clock needs the clock capability, which this script was not given")));
+        builtins.set_capabilities(Capabilities::none().with(Capability::Clock));
+        assert!(call(&builtins, "clock", Vec::new()).unwrap().as_number().unwrap().as_f64() > 0.0);
+        assert!(call(&builtins, "random", Vec::new()).is_err());
+    }
+
+    #[test]
+    fn host_functions_work()
+    {
+        let mut builtins = Builtins::new();
+        builtins.set_capabilities(Capabilities::all());
+
+        let path = env::temp_dir().join(format!("boa_capabilities_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        call(&builtins, "write_file", vec!(string(path), string("contents"))).unwrap();
+        assert_eq!(call(&builtins, "file_exists", vec!(string(path))), Ok(Value::Bool(true)));
+        assert_eq!(call(&builtins, "read_file", vec!(string(path))), Ok(string("contents")));
+        fs::remove_file(path).unwrap();
+        assert!(call(&builtins, "read_file", vec!(string(path))).is_err());
+
+        assert_eq!(call(&builtins, "has_env_var", vec!(string("BOA_SURELY_NOT_SET"))), Ok(Value::Bool(false)));
+        assert_eq!(call(&builtins, "env_var", vec!(string("BOA_SURELY_NOT_SET"))),
+            Err(String::from("The environment variable BOA_SURELY_NOT_SET is not set")));
+
+        for _ in 0..100
+        {
+            let n = call(&builtins, "random", Vec::new()).unwrap().as_number().unwrap().as_f64();
+            assert!((0.0..1.0).contains(&n));
+            let n = call(&builtins, "random_int", vec!(Value::integer(-2), Value::integer(2))).unwrap();
+            let n = n.as_number().unwrap().as_i64().unwrap();
+            assert!((-2..=2).contains(&n));
+        }
+        assert!(call(&builtins, "random_int", vec!(Value::integer(2), Value::integer(1))).is_err());
+    }
+}
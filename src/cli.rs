@@ -0,0 +1,544 @@
+use std::fs;
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+use serde_json;
+
+use builtins::Builtins;
+use capabilities::{Capabilities, Capability, ALL_CAPABILITIES};
+use compiler;
+use diagnostic::{self, Diagnostic};
+use error::Error;
+use highlight;
+use limits::Limits;
+use printer;
+use repl::Repl;
+use validation::ValidationOptions;
+use variable::Value;
+use vm::Vm;
+use {check, lex, parse, run_with_limits, CheckedProgram};
+
+//Exit codes of the boa command. Scripts that return a Number from main exit with
+//that number instead, so scripts should avoid returning these
+pub const EXIT_SUCCESS: i32 = 0;
+//Bad arguments or a file that can not be read
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_LEX_ERROR: i32 = 3;
+pub const EXIT_PARSE_ERROR: i32 = 4;
+//Errors found by the resolver, type checker or validation
+pub const EXIT_CHECK_ERROR: i32 = 5;
+pub const EXIT_RUNTIME_ERROR: i32 = 6;
+//The exit code for a Number returned from main that is not a valid exit code
+pub const EXIT_INVALID_CODE: i32 = 1;
+
+const USAGE: &'static str = "\
+Usage: boa <command> [options] <file> [arguments]
+       boa repl [options]
+       boa grammar <textmate|html>
+
+Commands:
+    run       Runs the main function of the script, passing it the arguments
+    check     Checks the script without running it
+    tokens    Prints the tokens of the script
+    ast       Prints the parse tree of the script
+    highlight Prints the script as highlighted HTML
+    repl      Runs code as it is typed
+    grammar   Prints a TextMate grammar or a highlighter for web pages
+
+Options:
+    --json                  Prints diagnostics as JSON, one on each line
+    --vm                    Runs the script on the bytecode virtual machine
+    --capabilities <list>   Only gives the script the capabilities in the comma
+                            separated list. Every capability is given by default
+    --trivia                Includes whitespace and comments in the tokens
+    --fuel <steps>          Stops the script after this many steps
+    --max-call-depth <n>    How many calls may be running at once, 10000 by default
+    --max-memory <bytes>    Stops the script when its strings and containers use more
+    --timeout <ms>          Stops the script after this many milliseconds
+";
+
+//The options that set the limits of run
+const LIMIT_OPTIONS: &'static [&'static str] = &["--fuel", "--max-call-depth", "--max-memory", "--timeout"];
+
+#[derive(Clone,Copy,Eq,PartialEq,Debug)]
+enum Command
+{
+    Run,
+    Check,
+    Tokens,
+    Ast,
+    Highlight,
+    Repl,
+    Grammar,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+struct Options
+{
+    command: Command,
+    //The kind of grammar for the grammar command
+    file: String,
+    //Passed on to the main function of the script
+    args: Vec<String>,
+    json: bool,
+    vm: bool,
+    capabilities: Capabilities,
+    trivia: bool,
+    limits: Limits,
+}
+
+//Runs the boa command with the arguments that followed the name of the program.
+//Returns the exit code
+pub fn main(args: &[String], stdin: &mut dyn BufRead, stdout: &mut dyn Write, stderr: &mut dyn Write) -> i32
+{
+    let options = match parse_options(args)
+    {
+        Ok(options) => options,
+        Err(message) => {
+            let _ = writeln!(stderr, "{}\n\n{}", message, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    if options.command == Command::Repl
+    {
+        let mut builtins = Builtins::new();
+        builtins.set_capabilities(options.capabilities);
+        return match Repl::new(builtins).run(stdin, stdout)
+        {
+            Ok(()) => EXIT_SUCCESS,
+            Err(e) => {
+                let _ = writeln!(stderr, "{}", e);
+                EXIT_USAGE
+            }
+        };
+    }
+
+    if options.command == Command::Grammar
+    {
+        let _ = match options.file.as_str()
+        {
+            "textmate" => writeln!(stdout, "{}", serde_json::to_string_pretty(&highlight::textmate_grammar()).unwrap()),
+            _ => write!(stdout, "{}", highlight::html_highlighter()),
+        };
+        return EXIT_SUCCESS;
+    }
+
+    let code = match fs::read_to_string(&options.file)
+    {
+        Ok(code) => code,
+        Err(e) => {
+            let _ = writeln!(stderr, "Failed to read {}: {}", options.file, e);
+            return EXIT_USAGE;
+        }
+    };
+
+    let result = run_command(&options, &code, stdout, stderr);
+    let _ = stdout.flush();
+    match result
+    {
+        Ok(exit_code) => exit_code,
+        Err(e) => {
+            print_diagnostics(&options, &code, &e.diagnostics(), stderr);
+            exit_code(&e)
+        }
+    }
+}
+
+fn run_command(options: &Options, code: &str, stdout: &mut dyn Write, stderr: &mut dyn Write) -> Result<i32, Error>
+{
+    //Code with errors is highlighted as far as possible, checking it is what check is for
+    if options.command == Command::Highlight
+    {
+        let _ = write!(stdout, "{}", highlight::highlight_html(code));
+        return Ok(EXIT_SUCCESS);
+    }
+
+    let tokens = lex(code)?;
+    if options.command == Command::Tokens
+    {
+        for token in tokens.iter().filter(|token| options.trivia || !token.is_trivia())
+        {
+            let (line, column) = token.span().line_column(code);
+            let _ = writeln!(stdout, "{:<8} {:<14} {:?}", format!("{}:{}", line, column),
+                format!("{:?}", token.token_type()), token.lexeme());
+        }
+        return Ok(EXIT_SUCCESS);
+    }
+
+    let program = parse(tokens)?;
+    if options.command == Command::Ast
+    {
+        let _ = write!(stdout, "{}", printer::print_program(&program));
+        return Ok(EXIT_SUCCESS);
+    }
+
+    //Scripts started from the command line are trusted as much as the user that
+    //started them, unless they are told otherwise
+    let mut builtins = Builtins::new();
+    builtins.set_capabilities(options.capabilities);
+    let checked = check(program, &builtins, &ValidationOptions::script())?;
+    print_diagnostics(options, code, &checked.warnings, stderr);
+    if options.command == Command::Check
+    {
+        return Ok(EXIT_SUCCESS);
+    }
+
+    let result = if options.vm
+    {
+        run_on_vm(&checked, &builtins, options.args.clone(), options.limits, stdout)?
+    }
+    else
+    {
+        run_with_limits(&checked, &builtins, options.args.clone(), options.limits, stdout)?
+    };
+    match result
+    {
+        //Only the lowest 8 bits of an exit code reach the parent process on every
+        //system, so other numbers fail instead of being cut down to something else
+        Value::Number(exit_code) => match exit_code.as_i64()
+        {
+            Some(exit_code @ 0..=255) => Ok(exit_code as i32),
+            _ => Ok(EXIT_INVALID_CODE)
+        },
+        _ => Ok(EXIT_SUCCESS)
+    }
+}
+
+fn run_on_vm(checked: &CheckedProgram, builtins: &Builtins, args: Vec<String>, limits: Limits,
+    output: &mut dyn Write) -> Result<Value, Error>
+{
+    let compiled = compiler::compile(&checked.program, &checked.types.call_targets, builtins);
+    let mut vm = Vm::new(&compiled, builtins, output);
+    vm.set_limits(limits);
+    Ok(vm.run_main(args)?)
+}
+
+pub fn exit_code(error: &Error) -> i32
+{
+    match *error
+    {
+        Error::Lex(_) => EXIT_LEX_ERROR,
+        Error::Parse(_) => EXIT_PARSE_ERROR,
+        Error::Check(_) => EXIT_CHECK_ERROR,
+        Error::Runtime(_) => EXIT_RUNTIME_ERROR,
+    }
+}
+
+fn print_diagnostics(options: &Options, code: &str, diagnostics: &[Diagnostic], stderr: &mut dyn Write)
+{
+    if diagnostics.is_empty()
+    {
+        return;
+    }
+    let _ = if options.json
+    {
+        write!(stderr, "{}", diagnostic::render_json(diagnostics, &options.file, code))
+    }
+    else
+    {
+        writeln!(stderr, "{}", diagnostic::render_all(diagnostics, &options.file, code))
+    };
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String>
+{
+    let command = match args.first().map(|arg| arg.as_str())
+    {
+        Some("run") => Command::Run,
+        Some("check") => Command::Check,
+        Some("tokens") => Command::Tokens,
+        Some("ast") => Command::Ast,
+        Some("highlight") => Command::Highlight,
+        Some("repl") => Command::Repl,
+        Some("grammar") => Command::Grammar,
+        Some(other) => return Err(format!("Unknown command {}", other)),
+        None => return Err(String::from("No command was given"))
+    };
+
+    let mut options = Options {
+        command: command,
+        file: String::new(),
+        args: Vec::new(),
+        json: false,
+        vm: false,
+        capabilities: Capabilities::all(),
+        trivia: false,
+        limits: Limits::default(),
+    };
+    let mut limited = false;
+
+    //Options come before the file, everything after it belongs to the script
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next()
+    {
+        match arg.as_str()
+        {
+            "--json" => options.json = true,
+            "--vm" => options.vm = true,
+            "--trivia" => options.trivia = true,
+            "--capabilities" => {
+                let list = rest.next().ok_or_else(|| String::from("--capabilities needs a list of capabilities"))?;
+                options.capabilities = parse_capabilities(list)?;
+            }
+            other if other.starts_with("--capabilities=") => {
+                options.capabilities = parse_capabilities(&other["--capabilities=".len()..])?;
+            }
+            other if LIMIT_OPTIONS.contains(&other) => {
+                let value = rest.next().ok_or_else(|| format!("{} needs a number", other))?;
+                set_limit(&mut options.limits, other, value)?;
+                limited = true;
+            }
+            other if other.split_once('=').is_some_and(|(name, _)| LIMIT_OPTIONS.contains(&name)) => {
+                let (name, value) = other.split_once('=').unwrap();
+                set_limit(&mut options.limits, name, value)?;
+                limited = true;
+            }
+            other if other.starts_with("--") => return Err(format!("Unknown option {}", other)),
+            file => {
+                options.file = String::from(file);
+                options.args = rest.cloned().collect();
+                break;
+            }
+        }
+    }
+
+    if options.command == Command::Repl
+    {
+        if !options.file.is_empty()
+        {
+            return Err(String::from("repl does not take a file"));
+        }
+        return Ok(options);
+    }
+    if options.command == Command::Grammar
+    {
+        if options.file != "textmate" && options.file != "html"
+        {
+            return Err(String::from("grammar needs the kind of grammar, textmate or html"));
+        }
+    }
+    else if options.file.is_empty()
+    {
+        return Err(String::from("No file was given"));
+    }
+    if options.command != Command::Run && !options.args.is_empty()
+    {
+        return Err(String::from("Only run passes arguments on to the script"));
+    }
+    if options.command != Command::Run && limited
+    {
+        return Err(String::from("Only run has limits"));
+    }
+    return Ok(options);
+}
+
+//Sets the limit of one of the LIMIT_OPTIONS
+fn set_limit(limits: &mut Limits, option: &str, value: &str) -> Result<(), String>
+{
+    let invalid = || format!("{} needs a whole number, not {}", option, value);
+    match option
+    {
+        "--fuel" => limits.fuel = Some(value.parse().map_err(|_| invalid())?),
+        "--max-call-depth" => limits.max_call_depth = Some(value.parse().map_err(|_| invalid())?),
+        "--max-memory" => limits.max_memory = Some(value.parse().map_err(|_| invalid())?),
+        _ => limits.timeout = Some(Duration::from_millis(value.parse().map_err(|_| invalid())?)),
+    }
+    return Ok(());
+}
+
+fn parse_capabilities(list: &str) -> Result<Capabilities, String>
+{
+    let mut capabilities = Capabilities::none();
+    for name in list.split(',').map(|name| name.trim()).filter(|name| !name.is_empty())
+    {
+        match Capability::from_name(name)
+        {
+            Some(capability) => capabilities = capabilities.with(capability),
+            None => {
+                let names: Vec<&str> = ALL_CAPABILITIES.iter().map(|c| c.name()).collect();
+                return Err(format!("Unknown capability {}, the capabilities are {}", name, names.join(", ")));
+            }
+        }
+    }
+    return Ok(capabilities);
+}
+
+#[cfg(test)]
+mod cli_tests
+{
+    use std::env;
+    use std::fs;
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use cli::*;
+
+    static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+    //Runs the command on a file with the code. Returns the exit code, stdout and stderr
+    fn boa(args: &[&str], code: &str) -> (i32, String, String)
+    {
+        let path = env::temp_dir().join(format!("boa_cli_{}_{}.boa", std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::SeqCst)));
+        fs::write(&path, code).unwrap();
+        let path = String::from(path.to_str().unwrap());
+
+        let mut full_args: Vec<String> = args.iter().map(|&arg| String::from(arg)).collect();
+        let position = full_args.iter().position(|arg| arg == "FILE").unwrap();
+        full_args[position] = path.clone();
+
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let exit_code = main(&full_args, &mut Cursor::new(""), &mut stdout, &mut stderr);
+        fs::remove_file(&path).unwrap();
+        let stderr = String::from_utf8(stderr).unwrap().replace(&path, "FILE");
+        (exit_code, String::from_utf8(stdout).unwrap(), stderr)
+    }
+
+    #[test]
+    fn running()
+    {
+        let code = "def Number main(List<String> args) { print(args[1]); return 7; }";
+        assert_eq!(boa(&["run", "FILE", "a", "--b"], code), (7, String::from("--b\n"), String::new()));
+        assert_eq!(boa(&["run", "--vm", "FILE", "a", "b"], code), (7, String::from("b\n"), String::new()));
+        assert_eq!(boa(&["check", "FILE"], code), (EXIT_SUCCESS, String::new(), String::new()));
+
+        for &(returned, exit_code) in &[("255", 255), ("256", EXIT_INVALID_CODE), ("4294967296", EXIT_INVALID_CODE),
+            ("-1", EXIT_INVALID_CODE), ("2.5", EXIT_INVALID_CODE), ("3.0", 3)]
+        {
+            let code = format!("def Number main() {{ return {}; }}", returned);
+            assert_eq!(boa(&["run", "FILE"], &code).0, exit_code, "{}", returned);
+            assert_eq!(boa(&["run", "--vm", "FILE"], &code).0, exit_code, "{}", returned);
+        }
+    }
+
+    #[test]
+    fn each_error_has_its_own_exit_code()
+    {
+        assert_eq!(boa(&["run", "FILE"], "def main() { let a = $; }").0, EXIT_LEX_ERROR);
+        assert_eq!(boa(&["check", "FILE"], "def main() { let = 1; }").0, EXIT_PARSE_ERROR);
+        assert_eq!(boa(&["check", "FILE"], "def main() { let a = 1 + true; }").0, EXIT_CHECK_ERROR);
+        assert_eq!(boa(&["check", "FILE"], "def main() { let a = 1 / 0; }").0, EXIT_SUCCESS);
+
+        let (exit_code, stdout, stderr) = boa(&["run", "FILE"], "def main() { print(1); let a = 1 / 0; }");
+        assert_eq!((exit_code, stdout.as_str()), (EXIT_RUNTIME_ERROR, "1\n"));
+        assert!(stderr.starts_with("error[E0500]: Division by zero\n --> FILE:1:32\n"), "{}", stderr);
+    }
+
+    #[test]
+    fn usage_errors()
+    {
+        assert_eq!(main(&[], &mut Cursor::new(""), &mut Vec::new(), &mut Vec::new()), EXIT_USAGE);
+        let (exit_code, _, stderr) = boa(&["build", "FILE"], "");
+        assert_eq!(exit_code, EXIT_USAGE);
+        assert!(stderr.starts_with("Unknown command build\n\nUsage: boa"));
+        assert_eq!(boa(&["check", "--fast", "FILE"], "").0, EXIT_USAGE);
+        assert_eq!(boa(&["check", "FILE", "extra"], "").0, EXIT_USAGE);
+        let (exit_code, _, stderr) = boa(&["run", "--capabilities", "network", "FILE"], "");
+        assert_eq!(exit_code, EXIT_USAGE);
+        assert!(stderr.starts_with("Unknown capability network, the capabilities are filesystem, clock"));
+
+        let mut stderr = Vec::new();
+        let args = vec!(String::from("run"), String::from("/surely/not/a/file.boa"));
+        assert_eq!(main(&args, &mut Cursor::new(""), &mut Vec::new(), &mut stderr), EXIT_USAGE);
+        assert!(String::from_utf8(stderr).unwrap().starts_with("Failed to read /surely/not/a/file.boa"));
+    }
+
+    #[test]
+    fn limits()
+    {
+        let (exit_code, _, stderr) = boa(&["run", "--fuel", "1000", "FILE"], "def main() { while true {} }");
+        assert_eq!(exit_code, EXIT_RUNTIME_ERROR);
+        assert!(stderr.starts_with("error[E0501]: The script ran out of fuel after 1000 steps"), "{}", stderr);
+        let (exit_code, _, stderr) = boa(&["run", "--vm", "--timeout=50", "FILE"], "def main() { while true {} }");
+        assert_eq!(exit_code, EXIT_RUNTIME_ERROR);
+        assert!(stderr.starts_with("error[E0504]: The script ran for longer than 50 ms"), "{}", stderr);
+
+        let code = "def Number sum(Number n) { if n == 0 { return 0; } return n + sum(n - 1); } \
+            def Number main() { return sum(20) - 200; }";
+        assert_eq!(boa(&["run", "--vm", "FILE"], code).0, 10);
+        let (exit_code, _, stderr) = boa(&["run", "--vm", "--max-call-depth", "10", "FILE"], code);
+        assert_eq!(exit_code, EXIT_RUNTIME_ERROR);
+        assert!(stderr.starts_with("error[E0502]: The maximum call depth of 10 was exceeded"), "{}", stderr);
+        let code = "def main() { let s = \"ab\"; while true { s = s + s; } }";
+        assert_eq!(boa(&["run", "--max-memory", "4096", "FILE"], code).0, EXIT_RUNTIME_ERROR);
+
+        let (exit_code, _, stderr) = boa(&["run", "--fuel", "lots", "FILE"], "");
+        assert_eq!(exit_code, EXIT_USAGE);
+        assert!(stderr.starts_with("--fuel needs a whole number, not lots"), "{}", stderr);
+        assert_eq!(boa(&["check", "--timeout=10", "FILE"], "").0, EXIT_USAGE);
+    }
+
+    #[test]
+    fn capabilities()
+    {
+        let code = "def main() { print(clock() > 0); }";
+        assert_eq!(boa(&["run", "FILE"], code).0, EXIT_SUCCESS);
+        assert_eq!(boa(&["run", "--capabilities=clock,random", "FILE"], code).0, EXIT_SUCCESS);
+        let (exit_code, _, stderr) = boa(&["run", "--capabilities", "", "FILE"], code);
+        assert_eq!(exit_code, EXIT_CHECK_ERROR);
+        assert!(stderr.starts_with("error[E0309]: clock needs the clock capability"), "{}", stderr);
+    }
+
+    #[test]
+    fn tokens_and_ast()
+    {
+        let (exit_code, stdout, _) = boa(&["tokens", "FILE"], "def main()\n{ #hi\n}");
+        assert_eq!(exit_code, EXIT_SUCCESS);
+        assert_eq!(stdout, "\
+1:1      Keyword        \"def\"
+1:5      Identifier     \"main\"
+1:9      OpenPar        \"(\"
+1:10     ClosePar       \")\"
+2:1      OpenCurl       \"{\"
+3:1      CloseCurl      \"}\"
+");
+        let (_, stdout, _) = boa(&["tokens", "--trivia", "FILE"], "def main()\n{ #hi\n}");
+        assert!(stdout.contains("2:3      LineComment    \"#hi\"\n"), "{}", stdout);
+
+        let (exit_code, stdout, _) = boa(&["ast", "FILE"], "def main() { print(1); }");
+        assert_eq!(exit_code, EXIT_SUCCESS);
+        assert_eq!(stdout, "Function main()\n  Expression\n    Call\n      Identifier print\n      Number 1\n");
+    }
+
+    #[test]
+    fn highlighting()
+    {
+        let (exit_code, stdout, _) = boa(&["highlight", "FILE"], "let a = 1 <");
+        assert_eq!(exit_code, EXIT_SUCCESS);
+        assert!(stdout.starts_with("<pre class=\"boa\"><code><span class=\"boa-keyword\">let</span>"), "{}", stdout);
+
+        let mut stdout = Vec::new();
+        let args = vec!(String::from("grammar"), String::from("textmate"));
+        assert_eq!(main(&args, &mut Cursor::new(""), &mut stdout, &mut Vec::new()), EXIT_SUCCESS);
+        let grammar: serde_json::Value = serde_json::from_slice(&stdout).unwrap();
+        assert_eq!(grammar["scopeName"], "source.boa");
+
+        let args = vec!(String::from("grammar"), String::from("vim"));
+        assert_eq!(main(&args, &mut Cursor::new(""), &mut Vec::new(), &mut Vec::new()), EXIT_USAGE);
+    }
+
+    #[test]
+    fn json_diagnostics()
+    {
+        let (exit_code, _, stderr) = boa(&["check", "--json", "FILE"], "def main() { print(x); }");
+        assert_eq!(exit_code, EXIT_CHECK_ERROR);
+        assert!(stderr.starts_with("{\"code\":\"E0200\""), "{}", stderr);
+        assert_eq!(stderr.lines().count(), 1);
+    }
+
+    #[test]
+    fn repl()
+    {
+        let args = vec!(String::from("repl"), String::from("--capabilities=clock"));
+        let mut stdout = Vec::new();
+        let exit_code = main(&args, &mut Cursor::new("let a = 2\na * 3\nrandom()\n"), &mut stdout, &mut Vec::new());
+        assert_eq!(exit_code, EXIT_SUCCESS);
+        let stdout = String::from_utf8(stdout).unwrap();
+        assert!(stdout.starts_with("> > 6 : Number\n> error[E0309]: random needs the random capability"), "{}", stdout);
+
+        let args = vec!(String::from("repl"), String::from("file.boa"));
+        assert_eq!(main(&args, &mut Cursor::new(""), &mut Vec::new(), &mut Vec::new()), EXIT_USAGE);
+    }
+}
@@ -0,0 +1,599 @@
+use std::collections::HashMap;
+
+use ast::*;
+use builtins::Builtins;
+use bytecode::{Chunk, CompiledFunction, CompiledProgram, Instruction};
+use interpreter::{function_value, place_span};
+use number::Number;
+use token::Span;
+use type_checker::{Accessor, CallTargets, ConstructorCall, MethodCall};
+use variable::Value;
+
+//Compiles a program that has passed the resolver and type checker into bytecode
+//for the virtual machine. targets are what the type checker found operators on
+//user types and accessors to call
+pub fn compile(program: &Program, targets: &CallTargets, builtins: &Builtins) -> CompiledProgram
+{
+    let mut functions = HashMap::new();
+    for item in &program.items
+    {
+        if let Item::Function(ref function) = *item
+        {
+            functions.insert(function.name.clone(), function);
+        }
+    }
+
+    let mut compiled = CompiledProgram::new();
+    for item in &program.items
+    {
+        match *item
+        {
+            Item::Function(ref function) => {
+                let name = function.name.clone();
+                compiled.add_function(FunctionCompiler::new(&functions, targets, builtins).compile(name, function));
+            }
+            //Functions of implement blocks are called by the virtual machine as Type.name
+            Item::Implement(ref implement) => {
+                for function in &implement.functions
+                {
+                    let name = implement.function_name(function);
+                    compiled.add_function(FunctionCompiler::new(&functions, targets, builtins).compile(name, function));
+                }
+            }
+            _ => {}
+        }
+    }
+    return compiled;
+}
+
+/*
+ * Compiles a single function. Variables are given local slots while compiling so the
+ * virtual machine never looks them up by name. Slots of scopes that have ended are
+ * reused by later scopes
+ */
+struct FunctionCompiler<'a>
+{
+    functions: &'a HashMap<String, &'a FunctionDecl>,
+    targets: &'a CallTargets,
+    builtins: &'a Builtins,
+    chunk: Chunk,
+    //The variables of each scope and the first slot the scope uses
+    scopes: Vec<(HashMap<String, usize>, usize)>,
+    next_slot: usize,
+    max_slots: usize,
+}
+
+impl<'a> FunctionCompiler<'a>
+{
+    fn new(functions: &'a HashMap<String, &'a FunctionDecl>, targets: &'a CallTargets, builtins: &'a Builtins)
+        -> FunctionCompiler<'a>
+    {
+        FunctionCompiler {
+            functions: functions,
+            targets: targets,
+            builtins: builtins,
+            chunk: Chunk::new(),
+            scopes: Vec::new(),
+            next_slot: 0,
+            max_slots: 0,
+        }
+    }
+
+    fn compile(mut self, name: String, function: &FunctionDecl) -> CompiledFunction
+    {
+        self.push_scope();
+        //The receiver is the first argument
+        if function.receiver.is_some()
+        {
+            self.declare("self");
+        }
+        for param in &function.params
+        {
+            self.declare(&param.name);
+        }
+        self.statements(&function.body.statements);
+        let unit = self.chunk.add_constant(Value::Unit);
+        self.chunk.emit(Instruction::Constant(unit), function.body.span);
+        self.chunk.emit(Instruction::Return, function.body.span);
+        self.pop_scope();
+
+        CompiledFunction {
+            name: name,
+            params: function.params.len() + if function.receiver.is_some() {1} else {0},
+            locals: self.max_slots,
+            chunk: self.chunk,
+        }
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    //                      Slots
+    ////////////////////////////////////////////////////////////////////////////
+    fn push_scope(&mut self)
+    {
+        self.scopes.push((HashMap::new(), self.next_slot));
+    }
+
+    fn pop_scope(&mut self)
+    {
+        let (_, first_slot) = self.scopes.pop().expect("Popped more scopes than were pushed");
+        self.next_slot = first_slot;
+    }
+
+    fn declare(&mut self, name: &str) -> usize
+    {
+        let slot = self.hidden_slot();
+        self.scopes.last_mut().unwrap().0.insert(String::from(name), slot);
+        return slot;
+    }
+
+    //A slot without a name, used by loops to keep track of where they are
+    fn hidden_slot(&mut self) -> usize
+    {
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        self.max_slots = self.max_slots.max(self.next_slot);
+        return slot;
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize>
+    {
+        self.scopes.iter().rev().filter_map(|&(ref names, _)| names.get(name)).next().cloned()
+    }
+
+    fn constant(&mut self, value: Value, span: Span)
+    {
+        let index = self.chunk.add_constant(value);
+        self.chunk.emit(Instruction::Constant(index), span);
+    }
+
+    fn fail(&mut self, message: String, span: Span)
+    {
+        let index = self.chunk.add_constant(Value::String(message));
+        self.chunk.emit(Instruction::Fail(index), span);
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    //                      Statements
+    ////////////////////////////////////////////////////////////////////////////
+    fn statements(&mut self, statements: &[Statement])
+    {
+        for statement in statements
+        {
+            self.statement(statement);
+        }
+    }
+
+    fn block(&mut self, block: &Block)
+    {
+        self.push_scope();
+        self.statements(&block.statements);
+        self.pop_scope();
+    }
+
+    fn statement(&mut self, statement: &Statement)
+    {
+        self.chunk.emit(Instruction::Step, statement.span);
+        match statement.kind
+        {
+            StatementKind::Let{ref name, ref value, ..} => {
+                match *value
+                {
+                    Some(ref value) => self.expr(value),
+                    None => self.constant(Value::Unit, statement.span)
+                }
+                let slot = self.declare(name);
+                self.chunk.emit(Instruction::StoreLocal(slot), statement.span);
+            }
+            StatementKind::Assign{ref target, op, ref value} => self.assign(target, op, Some(value), statement.span),
+            StatementKind::Increment{ref target, op} => self.assign(target, Some(op), None, statement.span),
+            StatementKind::Expr(ref expr) => {
+                self.expr(expr);
+                self.chunk.emit(Instruction::Pop, statement.span);
+            }
+            StatementKind::Return(ref value) => {
+                match *value
+                {
+                    Some(ref value) => self.expr(value),
+                    None => self.constant(Value::Unit, statement.span)
+                }
+                self.chunk.emit(Instruction::Return, statement.span);
+            }
+            StatementKind::If{ref branches, ref else_block} => {
+                let mut end_jumps = Vec::new();
+                for &(ref condition, ref block) in branches
+                {
+                    self.expr(condition);
+                    let next = self.chunk.emit(Instruction::JumpIfFalse(0), condition.span);
+                    self.block(block);
+                    end_jumps.push(self.chunk.emit(Instruction::Jump(0), statement.span));
+                    self.chunk.patch_jump(next);
+                }
+                if let Some(ref block) = *else_block
+                {
+                    self.block(block);
+                }
+                for jump in end_jumps
+                {
+                    self.chunk.patch_jump(jump);
+                }
+            }
+            StatementKind::While{ref condition, ref body} => {
+                let start = self.chunk.code.len();
+                self.expr(condition);
+                let exit = self.chunk.emit(Instruction::JumpIfFalse(0), condition.span);
+                self.chunk.emit(Instruction::Step, statement.span);
+                self.block(body);
+                self.chunk.emit(Instruction::Jump(start), statement.span);
+                self.chunk.patch_jump(exit);
+            }
+            StatementKind::For{ref var, ref start, ref end, ref step, ref body, ..} => {
+                self.for_loop(var, start, end, step.as_ref(), body, statement.span);
+            }
+            StatementKind::Foreach{ref var, ref value, ref container, ref body, ..} => {
+                let pairs = value.is_some();
+                self.push_scope();
+                let values = self.hidden_slot();
+                let position = self.hidden_slot();
+                self.expr(container);
+                self.chunk.emit(Instruction::IterStart{pairs: pairs}, container.span);
+                self.chunk.emit(Instruction::StoreLocal(values), container.span);
+                self.constant(Value::Number(Number::Integer(0)), container.span);
+                self.chunk.emit(Instruction::StoreLocal(position), container.span);
+
+                let next = self.chunk.emit(Instruction::IterNext{values: values, position: position, exit: 0, pairs: pairs}, statement.span);
+                self.chunk.emit(Instruction::Step, statement.span);
+                self.push_scope();
+                let slot = self.declare(var);
+                if let Some(ref value) = *value
+                {
+                    //The value is on top of the key
+                    let value_slot = self.declare(&value.name);
+                    self.chunk.emit(Instruction::StoreLocal(value_slot), statement.span);
+                }
+                self.chunk.emit(Instruction::StoreLocal(slot), statement.span);
+                self.statements(&body.statements);
+                self.pop_scope();
+                self.chunk.emit(Instruction::Jump(next), statement.span);
+                self.chunk.patch_jump(next);
+                self.chunk.emit(Instruction::IterEnd, statement.span);
+                self.pop_scope();
+            }
+            StatementKind::Block(ref block) => self.block(block),
+        }
+    }
+
+    fn for_loop(&mut self, var: &str, start: &Expr, end: &Expr, step: Option<&Expr>, body: &Block, span: Span)
+    {
+        self.push_scope();
+        let start_slot = self.hidden_slot();
+        let end_slot = self.hidden_slot();
+        let step_slot = self.hidden_slot();
+        let pass = self.hidden_slot();
+
+        self.expr(start);
+        self.chunk.emit(Instruction::CheckNumber, start.span);
+        self.chunk.emit(Instruction::StoreLocal(start_slot), start.span);
+        self.expr(end);
+        self.chunk.emit(Instruction::CheckNumber, end.span);
+        self.chunk.emit(Instruction::StoreLocal(end_slot), end.span);
+        match step
+        {
+            Some(step) => {
+                self.expr(step);
+                self.chunk.emit(Instruction::CheckNumber, step.span);
+                self.chunk.emit(Instruction::CheckStep, step.span);
+            }
+            None => self.constant(Value::Number(Number::Integer(1)), span)
+        }
+        self.chunk.emit(Instruction::StoreLocal(step_slot), span);
+        self.constant(Value::Number(Number::Integer(0)), span);
+        self.chunk.emit(Instruction::StoreLocal(pass), span);
+
+        let next = self.chunk.emit(Instruction::ForNext{start: start_slot, end: end_slot, step: step_slot, pass: pass, exit: 0}, start.span);
+        self.chunk.emit(Instruction::Step, body.span);
+        self.push_scope();
+        let slot = self.declare(var);
+        self.chunk.emit(Instruction::StoreLocal(slot), span);
+        self.statements(&body.statements);
+        self.pop_scope();
+        self.chunk.emit(Instruction::Jump(next), span);
+        self.chunk.patch_jump(next);
+        self.pop_scope();
+    }
+
+    //Compiles target = value, target op= value and target++. The parts of the target
+    //are evaluated first, then the value, then the current value of the target is
+    //read if it is needed
+    fn assign(&mut self, target: &Expr, op: Option<BinaryOp>, value: Option<&Expr>, span: Span)
+    {
+        match target.kind
+        {
+            ExprKind::Identifier(ref name) => {
+                let slot = match self.lookup(name)
+                {
+                    Some(slot) => slot,
+                    None => return self.fail(format!("{} is not declared", name), target.span)
+                };
+                self.value_and_operator(target, op, value, span, |compiler| {
+                    compiler.chunk.emit(Instruction::LoadLocal(slot), target.span);
+                });
+                self.chunk.emit(Instruction::StoreLocal(slot), target.span);
+            }
+            ExprKind::Index{ref object, ref index} => {
+                self.expr(object);
+                self.expr(index);
+                self.value_and_operator(target, op, value, span, |compiler| {
+                    compiler.chunk.emit(Instruction::Dup2, target.span);
+                    compiler.chunk.emit(Instruction::Index, target.span);
+                });
+                self.chunk.emit(Instruction::SetIndex, target.span);
+            }
+            ExprKind::Member{ref object, ref name, ..} => {
+                self.expr(object);
+                let name = self.chunk.add_constant(Value::String(name.clone()));
+                let name_span = place_span(target);
+                self.value_and_operator(target, op, value, span, |compiler| {
+                    compiler.chunk.emit(Instruction::Dup, name_span);
+                    compiler.chunk.emit(Instruction::Field(name), name_span);
+                });
+                self.chunk.emit(Instruction::SetField(name), name_span);
+            }
+            _ => self.fail(String::from("Only variables, members and elements can be assigned to"), target.span)
+        }
+    }
+
+    //Leaves the value to store on the stack. load pushes the current value of the
+    //target, which is combined with the new value by the operator
+    fn value_and_operator<F>(&mut self, target: &Expr, op: Option<BinaryOp>, value: Option<&Expr>, span: Span, load: F)
+        where F: Fn(&mut FunctionCompiler<'a>)
+    {
+        match (op, value)
+        {
+            (None, Some(value)) => self.expr(value),
+            (Some(op), Some(value)) => {
+                self.push_scope();
+                let temporary = self.hidden_slot();
+                self.expr(value);
+                self.chunk.emit(Instruction::StoreLocal(temporary), span);
+                load(self);
+                self.chunk.emit(Instruction::LoadLocal(temporary), span);
+                self.binary(op, target.id, span);
+                self.pop_scope();
+            }
+            (Some(op), None) => {
+                load(self);
+                self.constant(Value::Number(Number::Integer(1)), span);
+                self.chunk.emit(Instruction::Binary(op), span);
+            }
+            (None, None) => panic!("An assignment needs a value or an operator")
+        }
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    //                      Expressions
+    ////////////////////////////////////////////////////////////////////////////
+    fn expr(&mut self, expr: &Expr)
+    {
+        self.expr_kind(expr);
+        //Values of user types that are used as conditions are turned into a Bool by operator if
+        if let Some(function) = self.targets.conditions.get(&expr.id)
+        {
+            self.call_named(function, 1, expr.span);
+        }
+    }
+
+    fn expr_kind(&mut self, expr: &Expr)
+    {
+        match expr.kind
+        {
+            ExprKind::Number(ref lexeme) => match Number::parse(lexeme)
+            {
+                Some(number) => self.constant(Value::Number(number), expr.span),
+                None => self.fail(format!("{} is not a valid number", lexeme), expr.span)
+            },
+            ExprKind::String(ref s) => self.constant(Value::String(s.clone()), expr.span),
+            ExprKind::Bool(b) => self.constant(Value::Bool(b), expr.span),
+            ExprKind::Identifier(ref name) => {
+                if let Some(slot) = self.lookup(name)
+                {
+                    self.chunk.emit(Instruction::LoadLocal(slot), expr.span);
+                    return;
+                }
+                let function = match self.functions.get(name)
+                {
+                    Some(function) => Some(function_value(function)),
+                    None => self.builtins.get(name).map(|builtin| builtin.value())
+                };
+                match function
+                {
+                    Some(function) => self.constant(function, expr.span),
+                    None => self.fail(format!("{} is not declared", name), expr.span)
+                }
+            }
+            ExprKind::List(ref elements) => {
+                for element in elements
+                {
+                    self.expr(element);
+                }
+                self.chunk.emit(Instruction::List(elements.len()), expr.span);
+            }
+            ExprKind::Dictionary(ref entries) => {
+                for &(ref key, ref value) in entries
+                {
+                    self.expr(key);
+                    self.expr(value);
+                }
+                self.chunk.emit(Instruction::Dictionary(entries.len()), expr.span);
+            }
+            ExprKind::Unary{op, expr: ref operand} => {
+                self.expr(operand);
+                match self.targets.operators.get(&expr.id)
+                {
+                    Some(function) => self.call_named(function, 1, expr.span),
+                    None => {
+                        self.chunk.emit(Instruction::Unary(op), expr.span);
+                    }
+                }
+            }
+            ExprKind::Binary{op: BinaryOp::And, ref lhs, ref rhs} => {
+                self.short_circuit(lhs, rhs, false, expr.span);
+            }
+            ExprKind::Binary{op: BinaryOp::Or, ref lhs, ref rhs} => {
+                self.short_circuit(lhs, rhs, true, expr.span);
+            }
+            ExprKind::Binary{op, ref lhs, ref rhs} => {
+                self.expr(lhs);
+                self.expr(rhs);
+                self.binary(op, expr.id, expr.span);
+            }
+            ExprKind::Call{ref callee, ref args} => {
+                if let Some(accessor) = self.targets.accessors.get(&callee.id)
+                {
+                    return self.accessor(accessor, callee, args, expr.span);
+                }
+                if let Some(method) = self.targets.methods.get(&callee.id)
+                {
+                    return self.method(method, callee, args, expr.span);
+                }
+                if let Some(constructor) = self.targets.constructors.get(&callee.id)
+                {
+                    return self.construct(constructor, args, expr.span);
+                }
+                self.expr(callee);
+                self.chunk.emit(Instruction::CheckCallable, callee.span);
+                for arg in args
+                {
+                    self.expr(arg);
+                }
+                self.chunk.emit(Instruction::Call(args.len()), expr.span);
+            }
+            ExprKind::Member{ref object, ref name, name_span} => {
+                self.expr(object);
+                let name = self.chunk.add_constant(Value::String(name.clone()));
+                self.chunk.emit(Instruction::Field(name), name_span);
+            }
+            ExprKind::Index{ref object, ref index} => {
+                self.expr(object);
+                self.expr(index);
+                self.chunk.emit(Instruction::Index, expr.span);
+            }
+        }
+    }
+
+    //Generated getters and setters read and write their field directly
+    fn accessor(&mut self, accessor: &Accessor, callee: &Expr, args: &[Expr], span: Span)
+    {
+        let object = match callee.kind
+        {
+            ExprKind::Member{ref object, ..} => object,
+            _ => panic!("Accessors are only called as members")
+        };
+        self.expr(object);
+        match *accessor
+        {
+            Accessor::Get(ref field) => {
+                let name = self.chunk.add_constant(Value::String(field.clone()));
+                self.chunk.emit(Instruction::Field(name), span);
+            }
+            Accessor::Set(ref field) => {
+                self.expr(&args[0]);
+                let name = self.chunk.add_constant(Value::String(field.clone()));
+                self.chunk.emit(Instruction::SetField(name), span);
+                self.constant(Value::Unit, span);
+            }
+        }
+    }
+
+    //Methods get the object before the . as their first argument, static functions
+    //only get their arguments
+    fn method(&mut self, method: &MethodCall, callee: &Expr, args: &[Expr], span: Span)
+    {
+        let mut count = args.len();
+        if method.receiver
+        {
+            match callee.kind
+            {
+                ExprKind::Member{ref object, ..} => self.expr(object),
+                _ => panic!("Methods are only called as members")
+            }
+            count += 1;
+        }
+        for arg in args
+        {
+            self.expr(arg);
+        }
+        self.call_named(&method.function, count, span);
+    }
+
+    //Without a constructor the arguments are the values of the fields. Otherwise
+    //the new object is passed to the constructor as self and is kept on the stack
+    //under the nothing that the constructor returns
+    fn construct(&mut self, constructor: &ConstructorCall, args: &[Expr], span: Span)
+    {
+        let fields = constructor.fields.iter().map(|field| (field.clone(), Value::Unit)).collect();
+        let prototype = self.chunk.add_constant(Value::object(constructor.type_name.clone(), fields));
+        match constructor.function
+        {
+            Some(ref function) => {
+                self.chunk.emit(Instruction::Object{prototype: prototype, values: false}, span);
+                self.chunk.emit(Instruction::Dup, span);
+                for arg in args
+                {
+                    self.expr(arg);
+                }
+                self.call_named(function, args.len() + 1, span);
+                self.chunk.emit(Instruction::Pop, span);
+            }
+            None => {
+                for arg in args
+                {
+                    self.expr(arg);
+                }
+                self.chunk.emit(Instruction::Object{prototype: prototype, values: true}, span);
+            }
+        }
+    }
+
+    //Pops two values and combines them with op, or with the operator function that
+    //the type checker chose for the expression id. != uses the function of ==
+    fn binary(&mut self, op: BinaryOp, id: NodeId, span: Span)
+    {
+        match self.targets.operators.get(&id)
+        {
+            Some(function) => {
+                self.call_named(function, 2, span);
+                if op == BinaryOp::NotEqual
+                {
+                    self.chunk.emit(Instruction::Unary(UnaryOp::Not), span);
+                }
+            }
+            None => {
+                self.chunk.emit(Instruction::Binary(op), span);
+            }
+        }
+    }
+
+    fn call_named(&mut self, function: &str, args: usize, span: Span)
+    {
+        let function = self.chunk.add_constant(Value::String(function.to_string()));
+        self.chunk.emit(Instruction::CallNamed{function: function, args: args}, span);
+    }
+
+    //a & b and a | b. The right side is only evaluated if the left side doesn't
+    //decide the result, which is short_value
+    fn short_circuit(&mut self, lhs: &Expr, rhs: &Expr, short_value: bool, span: Span)
+    {
+        let jump = if short_value {Instruction::JumpIfTrue(0)} else {Instruction::JumpIfFalse(0)};
+
+        self.expr(lhs);
+        let lhs_jump = self.chunk.emit(jump, lhs.span);
+        self.expr(rhs);
+        let rhs_jump = self.chunk.emit(jump, rhs.span);
+        self.constant(Value::Bool(!short_value), span);
+        let end = self.chunk.emit(Instruction::Jump(0), span);
+        self.chunk.patch_jump(lhs_jump);
+        self.chunk.patch_jump(rhs_jump);
+        self.constant(Value::Bool(short_value), span);
+        self.chunk.patch_jump(end);
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::rc::Rc;
+
+use builtins::NativeFunction;
+use number::Number;
+use types::Type;
+use variable::{Dictionary, Value};
+
+/*
+ * Conversions between Rust values and the values of scripts. These are what lets
+ * native functions and calls into scripts use plain Rust types
+ */
+
+//A Rust type that corresponds to a type in scripts
+pub trait ScriptType
+{
+    fn script_type() -> Type;
+}
+
+//Fails for Rust values that scripts can't hold, like a NaN
+pub trait IntoValue: ScriptType
+{
+    fn into_value(self) -> Result<Value, String>;
+}
+
+pub trait FromValue: ScriptType + Sized
+{
+    fn from_value(value: Value) -> Result<Self, String>;
+}
+
+fn mismatch<T>(expected: &Type, value: &Value) -> Result<T, String>
+{
+    Err(format!("Expected {} but found {}", expected, value.type_name()))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+//                      Numbers
+////////////////////////////////////////////////////////////////////////////////
+impl ScriptType for f64
+{
+    fn script_type() -> Type
+    {
+        Type::Number
+    }
+}
+
+impl IntoValue for f64
+{
+    fn into_value(self) -> Result<Value, String>
+    {
+        Number::float(self)
+            .map(Value::Number)
+            .map_err(|_| format!("{} is not a Number, only finite numbers can be passed to scripts", self))
+    }
+}
+
+impl FromValue for f64
+{
+    fn from_value(value: Value) -> Result<f64, String>
+    {
+        value.as_number().map(|n| n.as_f64())
+    }
+}
+
+impl ScriptType for i64
+{
+    fn script_type() -> Type
+    {
+        Type::Number
+    }
+}
+
+impl IntoValue for i64
+{
+    fn into_value(self) -> Result<Value, String>
+    {
+        Ok(Value::integer(self))
+    }
+}
+
+//Only whole numbers can be converted to integers
+impl FromValue for i64
+{
+    fn from_value(value: Value) -> Result<i64, String>
+    {
+        let number = value.as_number()?;
+        number.as_i64().ok_or_else(|| format!("Expected a whole number but found {}", number))
+    }
+}
+
+//Numbers can also be passed as they are, which keeps integers exact
+impl ScriptType for Number
+{
+    fn script_type() -> Type
+    {
+        Type::Number
+    }
+}
+
+impl IntoValue for Number
+{
+    fn into_value(self) -> Result<Value, String>
+    {
+        match self
+        {
+            Number::Float(float) => float.into_value(),
+            integer => Ok(Value::Number(integer))
+        }
+    }
+}
+
+impl FromValue for Number
+{
+    fn from_value(value: Value) -> Result<Number, String>
+    {
+        value.as_number()
+    }
+}
+
+impl ScriptType for i32
+{
+    fn script_type() -> Type
+    {
+        Type::Number
+    }
+}
+
+impl IntoValue for i32
+{
+    fn into_value(self) -> Result<Value, String>
+    {
+        Ok(Value::integer(i64::from(self)))
+    }
+}
+
+impl FromValue for i32
+{
+    fn from_value(value: Value) -> Result<i32, String>
+    {
+        let integer = i64::from_value(value)?;
+        if integer < i64::from(i32::MIN) || integer > i64::from(i32::MAX)
+        {
+            return Err(format!("{} does not fit in a 32 bit integer", integer));
+        }
+        return Ok(integer as i32);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+//                      Strings and bools
+////////////////////////////////////////////////////////////////////////////////
+impl ScriptType for String
+{
+    fn script_type() -> Type
+    {
+        Type::String
+    }
+}
+
+impl IntoValue for String
+{
+    fn into_value(self) -> Result<Value, String>
+    {
+        Ok(Value::String(self))
+    }
+}
+
+impl FromValue for String
+{
+    fn from_value(value: Value) -> Result<String, String>
+    {
+        match value
+        {
+            Value::String(s) => Ok(s),
+            other => mismatch(&Type::String, &other)
+        }
+    }
+}
+
+impl ScriptType for &str
+{
+    fn script_type() -> Type
+    {
+        Type::String
+    }
+}
+
+impl IntoValue for &str
+{
+    fn into_value(self) -> Result<Value, String>
+    {
+        Ok(Value::String(String::from(self)))
+    }
+}
+
+impl ScriptType for bool
+{
+    fn script_type() -> Type
+    {
+        Type::Bool
+    }
+}
+
+impl IntoValue for bool
+{
+    fn into_value(self) -> Result<Value, String>
+    {
+        Ok(Value::Bool(self))
+    }
+}
+
+impl FromValue for bool
+{
+    fn from_value(value: Value) -> Result<bool, String>
+    {
+        value.as_bool()
+    }
+}
+
+//The result of functions that don't return anything
+impl ScriptType for ()
+{
+    fn script_type() -> Type
+    {
+        Type::Unit
+    }
+}
+
+impl IntoValue for ()
+{
+    fn into_value(self) -> Result<Value, String>
+    {
+        Ok(Value::Unit)
+    }
+}
+
+impl FromValue for ()
+{
+    fn from_value(value: Value) -> Result<(), String>
+    {
+        match value
+        {
+            Value::Unit => Ok(()),
+            other => mismatch(&Type::Unit, &other)
+        }
+    }
+}
+
+//Values can be passed through without converting them. They accept any type
+impl ScriptType for Value
+{
+    fn script_type() -> Type
+    {
+        Type::Any
+    }
+}
+
+impl IntoValue for Value
+{
+    fn into_value(self) -> Result<Value, String>
+    {
+        Ok(self)
+    }
+}
+
+impl FromValue for Value
+{
+    fn from_value(value: Value) -> Result<Value, String>
+    {
+        Ok(value)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+//                      Containers
+////////////////////////////////////////////////////////////////////////////////
+impl<T: ScriptType> ScriptType for Vec<T>
+{
+    fn script_type() -> Type
+    {
+        Type::list(T::script_type())
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T>
+{
+    fn into_value(self) -> Result<Value, String>
+    {
+        let elements = self.into_iter().map(IntoValue::into_value).collect::<Result<Vec<Value>, String>>()?;
+        Ok(Value::list(elements))
+    }
+}
+
+//Lists are copied into the Vec, changes to it are not seen by the script
+impl<T: FromValue> FromValue for Vec<T>
+{
+    fn from_value(value: Value) -> Result<Vec<T>, String>
+    {
+        match value
+        {
+            Value::List(elements) => elements.borrow().iter().cloned().map(T::from_value).collect(),
+            other => mismatch(&Vec::<T>::script_type(), &other)
+        }
+    }
+}
+
+impl<K: ScriptType, V: ScriptType> ScriptType for HashMap<K, V>
+{
+    fn script_type() -> Type
+    {
+        Type::dictionary(K::script_type(), V::script_type())
+    }
+}
+
+impl<K: IntoValue + Eq + Hash, V: IntoValue> IntoValue for HashMap<K, V>
+{
+    fn into_value(self) -> Result<Value, String>
+    {
+        let mut dictionary = Dictionary::new();
+        for (key, value) in self
+        {
+            dictionary.insert(key.into_value()?, value.into_value()?);
+        }
+        Ok(Value::dictionary(dictionary))
+    }
+}
+
+impl<K: FromValue + Eq + Hash, V: FromValue> FromValue for HashMap<K, V>
+{
+    fn from_value(value: Value) -> Result<HashMap<K, V>, String>
+    {
+        match value
+        {
+            Value::Dictionary(dictionary) => {
+                let mut result = HashMap::new();
+                for &(ref key, ref value) in dictionary.borrow().entries()
+                {
+                    result.insert(K::from_value(key.clone())?, V::from_value(value.clone())?);
+                }
+                Ok(result)
+            }
+            other => mismatch(&HashMap::<K, V>::script_type(), &other)
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+//                      Native functions
+////////////////////////////////////////////////////////////////////////////////
+
+//What a native function can return: a value, or a Result whose error stops
+//the script with a runtime error
+pub trait NativeResult
+{
+    fn script_type() -> Type;
+    fn into_result(self) -> Result<Value, String>;
+}
+
+impl<T: IntoValue> NativeResult for T
+{
+    fn script_type() -> Type
+    {
+        <T as ScriptType>::script_type()
+    }
+
+    fn into_result(self) -> Result<Value, String>
+    {
+        self.into_value()
+    }
+}
+
+impl<T: IntoValue, E: Display> NativeResult for Result<T, E>
+{
+    fn script_type() -> Type
+    {
+        <T as ScriptType>::script_type()
+    }
+
+    fn into_result(self) -> Result<Value, String>
+    {
+        self.map_err(|e| e.to_string())?.into_value()
+    }
+}
+
+//A Rust closure or function that can be registered as a native function. Args is
+//a tuple of the parameter types
+pub trait IntoNative<Args>
+{
+    fn params() -> Vec<Type>;
+    fn return_type() -> Type;
+    fn into_native(self) -> NativeFunction;
+}
+
+//Arguments for calls to script functions from Rust
+pub trait IntoArgs
+{
+    fn into_args(self) -> Result<Vec<Value>, String>;
+}
+
+impl IntoArgs for Vec<Value>
+{
+    fn into_args(self) -> Result<Vec<Value>, String>
+    {
+        Ok(self)
+    }
+}
+
+macro_rules! tuple_impls
+{
+    ($($arg:ident),*) => {
+        impl<Function, Return, $($arg),*> IntoNative<($($arg,)*)> for Function
+            where Function: Fn($($arg),*) -> Return + 'static,
+                  Return: NativeResult,
+                  $($arg: FromValue),*
+        {
+            fn params() -> Vec<Type>
+            {
+                vec!($(<$arg as ScriptType>::script_type()),*)
+            }
+
+            fn return_type() -> Type
+            {
+                Return::script_type()
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn into_native(self) -> NativeFunction
+            {
+                Rc::new(move |args: Vec<Value>| {
+                    let mut args = args.into_iter();
+                    $(let $arg = $arg::from_value(args.next().ok_or("Too few arguments")?)?;)*
+                    self($($arg),*).into_result()
+                })
+            }
+        }
+
+        impl<$($arg: IntoValue),*> IntoArgs for ($($arg,)*)
+        {
+            #[allow(non_snake_case)]
+            fn into_args(self) -> Result<Vec<Value>, String>
+            {
+                let ($($arg,)*) = self;
+                Ok(vec!($($arg.into_value()?),*))
+            }
+        }
+    }
+}
+
+tuple_impls!();
+tuple_impls!(A);
+tuple_impls!(A, B);
+tuple_impls!(A, B, C);
+tuple_impls!(A, B, C, D);
+tuple_impls!(A, B, C, D, E);
+tuple_impls!(A, B, C, D, E, F);
+
+#[cfg(test)]
+mod convert_tests
+{
+    use std::collections::HashMap;
+
+    use convert::*;
+
+    #[test]
+    fn round_trips()
+    {
+        assert_eq!(i64::from_value(5i64.into_value().unwrap()), Ok(5));
+        assert_eq!(f64::from_value(2.5.into_value().unwrap()), Ok(2.5));
+        assert_eq!(String::from_value("text".into_value().unwrap()), Ok(String::from("text")));
+        assert_eq!(Vec::<bool>::from_value(vec!(true, false).into_value().unwrap()), Ok(vec!(true, false)));
+
+        let mut map = HashMap::new();
+        map.insert(String::from("a"), vec!(1i64));
+        assert_eq!(HashMap::<String, Vec<i64>>::from_value(map.clone().into_value().unwrap()), Ok(map));
+    }
+
+    #[test]
+    fn types()
+    {
+        assert_eq!(<Vec<String> as ScriptType>::script_type(), Type::list(Type::String));
+        assert_eq!(<HashMap<String, f64> as ScriptType>::script_type(), Type::dictionary(Type::String, Type::Number));
+        assert_eq!(<() as ScriptType>::script_type(), Type::Unit);
+    }
+
+    #[test]
+    fn failed_conversions()
+    {
+        assert_eq!(i64::from_value(1.5.into_value().unwrap()), Err(String::from("Expected a whole number but found 1.5")));
+        assert_eq!(i32::from_value(5_000_000_000i64.into_value().unwrap()), Err(String::from("5000000000 does not fit in a 32 bit integer")));
+        assert_eq!(String::from_value(Value::Bool(true)), Err(String::from("Expected String but found Bool")));
+        assert_eq!(Vec::<i64>::from_value(Value::integer(1)), Err(String::from("Expected List<Number> but found Number")));
+        assert_eq!(f64::NAN.into_value(), Err(String::from("NaN is not a Number, only finite numbers can be passed to scripts")));
+        assert_eq!(vec!(1.0, f64::INFINITY).into_value(), Err(String::from("inf is not a Number, only finite numbers can be passed to scripts")));
+        assert!(Number::Float(f64::NEG_INFINITY).into_value().is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use std::fmt;
+
+use serde_json::{self, json};
+
+use token::Span;
+
+#[derive(Clone,Copy,Eq,PartialEq,Hash,Debug)]
+pub enum Severity
+{
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self
+        {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+//A secondary piece of code that is related to a diagnostic, for example the
+//place where a variable was declared
+#[derive(Clone,Eq,PartialEq,Debug)]
+pub struct Label
+{
+    pub span: Span,
+    pub message: String,
+}
+
+//A change to the code that would fix the problem
+#[derive(Clone,Eq,PartialEq,Debug)]
+pub struct Suggestion
+{
+    pub message: String,
+    //The code that is replaced. Empty spans insert the replacement
+    pub span: Span,
+    pub replacement: String,
+}
+
+/*
+ * A problem found in a script. Every pass from the lexer to the runtime reports
+ * its problems as diagnostics
+ */
+#[derive(Clone,Eq,PartialEq,Debug)]
+pub struct Diagnostic
+{
+    pub severity: Severity,
+    //One of the codes in error_codes
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Diagnostic
+{
+    pub fn error(code: &'static str, message: String, span: Span) -> Diagnostic
+    {
+        Diagnostic::new(Severity::Error, code, message, span)
+    }
+
+    pub fn warning(code: &'static str, message: String, span: Span) -> Diagnostic
+    {
+        Diagnostic::new(Severity::Warning, code, message, span)
+    }
+
+    fn new(severity: Severity, code: &'static str, message: String, span: Span) -> Diagnostic
+    {
+        Diagnostic {
+            severity: severity,
+            code: code,
+            message: message,
+            span: span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            suggestions: Vec::new()
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: String) -> Diagnostic
+    {
+        self.labels.push(Label{span: span, message: message});
+        return self;
+    }
+
+    pub fn with_note(mut self, note: String) -> Diagnostic
+    {
+        self.notes.push(note);
+        return self;
+    }
+
+    pub fn with_suggestion(mut self, message: String, span: Span, replacement: String) -> Diagnostic
+    {
+        self.suggestions.push(Suggestion{message: message, span: span, replacement: replacement});
+        return self;
+    }
+
+    pub fn is_error(&self) -> bool
+    {
+        self.severity == Severity::Error
+    }
+
+    //Shows the diagnostic with the code it is about, in the same style as rustc:
+    //
+    //    error[E0300]: Expected Number but found String
+    //     --> file.boa:2:20
+    //      |
+    //    2 |     let Number a = "text";
+    //      |                    ^^^^^^
+    //
+    //Labels are underlined with - and suggestions are shown as the fixed line
+    pub fn render(&self, filename: &str, code: &str) -> String
+    {
+        let mut lines: Vec<usize> = vec!(line_of(code, self.span.start).number);
+        lines.extend(self.labels.iter().map(|label| line_of(code, label.span.start).number));
+        lines.extend(self.suggestions.iter().map(|s| line_of(code, s.span.start).number));
+        let gutter = " ".repeat(lines.iter().max().unwrap().to_string().len());
+
+        let (line, column) = self.span.line_column(code);
+        let mut result = format!("{}[{}]: {}\n", self.severity, self.code, self.message);
+        result.push_str(&format!("{}--> {}:{}:{}\n", gutter, filename, line, column));
+        result.push_str(&format!("{} |\n", gutter));
+
+        //Every line with a span on it is shown once, in the order of the code
+        let mut marks: Vec<(Span, char, &str)> = vec!();
+        let mut primary_label = "";
+        for label in &self.labels
+        {
+            if label.span == self.span && primary_label.is_empty()
+            {
+                primary_label = &label.message;
+            }
+            else
+            {
+                marks.push((label.span, '-', &label.message));
+            }
+        }
+        marks.insert(0, (self.span, '^', primary_label));
+
+        let mut shown_lines: Vec<Line> = marks.iter().map(|mark| line_of(code, mark.0.start)).collect();
+        shown_lines.sort_by_key(|line| line.number);
+        shown_lines.dedup_by_key(|line| line.number);
+        let mut previous = None;
+        for line in shown_lines
+        {
+            if previous.map(|p| line.number > p + 1).unwrap_or(false)
+            {
+                result.push_str("...\n");
+            }
+            previous = Some(line.number);
+
+            let text = &code[line.start..line.end];
+            result.push_str(&format!("{:>width$} | {}\n", line.number, text, width = gutter.len()));
+            for &(span, underline, message) in marks.iter().filter(|mark| line_of(code, mark.0.start).number == line.number)
+            {
+                result.push_str(&format!("{} | {}", gutter, underline_span(code, &line, span, underline)));
+                if !message.is_empty()
+                {
+                    result.push(' ');
+                    result.push_str(message);
+                }
+                result.push('\n');
+            }
+        }
+
+        for note in &self.notes
+        {
+            result.push_str(&format!("{} = note: {}\n", gutter, note));
+        }
+        for suggestion in &self.suggestions
+        {
+            let line = line_of(code, suggestion.span.start);
+            let fixed = format!("{}{}{}",
+                &code[line.start..suggestion.span.start],
+                suggestion.replacement,
+                &code[suggestion.span.end.min(line.end).max(suggestion.span.start)..line.end]);
+            let inserted = Span::new(suggestion.span.start, suggestion.span.start + suggestion.replacement.len());
+            let fixed_line = Line{number: line.number, start: 0, end: fixed.len()};
+            let offset = line.start;
+            let shifted = Span::new(inserted.start - offset, inserted.end - offset);
+
+            result.push_str(&format!("{} = help: {}\n", gutter, suggestion.message));
+            result.push_str(&format!("{} |\n", gutter));
+            result.push_str(&format!("{:>width$} | {}\n", line.number, fixed, width = gutter.len()));
+            result.push_str(&format!("{} | {}\n", gutter, underline_span(&fixed, &fixed_line, shifted, '+')));
+        }
+        return result;
+    }
+
+    //The diagnostic as JSON for tools. Spans have their byte offsets as well as
+    //1-based lines and columns
+    pub fn to_json(&self, filename: &str, code: &str) -> serde_json::Value
+    {
+        json!({
+            "severity": self.severity.to_string(),
+            "code": self.code,
+            "message": self.message,
+            "file": filename,
+            "span": span_json(self.span, code),
+            "labels": self.labels.iter()
+                .map(|label| json!({"span": span_json(label.span, code), "message": label.message}))
+                .collect::<Vec<_>>(),
+            "notes": self.notes,
+            "suggestions": self.suggestions.iter()
+                .map(|s| json!({"message": s.message, "span": span_json(s.span, code), "replacement": s.replacement}))
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+//Renders diagnostics after each other, separated by empty lines
+pub fn render_all(diagnostics: &[Diagnostic], filename: &str, code: &str) -> String
+{
+    let rendered: Vec<String> = diagnostics.iter().map(|d| d.render(filename, code)).collect();
+    rendered.join("\n")
+}
+
+//Renders diagnostics as JSON with one diagnostic on each line
+pub fn render_json(diagnostics: &[Diagnostic], filename: &str, code: &str) -> String
+{
+    diagnostics.iter()
+        .map(|d| format!("{}\n", d.to_json(filename, code)))
+        .collect()
+}
+
+fn span_json(span: Span, code: &str) -> serde_json::Value
+{
+    let (line, column) = span.line_column(code);
+    let (end_line, end_column) = Span::new(span.end, span.end).line_column(code);
+    json!({
+        "start": span.start,
+        "end": span.end,
+        "line": line,
+        "column": column,
+        "end_line": end_line,
+        "end_column": end_column,
+    })
+}
+
+//A line of the code, as byte offsets without the line break
+#[derive(Clone,Copy,Debug)]
+struct Line
+{
+    number: usize,
+    start: usize,
+    end: usize,
+}
+
+fn line_of(code: &str, offset: usize) -> Line
+{
+    let offset = offset.min(code.len());
+    let start = code[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let end = code[start..].find('\n').map(|i| start + i).unwrap_or(code.len());
+    Line {
+        number: code[..start].matches('\n').count() + 1,
+        start: start,
+        end: end
+    }
+}
+
+//Spaces up to the span and then the span marked with the underline character.
+//Tabs are kept so that the underline lines up with the code. Spans that go over
+//several lines are underlined to the end of the first line
+fn underline_span(code: &str, line: &Line, span: Span, underline: char) -> String
+{
+    let start = span.start.max(line.start).min(line.end);
+    let end = span.end.min(line.end).max(start);
+    let padding: String = code[line.start..start].chars()
+        .map(|c| if c == '\t' {'\t'} else {' '})
+        .collect();
+    let width = code[start..end].chars().count().max(1);
+    let marks: String = (0..width).map(|_| underline).collect();
+    format!("{}{}", padding, marks)
+}
+
+#[cfg(test)]
+mod diagnostic_tests
+{
+    use diagnostic::*;
+    use error_codes;
+
+    const CODE: &'static str = "def main()\n{\n    let a = 1;\n    let Number b = \"text\"\n}";
+
+    fn span_of(text: &str) -> Span
+    {
+        let start = CODE.find(text).unwrap();
+        Span::new(start, start + text.len())
+    }
+
+    #[test]
+    fn rendering()
+    {
+        let diagnostic = Diagnostic::error(error_codes::TYPE_MISMATCH, String::from("Expected Number but found String"),
+                span_of("\"text\""))
+            .with_label(span_of("Number"), String::from("expected because of this"))
+            .with_label(span_of("a = 1"), String::from("an unrelated label"))
+            .with_note(String::from("Strings can not be used as Numbers"))
+            .with_suggestion(String::from("add a ;"), Span::new(span_of("\"text\"").end, span_of("\"text\"").end),
+                String::from(";"));
+
+        let expected = "\
+error[E0300]: Expected Number but found String
+ --> test.boa:4:20
+  |
+3 |     let a = 1;
+  |         ----- an unrelated label
+4 |     let Number b = \"text\"
+  |                    ^^^^^^
+  |         ------ expected because of this
+  = note: Strings can not be used as Numbers
+  = help: add a ;
+  |
+4 |     let Number b = \"text\";
+  |                          +
+";
+        assert_eq!(diagnostic.render("test.boa", CODE), expected);
+    }
+
+    #[test]
+    fn primary_labels_and_gaps()
+    {
+        let b = Span::new(span_of("b =").start, span_of("b =").start + 1);
+        let diagnostic = Diagnostic::warning(error_codes::SHADOWED_DECLARATION, String::from("message"), b)
+            .with_label(b, String::from("on the caret line"))
+            .with_label(span_of("main"), String::from("far away"));
+        let expected = "\
+warning[W0203]: message
+ --> test.boa:4:16
+  |
+1 | def main()
+  |     ---- far away
+...
+4 |     let Number b = \"text\"
+  |                ^ on the caret line
+";
+        assert_eq!(diagnostic.render("test.boa", CODE), expected);
+    }
+
+    #[test]
+    fn json()
+    {
+        let diagnostic = Diagnostic::error(error_codes::NOT_DECLARED, String::from("x is not declared"), span_of("a = 1"))
+            .with_note(String::from("a note"));
+        let rendered = render_json(&[diagnostic.clone(), diagnostic], "test.boa", CODE);
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines.len(), 2);
+
+        let parsed: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(parsed["severity"], "error");
+        assert_eq!(parsed["code"], "E0200");
+        assert_eq!(parsed["file"], "test.boa");
+        assert_eq!(parsed["span"]["line"], 3);
+        assert_eq!(parsed["span"]["column"], 9);
+        assert_eq!(parsed["span"]["end_column"], 14);
+        assert_eq!(parsed["notes"][0], "a note");
+        assert_eq!(parsed["labels"].as_array().unwrap().len(), 0);
+    }
+}
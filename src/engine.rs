@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::io::{self, Write};
+
+use ast::{Item, Program};
+use builtins::{BuiltinFunction, Builtins};
+use capabilities::{Capabilities, Capability};
+use convert::{FromValue, IntoArgs, IntoNative};
+use diagnostic::Diagnostic;
+use interpreter::{Interpreter, RuntimeError};
+use limits::Limits;
+use token::Span;
+use type_checker::{CallTargets, FunctionSignature};
+use types::Type;
+use validation::ValidationOptions;
+use variable::Value;
+use {check, lex, parse, Error};
+
+/*
+ * Everything that can go wrong when a host uses the engine
+ */
+#[derive(Clone,PartialEq,Debug)]
+pub enum EngineError
+{
+    //The script has errors that were found before it was run
+    Compile(Vec<Diagnostic>),
+    Runtime(RuntimeError),
+    //A value could not be converted between Rust and the script
+    Conversion(String),
+    //The loaded script has no function with this name
+    UnknownFunction(String),
+}
+
+impl fmt::Display for EngineError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self
+        {
+            EngineError::Compile(ref diagnostics) => {
+                let messages: Vec<&str> = diagnostics.iter()
+                    .filter(|d| d.is_error())
+                    .map(|d| d.message.as_str())
+                    .collect();
+                write!(f, "The script has errors: {}", messages.join(", "))
+            }
+            EngineError::Runtime(ref error) => write!(f, "{}", error.message),
+            EngineError::Conversion(ref message) => write!(f, "{}", message),
+            EngineError::UnknownFunction(ref name) => write!(f, "There is no function called {}", name),
+        }
+    }
+}
+
+impl error::Error for EngineError {}
+
+//Everything that stops a script from loading is a compile error for the host
+impl From<Error> for EngineError
+{
+    fn from(error: Error) -> EngineError
+    {
+        match error
+        {
+            Error::Runtime(error) => EngineError::Runtime(error),
+            other => EngineError::Compile(other.diagnostics()),
+        }
+    }
+}
+
+/*
+ * Runs scripts inside a Rust program. The host registers native functions, loads
+ * a script and then calls the functions of the script
+ *
+ *     let mut engine = Engine::new();
+ *     engine.register_function("tax", |amount: f64| amount * 0.25);
+ *     engine.load("def Number total(Number amount) { return amount + tax(amount); }")?;
+ *     let total: f64 = engine.call("total", (100.0,))?;
+ */
+pub struct Engine
+{
+    builtins: Builtins,
+    program: Program,
+    targets: CallTargets,
+    signatures: HashMap<String, FunctionSignature>,
+    limits: Limits,
+    output: Box<dyn Write>,
+}
+
+impl Engine
+{
+    pub fn new() -> Engine
+    {
+        Engine {
+            builtins: Builtins::new(),
+            program: Program{items: Vec::new()},
+            targets: CallTargets::default(),
+            signatures: HashMap::new(),
+            limits: Limits::default(),
+            output: Box::new(io::stdout()),
+        }
+    }
+
+    //Where print writes to. Standard output by default
+    pub fn set_output(&mut self, output: Box<dyn Write>)
+    {
+        self.output = output;
+    }
+
+    //The limits of every call into the script. Each call gets the full limits. Scripts
+    //run on the stack of the thread that calls them, which limits the call depth as well
+    pub fn set_limits(&mut self, limits: Limits)
+    {
+        self.limits = limits;
+    }
+
+    //The capabilities that scripts are given. None by default, so scripts can't
+    //use the file system, clock, environment or random numbers unless they are
+    //allowed to. Scripts are checked against the capabilities when they are loaded
+    pub fn set_capabilities(&mut self, capabilities: Capabilities)
+    {
+        self.builtins.set_capabilities(capabilities);
+    }
+
+    //Makes a Rust function callable from scripts. The parameter and return types
+    //become the signature that the type checker uses, so functions have to be
+    //registered before the script that uses them is loaded
+    pub fn register_function<Args, F: IntoNative<Args>>(&mut self, name: &str, function: F) -> &mut Engine
+    {
+        let native = BuiltinFunction::native(name, F::params(), F::return_type(), function.into_native());
+        self.builtins.add(native);
+        return self;
+    }
+
+    //Like register_function, but scripts can only use the function if they are
+    //given the capability
+    pub fn register_capability_function<Args, F: IntoNative<Args>>(&mut self, capability: Capability, name: &str,
+        function: F) -> &mut Engine
+    {
+        let mut native = BuiltinFunction::native(name, F::params(), F::return_type(), function.into_native());
+        native.capability = Some(capability);
+        self.builtins.add(native);
+        return self;
+    }
+
+    //Checks a script and makes its functions available to call. Replaces the
+    //script that was loaded before. Warnings don't stop the script from loading
+    pub fn load(&mut self, code: &str) -> Result<Vec<Diagnostic>, EngineError>
+    {
+        let program = parse(lex(code)?)?;
+        let checked = check(program, &self.builtins, &ValidationOptions::library())?;
+
+        self.signatures = HashMap::new();
+        for item in &checked.program.items
+        {
+            if let Item::Function(ref function) = *item
+            {
+                if let Some(signature) = checked.types.functions.get(&function.name)
+                {
+                    self.signatures.insert(function.name.clone(), signature.clone());
+                }
+            }
+        }
+        self.program = checked.program;
+        self.targets = checked.types.call_targets;
+        return Ok(checked.warnings);
+    }
+
+    //Calls a function of the loaded script. The arguments are a tuple of values
+    //that can be converted to the parameter types
+    pub fn call<R: FromValue, A: IntoArgs>(&mut self, name: &str, args: A) -> Result<R, EngineError>
+    {
+        let args = args.into_args().map_err(|e| EngineError::Conversion(format!("The arguments of {}: {}", name, e)))?;
+        let signature = match self.signatures.get(name)
+        {
+            Some(signature) => signature,
+            None => return Err(EngineError::UnknownFunction(String::from(name)))
+        };
+        check_arguments(name, &signature.params, &args)?;
+
+        let mut interpreter = Interpreter::new(&self.program, &self.builtins, &mut *self.output);
+        interpreter.set_limits(self.limits);
+        interpreter.set_call_targets(self.targets.clone());
+        let result = interpreter.call_function(name, args, Span::default()).map_err(EngineError::Runtime)?;
+        R::from_value(result).map_err(|e| EngineError::Conversion(format!("The result of {}: {}", name, e)))
+    }
+
+    //Runs the main function of the loaded script like the command line does
+    pub fn run_main(&mut self, args: Vec<String>) -> Result<Value, EngineError>
+    {
+        let mut interpreter = Interpreter::new(&self.program, &self.builtins, &mut *self.output);
+        interpreter.set_limits(self.limits);
+        interpreter.set_call_targets(self.targets.clone());
+        interpreter.run_main(args).map_err(EngineError::Runtime)
+    }
+}
+
+fn check_arguments(name: &str, params: &[Type], args: &[Value]) -> Result<(), EngineError>
+{
+    if params.len() != args.len()
+    {
+        return Err(EngineError::Conversion(format!("{} expects {} arguments but {} were given",
+            name, params.len(), args.len())));
+    }
+    for (i, (param, arg)) in params.iter().zip(args).enumerate()
+    {
+        //Values made by the host can have elements of different types or numbers
+        //that aren't finite, which only show when the whole value is looked at
+        if !arg.has_type(param)
+        {
+            let found = if param.accepts(&arg.type_of()) {arg.debug_string()} else {arg.type_of().to_string()};
+            return Err(EngineError::Conversion(format!("Argument {} of {}: Expected {} but found {}",
+                i + 1, name, param, found)));
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod engine_tests
+{
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    use capabilities::{Capabilities, Capability};
+    use engine::*;
+    use limits::Limit;
+    use number::Number;
+
+    //Output that the test can still read after giving it to the engine
+    #[derive(Clone)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput
+    {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+        {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()>
+        {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn calling_script_functions()
+    {
+        let mut engine = Engine::new();
+        engine.load(r#"
+            def Number add(Number a, Number b) { return a + b; }
+            def String greet(String name) { return "Hello " + name; }
+            def List<Number> doubled(List<Number> l) { return [l[0] * 2, l[1] * 2]; }
+            def Dictionary<String, Bool> flags() { return ["on": true]; }
+        "#).unwrap();
+
+        assert_eq!(engine.call::<i64, _>("add", (1, 2)), Ok(3));
+        assert_eq!(engine.call::<f64, _>("add", (1, 0.5)), Ok(1.5));
+        assert_eq!(engine.call::<String, _>("greet", ("Boa",)), Ok(String::from("Hello Boa")));
+        assert_eq!(engine.call::<Vec<i64>, _>("doubled", (vec!(1, 2),)), Ok(vec!(2, 4)));
+
+        let mut flags = HashMap::new();
+        flags.insert(String::from("on"), true);
+        assert_eq!(engine.call::<HashMap<String, bool>, _>("flags", ()), Ok(flags));
+    }
+
+    #[test]
+    fn call_errors()
+    {
+        let mut engine = Engine::new();
+        engine.load("def Number half(Number n) { return n / 2; }").unwrap();
+
+        assert_eq!(engine.call::<i64, _>("missing", ()), Err(EngineError::UnknownFunction(String::from("missing"))));
+        assert_eq!(engine.call::<i64, _>("half", ()),
+            Err(EngineError::Conversion(String::from("half expects 1 arguments but 0 were given"))));
+        assert_eq!(engine.call::<i64, _>("half", ("2",)),
+            Err(EngineError::Conversion(String::from("Argument 1 of half: Expected Number but found String"))));
+        assert_eq!(engine.call::<i64, _>("half", (3,)),
+            Err(EngineError::Conversion(String::from("The result of half: Expected a whole number but found 1.5"))));
+        assert_eq!(engine.call::<f64, _>("half", (3,)), Ok(1.5));
+        assert_eq!(engine.call::<f64, _>("half", (f64::NAN,)),
+            Err(EngineError::Conversion(String::from("The arguments of half: NaN is not a Number, only finite numbers can be passed to scripts"))));
+
+        engine.load("def Number first(List<Number> l) { return l[0] + 1; }").unwrap();
+        let mixed = Value::list(vec!(Value::integer(1), Value::String(String::from("x"))));
+        assert_eq!(engine.call::<f64, _>("first", vec!(mixed)),
+            Err(EngineError::Conversion(String::from("Argument 1 of first: Expected List<Number> but found [1, \"x\"]"))));
+        let infinite = Value::list(vec!(Value::Number(Number::Float(f64::INFINITY))));
+        assert!(engine.call::<f64, _>("first", vec!(infinite)).is_err());
+
+        engine.load("def Number spin() { while true {} return 0; }").unwrap();
+        engine.set_limits(Limits{fuel: Some(100), ..Limits::unlimited()});
+        match engine.call::<i64, _>("spin", ())
+        {
+            Err(EngineError::Runtime(error)) => assert_eq!(error.limit, Some(Limit::Fuel)),
+            other => panic!("Expected the script to run out of fuel, got {:?}", other)
+        }
+
+        match engine.load("def main() { let a = \"a\" + 1; }")
+        {
+            Err(EngineError::Compile(diagnostics)) =>
+                assert_eq!(diagnostics[0].message, "The operator + can not be applied to String and Number"),
+            other => panic!("Expected a compile error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn native_functions()
+    {
+        let output = SharedOutput(Rc::new(RefCell::new(Vec::new())));
+        let mut engine = Engine::new();
+        engine.set_output(Box::new(output.clone()));
+        engine
+            .register_function("tax", |amount: f64| amount * 0.25)
+            .register_function("shout", |s: String, times: i64| s.to_uppercase().repeat(times as usize))
+            .register_function("checked_sqrt", |n: f64| if n < 0.0 {Err("Negative number")} else {Ok(n.sqrt())})
+            .register_function("now", || 1234i64);
+
+        engine.load(r#"
+            def Number total(Number amount) { return amount + tax(amount); }
+            def main() { print(shout("hi", 2)); print(now()); print(checked_sqrt(16)); }
+            def Number broken() { return checked_sqrt(-1); }
+        "#).unwrap();
+
+        assert_eq!(engine.call::<f64, _>("total", (100,)), Ok(125.0));
+        engine.run_main(Vec::new()).unwrap();
+        assert_eq!(String::from_utf8(output.0.borrow().clone()).unwrap(), "HIHI\n1234\n4\n");
+
+        match engine.call::<f64, _>("broken", ())
+        {
+            Err(EngineError::Runtime(error)) => {
+                assert_eq!(error.message, "Negative number");
+                assert_eq!(error.trace.iter().map(|frame| frame.function.as_str()).collect::<Vec<_>>(), vec!("broken"));
+            }
+            other => panic!("Expected a runtime error, got {:?}", other)
+        }
+
+        //The type checker knows the signatures of native functions
+        match engine.load("def main() { tax(\"a\"); let String s = now(); }")
+        {
+            Err(EngineError::Compile(diagnostics)) => {
+                let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+                assert_eq!(messages, vec!("Expected Number but found String", "Expected String but found Number"));
+            }
+            other => panic!("Expected a compile error, got {:?}", other)
+        }
+
+        //Registering a name again replaces the function
+        engine.register_function("now", || 5678i64);
+        engine.load("def Number later() { return now(); }").unwrap();
+        assert_eq!(engine.call::<i64, _>("later", ()), Ok(5678));
+    }
+
+    #[test]
+    fn capabilities()
+    {
+        let mut engine = Engine::new();
+        engine.register_capability_function(Capability::Clock, "tick", || 7i64);
+        let code = "def String tenant() { return read_file(\"/etc/passwd\"); } def Number ticks() { return tick(); }";
+        match engine.load(code)
+        {
+            Err(EngineError::Compile(diagnostics)) => {
+                let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+                assert_eq!(messages, vec!(
+                    "read_file needs the filesystem capability, which this script was not given",
+                    "tick needs the clock capability, which this script was not given"));
+            }
+            other => panic!("Expected a compile error, got {:?}", other)
+        }
+
+        engine.set_capabilities(Capabilities::none().with(Capability::Clock));
+        assert!(engine.load(code).is_err());
+        engine.load("def Number ticks() { return tick(); }").unwrap();
+        assert_eq!(engine.call::<i64, _>("ticks", ()), Ok(7));
+    }
+}
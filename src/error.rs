@@ -0,0 +1,67 @@
+use std::error;
+use std::fmt;
+
+use diagnostic::Diagnostic;
+use interpreter::RuntimeError;
+use lexer::LexError;
+use parser::ParseError;
+
+/*
+ * Everything that can stop a script from being built or run. There is one variant
+ * for each step of the pipeline so that callers can tell them apart
+ */
+#[derive(Clone,PartialEq,Debug)]
+pub enum Error
+{
+    Lex(LexError),
+    Parse(ParseError),
+    //The resolver, type checker or validation found errors. Contains the
+    //warnings that were found as well
+    Check(Vec<Diagnostic>),
+    Runtime(RuntimeError),
+}
+
+impl Error
+{
+    //The error as diagnostics that can be shown next to the code
+    pub fn diagnostics(&self) -> Vec<Diagnostic>
+    {
+        match *self
+        {
+            Error::Lex(ref e) => vec!((**e).clone()),
+            Error::Parse(ref e) => vec!((**e).clone()),
+            Error::Check(ref diagnostics) => diagnostics.clone(),
+            Error::Runtime(ref e) => vec!(e.to_diagnostic()),
+        }
+    }
+}
+
+impl fmt::Display for Error
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self
+        {
+            Error::Lex(ref e) => write!(f, "{}", e.message),
+            Error::Parse(ref e) => write!(f, "{}", e.message),
+            Error::Check(ref diagnostics) => {
+                let messages: Vec<&str> = diagnostics.iter()
+                    .filter(|d| d.is_error())
+                    .map(|d| d.message.as_str())
+                    .collect();
+                write!(f, "{}", messages.join(", "))
+            }
+            Error::Runtime(ref e) => write!(f, "{}", e.message),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<RuntimeError> for Error
+{
+    fn from(error: RuntimeError) -> Error
+    {
+        Error::Runtime(error)
+    }
+}
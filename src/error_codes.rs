@@ -0,0 +1,125 @@
+/*
+ * The codes of every diagnostic. Codes never change meaning once they are
+ * released, so tools can match on them instead of on the messages. Errors start
+ * with E and warnings with W. The hundreds tell which pass reports them
+ */
+
+//Lexer
+pub const UNTERMINATED_STRING: &'static str = "E0001";
+pub const UNTERMINATED_COMMENT: &'static str = "E0002";
+pub const UNKNOWN_CHARACTER: &'static str = "E0003";
+
+//Parser
+pub const SYNTAX_ERROR: &'static str = "E0100";
+pub const TOO_DEEPLY_NESTED: &'static str = "E0101";
+
+//Resolver
+pub const NOT_DECLARED: &'static str = "E0200";
+pub const USED_BEFORE_DECLARATION: &'static str = "E0201";
+pub const ALREADY_DECLARED: &'static str = "E0202";
+pub const SHADOWED_DECLARATION: &'static str = "W0203";
+
+//Type checker
+pub const TYPE_MISMATCH: &'static str = "E0300";
+pub const UNKNOWN_TYPE: &'static str = "E0301";
+pub const DUPLICATE_MEMBER: &'static str = "E0302";
+pub const MISSING_RETURN: &'static str = "E0303";
+pub const CANNOT_INFER: &'static str = "E0304";
+pub const INVALID_RETURN: &'static str = "E0305";
+pub const NOT_ITERABLE: &'static str = "E0306";
+pub const INVALID_ASSIGNMENT: &'static str = "E0307";
+pub const NOT_INDEXABLE: &'static str = "E0308";
+pub const MISSING_CAPABILITY: &'static str = "E0309";
+pub const NOT_CALLABLE: &'static str = "E0310";
+pub const WRONG_ARGUMENT_COUNT: &'static str = "E0311";
+pub const NO_MEMBER: &'static str = "E0312";
+pub const INVALID_OPERANDS: &'static str = "E0313";
+pub const PRIVATE_MEMBER: &'static str = "E0314";
+pub const WRONG_TYPE_ARGUMENTS: &'static str = "E0315";
+pub const ZERO_STEP: &'static str = "E0316";
+pub const INVALID_OPERATOR: &'static str = "E0317";
+pub const AMBIGUOUS_OPERATOR: &'static str = "E0318";
+pub const UNINITIALISED_FIELD: &'static str = "E0319";
+pub const INVALID_METHOD_CALL: &'static str = "E0320";
+pub const INVALID_CONSTRUCTOR: &'static str = "E0321";
+pub const TYPE_AS_VALUE: &'static str = "E0322";
+pub const INVALID_KEY_TYPE: &'static str = "E0323";
+
+//Validation
+pub const TOP_LEVEL_CODE: &'static str = "E0400";
+pub const MISSING_MAIN: &'static str = "E0401";
+pub const INVALID_MAIN: &'static str = "E0402";
+
+//Runtime
+pub const RUNTIME_ERROR: &'static str = "E0500";
+pub const OUT_OF_FUEL: &'static str = "E0501";
+pub const CALL_DEPTH_EXCEEDED: &'static str = "E0502";
+pub const OUT_OF_MEMORY: &'static str = "E0503";
+pub const TIMED_OUT: &'static str = "E0504";
+
+//Every code with a short explanation
+pub const ALL_CODES: &'static [(&'static str, &'static str)] = &[
+    (UNTERMINATED_STRING, "A string literal is missing its closing quote"),
+    (UNTERMINATED_COMMENT, "A block comment is missing its closing */"),
+    (UNKNOWN_CHARACTER, "A character that is not part of the language"),
+    (SYNTAX_ERROR, "The code does not follow the grammar of the language"),
+    (TOO_DEEPLY_NESTED, "Code that is nested deeper than the parser allows"),
+    (NOT_DECLARED, "A name that is not declared"),
+    (USED_BEFORE_DECLARATION, "A variable that is used before it is declared"),
+    (ALREADY_DECLARED, "A name that is declared twice in the same scope"),
+    (SHADOWED_DECLARATION, "A declaration that hides a declaration of an outer scope"),
+    (TYPE_MISMATCH, "A value that does not have the expected type"),
+    (UNKNOWN_TYPE, "A type that is not declared"),
+    (DUPLICATE_MEMBER, "A type, field or method that is declared twice"),
+    (MISSING_RETURN, "A function that does not return a value on every path"),
+    (CANNOT_INFER, "A variable whose type can not be inferred"),
+    (INVALID_RETURN, "A return that does not fit the function it is in"),
+    (NOT_ITERABLE, "A foreach over a value that can not be iterated"),
+    (INVALID_ASSIGNMENT, "An assignment to something that can not be assigned to"),
+    (NOT_INDEXABLE, "Indexing into a value that has no elements"),
+    (MISSING_CAPABILITY, "A host function of a capability that the script was not given"),
+    (NOT_CALLABLE, "Calling a value that is not a function"),
+    (WRONG_ARGUMENT_COUNT, "A call with the wrong number of arguments"),
+    (NO_MEMBER, "A member that the type does not have"),
+    (INVALID_OPERANDS, "An operator applied to values it does not work on"),
+    (PRIVATE_MEMBER, "A private member used outside of its type"),
+    (WRONG_TYPE_ARGUMENTS, "A type with the wrong number of type arguments"),
+    (ZERO_STEP, "A for loop whose step is always 0"),
+    (INVALID_OPERATOR, "An operator function with the wrong parameters or return type"),
+    (AMBIGUOUS_OPERATOR, "An operator that more than one operator function can be used for"),
+    (UNINITIALISED_FIELD, "A constructor that doesn't give every field a value, or uses the instance before it has"),
+    (INVALID_METHOD_CALL, "A method called without an instance, or a static function or constructor called on one"),
+    (INVALID_CONSTRUCTOR, "A constructor that doesn't take self or that returns a value"),
+    (TYPE_AS_VALUE, "A type used as a value instead of being called or used for its static functions"),
+    (INVALID_KEY_TYPE, "A dictionary key of a type whose values can change"),
+    (TOP_LEVEL_CODE, "Statements outside of functions"),
+    (MISSING_MAIN, "A script without a main function"),
+    (INVALID_MAIN, "A main function with the wrong parameters or return type"),
+    (RUNTIME_ERROR, "An error while the script was running"),
+    (OUT_OF_FUEL, "The script took more steps than it was allowed to"),
+    (CALL_DEPTH_EXCEEDED, "The script made more nested calls than it was allowed to"),
+    (OUT_OF_MEMORY, "The script allocated more memory than it was allowed to"),
+    (TIMED_OUT, "The script ran for longer than it was allowed to"),
+];
+
+pub fn explanation(code: &str) -> Option<&'static str>
+{
+    ALL_CODES.iter().find(|&&(c, _)| c == code).map(|&(_, explanation)| explanation)
+}
+
+#[cfg(test)]
+mod error_codes_tests
+{
+    use std::collections::HashSet;
+
+    use error_codes::*;
+
+    #[test]
+    fn codes_are_unique()
+    {
+        let codes: HashSet<&str> = ALL_CODES.iter().map(|&(code, _)| code).collect();
+        assert_eq!(codes.len(), ALL_CODES.len());
+        assert!(ALL_CODES.iter().all(|&(code, _)| code.len() == 5 && (code.starts_with('E') || code.starts_with('W'))));
+        assert_eq!(explanation("E0300"), Some("A value that does not have the expected type"));
+    }
+}
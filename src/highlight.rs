@@ -0,0 +1,310 @@
+use std::cmp::Reverse;
+
+use regex;
+use serde_json::{self, json, Value};
+
+use analysis::{self, SemanticKind, BUILTIN_TYPES, SEMANTIC_KINDS};
+use builtins::Builtins;
+use lexer::{KEYWORDS, OPERATORS};
+use validation::ValidationOptions;
+
+/*
+ * Highlighters for other tools, generated from the tables of the lexer so that
+ * they know the same keywords and operators as the language itself
+ */
+
+//Keywords that declare something rather than control the flow of the code
+const DECLARATION_KEYWORDS: [&'static str; 8] = ["def", "let", "type", "implement", "public", "private", "hidden", "operator"];
+const CONSTANT_KEYWORDS: [&'static str; 2] = ["true", "false"];
+
+//Matches any of the words and nothing that only starts with one of them
+fn words_regex(words: &[&str]) -> String
+{
+    let escaped: Vec<String> = words.iter().map(|word| regex::escape(word)).collect();
+    format!(r"\b(?:{})\b", escaped.join("|"))
+}
+
+//The operators of the lexer with the longest first, so that == is not found as two =
+fn operators_longest_first() -> Vec<&'static str>
+{
+    let mut operators: Vec<&'static str> = OPERATORS.to_vec();
+    operators.sort_by_key(|op| Reverse(op.len()));
+    return operators;
+}
+
+fn control_keywords() -> Vec<&'static str>
+{
+    KEYWORDS.iter()
+        .filter(|keyword| !DECLARATION_KEYWORDS.contains(keyword) && !CONSTANT_KEYWORDS.contains(keyword))
+        .cloned()
+        .collect()
+}
+
+//A TextMate grammar for editors that don't use the language server
+pub fn textmate_grammar() -> Value
+{
+    let operators: Vec<String> = operators_longest_first().iter().map(|op| regex::escape(op)).collect();
+    json!({
+        "name": "Boa",
+        "scopeName": "source.boa",
+        "fileTypes": ["boa"],
+        "patterns": [
+            {"include": "#comments"},
+            {"include": "#strings"},
+            {"include": "#numbers"},
+            {"include": "#declarations"},
+            {"include": "#keywords"},
+            {"include": "#types"},
+            {"include": "#calls"},
+            {"include": "#operators"},
+        ],
+        "repository": {
+            "comments": {"patterns": [
+                {"name": "comment.line.number-sign.boa", "match": "#.*$"},
+                {"name": "comment.block.boa", "begin": r"/\*", "end": r"\*/"},
+            ]},
+            "strings": {"patterns": [
+                {"name": "string.quoted.double.boa", "begin": "\"", "end": "\"",
+                    "patterns": [{"name": "constant.character.escape.boa", "match": r"\\."}]},
+                {"name": "string.quoted.single.boa", "begin": "'", "end": "'",
+                    "patterns": [{"name": "constant.character.escape.boa", "match": r"\\."}]},
+            ]},
+            "numbers": {"patterns": [
+                {"name": "constant.numeric.hex.boa", "match": r"\b0x[0-9A-Fa-f]+\b"},
+                {"name": "constant.numeric.decimal.boa", "match": r"\b[0-9]+\.?[0-9]*\b"},
+            ]},
+            "declarations": {"patterns": [
+                {
+                    "match": r"\b(def)\s+(?:([a-zA-Z_]\w*(?:<[^>]*>)?)\s+)?([a-zA-Z_]\w*)\s*(?=\()",
+                    "captures": {
+                        "1": {"name": "keyword.declaration.boa"},
+                        "2": {"name": "entity.name.type.boa"},
+                        "3": {"name": "entity.name.function.boa"},
+                    }
+                },
+                {
+                    "match": r"\b(type|implement\s+for)\s+([a-zA-Z_]\w*)",
+                    "captures": {
+                        "1": {"name": "keyword.declaration.boa"},
+                        "2": {"name": "entity.name.type.boa"},
+                    }
+                },
+            ]},
+            "keywords": {"patterns": [
+                {"name": "constant.language.boa", "match": words_regex(&CONSTANT_KEYWORDS)},
+                {"name": "keyword.declaration.boa", "match": words_regex(&DECLARATION_KEYWORDS)},
+                {"name": "keyword.control.boa", "match": words_regex(&control_keywords())},
+            ]},
+            "types": {"patterns": [
+                {"name": "support.type.boa", "match": words_regex(&BUILTIN_TYPES)},
+            ]},
+            "calls": {"patterns": [
+                {"name": "entity.name.function.boa", "match": r"\b[a-zA-Z_]\w*(?=\s*\()"},
+            ]},
+            "operators": {"patterns": [
+                {"name": "keyword.operator.boa", "match": operators.join("|")},
+                {"name": "keyword.operator.assignment.boa", "match": "="},
+            ]},
+        }
+    })
+}
+
+//The colour of each kind of token in the HTML highlighters. Kinds without a
+//colour look like the text around them
+fn css_color(kind: SemanticKind) -> Option<&'static str>
+{
+    match kind
+    {
+        SemanticKind::Keyword => Some("#8959a8"),
+        SemanticKind::Comment => Some("#8e908c"),
+        SemanticKind::String => Some("#718c00"),
+        SemanticKind::Number => Some("#f5871f"),
+        SemanticKind::Operator => Some("#3e999f"),
+        SemanticKind::Type => Some("#c82829"),
+        SemanticKind::Function | SemanticKind::Method => Some("#4271ae"),
+        SemanticKind::Parameter | SemanticKind::Variable | SemanticKind::Property => None,
+    }
+}
+
+//The classes of the spans that the HTML highlighters make
+pub fn stylesheet() -> String
+{
+    let mut css = String::new();
+    for kind in SEMANTIC_KINDS.iter()
+    {
+        if let Some(color) = css_color(*kind)
+        {
+            css.push_str(&format!(".boa-{} {{ color: {}; }}\n", kind.name(), color));
+        }
+    }
+    return css;
+}
+
+const HIGHLIGHTER_SCRIPT: &'static str = r##"(function () {
+    var keywords = KEYWORDS;
+    var types = TYPES;
+    var operators = OPERATORS;
+
+    function escapeRegex(text) {
+        return text.replace(/[.*+?^${}()|[\]\\\/]/g, "\\$&");
+    }
+    function escapeHtml(text) {
+        return text.replace(/&/g, "&amp;").replace(/</g, "&lt;").replace(/>/g, "&gt;");
+    }
+    function span(kind, text) {
+        return "<span class=\"boa-" + kind + "\">" + escapeHtml(text) + "</span>";
+    }
+
+    //The same kinds of tokens as the lexer finds, in the same order
+    var token = new RegExp([
+        "(#.*|\\/\\*[\\s\\S]*?\\*\\/)",
+        "(\"(?:[^\"\\\\]|\\\\[\\s\\S])*\"|'(?:[^'\\\\]|\\\\[\\s\\S])*')",
+        "(0x[0-9A-Fa-f]+\\b|[0-9]+\\.?[0-9]*\\b)",
+        "([a-zA-Z_]\\w*)",
+        "(" + operators.map(escapeRegex).join("|") + "|=)"
+    ].join("|"), "g");
+
+    function highlight(code) {
+        var html = "";
+        var last = 0;
+        code.replace(token, function (match, comment, string, number, name, operator, offset) {
+            html += escapeHtml(code.slice(last, offset));
+            last = offset + match.length;
+            if (comment) {
+                html += span("comment", match);
+            } else if (string) {
+                html += span("string", match);
+            } else if (number) {
+                html += span("number", match);
+            } else if (operator) {
+                html += span("operator", match);
+            } else if (keywords.indexOf(name) >= 0) {
+                html += span("keyword", match);
+            } else if (types.indexOf(name) >= 0) {
+                html += span("type", match);
+            } else if (/^\s*\(/.test(code.slice(last))) {
+                html += span("function", match);
+            } else {
+                html += escapeHtml(match);
+            }
+            return match;
+        });
+        return html + escapeHtml(code.slice(last));
+    }
+
+    var blocks = document.querySelectorAll("code.language-boa");
+    for (var i = 0; i < blocks.length; i++) {
+        blocks[i].innerHTML = highlight(blocks[i].textContent);
+    }
+})();
+"##;
+
+//A style and a script for web pages that highlights the code in every
+//<code class="language-boa"> when the page is loaded
+pub fn html_highlighter() -> String
+{
+    let script = HIGHLIGHTER_SCRIPT
+        .replace("KEYWORDS", &serde_json::to_string(&KEYWORDS.to_vec()).unwrap())
+        .replace("TYPES", &serde_json::to_string(&BUILTIN_TYPES.to_vec()).unwrap())
+        .replace("OPERATORS", &serde_json::to_string(&operators_longest_first()).unwrap());
+    format!("<!-- Generated by boa grammar html, do not edit -->\n<style>\n{}</style>\n<script>\n{}</script>\n",
+        stylesheet(), script)
+}
+
+fn escape_html(text: &str) -> String
+{
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+//Highlights the code with the lexer and the resolver, which is more exact than
+//the highlighters for other tools. Code with errors is highlighted as far as it
+//can be lexed
+pub fn highlight_html(code: &str) -> String
+{
+    let analysis = analysis::analyse(code, &Builtins::new(), &ValidationOptions::library());
+    let mut html = String::from("<pre class=\"boa\"><code>");
+    let mut last = 0;
+    for token in analysis.semantic_tokens()
+    {
+        html.push_str(&escape_html(&code[last..token.span.start]));
+        html.push_str(&format!("<span class=\"boa-{}\">{}</span>", token.kind.name(),
+            escape_html(&code[token.span.start..token.span.end])));
+        last = token.span.end;
+    }
+    html.push_str(&escape_html(&code[last..]));
+    html.push_str("</code></pre>\n");
+    return html;
+}
+
+#[cfg(test)]
+mod highlight_tests
+{
+    use regex::Regex;
+
+    use highlight::*;
+    use lexer::Lexer;
+    use token::TokenType;
+
+    //The scope that the grammar gives to all of the text, or None if no single
+    //pattern of the repository matches all of it
+    fn scope_of(grammar: &Value, text: &str) -> Option<String>
+    {
+        for section in ["numbers", "keywords", "types", "operators"].iter()
+        {
+            for pattern in grammar["repository"][section]["patterns"].as_array().unwrap()
+            {
+                let regex = Regex::new(&format!(r"\A(?:{})", pattern["match"].as_str().unwrap())).unwrap();
+                if regex.find(text).map(|m| m.end()) == Some(text.len())
+                {
+                    return Some(String::from(pattern["name"].as_str().unwrap()));
+                }
+            }
+        }
+        return None;
+    }
+
+    #[test]
+    fn grammar_agrees_with_the_lexer()
+    {
+        let grammar = textmate_grammar();
+        let mut code = KEYWORDS.join(" ");
+        code.push(' ');
+        code.push_str(&OPERATORS.join(" "));
+        code.push_str(" = 12 3.5 0xfF Number");
+
+        for token in Lexer::new().tokenize(code).iter().filter(|token| !token.is_trivia())
+        {
+            let scope = scope_of(&grammar, token.lexeme()).unwrap_or_default();
+            let expected = match token.token_type()
+            {
+                TokenType::Keyword => "keyword.",
+                TokenType::Number | TokenType::HexNumber => "constant.numeric.",
+                TokenType::Operator | TokenType::Assignment => "keyword.operator",
+                TokenType::Identifier => "support.type",
+                other => panic!("Unexpected token {:?}", other),
+            };
+            if token.lexeme() == "true" || token.lexeme() == "false"
+            {
+                assert_eq!(scope, "constant.language.boa");
+                continue;
+            }
+            assert!(scope.starts_with(expected), "{} got {}", token.lexeme(), scope);
+        }
+    }
+
+    #[test]
+    fn html()
+    {
+        let highlighter = html_highlighter();
+        assert!(highlighter.contains(".boa-keyword { color: #8959a8; }"));
+        assert!(highlighter.contains("\"implement\""));
+        assert!(highlighter.contains("\"//\""));
+        assert!(!highlighter.contains("KEYWORDS"));
+
+        assert_eq!(highlight_html("def f(Number a) { return a < 1; }"),
+            "<pre class=\"boa\"><code><span class=\"boa-keyword\">def</span> <span class=\"boa-function\">f</span>(\
+             <span class=\"boa-type\">Number</span> <span class=\"boa-parameter\">a</span>) \
+             { <span class=\"boa-keyword\">return</span> <span class=\"boa-parameter\">a</span> \
+             <span class=\"boa-operator\">&lt;</span> <span class=\"boa-number\">1</span>; }</code></pre>\n");
+    }
+}
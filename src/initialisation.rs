@@ -0,0 +1,234 @@
+use ast::*;
+use diagnostic::Diagnostic;
+use error_codes;
+use token::Span;
+
+//Checks that a constructor gives every field of its type a value on every path
+//before it finishes, and that it doesn't read a field or use self before then
+pub fn check_constructor(type_name: &str, fields: &[String], constructor: &FunctionDecl) -> Vec<Diagnostic>
+{
+    let mut checker = Initialisation {
+        type_name: type_name,
+        fields: fields,
+        diagnostics: Vec::new(),
+    };
+    let mut assigned = Vec::new();
+    if !checker.statements(&constructor.body.statements, &mut assigned)
+    {
+        checker.finished(&assigned, constructor.name_span, "finishes");
+    }
+    return checker.diagnostics;
+}
+
+/*
+ * Follows which fields have been given a value through the statements of a
+ * constructor. Branches only count the fields that every path assigns, loops
+ * don't count at all since they might not run
+ */
+struct Initialisation<'a>
+{
+    type_name: &'a str,
+    fields: &'a [String],
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Initialisation<'a>
+{
+    //Returns true if the statements always return
+    fn statements(&mut self, statements: &[Statement], assigned: &mut Vec<String>) -> bool
+    {
+        for statement in statements
+        {
+            if self.statement(statement, assigned)
+            {
+                return true;
+            }
+        }
+        return false;
+    }
+
+    fn statement(&mut self, statement: &Statement, assigned: &mut Vec<String>) -> bool
+    {
+        match statement.kind
+        {
+            StatementKind::Let{ref value, ..} => {
+                if let Some(ref value) = *value
+                {
+                    self.expr(value, assigned);
+                }
+            }
+            StatementKind::Assign{ref target, op, ref value} => {
+                self.expr(value, assigned);
+                match self.field_of_self(target)
+                {
+                    Some(field) if op.is_none() => {
+                        if !assigned.contains(&field)
+                        {
+                            assigned.push(field);
+                        }
+                    }
+                    _ => self.expr(target, assigned)
+                }
+            }
+            StatementKind::Increment{ref target, ..} => self.expr(target, assigned),
+            StatementKind::Expr(ref expr) => self.expr(expr, assigned),
+            StatementKind::Return(ref value) => {
+                if let Some(ref value) = *value
+                {
+                    self.expr(value, assigned);
+                }
+                self.finished(assigned, statement.span, "returns");
+                return true;
+            }
+            StatementKind::If{ref branches, ref else_block} => {
+                //The fields that are assigned after the if are the ones that every
+                //branch which doesn't return assigns
+                let mut after: Option<Vec<String>> = None;
+                for &(ref condition, ref block) in branches
+                {
+                    self.expr(condition, assigned);
+                    let mut branch = assigned.clone();
+                    if !self.statements(&block.statements, &mut branch)
+                    {
+                        after = Some(intersection(after, branch));
+                    }
+                }
+                match *else_block
+                {
+                    Some(ref block) => {
+                        let mut branch = assigned.clone();
+                        if !self.statements(&block.statements, &mut branch)
+                        {
+                            after = Some(intersection(after, branch));
+                        }
+                    }
+                    None => after = Some(intersection(after, assigned.clone()))
+                }
+                match after
+                {
+                    Some(after) => *assigned = after,
+                    None => return true
+                }
+            }
+            StatementKind::While{ref condition, ref body} => {
+                self.expr(condition, assigned);
+                self.statements(&body.statements, &mut assigned.clone());
+            }
+            StatementKind::For{ref start, ref end, ref step, ref body, ..} => {
+                self.expr(start, assigned);
+                self.expr(end, assigned);
+                if let Some(ref step) = *step
+                {
+                    self.expr(step, assigned);
+                }
+                self.statements(&body.statements, &mut assigned.clone());
+            }
+            StatementKind::Foreach{ref container, ref body, ..} => {
+                self.expr(container, assigned);
+                self.statements(&body.statements, &mut assigned.clone());
+            }
+            StatementKind::Block(ref block) => return self.statements(&block.statements, assigned),
+        }
+        return false;
+    }
+
+    //Reports the uses of self and its fields in an expression that come too early
+    fn expr(&mut self, expr: &Expr, assigned: &[String])
+    {
+        if let Some(field) = self.field_of_self(expr)
+        {
+            if !assigned.contains(&field)
+            {
+                self.diagnostics.push(Diagnostic::error(error_codes::UNINITIALISED_FIELD,
+                    format!("self.{} is used before it has a value", field), expr.span));
+            }
+            return;
+        }
+        match expr.kind
+        {
+            ExprKind::Number(_) | ExprKind::String(_) | ExprKind::Bool(_) => {}
+            ExprKind::Identifier(ref name) => {
+                let missing = self.missing(assigned);
+                if name == "self" && !missing.is_empty()
+                {
+                    self.diagnostics.push(Diagnostic::error(error_codes::UNINITIALISED_FIELD,
+                            String::from("self is used before all of its fields have a value"), expr.span)
+                        .with_note(format!("{} still {} a value", missing.join(", "), if missing.len() == 1 {"needs"} else {"need"})));
+                }
+            }
+            ExprKind::List(ref elements) => {
+                for element in elements
+                {
+                    self.expr(element, assigned);
+                }
+            }
+            ExprKind::Dictionary(ref entries) => {
+                for &(ref key, ref value) in entries
+                {
+                    self.expr(key, assigned);
+                    self.expr(value, assigned);
+                }
+            }
+            ExprKind::Unary{ref expr, ..} => self.expr(expr, assigned),
+            ExprKind::Binary{ref lhs, ref rhs, ..} => {
+                self.expr(lhs, assigned);
+                self.expr(rhs, assigned);
+            }
+            ExprKind::Call{ref callee, ref args} => {
+                self.expr(callee, assigned);
+                for arg in args
+                {
+                    self.expr(arg, assigned);
+                }
+            }
+            ExprKind::Member{ref object, ..} => self.expr(object, assigned),
+            ExprKind::Index{ref object, ref index} => {
+                self.expr(object, assigned);
+                self.expr(index, assigned);
+            }
+        }
+    }
+
+    //Reports the fields that don't have a value when the constructor finishes or returns
+    fn finished(&mut self, assigned: &[String], span: Span, how: &str)
+    {
+        let missing = self.missing(assigned);
+        if !missing.is_empty()
+        {
+            let plural = if missing.len() == 1 {""} else {"s"};
+            self.diagnostics.push(Diagnostic::error(error_codes::UNINITIALISED_FIELD,
+                    format!("The constructor of {} {} without giving a value to the field{} {}", self.type_name, how, plural, missing.join(", ")),
+                    span)
+                .with_note(String::from("Every field has to be assigned with self.field = value on every path")));
+        }
+    }
+
+    fn missing(&self, assigned: &[String]) -> Vec<String>
+    {
+        self.fields.iter().filter(|field| !assigned.contains(field)).cloned().collect()
+    }
+
+    //The field in self.field
+    fn field_of_self(&self, expr: &Expr) -> Option<String>
+    {
+        match expr.kind
+        {
+            ExprKind::Member{ref object, ref name, ..} => match object.kind
+            {
+                ExprKind::Identifier(ref object) if object == "self" && self.fields.contains(name) => Some(name.clone()),
+                _ => None
+            },
+            _ => None
+        }
+    }
+}
+
+//The fields that are in both, where None is every field
+fn intersection(current: Option<Vec<String>>, other: Vec<String>) -> Vec<String>
+{
+    match current
+    {
+        Some(current) => current.into_iter().filter(|field| other.contains(field)).collect(),
+        None => other
+    }
+}
@@ -0,0 +1,1279 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::mem;
+
+use ast::*;
+use builtins::Builtins;
+use diagnostic::Diagnostic;
+use error_codes;
+use limits::{allocation_size, Budget, Limit, Limits};
+use number::{Number, NumberResult};
+use script_state::{ScopeKind, ScriptState};
+use stdlib;
+use token::Span;
+use type_checker::{Accessor, CallTargets, ConstructorCall, MethodCall};
+use types::Type;
+use variable::{Dictionary, Value};
+
+/*
+ * A function call that was running when a runtime error happened
+ */
+#[derive(Clone,PartialEq,Debug)]
+pub struct TraceFrame
+{
+    pub function: String,
+    //Where the function was called from. Empty for the call to main
+    pub call_site: Span,
+}
+
+/*
+ * Error that stops the execution of a script
+ */
+#[derive(Clone,PartialEq,Debug)]
+pub struct RuntimeError
+{
+    pub message: String,
+    pub span: Span,
+    //Set when the script was stopped because it went over one of its limits
+    pub limit: Option<Limit>,
+    //The calls that were running, the one where the error happened first
+    pub trace: Vec<TraceFrame>,
+}
+
+impl RuntimeError
+{
+    pub fn new(message: String, span: Span) -> RuntimeError
+    {
+        RuntimeError {
+            message: message,
+            span: span,
+            limit: None,
+            trace: Vec::new()
+        }
+    }
+
+    pub fn limit_exceeded(limit: Limit, message: String, span: Span) -> RuntimeError
+    {
+        RuntimeError {
+            message: message,
+            span: span,
+            limit: Some(limit),
+            trace: Vec::new()
+        }
+    }
+
+    //The error as a diagnostic. The calls that led to the error are labels,
+    //with the function they happened in next to the error itself
+    pub fn to_diagnostic(&self) -> Diagnostic
+    {
+        let code = match self.limit
+        {
+            None => error_codes::RUNTIME_ERROR,
+            Some(Limit::Fuel) => error_codes::OUT_OF_FUEL,
+            Some(Limit::CallDepth) => error_codes::CALL_DEPTH_EXCEEDED,
+            Some(Limit::Memory) => error_codes::OUT_OF_MEMORY,
+            Some(Limit::Time) => error_codes::TIMED_OUT,
+        };
+        let mut diagnostic = Diagnostic::error(code, self.message.clone(), self.span);
+        if let Some(frame) = self.trace.first()
+        {
+            diagnostic = diagnostic.with_label(self.span, format!("in {}", frame.function));
+        }
+
+        let calls: Vec<_> = self.trace.iter().zip(self.trace.iter().skip(1)).collect();
+        let mut i = 0;
+        while i < calls.len()
+        {
+            let (frame, caller) = calls[i];
+            diagnostic = diagnostic.with_label(frame.call_site,
+                format!("{} is called from {}", frame.function, caller.function));
+
+            //Recursion repeats the same call many times, it is only shown once
+            let repeats = calls[i + 1..].iter().take_while(|&&(next, _)| next == frame).count();
+            if repeats > 0
+            {
+                diagnostic = diagnostic.with_note(format!("the call of {} from {} is repeated {} more times",
+                    frame.function, caller.function, repeats));
+            }
+            i += repeats + 1;
+        }
+        return diagnostic;
+    }
+
+    //Shows the error with the code around it and the calls that led to it
+    pub fn render(&self, filename: &str, code: &str) -> String
+    {
+        self.to_diagnostic().render(filename, code)
+    }
+}
+
+type RunResult<T> = Result<T, RuntimeError>;
+
+//The name of the code outside of functions in stack traces
+pub const TOP_LEVEL: &'static str = "<top level>";
+
+//What happens after a statement has been executed
+enum Flow
+{
+    Normal,
+    Return(Value),
+}
+
+/*
+ * Runs a program by walking its parse tree. The program is expected to have
+ * passed the resolver and type checker
+ */
+pub struct Interpreter<'a>
+{
+    //Functions of implement blocks are stored as Type.name
+    functions: HashMap<String, &'a FunctionDecl>,
+    builtins: &'a Builtins,
+    //The containers that running foreach loops go through
+    iterating: Vec<Value>,
+    //What the type checker found operators on user types and accessors to call
+    targets: CallTargets,
+    state: ScriptState,
+    budget: Budget,
+    output: &'a mut dyn Write,
+}
+
+impl<'a> Interpreter<'a>
+{
+    pub fn new(program: &'a Program, builtins: &'a Builtins, output: &'a mut dyn Write) -> Interpreter<'a>
+    {
+        let mut functions = HashMap::new();
+        for item in &program.items
+        {
+            match *item
+            {
+                Item::Function(ref function) => {
+                    functions.insert(function.name.clone(), function);
+                }
+                Item::Implement(ref implement) => {
+                    for function in &implement.functions
+                    {
+                        functions.insert(implement.function_name(function), function);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Interpreter {
+            functions: functions,
+            builtins: builtins,
+            iterating: Vec::new(),
+            targets: CallTargets::default(),
+            state: ScriptState::new(),
+            budget: Budget::new(Limits::default()),
+            output: output,
+        }
+    }
+
+    //Limits what the script can do. The time limit starts counting when this is called
+    pub fn set_limits(&mut self, limits: Limits)
+    {
+        self.budget = Budget::new(limits);
+    }
+
+    //Makes operators on user types call their operator functions and accessors read
+    //and write their fields. Without this user types can only use == and !=
+    pub fn set_call_targets(&mut self, targets: CallTargets)
+    {
+        self.targets = targets;
+    }
+
+    //Runs the main function of the script. args are passed on to main if it
+    //takes the command line arguments
+    pub fn run_main(&mut self, args: Vec<String>) -> RunResult<Value>
+    {
+        let args = main_args(self.functions.get("main").map(|main| main.params.len()), args)?;
+        self.call_function("main", args, Span::default())
+    }
+
+    //Runs code outside of functions, the way the REPL does. The variables that
+    //the code declares are kept in the state for code that runs later. Returns
+    //the value of the last statement if it is an expression
+    pub fn run_top_level(&mut self, state: &mut ScriptState, statements: &[&Statement]) -> RunResult<Value>
+    {
+        mem::swap(&mut self.state, state);
+        if self.state.frames().is_empty()
+        {
+            self.state.push_frame(String::from(TOP_LEVEL), Span::default());
+        }
+
+        let mut result = Ok(Value::Unit);
+        for (i, statement) in statements.iter().enumerate()
+        {
+            result = match statement.kind
+            {
+                StatementKind::Expr(ref expr) if i == statements.len() - 1 => self.evaluate(expr),
+                _ => self.execute(statement).map(|_| Value::Unit)
+            };
+            if result.is_err()
+            {
+                break;
+            }
+        }
+        let result = result.map_err(|error| self.add_trace(error));
+
+        mem::swap(&mut self.state, state);
+        return result;
+    }
+
+    pub fn call_function(&mut self, name: &str, args: Vec<Value>, call_site: Span) -> RunResult<Value>
+    {
+        let function = match self.functions.get(name)
+        {
+            Some(function) => *function,
+            None => return self.call_builtin(name, args, call_site)
+        };
+        //The receiver comes before the parameters
+        let receiver = if function.receiver.is_some() {1} else {0};
+        if function.params.len() + receiver != args.len()
+        {
+            return Err(RuntimeError::new(format!("{} expects {} arguments but {} were given",
+                name, function.params.len() + receiver, args.len()), call_site));
+        }
+        self.budget.enter_call(self.state.frames().len(), call_site)?;
+
+        self.state.push_frame(String::from(name), call_site);
+        let mut args = args.into_iter();
+        if receiver == 1
+        {
+            let object = args.next().unwrap();
+            self.state.declare(String::from("self"), object.type_of(), object);
+        }
+        for (param, arg) in function.params.iter().zip(args)
+        {
+            self.state.declare(param.name.clone(), Type::from_type_expr(&param.type_expr), arg);
+        }
+        let result = self.execute_statements(&function.body.statements)
+            .map_err(|error| self.add_trace(error));
+        self.state.pop_frame();
+
+        match result?
+        {
+            Flow::Return(value) => Ok(value),
+            Flow::Normal => Ok(Value::Unit)
+        }
+    }
+
+    //Records the calls that are running in an error that doesn't know them yet. This
+    //happens in the innermost call, which is the only one that sees every call
+    fn add_trace(&self, mut error: RuntimeError) -> RuntimeError
+    {
+        if error.trace.is_empty()
+        {
+            error.trace = self.state.frames().iter().rev()
+                .map(|frame| TraceFrame{function: frame.function.clone(), call_site: frame.call_site})
+                .collect();
+        }
+        return error;
+    }
+
+    //A reference to the script or builtin function with the given name
+    fn function_value(&self, name: &str) -> Option<Value>
+    {
+        match self.functions.get(name)
+        {
+            Some(function) => Some(function_value(function)),
+            None => self.builtins.get(name).map(|builtin| builtin.value())
+        }
+    }
+
+    fn call_builtin(&mut self, name: &str, args: Vec<Value>, call_site: Span) -> RunResult<Value>
+    {
+        let call = BuiltinCall::start(name, &args, &self.iterating, &mut self.budget, call_site)?;
+        let result = if stdlib::takes_functions(name)
+        {
+            self.call_with_functions(name, args, call_site)?
+        }
+        else
+        {
+            self.builtins.call(name, args, self.output).map_err(|e| RuntimeError::new(e, call_site))?
+        };
+        call.finish(&result, &mut self.budget)?;
+        return Ok(result);
+    }
+
+    //Builtins like map that call functions of the script go through the list like
+    //foreach, so the functions can't change it
+    fn call_with_functions(&mut self, name: &str, args: Vec<Value>, call_site: Span) -> RunResult<Value>
+    {
+        self.iterating.push(args[0].clone());
+        let result = stdlib::call_with_functions(name, args, &mut |function, args| match *function
+            {
+                Value::Function(ref function) => self.call_function(&function.name, args, call_site),
+                ref other => Err(RuntimeError::new(format!("A value of type {} can not be called", other.type_name()), call_site))
+            }, call_site);
+        self.iterating.pop();
+        return result;
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    //                      Statements
+    ////////////////////////////////////////////////////////////////////////////
+    fn execute_block(&mut self, block: &Block, kind: ScopeKind) -> RunResult<Flow>
+    {
+        self.state.push_scope(kind);
+        let result = self.execute_statements(&block.statements);
+        self.state.pop_scope();
+        return result;
+    }
+
+    fn execute_statements(&mut self, statements: &[Statement]) -> RunResult<Flow>
+    {
+        for statement in statements
+        {
+            if let Flow::Return(value) = self.execute(statement)?
+            {
+                return Ok(Flow::Return(value));
+            }
+        }
+        return Ok(Flow::Normal);
+    }
+
+    fn execute(&mut self, statement: &Statement) -> RunResult<Flow>
+    {
+        self.budget.step(statement.span)?;
+        match statement.kind
+        {
+            StatementKind::Let{ref type_expr, ref name, ref value, ..} => {
+                let value = match *value
+                {
+                    Some(ref value) => self.evaluate(value)?,
+                    None => Value::Unit
+                };
+                let declared_type = declared_type(type_expr.as_ref(), &value);
+                self.state.declare(name.clone(), declared_type, value);
+            }
+            StatementKind::Assign{ref target, op, ref value} => self.execute_assign(target, op, value, statement.span)?,
+            StatementKind::Increment{ref target, op} => self.execute_increment(target, op, statement.span)?,
+            StatementKind::Expr(ref expr) => {
+                self.evaluate(expr)?;
+            }
+            StatementKind::Return(ref value) => {
+                let value = match *value
+                {
+                    Some(ref value) => self.evaluate(value)?,
+                    None => Value::Unit
+                };
+                return Ok(Flow::Return(value));
+            }
+            StatementKind::If{ref branches, ref else_block} => {
+                for &(ref condition, ref block) in branches
+                {
+                    if self.condition(condition)?
+                    {
+                        return self.execute_block(block, ScopeKind::Block);
+                    }
+                }
+                if let Some(ref block) = *else_block
+                {
+                    return self.execute_block(block, ScopeKind::Block);
+                }
+            }
+            StatementKind::While{ref condition, ref body} => return self.execute_while(condition, body, statement.span),
+            StatementKind::For{ref var, ref start, ref end, ref step, ref body, ..} => {
+                return self.execute_for(var, start, end, step.as_ref(), body);
+            }
+            StatementKind::Foreach{ref type_expr, ref var, ref value, ref container, ref body, ..} => {
+                return self.execute_foreach(type_expr.as_ref(), var, value.as_ref(), container, body, statement.span);
+            }
+            StatementKind::Block(ref block) => return self.execute_block(block, ScopeKind::Block),
+        }
+        return Ok(Flow::Normal);
+    }
+
+    //The statements have their own functions to keep the stack frame of execute
+    //small, since it is part of every recursive call in a script
+    fn execute_assign(&mut self, target: &Expr, op: Option<BinaryOp>, value: &Expr, span: Span) -> RunResult<()>
+    {
+        let place = self.place(target)?;
+        let value = self.evaluate(value)?;
+        let value = match op
+        {
+            Some(op) => {
+                let current = self.load(&place, target)?;
+                match self.targets.operators.get(&target.id).cloned()
+                {
+                    Some(function) => self.call_operator(&function, op, current, value, span)?,
+                    None => self.binary(op, &current, &value, span)?
+                }
+            }
+            None => value
+        };
+        self.store(place, value, target)
+    }
+
+    fn execute_increment(&mut self, target: &Expr, op: BinaryOp, span: Span) -> RunResult<()>
+    {
+        let place = self.place(target)?;
+        let current = self.load(&place, target)?;
+        let value = self.binary(op, &current, &Value::integer(1), span)?;
+        self.store(place, value, target)
+    }
+
+    fn execute_while(&mut self, condition: &Expr, body: &Block, span: Span) -> RunResult<Flow>
+    {
+        while self.condition(condition)?
+        {
+            self.budget.step(span)?;
+            if let Flow::Return(value) = self.execute_block(body, ScopeKind::Loop)?
+            {
+                return Ok(Flow::Return(value));
+            }
+        }
+        return Ok(Flow::Normal);
+    }
+
+    fn execute_foreach(&mut self, type_expr: Option<&TypeExpr>, var: &str, value: Option<&LoopValue>, container: &Expr,
+        body: &Block, span: Span) -> RunResult<Flow>
+    {
+        let container_value = self.evaluate(container)?;
+        let container_span = container.span;
+        let container_value = iterated_value(container_value,
+            &mut |iterate, object| self.call_function(iterate, vec!(object), container_span))?;
+        let entries: Vec<(Value, Option<Value>)> = match value
+        {
+            Some(_) => container_value.iteration_entries()
+                .map(|entries| entries.into_iter().map(|(key, value)| (key, Some(value))).collect()),
+            None => container_value.iteration_values()
+                .map(|values| values.into_iter().map(|value| (value, None)).collect())
+        }.map_err(|e| RuntimeError::new(e, container.span))?;
+
+        //The container can't be changed until the loop is done, even if it
+        //ends with an error or a return
+        self.iterating.push(container_value);
+        let result = self.execute_foreach_body(type_expr, var, value, entries, body, span);
+        self.iterating.pop();
+        return result;
+    }
+
+    fn execute_foreach_body(&mut self, type_expr: Option<&TypeExpr>, var: &str, value: Option<&LoopValue>,
+        entries: Vec<(Value, Option<Value>)>, body: &Block, span: Span) -> RunResult<Flow>
+    {
+        for (key, entry_value) in entries
+        {
+            self.budget.step(span)?;
+            self.state.push_scope(ScopeKind::Loop);
+            let key_type = declared_type(type_expr, &key);
+            self.state.declare(String::from(var), key_type, key);
+            if let (Some(value), Some(entry_value)) = (value, entry_value)
+            {
+                let value_type = declared_type(value.type_expr.as_ref(), &entry_value);
+                self.state.declare(value.name.clone(), value_type, entry_value);
+            }
+            let result = self.execute_statements(&body.statements);
+            self.state.pop_scope();
+            if let Flow::Return(value) = result?
+            {
+                return Ok(Flow::Return(value));
+            }
+        }
+        return Ok(Flow::Normal);
+    }
+
+    //for var is start to end step amount. The end is included and the step
+    //defaults to 1. Negative steps count down. The variable is start + n * step
+    //in the nth pass, so fractional steps don't add up rounding errors
+    fn execute_for(&mut self, var: &str, start: &Expr, end: &Expr, step: Option<&Expr>, body: &Block) -> RunResult<Flow>
+    {
+        let start_value = self.number(start)?;
+        let end_value = self.number(end)?;
+        let step_value = match step
+        {
+            Some(step) => {
+                let value = self.number(step)?;
+                if value.is_zero()
+                {
+                    return Err(RuntimeError::new(String::from("The step of a for loop can not be 0"), step.span));
+                }
+                value
+            }
+            None => Number::Integer(1)
+        };
+
+        let mut pass = 0;
+        loop
+        {
+            let current = for_value(start_value, step_value, pass).map_err(|e| RuntimeError::new(e, start.span))?;
+            if !for_running(current, end_value, step_value)
+            {
+                return Ok(Flow::Normal);
+            }
+            self.budget.step(body.span)?;
+            self.state.push_scope(ScopeKind::Loop);
+            self.state.declare(String::from(var), Type::Number, Value::Number(current));
+            let result = self.execute_statements(&body.statements);
+            self.state.pop_scope();
+            if let Flow::Return(value) = result?
+            {
+                return Ok(Flow::Return(value));
+            }
+            pass += 1;
+        }
+    }
+
+    //Evaluates the parts of an assignment target. This happens once, before the
+    //assigned value, even if the target is both read and written like in l[i] += 1
+    fn place(&mut self, target: &Expr) -> RunResult<Place>
+    {
+        match target.kind
+        {
+            ExprKind::Identifier(ref name) => Ok(Place::Variable(name.clone())),
+            ExprKind::Index{ref object, ref index} => {
+                let container = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                Ok(Place::Element(container, index))
+            }
+            ExprKind::Member{ref object, ref name, ..} => {
+                let object = self.evaluate(object)?;
+                Ok(Place::Field(object, name.clone()))
+            }
+            _ => Err(RuntimeError::new(String::from("Only variables, members and elements can be assigned to"), target.span))
+        }
+    }
+
+    fn load(&self, place: &Place, target: &Expr) -> RunResult<Value>
+    {
+        let result = match *place
+        {
+            Place::Variable(ref name) => match self.state.lookup(name)
+            {
+                Some(variable) => Ok(variable.value.clone()),
+                None => Err(format!("{} is not declared", name))
+            },
+            Place::Element(ref container, ref index) => container.index(index),
+            Place::Field(ref object, ref name) => object.field(name),
+        };
+        result.map_err(|e| RuntimeError::new(e, place_span(target)))
+    }
+
+    //Lists, dictionaries and objects are shared, so storing an element or field
+    //changes the container that the target expression evaluated to
+    fn store(&mut self, place: Place, value: Value, target: &Expr) -> RunResult<()>
+    {
+        let result = match place
+        {
+            Place::Variable(name) => match self.state.lookup_mut(&name)
+            {
+                Some(variable) => {
+                    variable.value = value;
+                    Ok(())
+                }
+                None => Err(format!("{} is not declared", name))
+            },
+            Place::Element(container, index) => {
+                if self.iterating.iter().any(|iterated| iterated.is_same(&container))
+                {
+                    return Err(RuntimeError::new(container.changed_while_iterating(), place_span(target)));
+                }
+                //Dictionaries grow when a new key is stored
+                let old_size = allocation_size(&container);
+                let result = container.set_index(&index, value);
+                if result.is_ok()
+                {
+                    self.budget.grow(&container, old_size, target.span)?;
+                }
+                result
+            }
+            Place::Field(object, name) => object.set_field(&name, value),
+        };
+        result.map_err(|e| RuntimeError::new(e, place_span(target)))
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    //                      Expressions
+    ////////////////////////////////////////////////////////////////////////////
+    pub fn evaluate(&mut self, expr: &Expr) -> RunResult<Value>
+    {
+        match expr.kind
+        {
+            ExprKind::Number(ref lexeme) => Number::parse(lexeme)
+                .map(Value::Number)
+                .ok_or_else(|| RuntimeError::new(format!("{} is not a valid number", lexeme), expr.span)),
+            ExprKind::String(ref s) => self.allocate(Value::String(s.clone()), expr.span),
+            ExprKind::Bool(b) => Ok(Value::Bool(b)),
+            ExprKind::Identifier(ref name) => {
+                if let Some(variable) = self.state.lookup(name)
+                {
+                    return Ok(variable.value.clone());
+                }
+                self.function_value(name)
+                    .ok_or_else(|| RuntimeError::new(format!("{} is not declared", name), expr.span))
+            }
+            ExprKind::List(ref elements) => self.evaluate_list(elements, expr.span),
+            ExprKind::Dictionary(ref entries) => self.evaluate_dictionary(entries, expr.span),
+            ExprKind::Unary{op, expr: ref operand} => self.evaluate_unary(op, operand, expr.id, expr.span),
+            ExprKind::Binary{op: BinaryOp::And, ref lhs, ref rhs} => {
+                let result = self.condition(lhs)? && self.condition(rhs)?;
+                Ok(Value::Bool(result))
+            }
+            ExprKind::Binary{op: BinaryOp::Or, ref lhs, ref rhs} => {
+                let result = self.condition(lhs)? || self.condition(rhs)?;
+                Ok(Value::Bool(result))
+            }
+            ExprKind::Binary{op, ref lhs, ref rhs} => self.evaluate_binary(op, lhs, rhs, expr.id, expr.span),
+            ExprKind::Call{ref callee, ref args} => self.call(callee, args, expr.span),
+            ExprKind::Member{ref object, ref name, name_span} => {
+                let value = self.evaluate(object)?;
+                value.field(name).map_err(|e| RuntimeError::new(e, name_span))
+            }
+            ExprKind::Index{ref object, ref index} => self.evaluate_index(object, index, expr.span),
+        }
+    }
+
+    //Like the statements, the bigger expressions have their own functions to keep
+    //the stack frame of evaluate small
+    fn evaluate_list(&mut self, elements: &[Expr], span: Span) -> RunResult<Value>
+    {
+        let mut values = Vec::new();
+        for element in elements
+        {
+            values.push(self.evaluate(element)?);
+        }
+        self.allocate(Value::list(values), span)
+    }
+
+    fn evaluate_dictionary(&mut self, entries: &[(Expr, Expr)], span: Span) -> RunResult<Value>
+    {
+        let mut dictionary = Dictionary::new();
+        for &(ref key, ref value) in entries
+        {
+            let key = self.evaluate(key)?;
+            let value = self.evaluate(value)?;
+            dictionary.insert(key, value);
+        }
+        self.allocate(Value::dictionary(dictionary), span)
+    }
+
+    fn evaluate_unary(&mut self, op: UnaryOp, operand: &Expr, id: NodeId, span: Span) -> RunResult<Value>
+    {
+        let value = self.condition_value(operand)?;
+        match self.targets.operators.get(&id).cloned()
+        {
+            Some(function) => self.call_function(&function, vec![value], span),
+            None => value.unary(op).map_err(|e| RuntimeError::new(e, span))
+        }
+    }
+
+    fn evaluate_binary(&mut self, op: BinaryOp, lhs: &Expr, rhs: &Expr, id: NodeId, span: Span) -> RunResult<Value>
+    {
+        let lhs = self.evaluate(lhs)?;
+        let rhs = self.evaluate(rhs)?;
+        if let Some(function) = self.targets.operators.get(&id).cloned()
+        {
+            return self.call_operator(&function, op, lhs, rhs, span);
+        }
+        let result = self.binary(op, &lhs, &rhs, span)?;
+        self.allocate(result, span)
+    }
+
+    //Calls the operator function of a binary operator. != uses the function of ==
+    fn call_operator(&mut self, function: &str, op: BinaryOp, lhs: Value, rhs: Value, span: Span) -> RunResult<Value>
+    {
+        let result = self.call_function(function, vec![lhs, rhs], span)?;
+        if op == BinaryOp::NotEqual
+        {
+            return result.unary(UnaryOp::Not).map_err(|e| RuntimeError::new(e, span));
+        }
+        return Ok(result);
+    }
+
+    fn evaluate_index(&mut self, object: &Expr, index: &Expr, span: Span) -> RunResult<Value>
+    {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        let element = object.index(&index).map_err(|e| RuntimeError::new(e, span))?;
+        //Indexing a String creates a new String, elements of containers already exist
+        if let Value::String(_) = object
+        {
+            return self.allocate(element, span);
+        }
+        return Ok(element);
+    }
+
+    fn call(&mut self, callee: &Expr, args: &[Expr], span: Span) -> RunResult<Value>
+    {
+        if let Some(accessor) = self.targets.accessors.get(&callee.id).cloned()
+        {
+            return self.call_accessor(accessor, callee, args, span);
+        }
+        if let Some(method) = self.targets.methods.get(&callee.id).cloned()
+        {
+            return self.call_method(method, callee, args, span);
+        }
+        if let Some(constructor) = self.targets.constructors.get(&callee.id).cloned()
+        {
+            return self.construct(constructor, args, span);
+        }
+        let function = match self.evaluate(callee)?
+        {
+            Value::Function(function) => function,
+            other => return Err(RuntimeError::new(
+                    format!("A value of type {} can not be called", other.type_name()), callee.span))
+        };
+        let mut values = Vec::new();
+        for arg in args
+        {
+            values.push(self.evaluate(arg)?);
+        }
+        self.call_function(&function.name, values, span)
+    }
+
+    //Methods get the object before the . as self, static functions only get their arguments
+    fn call_method(&mut self, method: MethodCall, callee: &Expr, args: &[Expr], span: Span) -> RunResult<Value>
+    {
+        let mut values = Vec::new();
+        if method.receiver
+        {
+            match callee.kind
+            {
+                ExprKind::Member{ref object, ..} => values.push(self.evaluate(object)?),
+                _ => panic!("Methods are only called as members")
+            }
+        }
+        for arg in args
+        {
+            values.push(self.evaluate(arg)?);
+        }
+        self.call_function(&method.function, values, span)
+    }
+
+    //Without a constructor the arguments are the values of the fields. Otherwise
+    //the fields start out as nothing and the constructor gives them their values
+    fn construct(&mut self, constructor: ConstructorCall, args: &[Expr], span: Span) -> RunResult<Value>
+    {
+        let mut values = Vec::new();
+        for arg in args
+        {
+            values.push(self.evaluate(arg)?);
+        }
+        match constructor.function
+        {
+            Some(ref function) => {
+                let fields = constructor.fields.iter().map(|field| (field.clone(), Value::Unit)).collect();
+                let object = self.allocate(Value::object(constructor.type_name.clone(), fields), span)?;
+                values.insert(0, object.clone());
+                self.call_function(function, values, span)?;
+                return Ok(object);
+            }
+            None => {
+                let fields = constructor.fields.iter().cloned().zip(values).collect();
+                return self.allocate(Value::object(constructor.type_name.clone(), fields), span);
+            }
+        }
+    }
+
+    //Generated getters and setters read and write their field directly
+    fn call_accessor(&mut self, accessor: Accessor, callee: &Expr, args: &[Expr], span: Span) -> RunResult<Value>
+    {
+        let object = match callee.kind
+        {
+            ExprKind::Member{ref object, ..} => self.evaluate(object)?,
+            _ => panic!("Accessors are only called as members")
+        };
+        let result = match accessor
+        {
+            Accessor::Get(ref field) => object.field(field),
+            Accessor::Set(ref field) => {
+                let value = self.evaluate(&args[0])?;
+                object.set_field(field, value).map(|_| Value::Unit)
+            }
+        };
+        result.map_err(|e| RuntimeError::new(e, span))
+    }
+
+    //Counts the memory of a value that was just created
+    fn allocate(&mut self, value: Value, span: Span) -> RunResult<Value>
+    {
+        self.budget.allocate(&value, span)?;
+        return Ok(value);
+    }
+
+    fn binary(&self, op: BinaryOp, lhs: &Value, rhs: &Value, span: Span) -> RunResult<Value>
+    {
+        lhs.binary(op, rhs).map_err(|e| RuntimeError::new(e, span))
+    }
+
+    fn condition(&mut self, expr: &Expr) -> RunResult<bool>
+    {
+        self.condition_value(expr)?.as_bool().map_err(|e| RuntimeError::new(e, expr.span))
+    }
+
+    //Evaluates an expression that might be used as a condition. Values of user
+    //types are turned into a Bool by their operator if
+    fn condition_value(&mut self, expr: &Expr) -> RunResult<Value>
+    {
+        let value = self.evaluate(expr)?;
+        match self.targets.conditions.get(&expr.id).cloned()
+        {
+            Some(function) => self.call_function(&function, vec![value], expr.span),
+            None => Ok(value)
+        }
+    }
+
+    fn number(&mut self, expr: &Expr) -> RunResult<Number>
+    {
+        self.evaluate(expr)?.as_number().map_err(|e| RuntimeError::new(e, expr.span))
+    }
+}
+
+//What an assignment writes to
+enum Place
+{
+    Variable(String),
+    Element(Value, Value),
+    Field(Value, String),
+}
+
+//Where errors about an assignment target are reported. Members report the name
+//of the field like when they are read
+pub fn place_span(target: &Expr) -> Span
+{
+    match target.kind
+    {
+        ExprKind::Member{name_span, ..} => name_span,
+        _ => target.span
+    }
+}
+
+//A reference to a function declared in the script
+pub fn function_value(function: &FunctionDecl) -> Value
+{
+    let params = function.params.iter().map(|p| Type::from_type_expr(&p.type_expr)).collect();
+    let return_type = function.return_type.as_ref().map(Type::from_type_expr).unwrap_or(Type::Unit);
+    Value::function(function.name.clone(), params, return_type)
+}
+
+//The value of the variable of a for loop in the given pass, starting from 0
+pub fn for_value(start: Number, step: Number, pass: i64) -> NumberResult
+{
+    start.add(step.mul(Number::Integer(pass))?)
+}
+
+//Whether a for loop runs for the value, which is when it has not gone past
+//the end in the direction of the step
+pub fn for_running(value: Number, end: Number, step: Number) -> bool
+{
+    let zero = Number::Integer(0);
+    (step > zero && value <= end) || (step < zero && value >= end)
+}
+
+//The arguments that main is called with. main_params is the number of parameters
+//main has, or None if the script has no main function
+pub fn main_args(main_params: Option<usize>, args: Vec<String>) -> RunResult<Vec<Value>>
+{
+    match main_params
+    {
+        Some(0) => Ok(Vec::new()),
+        Some(_) => Ok(vec!(Value::list(args.into_iter().map(Value::String).collect()))),
+        None => Err(RuntimeError::new(String::from("The script has no main() function to run"), Span::default()))
+    }
+}
+
+//User types are iterated through what their iterate method returns. call runs
+//the method with the given name on an object
+pub fn iterated_value(mut container: Value, call: &mut dyn FnMut(&str, Value) -> RunResult<Value>) -> RunResult<Value>
+{
+    while let Value::Object(_) = container
+    {
+        let iterate = format!("{}.iterate", container.type_name());
+        container = call(&iterate, container)?;
+    }
+    return Ok(container);
+}
+
+/*
+ * What both engines do around a call to a builtin. start uses up a step and checks
+ * that the receiver can be changed before the builtin runs, finish counts the
+ * memory it used afterwards
+ */
+pub struct BuiltinCall
+{
+    //Methods that change a container count what it grows by
+    receiver: Option<Value>,
+    old_size: usize,
+    call_site: Span,
+}
+
+impl BuiltinCall
+{
+    pub fn start<'v, I>(name: &str, args: &[Value], iterating: I, budget: &mut Budget, call_site: Span) -> RunResult<BuiltinCall>
+        where I: IntoIterator<Item = &'v Value>
+    {
+        budget.step(call_site)?;
+        budget.check_time(call_site)?;
+        let receiver = if stdlib::changes_receiver(name) {args.first().cloned()} else {None};
+        if let Some(ref receiver) = receiver
+        {
+            if iterating.into_iter().any(|iterated| iterated.is_same(receiver))
+            {
+                return Err(RuntimeError::new(receiver.changed_while_iterating(), call_site));
+            }
+        }
+        return Ok(BuiltinCall {
+            old_size: receiver.as_ref().map_or(0, allocation_size),
+            receiver: receiver,
+            call_site: call_site,
+        });
+    }
+
+    pub fn finish(self, result: &Value, budget: &mut Budget) -> RunResult<()>
+    {
+        if let Some(ref receiver) = self.receiver
+        {
+            budget.grow(receiver, self.old_size, self.call_site)?;
+        }
+        return budget.allocate(result, self.call_site);
+    }
+}
+
+//The type a variable is declared with. Variables without a written type get the
+//type of their first value
+pub fn declared_type(type_expr: Option<&TypeExpr>, value: &Value) -> Type
+{
+    match type_expr
+    {
+        Some(type_expr) => Type::from_type_expr(type_expr),
+        None => value.type_of()
+    }
+}
+
+
+#[cfg(test)]
+mod interpreter_tests
+{
+    use std::time::{Duration, Instant};
+
+    use builtins::Builtins;
+    use interpreter::*;
+    use lexer::Lexer;
+    use limits::{run_with_stack, DEFAULT_MAX_CALL_DEPTH};
+    use parser::parse_token_list;
+    use resolver::resolve;
+    use type_checker::check;
+    use validation::{validate, ValidationOptions};
+
+    fn run_with_args(code: &str, args: Vec<String>) -> (Result<Value, RuntimeError>, String)
+    {
+        run_with_limits(code, args, Limits::default())
+    }
+
+    fn run_with_limits(code: &str, args: Vec<String>, limits: Limits) -> (Result<Value, RuntimeError>, String)
+    {
+        let tokens = Lexer::new().tokenize(String::from(code));
+        let program = parse_token_list(tokens).unwrap();
+        let builtins = Builtins::new();
+        let resolution = resolve(&program, &builtins.names());
+        assert!(!resolution.has_errors(), "{:?}", resolution.diagnostics);
+        let checked = check(&program, &resolution, &builtins);
+        assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+        assert!(validate(&program, &ValidationOptions::script()).is_empty());
+
+        let mut output = Vec::new();
+        let result = {
+            let mut interpreter = Interpreter::new(&program, &builtins, &mut output);
+            interpreter.set_limits(limits);
+            interpreter.set_call_targets(checked.call_targets);
+            interpreter.run_main(args)
+        };
+        (result, String::from_utf8(output).unwrap())
+    }
+
+    fn run(code: &str) -> String
+    {
+        let (result, output) = run_with_args(code, Vec::new());
+        result.unwrap();
+        output
+    }
+
+    fn run_error(code: &str) -> RuntimeError
+    {
+        run_with_args(code, Vec::new()).0.unwrap_err()
+    }
+
+    #[test]
+    fn arithmetic_and_printing()
+    {
+        assert_eq!(run("def main() { print(1 + 2 * 3); print((1 + 2) * 3); print(7 / 2); print(-4 - 1); }"), "7\n9\n3.5\n-5\n");
+        assert_eq!(run("def main() { print(\"a\" + \"b\"); print(0xff); print(1 < 2 & 2 != 3); }"), "ab\n255\ntrue\n");
+        assert_eq!(run("def main() { print([1, 2]); print([\"a\": [\"b\"]]); print([:]); }"), "[1, 2]\n[\"a\": [\"b\"]]\n[:]\n");
+    }
+
+    #[test]
+    fn number_semantics()
+    {
+        let code = r#"
+            def main()
+            {
+                let cents = 0;
+                for i is 1 to 10 { cents += 10; }
+                print(cents == 100);
+                print(0.1 + 0.2 == 0.3);
+                print(0.1 + 0.2);
+                print(6 / 3); print(7 / 2);
+                print(7 // 2); print(-7 // 2); print(7.5 // 2);
+                print(7 % 3); print(-7 % 3); print(7 % -3);
+                let n = 10; n %= 4; print(n);
+                print(1 == 1.0); print(2 > 1.5);
+                print(9223372036854775807); print(9223372036854775807 + 1);
+            }
+        "#;
+        assert_eq!(run(code), "true\nfalse\n0.30000000000000004\n2\n3.5\n3\n-4\n3\n1\n2\n-2\n2\ntrue\ntrue\n9223372036854775807\n9.223372036854776e18\n");
+
+        assert_eq!(run_error("def main() { print(1 % 0); }").message, "Division by zero");
+        assert_eq!(run_error("def main() { print(1 // 0.0); }").message, "Division by zero");
+        assert_eq!(run_error("def main() { let l = [1, 2]; print(l[0.5]); }").message, "Index 0.5 is out of bounds for a length of 2");
+    }
+
+    #[test]
+    fn functions()
+    {
+        let code = r#"
+            def Number fib(Number n)
+            {
+                if n < 2 { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            def greet(String name) { print("Hello " + name); }
+            def main() { print(fib(15)); greet("Boa"); }
+        "#;
+        assert_eq!(run(code), "610\nHello Boa\n");
+
+        //Functions can be stored in variables and called through them
+        let code = r#"
+            def Number add(Number a, Number b) { return a + b; }
+            def main() { let f = add; print(f(1, 2)); let p = print; p(f); }
+        "#;
+        assert_eq!(run(code), "3\n<function add>\n");
+    }
+
+    #[test]
+    fn copy_and_reference_semantics()
+    {
+        //Numbers and strings are copied, lists and dictionaries are shared with the caller
+        let code = r#"
+            def change(List<Number> l, Dictionary<String, Number> d, Number n, String s)
+            {
+                l[0] = 5; d["a"] = 5; n = 5; s = "changed";
+            }
+            def main()
+            {
+                let l = [1]; let d = ["a": 1]; let n = 1; let s = "s";
+                change(l, d, n, s);
+                print(l); print(d); print(n); print(s);
+            }
+        "#;
+        assert_eq!(run(code), "[5]\n[\"a\": 5]\n1\ns\n");
+
+        //Assigning a container to a variable shares it, assigning a new container does not
+        let code = r#"
+            def main()
+            {
+                let a = [[1], [2]];
+                let b = a;
+                let inner = a[0];
+                b[1] = [3];
+                inner[0] = 4;
+                print(a);
+                b = [[0]];
+                print(a);
+                print(b == [[0]]);
+                print(a == [[4], [3]]);
+            }
+        "#;
+        assert_eq!(run(code), "[[4], [3]]\n[[4], [3]]\ntrue\ntrue\n");
+    }
+
+    #[test]
+    fn control_flow()
+    {
+        let code = r#"
+            def String classify(Number n)
+            {
+                if n < 0 { return "negative"; }
+                elseif n == 0 { return "zero"; }
+                else { return "positive"; }
+            }
+            def main()
+            {
+                print(classify(-1)); print(classify(0)); print(classify(3));
+
+                let i = 0;
+                while i < 3 { i++; }
+                print(i);
+
+                let total = 0;
+                for j is 1 to 10 { total += j; }
+                print(total);
+                for j is 10 to 0 step -5 { print(j); }
+                for j is 0 to 1 step 0.5 { print(j); }
+
+                foreach x in [1, 2, 3] { total -= x; }
+                print(total);
+                foreach c in "ab" { print(c); }
+                foreach key in ["x": 1, "y": 2] { print(key); }
+                foreach key, value in ["x": 1, "y": 2] { print(key); print(value); }
+
+                if true { let shadow = 1; }
+                let shadow = 2;
+                print(shadow);
+            }
+        "#;
+        assert_eq!(run(code), "negative\nzero\npositive\n3\n55\n10\n5\n0\n0\n0.5\n1\n49\na\nb\nx\ny\nx\n1\ny\n2\n2\n");
+
+        //Return from inside loops
+        let code = r#"
+            def Number find(List<Number> l, Number target)
+            {
+                let i = 0;
+                foreach x in l { if x == target { return i; } i++; }
+                return -1;
+            }
+            def main() { print(find([4, 5, 6], 6)); print(find([4], 1)); }
+        "#;
+        assert_eq!(run(code), "2\n-1\n");
+    }
+
+    #[test]
+    fn containers()
+    {
+        let code = r#"
+            def main()
+            {
+                let l = [[1, 2], [3]];
+                l[0][1] = 7;
+                l[1][0] += 1;
+                print(l);
+                let d = ["a": 1];
+                d["b"] = 2;
+                d["a"]++;
+                print(d);
+                print(d["b"] + l[0][0]);
+                print("abc"[1]);
+            }
+        "#;
+        assert_eq!(run(code), "[[1, 7], [4]]\n[\"a\": 2, \"b\": 2]\n3\nb\n");
+    }
+
+    #[test]
+    fn main_arguments_and_result()
+    {
+        let (result, output) = run_with_args("def Number main(List<String> args) { print(args); return 3; }",
+            vec!(String::from("a"), String::from("b")));
+        assert_eq!(result.unwrap(), Value::integer(3));
+        assert_eq!(output, "[\"a\", \"b\"]\n");
+    }
+
+    #[test]
+    fn runtime_errors()
+    {
+        let code = "def main() { let a = 0; print(1 / a); }";
+        let error = run_error(code);
+        assert_eq!(error.message, "Division by zero");
+        assert_eq!(&code[error.span.start..error.span.end], "1 / a");
+
+        assert_eq!(run_error("def main() { let l = [1]; print(l[1]); }").message, "Index 1 is out of bounds for a length of 1");
+        assert_eq!(run_error("def main() { let l = [1]; l[-1] = 2; }").message, "Index -1 is out of bounds for a length of 1");
+        assert_eq!(run_error("def main() { let d = [\"a\": 1]; print(d[\"b\"]); }").message, "The key \"b\" is not in the dictionary");
+        assert_eq!(run_error("def main() { let s = 0; for i is 0 to 1 step s {} }").message, "The step of a for loop can not be 0");
+
+        let code = "def main() { let l = [1, 2]; foreach x in l { l[0] = x; } }";
+        let error = run_error(code);
+        assert_eq!(error.message, "The List can not be changed while a foreach loop goes through it");
+        assert_eq!(&code[error.span.start..error.span.end], "l[0]");
+    }
+
+    #[test]
+    fn limits()
+    {
+        let limited = |code: &str, limits: Limits| run_with_limits(code, Vec::new(), limits).0.unwrap_err();
+
+        let error = limited("def main() { while true {} }", Limits{fuel: Some(10_000), ..Limits::unlimited()});
+        assert_eq!(error.limit, Some(Limit::Fuel));
+        assert_eq!(error.message, "The script ran out of fuel after 10000 steps");
+
+        let error = limited("def main() { for i is 0 to 1000000 {} }",
+            Limits{fuel: Some(1000), ..Limits::unlimited()});
+        assert_eq!(error.limit, Some(Limit::Fuel));
+
+        //Runaway recursion stops at the default call depth instead of overflowing the stack
+        let code = "def Number down(Number n) { return 1 + [down(n + 1)][0]; } def main() { down(0); }";
+        let error = run_with_stack(|| run_error(code));
+        assert_eq!(error.limit, Some(Limit::CallDepth));
+        assert_eq!(error.message, format!("The maximum call depth of {} was exceeded", DEFAULT_MAX_CALL_DEPTH));
+        assert_eq!(&code[error.span.start..error.span.end], "down(n + 1)");
+        let error = limited(code, Limits{max_call_depth: Some(10), ..Limits::unlimited()});
+        assert_eq!(error.message, "The maximum call depth of 10 was exceeded");
+        //Threads with a normal stack stop the script before it runs out
+        let error = limited(code, Limits::unlimited());
+        assert_eq!(error.limit, Some(Limit::CallDepth));
+        assert!(error.message.ends_with("which is more than the stack of the thread running it has room for"), "{}", error.message);
+        let code = "def Number sum(Number n) { if n == 0 { return 0; } return n + sum(n - 1); } def main() { print(sum(5000)); }";
+        assert_eq!(run_with_stack(|| run(code)), "12502500\n");
+
+        let code = "def main() { let s = \"ab\"; while true { s = s + s; } }";
+        let error = limited(code, Limits{max_memory: Some(1 << 20), ..Limits::unlimited()});
+        assert_eq!(error.limit, Some(Limit::Memory));
+        let code = "def main() { let d = [0: 0]; let i = 1; while true { d[i] = i; i++; } }";
+        assert_eq!(limited(code, Limits{max_memory: Some(1 << 16), ..Limits::unlimited()}).limit, Some(Limit::Memory));
+
+        let start = Instant::now();
+        let error = limited("def main() { while true {} }", Limits{timeout: Some(Duration::from_millis(50)), ..Limits::unlimited()});
+        assert_eq!(error.limit, Some(Limit::Time));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        //Each call searches millions of characters, the time runs out long before 1024 of them are made
+        let code = "def main() { let s = \"a\"; for i is 0 to 22 { s = s + s; } while true { s.find(\"b\"); } }";
+        let start = Instant::now();
+        let error = limited(code, Limits{timeout: Some(Duration::from_millis(100)), ..Limits::unlimited()});
+        assert_eq!(error.limit, Some(Limit::Time));
+        assert!(start.elapsed() < Duration::from_secs(2), "{:?}", start.elapsed());
+
+        //Scripts that stay inside their limits are not affected
+        let limits = Limits{fuel: Some(1000), max_call_depth: Some(20), max_memory: Some(1000), timeout: Some(Duration::from_secs(10))};
+        let (result, output) = run_with_limits("def main() { print(\"a\" + \"b\"); }", Vec::new(), limits);
+        assert_eq!((result, output.as_str()), (Ok(Value::Unit), "ab\n"));
+    }
+
+    #[test]
+    fn stack_traces()
+    {
+        let code = "def Number divide(Number a, Number b)\n{\n\treturn a / b;\n}\n\
+                    def Number average(List<Number> l) { return divide(l[0] + l[1], 0); }\n\
+                    def main() { print(average([1, 2])); }";
+        let error = run_error(code);
+        let frames: Vec<(&str, &str)> = error.trace.iter()
+            .map(|frame| (frame.function.as_str(), &code[frame.call_site.start..frame.call_site.end]))
+            .collect();
+        assert_eq!(frames, vec!(("divide", "divide(l[0] + l[1], 0)"), ("average", "average([1, 2])"), ("main", "")));
+
+        let expected = "\
+error[E0500]: Division by zero
+ --> test.boa:3:9
+  |
+3 | \treturn a / b;
+  | \t       ^^^^^ in divide
+...
+5 | def Number average(List<Number> l) { return divide(l[0] + l[1], 0); }
+  |                                             ---------------------- divide is called from average
+6 | def main() { print(average([1, 2])); }
+  |                    --------------- average is called from main
+";
+        assert_eq!(error.render("test.boa", code), expected);
+
+        //Recursion is only shown once
+        let code = "def Number down(Number n) { return down(n + 1); } def main() { down(0); }";
+        let error = run_with_stack(|| run_error(code));
+        assert_eq!(error.trace.len(), DEFAULT_MAX_CALL_DEPTH);
+        let rendered = error.render("test.boa", code);
+        assert!(rendered.starts_with("error[E0502]: The maximum call depth"), "{}", rendered);
+        assert!(rendered.ends_with(&format!("  = note: the call of down from down is repeated {} more times\n",
+            DEFAULT_MAX_CALL_DEPTH - 3)), "{}", rendered);
+        assert!(rendered.contains("down is called from main\n"));
+    }
+}
+
@@ -1,10 +1,11 @@
 
-use regex::{Regex};
-use std::collections::{HashMap};
+use regex::{self, Regex};
 
+use diagnostic::Diagnostic;
+use error_codes;
 use token::*;
 
-const OPERATORS: [&'static str; 17] = [
+pub const OPERATORS: [&'static str; 22] = [
     "==",
     "!=",
     "<=",
@@ -20,24 +21,26 @@ const OPERATORS: [&'static str; 17] = [
     "-=",
     "*=",
     "/=",
+    "%=",
+    "//",
 
     "+",
     "-",
     "/",
+    "%",
     "*",
     "!",
-
+    "&",
+    "|",
 ];
 
 fn get_operator_regex() -> String
 {
     let mut operator_regex = String::from(r"\A(");
-    for op in OPERATORS.into_iter()
+    for op in OPERATORS.iter()
     {
         //Escape all characters that need to be escaped
-        let mut escaped = op.replace("*", r"\*");
-        escaped = escaped.replace("+", r"\+");
-        escaped = escaped.replace("-", r"-");
+        let escaped = regex::escape(op);
 
         operator_regex.push_str("(");
         operator_regex.push_str(escaped.as_str());
@@ -53,20 +56,39 @@ fn get_operator_regex() -> String
     return operator_regex;
 }
 
-const KEYWORDS: [&'static str; 9] = [
+pub const KEYWORDS: [&'static str; 22] = [
     "if",
     "elseif",
     "else",
 
     "def",
+    "return",
+    "let",
+
     "for",
     "foreach",
     "while",
+    "is",
+    "to",
+    "step",
+    "in",
 
     "type",
     "implement",
+    "public",
+    "private",
+    "hidden",
+    "operator",
+    "self",
+
+    "true",
+    "false",
 ];
 
+//Error produced when the lexer finds something that isn't part of the language.
+//Boxed like ParseError to keep the Results that carry it small
+pub type LexError = Box<Diagnostic>;
+
 
 struct TokenTemplate
 {
@@ -98,11 +120,23 @@ impl Lexer
     //Returns a tokenized version of the string
     pub fn tokenize(&self, code: String) -> Vec<Token>
     {
-        let mut current_code = code.clone();
+        match self.try_tokenize(&code)
+        {
+            Ok(tokens) => tokens,
+            Err(e) => panic!("{}", e.message)
+        }
+    }
+
+    //Returns a tokenized version of the string or an error pointing at the
+    //first piece of code that could not be tokenized
+    pub fn try_tokenize(&self, code: &str) -> Result<Vec<Token>, LexError>
+    {
+        let mut offset = 0;
 
         let mut tokens = Vec::new();
-        while current_code.len() != 0
+        while offset != code.len()
         {
+            let current_code = &code[offset..];
             let mut found_matching = false;
 
             //Go through the token templates to find matches
@@ -110,43 +144,81 @@ impl Lexer
             {
                 let re = &token_template.regex;
 
-                match re.find(current_code.as_str()){
-                    Some((0, end)) => {
-                        let new_token = Token::new(current_code.drain(..end).collect(), token_template.token_type);
-
-                        tokens.push(new_token);
+                match re.find(current_code){
+                    Some(m) if m.start() == 0 && m.end() != 0 => {
+                        let lexeme = &current_code[..m.end()];
+                        let token_type = if token_template.token_type == TokenType::Identifier
+                                && KEYWORDS.contains(&lexeme)
+                        {
+                            TokenType::Keyword
+                        }
+                        else
+                        {
+                            token_template.token_type
+                        };
+
+                        let span = Span::new(offset, offset + m.end());
+                        tokens.push(Token::with_span(String::from(lexeme), token_type, span));
+                        offset += m.end();
                         found_matching = true;
                         break;
                     }
-                    Some((start, _)) => panic!("Found match but not at start. Type: {:?}, Code: {}", token_template.token_type, current_code),
-                    _ =>{} //TODO: Do something nicer than this
+                    //Without this the / of a block comment that is never closed
+                    //would be found as an operator
+                    _ if token_template.token_type == TokenType::BlockComment && current_code.starts_with("/*") => break,
+                    _ => {}
                 }
             }
 
             if !found_matching
             {
-                //Somehow alert the user that no matching patterns were found
-                panic!("No matching pattern was found for the character {}", code.chars().next().unwrap());
+                let c = current_code.chars().next().unwrap();
+                let (code, message) = if c == '"' || c == '\''
+                {
+                    (error_codes::UNTERMINATED_STRING, String::from("Unterminated string literal"))
+                }
+                else if current_code.starts_with("/*")
+                {
+                    (error_codes::UNTERMINATED_COMMENT, String::from("Unterminated block comment"))
+                }
+                else
+                {
+                    (error_codes::UNKNOWN_CHARACTER, format!("No matching pattern was found for the character {}", c))
+                };
+                return Err(Box::new(Diagnostic::error(code, message, Span::new(offset, offset + c.len_utf8()))));
             }
         }
-        return tokens;
+        return Ok(tokens);
     }
-    
+
     fn setup_templates(&mut self)
     {
         self.add_token_template(TokenType::Whitespace, r"\A\s\s*(\b|\B|$)");
         self.add_token_template(TokenType::LineComment, r"\A(?m:#.*$)");
-        self.add_token_template(TokenType::BlockComment, r"\A/\*(.|\s)*\*/");
+        self.add_token_template(TokenType::BlockComment, r"\A/\*(.|\s)*?\*/");
 
-        self.add_token_template(TokenType::StringLiteral, r#"\A("(.|\s)*")|(\A'(.|\s)*')"#);
+        self.add_token_template(TokenType::StringLiteral, r#"\A("([^"\\]|\\(.|\s))*")|(\A'([^'\\]|\\(.|\s))*')"#);
 
         self.add_token_template(TokenType::Identifier, r"\A[a-zA-Z_]\w*\b");
         self.add_token_template(TokenType::Number, r"\A[0-9][0-9]*[.]?[0-9]*\b");
         self.add_token_template(TokenType::HexNumber, r"\A[0]x[0-9A-Fa-f][0-9A-Fa-f]*\b");
 
-        self.add_token_template(TokenType::EndStatement, ";");
+        self.add_token_template(TokenType::EndStatement, r"\A;");
 
         self.add_token_template(TokenType::Operator, get_operator_regex().as_str());
+
+        //Assignment has to go after the operators to not steal the first half of ==
+        self.add_token_template(TokenType::Assignment, r"\A=");
+
+        self.add_token_template(TokenType::OpenPar, r"\A\(");
+        self.add_token_template(TokenType::ClosePar, r"\A\)");
+        self.add_token_template(TokenType::OpenCurl, r"\A\{");
+        self.add_token_template(TokenType::CloseCurl, r"\A\}");
+        self.add_token_template(TokenType::OpenSq, r"\A\[");
+        self.add_token_template(TokenType::CloseSq, r"\A\]");
+        self.add_token_template(TokenType::Comma, r"\A,");
+        self.add_token_template(TokenType::Dot, r"\A\.");
+        self.add_token_template(TokenType::Colon, r"\A:");
     }
 
     fn add_token_template(&mut self, token_type: TokenType, reg: &str)
@@ -173,6 +245,7 @@ enum MatchType
 #[cfg(test)]
 impl Lexer
 {
+    #[allow(clippy::ptr_arg)]
     fn matches_token(&self, token_type: TokenType, string: &String) -> MatchType
     {
         let mut regex: Option<&Regex> = None;
@@ -192,13 +265,13 @@ impl Lexer
 
         //return re.is_match(string);
         match re.find(string){
-            Some((0, len)) => if len == string.len()
+            Some(m) if m.start() == 0 => if m.end() == string.len()
                               {
                                   return MatchType::Match
                               }
                               else
                               {
-                                  return MatchType::WrongLen(len)
+                                  return MatchType::WrongLen(m.end())
                               },
             _ => return MatchType::NoMatch
         };
@@ -208,9 +281,11 @@ impl Lexer
 #[cfg(test)]
 mod lexer_tests
 {
+    use error_codes;
     use lexer::Lexer;
     use token::TokenType;
     use token::Token;
+    use token::Span;
     use lexer::MatchType;
 
     #[test]
@@ -299,6 +374,9 @@ mod lexer_tests
         assert_eq!(lexer.matches_token(TokenType::Operator, &String::from("/=")), MatchType::Match);
         assert_eq!(lexer.matches_token(TokenType::Operator, &String::from("++")), MatchType::Match);
         assert_eq!(lexer.matches_token(TokenType::Operator, &String::from("--")), MatchType::Match);
+        assert_eq!(lexer.matches_token(TokenType::Operator, &String::from("//")), MatchType::Match);
+        assert_eq!(lexer.matches_token(TokenType::Operator, &String::from("%")), MatchType::Match);
+        assert_eq!(lexer.matches_token(TokenType::Operator, &String::from("%=")), MatchType::Match);
 
         assert_eq!(lexer.matches_token(TokenType::Operator, &String::from(" + ")), MatchType::NoMatch);
         assert_eq!(lexer.matches_token(TokenType::Operator, &String::from(" == ")), MatchType::NoMatch);
@@ -417,4 +495,72 @@ mod lexer_tests
             assert_eq!(lexer.tokenize(code), tokens);
         }
     }
+
+    #[test]
+    fn keyword_and_punctuation_test()
+    {
+        let lexer = Lexer::new();
+
+        let code = String::from("if (a == b) { let x = [1, 2]; } else print(\"a\", \"b\"):y.z");
+        let tokens: Vec<(String, TokenType)> = lexer.tokenize(code).into_iter()
+            .filter(|t| t.token_type() != TokenType::Whitespace)
+            .map(|t| (String::from(t.lexeme()), t.token_type()))
+            .collect();
+        let expected = vec!(
+                ("if", TokenType::Keyword),
+                ("(", TokenType::OpenPar),
+                ("a", TokenType::Identifier),
+                ("==", TokenType::Operator),
+                ("b", TokenType::Identifier),
+                (")", TokenType::ClosePar),
+                ("{", TokenType::OpenCurl),
+                ("let", TokenType::Keyword),
+                ("x", TokenType::Identifier),
+                ("=", TokenType::Assignment),
+                ("[", TokenType::OpenSq),
+                ("1", TokenType::Number),
+                (",", TokenType::Comma),
+                ("2", TokenType::Number),
+                ("]", TokenType::CloseSq),
+                (";", TokenType::EndStatement),
+                ("}", TokenType::CloseCurl),
+                ("else", TokenType::Keyword),
+                ("print", TokenType::Identifier),
+                ("(", TokenType::OpenPar),
+                ("\"a\"", TokenType::StringLiteral),
+                (",", TokenType::Comma),
+                ("\"b\"", TokenType::StringLiteral),
+                (")", TokenType::ClosePar),
+                (":", TokenType::Colon),
+                ("y", TokenType::Identifier),
+                (".", TokenType::Dot),
+                ("z", TokenType::Identifier),
+            );
+        let expected: Vec<(String, TokenType)> = expected.into_iter()
+            .map(|(lexeme, token_type)| (String::from(lexeme), token_type))
+            .collect();
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn span_and_error_test()
+    {
+        let lexer = Lexer::new();
+
+        let tokens = lexer.try_tokenize("ab /* c */ 12").unwrap();
+        assert_eq!(tokens[0].span(), Span::new(0, 2));
+        assert_eq!(tokens[2].span(), Span::new(3, 10));
+        assert_eq!(tokens[4].span(), Span::new(11, 13));
+
+        let error = lexer.try_tokenize("a = $").unwrap_err();
+        assert_eq!(error.span, Span::new(4, 5));
+        assert_eq!(error.message, "No matching pattern was found for the character $");
+
+        let error = lexer.try_tokenize("a = \"abc").unwrap_err();
+        assert_eq!(error.message, "Unterminated string literal");
+
+        let error = lexer.try_tokenize("a /* b").unwrap_err();
+        assert_eq!((error.code, error.message.as_str()), (error_codes::UNTERMINATED_COMMENT, "Unterminated block comment"));
+        assert_eq!(lexer.try_tokenize("a / b").unwrap().len(), 5);
+    }
 }
@@ -1,6 +1,185 @@
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::redundant_static_lifetimes,
+         clippy::single_char_add_str, clippy::new_without_default, clippy::needless_borrowed_reference)]
+
+extern crate regex;
+extern crate serde_json;
+
+pub mod variable;
+pub mod number;
+pub mod script_state;
+pub mod lexer;
+pub mod parser;
+pub mod token;
+pub mod ast;
+pub mod diagnostic;
+pub mod error_codes;
+pub mod resolver;
+pub mod types;
+pub mod builtins;
+pub mod capabilities;
+pub mod stdlib;
+pub mod type_checker;
+pub mod initialisation;
+pub mod validation;
+pub mod interpreter;
+pub mod limits;
+pub mod bytecode;
+pub mod compiler;
+pub mod vm;
+pub mod convert;
+pub mod engine;
+pub mod error;
+pub mod printer;
+pub mod analysis;
+pub mod lsp;
+pub mod highlight;
+pub mod repl;
+pub mod cli;
+
+use std::io::Write;
+
+use ast::Program;
+use builtins::Builtins;
+use diagnostic::Diagnostic;
+use interpreter::Interpreter;
+use resolver::Resolution;
+use token::Token;
+use type_checker::TypeCheckResult;
+use validation::ValidationOptions;
+
+pub use convert::{FromValue, IntoArgs, IntoValue, ScriptType};
+pub use engine::{Engine, EngineError};
+pub use error::Error;
+pub use limits::{Limit, Limits};
+pub use types::Type;
+pub use variable::Value;
+
+/*
+ * The steps that turn code into a running script:
+ *
+ *     let builtins = Builtins::new();
+ *     let program = boa::parse(boa::lex(&code)?)?;
+ *     let checked = boa::check(program, &builtins, &ValidationOptions::script())?;
+ *     let result = boa::run(&checked, &builtins, args, &mut io::stdout())?;
+ *
+ * Each step stops at the first kind of error it finds. The modules that the steps
+ * are built from are public as well, for tools that need more than this
+ */
+
+//A program that has passed every check and is ready to run
+pub struct CheckedProgram
+{
+    pub program: Program,
+    pub resolution: Resolution,
+    pub types: TypeCheckResult,
+    //Problems that don't stop the program from running
+    pub warnings: Vec<Diagnostic>,
+}
+
+pub fn lex(code: &str) -> Result<Vec<Token>, Error>
+{
+    lexer::Lexer::new().try_tokenize(code).map_err(Error::Lex)
+}
+
+pub fn parse(tokens: Vec<Token>) -> Result<Program, Error>
+{
+    parser::parse_token_list(tokens).map_err(Error::Parse)
+}
+
+//Resolves names, checks types and validates the structure of the program. The
+//builtins are the functions that the program is allowed to call
+pub fn check(program: Program, builtins: &Builtins, options: &ValidationOptions) -> Result<CheckedProgram, Error>
+{
+    let resolution = resolver::resolve(&program, &builtins.names());
+    let types = type_checker::check(&program, &resolution, builtins);
+    let mut diagnostics = resolution.diagnostics.clone();
+    diagnostics.extend(types.diagnostics.iter().cloned());
+    diagnostics.extend(validation::validate(&program, options));
+    if diagnostics.iter().any(|d| d.is_error())
+    {
+        return Err(Error::Check(diagnostics));
+    }
+
+    Ok(CheckedProgram {
+        program: program,
+        resolution: resolution,
+        types: types,
+        warnings: diagnostics,
+    })
+}
+
+//Runs the main function of the program. Returns the value that main returned. Every
+//call in the script uses some of the stack of the thread, run it inside
+//limits::run_with_stack for scripts that make deep calls
+pub fn run(checked: &CheckedProgram, builtins: &Builtins, args: Vec<String>, output: &mut dyn Write)
+    -> Result<Value, Error>
+{
+    run_with_limits(checked, builtins, args, Limits::default(), output)
+}
+
+//Like run, but stops the program when it goes over one of the limits
+pub fn run_with_limits(checked: &CheckedProgram, builtins: &Builtins, args: Vec<String>, limits: Limits,
+    output: &mut dyn Write) -> Result<Value, Error>
+{
+    let mut interpreter = Interpreter::new(&checked.program, builtins, output);
+    interpreter.set_limits(limits);
+    interpreter.set_call_targets(checked.types.call_targets.clone());
+    Ok(interpreter.run_main(args)?)
+}
+
 #[cfg(test)]
-mod tests {
+mod pipeline_tests
+{
+    use super::*;
+
+    fn run_code(code: &str) -> Result<(Value, String), Error>
+    {
+        let builtins = Builtins::new();
+        let checked = check(parse(lex(code)?)?, &builtins, &ValidationOptions::script())?;
+        let mut output = Vec::new();
+        let result = run(&checked, &builtins, Vec::new(), &mut output)?;
+        Ok((result, String::from_utf8(output).unwrap()))
+    }
+
+    #[test]
+    fn running_code()
+    {
+        assert_eq!(run_code("def Number main() { print(\"hi\"); return 3; }"),
+            Ok((Value::integer(3), String::from("hi\n"))));
+    }
+
+    #[test]
+    fn each_step_has_its_own_error()
+    {
+        match run_code("def main() { let a = 1 $ 2; }")
+        {
+            Err(Error::Lex(_)) => {}
+            other => panic!("Expected a lex error, got {:?}", other)
+        }
+        match run_code("def main() { let = 1; }")
+        {
+            Err(Error::Parse(_)) => {}
+            other => panic!("Expected a parse error, got {:?}", other)
+        }
+        match run_code("def main() { let a = 1 + true; }")
+        {
+            Err(Error::Check(diagnostics)) => assert_eq!(diagnostics.len(), 1),
+            other => panic!("Expected a check error, got {:?}", other)
+        }
+        match run_code("def main() { let a = 1 / 0; }")
+        {
+            Err(Error::Runtime(e)) => assert_eq!(e.message, "Division by zero"),
+            other => panic!("Expected a runtime error, got {:?}", other)
+        }
+    }
+
     #[test]
-    fn it_works() {
+    fn warnings_are_kept()
+    {
+        let builtins = Builtins::new();
+        let program = parse(lex("def Number f(Number a) { if true { let a = 2; } return a; }").unwrap()).unwrap();
+        let checked = check(program, &builtins, &ValidationOptions::library()).unwrap();
+        assert!(!checked.warnings.is_empty());
+        assert!(checked.warnings.iter().all(|d| !d.is_error()));
     }
 }
@@ -0,0 +1,287 @@
+use std::cell::Cell;
+use std::fmt;
+use std::hint;
+use std::mem;
+use std::panic;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use interpreter::RuntimeError;
+use token::Span;
+use variable::Value;
+
+/*
+ * How much a script is allowed to do before it is stopped. Scripts that can't be
+ * trusted should be run with every limit set
+ */
+#[derive(Clone,Copy,Eq,PartialEq,Debug)]
+pub struct Limits
+{
+    //The number of steps the script may take. The interpreter uses a step for
+    //every statement, loop iteration and function call, the VM for every instruction
+    pub fuel: Option<u64>,
+    //How many function calls may be running at once
+    pub max_call_depth: Option<usize>,
+    //The total number of bytes that strings and containers may allocate while
+    //the script runs. Memory that is freed again is not given back
+    pub max_memory: Option<usize>,
+    //How long the script may run for
+    pub timeout: Option<Duration>,
+}
+
+impl Limits
+{
+    pub fn unlimited() -> Limits
+    {
+        Limits {
+            fuel: None,
+            max_call_depth: None,
+            max_memory: None,
+            timeout: None,
+        }
+    }
+}
+
+//Only the call depth is limited by default, so that recursion that never ends is
+//stopped. Every call in the interpreter also uses some of the stack of the host,
+//which is checked as well, see run_with_stack
+impl Default for Limits
+{
+    fn default() -> Limits
+    {
+        Limits {
+            max_call_depth: Some(DEFAULT_MAX_CALL_DEPTH),
+            ..Limits::unlimited()
+        }
+    }
+}
+
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+//The stack of the threads that run_with_stack starts. It is enough for the interpreter
+//to reach the default call depth, and the system only hands out the part that is used
+pub const SCRIPT_STACK_SIZE: usize = 512 << 20;
+
+//The stack that Rust gives new threads. How much stack a thread really has can't be
+//found out, so threads that weren't started by run_with_stack are assumed to have this
+const DEFAULT_THREAD_STACK_SIZE: usize = 2 << 20;
+
+thread_local!
+{
+    //How much stack the calls of a script may use on this thread. The rest is kept
+    //for the code that started the script and for builtins
+    static STACK_ROOM: Cell<usize> = const { Cell::new(DEFAULT_THREAD_STACK_SIZE / 2) };
+}
+
+//Runs f on a new thread with a stack of SCRIPT_STACK_SIZE and waits for it. The
+//interpreter uses the stack of the host for every call in the script, so on other
+//threads scripts are stopped long before they reach the default call depth
+pub fn run_with_stack<T, F>(f: F) -> T
+    where F: FnOnce() -> T + Send, T: Send
+{
+    thread::scope(|scope| {
+        let thread = thread::Builder::new()
+            .stack_size(SCRIPT_STACK_SIZE)
+            .spawn_scoped(scope, || {
+                STACK_ROOM.with(|room| room.set(SCRIPT_STACK_SIZE - DEFAULT_THREAD_STACK_SIZE));
+                f()
+            })
+            .expect("Failed to start a thread for the script");
+        match thread.join()
+        {
+            Ok(result) => result,
+            Err(panic) => panic::resume_unwind(panic)
+        }
+    })
+}
+
+//An address on the stack of the current thread, the stack grows towards or away
+//from zero as calls are made
+#[inline(never)]
+fn stack_position() -> usize
+{
+    let marker = 0u8;
+    return hint::black_box(&marker) as *const u8 as usize;
+}
+
+//The limit that stopped a script
+#[derive(Clone,Copy,Eq,PartialEq,Hash,Debug)]
+pub enum Limit
+{
+    Fuel,
+    CallDepth,
+    Memory,
+    Time,
+}
+
+impl fmt::Display for Limit
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        let name = match *self
+        {
+            Limit::Fuel => "fuel",
+            Limit::CallDepth => "call depth",
+            Limit::Memory => "memory",
+            Limit::Time => "time",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//Looking at the clock is slow compared to a step, so it is only done this often.
+//Builtins can take much longer than a step, so the clock is also looked at before
+//each of them with check_time
+const STEPS_BETWEEN_CLOCK_CHECKS: u64 = 1024;
+
+/*
+ * What a running script has used of its limits
+ */
+pub struct Budget
+{
+    limits: Limits,
+    fuel_used: u64,
+    memory_used: usize,
+    deadline: Option<Instant>,
+    //Where the stack was when the first call was made
+    stack_start: Option<usize>,
+}
+
+impl Budget
+{
+    //The time limit starts counting when the budget is created
+    pub fn new(limits: Limits) -> Budget
+    {
+        Budget {
+            limits: limits,
+            fuel_used: 0,
+            memory_used: 0,
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+            stack_start: None,
+        }
+    }
+
+    pub fn limits(&self) -> &Limits
+    {
+        &self.limits
+    }
+
+    pub fn step(&mut self, span: Span) -> Result<(), RuntimeError>
+    {
+        self.fuel_used += 1;
+        if let Some(fuel) = self.limits.fuel
+        {
+            if self.fuel_used > fuel
+            {
+                return Err(RuntimeError::limit_exceeded(Limit::Fuel,
+                    format!("The script ran out of fuel after {} steps", fuel), span));
+            }
+        }
+        if self.fuel_used.is_multiple_of(STEPS_BETWEEN_CLOCK_CHECKS)
+        {
+            return self.check_time(span);
+        }
+        return Ok(());
+    }
+
+    pub fn check_time(&self, span: Span) -> Result<(), RuntimeError>
+    {
+        if let Some(deadline) = self.deadline
+        {
+            if Instant::now() >= deadline
+            {
+                return Err(RuntimeError::limit_exceeded(Limit::Time,
+                    format!("The script ran for longer than {} ms", self.limits.timeout.unwrap().as_millis()), span));
+            }
+        }
+        return Ok(());
+    }
+
+    //Called before a function is called while depth calls are already running
+    pub fn enter_call(&mut self, depth: usize, span: Span) -> Result<(), RuntimeError>
+    {
+        if let Some(max_depth) = self.limits.max_call_depth
+        {
+            if depth >= max_depth
+            {
+                return Err(RuntimeError::limit_exceeded(Limit::CallDepth,
+                    format!("The maximum call depth of {} was exceeded", max_depth), span));
+            }
+        }
+        let here = stack_position();
+        let start = *self.stack_start.get_or_insert(here);
+        if start.abs_diff(here) > STACK_ROOM.with(|room| room.get())
+        {
+            return Err(RuntimeError::limit_exceeded(Limit::CallDepth,
+                format!("The script went {} calls deep, which is more than the stack of the thread running it has room for", depth),
+                span));
+        }
+        self.step(span)
+    }
+
+    //Records that a value was created
+    pub fn allocate(&mut self, value: &Value, span: Span) -> Result<(), RuntimeError>
+    {
+        self.allocate_bytes(allocation_size(value), span)
+    }
+
+    //Records that a container grew from old_size bytes to the size it has now
+    pub fn grow(&mut self, container: &Value, old_size: usize, span: Span) -> Result<(), RuntimeError>
+    {
+        self.allocate_bytes(allocation_size(container).saturating_sub(old_size), span)
+    }
+
+    fn allocate_bytes(&mut self, bytes: usize, span: Span) -> Result<(), RuntimeError>
+    {
+        self.memory_used = self.memory_used.saturating_add(bytes);
+        if let Some(max_memory) = self.limits.max_memory
+        {
+            if self.memory_used > max_memory
+            {
+                return Err(RuntimeError::limit_exceeded(Limit::Memory,
+                    format!("The script used more than {} bytes of memory", max_memory), span));
+            }
+        }
+        return Ok(());
+    }
+}
+
+//The memory that a value owns directly. Elements that are containers themselves
+//were counted when they were created
+pub fn allocation_size(value: &Value) -> usize
+{
+    match *value
+    {
+        Value::String(ref s) => s.len(),
+        Value::List(ref elements) => elements.borrow().len() * mem::size_of::<Value>(),
+        Value::Dictionary(ref dictionary) => dictionary.borrow().len() * 2 * mem::size_of::<Value>(),
+        Value::Object(ref object) => object.borrow().fields.len() * mem::size_of::<(String, Value)>(),
+        _ => 0
+    }
+}
+
+#[cfg(test)]
+mod limits_tests
+{
+    use limits::*;
+
+    #[test]
+    fn fuel_runs_out()
+    {
+        let mut budget = Budget::new(Limits{fuel: Some(2), ..Limits::unlimited()});
+        assert!(budget.step(Span::default()).is_ok());
+        assert!(budget.step(Span::default()).is_ok());
+        assert_eq!(budget.step(Span::default()).unwrap_err().limit, Some(Limit::Fuel));
+    }
+
+    #[test]
+    fn memory_is_counted()
+    {
+        let mut budget = Budget::new(Limits{max_memory: Some(10), ..Limits::unlimited()});
+        assert!(budget.allocate(&Value::String(String::from("12345")), Span::default()).is_ok());
+        assert!(budget.allocate(&Value::integer(5), Span::default()).is_ok());
+        let error = budget.allocate(&Value::String(String::from("123456")), Span::default()).unwrap_err();
+        assert_eq!(error.limit, Some(Limit::Memory));
+        assert_eq!(error.message, "The script used more than 10 bytes of memory");
+    }
+}
@@ -0,0 +1,705 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{self, json, Value};
+
+use analysis::{self, Analysis, CompletionKind, DocumentSymbol, SemanticToken, SymbolKind, SEMANTIC_KINDS};
+use builtins::Builtins;
+use capabilities::Capabilities;
+use diagnostic::{Diagnostic, Severity};
+use token::Span;
+use validation::ValidationOptions;
+
+/*
+ * A language server that editors talk to with JSON-RPC over stdin and stdout.
+ * It keeps the open documents up to date with the changes the editor sends and
+ * answers questions about them with what the checks found out
+ */
+
+//Error codes defined by JSON-RPC and the language server protocol
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+
+//Documents are only sent as a whole when they are opened, after that the
+//editor sends the ranges that changed
+const INCREMENTAL_SYNC: i64 = 2;
+
+type RequestResult = Result<Value, (i64, String)>;
+
+//Reads messages from input and answers them on output until the editor says
+//exit. Returns the exit code for the server
+pub fn run(input: &mut dyn BufRead, output: &mut dyn Write) -> i32
+{
+    let mut server = Server::new();
+    loop
+    {
+        let body = match read_message(input)
+        {
+            Ok(Some(body)) => body,
+            //The editor went away without asking the server to exit
+            Ok(None) => return 1,
+            Err(e) => {
+                let _ = writeln!(io::stderr(), "boa-lsp: {}", e);
+                return 1;
+            }
+        };
+        let replies = match serde_json::from_str::<Value>(&body)
+        {
+            Ok(message) => server.handle(&message),
+            Err(e) => vec!(error_response(Value::Null, PARSE_ERROR, e.to_string())),
+        };
+        for reply in replies
+        {
+            if write_message(output, &reply).is_err()
+            {
+                return 1;
+            }
+        }
+        if let Some(code) = server.exit_code()
+        {
+            return code;
+        }
+    }
+}
+
+//Reads the body of the next message, or None at the end of the input
+pub fn read_message(input: &mut dyn BufRead) -> io::Result<Option<String>>
+{
+    let mut length = None;
+    loop
+    {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0
+        {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty()
+        {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:")
+        {
+            let value = value.trim().parse::<usize>()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid header {}", header)))?;
+            length = Some(value);
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "A message has no Content-Length"))?;
+    let mut body = vec!(0; length);
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "A message is not UTF-8"))
+}
+
+pub fn write_message(output: &mut dyn Write, message: &Value) -> io::Result<()>
+{
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+pub struct Server
+{
+    builtins: Builtins,
+    documents: HashMap<String, Analysis>,
+    shutdown: bool,
+    exit_code: Option<i32>,
+}
+
+impl Server
+{
+    //Documents are checked with every capability, like scripts started with the boa command
+    pub fn new() -> Server
+    {
+        let mut builtins = Builtins::new();
+        builtins.set_capabilities(Capabilities::all());
+        Server {
+            builtins: builtins,
+            documents: HashMap::new(),
+            shutdown: false,
+            exit_code: None,
+        }
+    }
+
+    //Some once the editor has told the server to exit. The exit code is 1 if
+    //the editor didn't ask the server to shut down first
+    pub fn exit_code(&self) -> Option<i32>
+    {
+        self.exit_code
+    }
+
+    //The text of an open document
+    pub fn document(&self, uri: &str) -> Option<&str>
+    {
+        self.documents.get(uri).map(|document| document.code.as_str())
+    }
+
+    //Handles a message from the editor and returns the messages to send back
+    pub fn handle(&mut self, message: &Value) -> Vec<Value>
+    {
+        let method = match message["method"].as_str()
+        {
+            Some(method) => method,
+            //Replies to requests from the server, which it doesn't send
+            None => return Vec::new()
+        };
+        let params = &message["params"];
+        match message.get("id")
+        {
+            Some(id) => {
+                let result = if self.shutdown && method != "shutdown"
+                {
+                    Err((INVALID_REQUEST, String::from("The server is shutting down")))
+                }
+                else
+                {
+                    self.request(method, params)
+                };
+                let response = match result
+                {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                    Err((code, message)) => error_response(id.clone(), code, message),
+                };
+                vec!(response)
+            }
+            None => self.notification(method, params),
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> RequestResult
+    {
+        match method
+        {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": {"openClose": true, "change": INCREMENTAL_SYNC},
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": {"triggerCharacters": ["."]},
+                    "semanticTokensProvider": {
+                        "legend": {
+                            "tokenTypes": SEMANTIC_KINDS.iter().map(|kind| kind.name()).collect::<Vec<_>>(),
+                            "tokenModifiers": ["declaration"],
+                        },
+                        "full": true,
+                    },
+                },
+                "serverInfo": {"name": "boa-lsp", "version": env!("CARGO_PKG_VERSION")},
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => {
+                let (document, offset) = self.position(params)?;
+                let code = &document.code;
+                Ok(match document.hover(offset)
+                {
+                    Some((span, description)) => json!({
+                        "contents": {"kind": "markdown", "value": format!("```boa\n{}\n```", description)},
+                        "range": range(code, span),
+                    }),
+                    None => Value::Null
+                })
+            }
+            "textDocument/definition" => {
+                let (analysis, offset) = self.position(params)?;
+                let definition = analysis.symbol_at(offset).and_then(|o| analysis.definition(&o.symbol));
+                Ok(match definition
+                {
+                    Some(span) => location(uri(params)?, &analysis.code, span),
+                    None => Value::Null
+                })
+            }
+            "textDocument/references" => {
+                let uri = uri(params)?;
+                let (analysis, offset) = self.position(params)?;
+                let include_declaration = params["context"]["includeDeclaration"].as_bool().unwrap_or(true);
+                let occurrence = match analysis.symbol_at(offset)
+                {
+                    Some(occurrence) => occurrence,
+                    None => return Ok(Value::Null)
+                };
+                let definition = analysis.definition(&occurrence.symbol);
+                let locations: Vec<Value> = analysis.references(&occurrence.symbol).into_iter()
+                    .filter(|span| include_declaration || Some(*span) != definition)
+                    .map(|span| location(uri, &analysis.code, span))
+                    .collect();
+                Ok(Value::from(locations))
+            }
+            "textDocument/documentSymbol" => {
+                let document = self.document_of(params)?;
+                let code = &document.code;
+                let symbols: Vec<Value> = document.document_symbols().iter()
+                    .map(|symbol| document_symbol(code, symbol))
+                    .collect();
+                Ok(Value::from(symbols))
+            }
+            "textDocument/completion" => {
+                let (document, offset) = self.position(params)?;
+                let items: Vec<Value> = document.completions(offset, &self.builtins).into_iter()
+                    .map(|completion| json!({
+                        "label": completion.label,
+                        "kind": completion_kind(completion.kind),
+                        "detail": completion.detail,
+                    }))
+                    .collect();
+                Ok(Value::from(items))
+            }
+            "textDocument/semanticTokens/full" => {
+                let document = self.document_of(params)?;
+                Ok(json!({"data": semantic_token_data(&document.code, &document.semantic_tokens())}))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method)))
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value>
+    {
+        match method
+        {
+            "exit" => {
+                self.exit_code = Some(if self.shutdown {0} else {1});
+                Vec::new()
+            }
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                match (document["uri"].as_str(), document["text"].as_str())
+                {
+                    (Some(uri), Some(text)) => {
+                        let version = document["version"].as_i64().unwrap_or(0);
+                        self.update(uri, version, String::from(text))
+                    }
+                    _ => Vec::new()
+                }
+            }
+            "textDocument/didChange" => {
+                let uri = match params["textDocument"]["uri"].as_str()
+                {
+                    Some(uri) => uri,
+                    None => return Vec::new()
+                };
+                let mut text = match self.documents.get(uri)
+                {
+                    Some(document) => document.code.clone(),
+                    None => return Vec::new()
+                };
+                let changes = params["contentChanges"].as_array().cloned().unwrap_or_default();
+                for change in changes
+                {
+                    let new_text = change["text"].as_str().unwrap_or("");
+                    text = match change.get("range")
+                    {
+                        Some(change_range) => {
+                            let start = offset_at(&text, &change_range["start"]);
+                            let end = offset_at(&text, &change_range["end"]).max(start);
+                            format!("{}{}{}", &text[..start], new_text, &text[end..])
+                        }
+                        None => String::from(new_text)
+                    };
+                }
+                let version = params["textDocument"]["version"].as_i64().unwrap_or(0);
+                self.update(uri, version, text)
+            }
+            "textDocument/didClose" => {
+                match params["textDocument"]["uri"].as_str()
+                {
+                    Some(uri) => {
+                        self.documents.remove(uri);
+                        vec!(publish_diagnostics(uri, None, &[], ""))
+                    }
+                    None => Vec::new()
+                }
+            }
+            //initialized and everything the server doesn't support
+            _ => Vec::new()
+        }
+    }
+
+    //Checks the new text of a document and sends what was found to the editor
+    fn update(&mut self, uri: &str, version: i64, text: String) -> Vec<Value>
+    {
+        //Documents can be libraries so they don't need a main function
+        let analysis = analysis::analyse(&text, &self.builtins, &ValidationOptions::library());
+        let diagnostics = publish_diagnostics(uri, Some(version), &analysis.diagnostics, &text);
+        self.documents.insert(String::from(uri), analysis);
+        vec!(diagnostics)
+    }
+
+    fn document_of(&self, params: &Value) -> Result<&Analysis, (i64, String)>
+    {
+        let uri = uri(params)?;
+        self.documents.get(uri).ok_or_else(|| (INVALID_PARAMS, format!("{} is not open", uri)))
+    }
+
+    //The document and the offset of the position in a request
+    fn position(&self, params: &Value) -> Result<(&Analysis, usize), (i64, String)>
+    {
+        let document = self.document_of(params)?;
+        if !params["position"].is_object()
+        {
+            return Err((INVALID_PARAMS, String::from("The request has no position")));
+        }
+        let offset = offset_at(&document.code, &params["position"]);
+        return Ok((document, offset));
+    }
+}
+
+fn uri(params: &Value) -> Result<&str, (i64, String)>
+{
+    params["textDocument"]["uri"].as_str().ok_or_else(|| (INVALID_PARAMS, String::from("The request has no document")))
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value
+{
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+fn publish_diagnostics(uri: &str, version: Option<i64>, diagnostics: &[Diagnostic], code: &str) -> Value
+{
+    let diagnostics: Vec<Value> = diagnostics.iter().map(|diagnostic| {
+        let mut message = diagnostic.message.clone();
+        for note in &diagnostic.notes
+        {
+            message.push_str(&format!("\nnote: {}", note));
+        }
+        let related: Vec<Value> = diagnostic.labels.iter()
+            .map(|label| json!({"location": location(uri, code, label.span), "message": label.message}))
+            .collect();
+        json!({
+            "range": range(code, diagnostic.span),
+            "severity": match diagnostic.severity { Severity::Error => 1, Severity::Warning => 2 },
+            "code": diagnostic.code,
+            "source": "boa",
+            "message": message,
+            "relatedInformation": related,
+        })
+    }).collect();
+    let mut params = json!({"uri": uri, "diagnostics": diagnostics});
+    if let Some(version) = version
+    {
+        params["version"] = json!(version);
+    }
+    json!({"jsonrpc": "2.0", "method": "textDocument/publishDiagnostics", "params": params})
+}
+
+fn document_symbol(code: &str, symbol: &DocumentSymbol) -> Value
+{
+    let kind = match symbol.kind
+    {
+        SymbolKind::Function => 12,
+        SymbolKind::Type => 23,
+        SymbolKind::Field => 8,
+        SymbolKind::Implement => 3,
+        SymbolKind::Method => 6,
+        SymbolKind::Variable => 13,
+    };
+    let children: Vec<Value> = symbol.children.iter().map(|child| document_symbol(code, child)).collect();
+    json!({
+        "name": symbol.name,
+        "detail": symbol.detail,
+        "kind": kind,
+        "range": range(code, symbol.span),
+        "selectionRange": range(code, symbol.name_span),
+        "children": children,
+    })
+}
+
+fn completion_kind(kind: CompletionKind) -> i64
+{
+    match kind
+    {
+        CompletionKind::Method => 2,
+        CompletionKind::Function => 3,
+        CompletionKind::Field => 5,
+        CompletionKind::Variable => 6,
+        CompletionKind::Keyword => 14,
+        CompletionKind::Type => 22,
+    }
+}
+
+//Semantic tokens are sent as five numbers each: the line relative to the previous
+//token, the character relative to the previous token if it is on the same line,
+//the length, the index of the kind and the modifiers. Tokens that span several
+//lines are split into one token for each line
+fn semantic_token_data(code: &str, tokens: &[SemanticToken]) -> Vec<u64>
+{
+    let mut data = Vec::new();
+    let mut previous = (0, 0);
+    let mut line = 0;
+    let mut line_start = 0;
+    let mut scanned = 0;
+    for token in tokens
+    {
+        for (i, c) in code[scanned..token.span.start].char_indices()
+        {
+            if c == '\n'
+            {
+                line += 1;
+                line_start = scanned + i + 1;
+            }
+        }
+
+        let kind = SEMANTIC_KINDS.iter().position(|kind| *kind == token.kind).unwrap_or(0) as u64;
+        let modifiers = if token.declaration {1} else {0};
+        let mut part_start = token.span.start;
+        for (i, part) in code[token.span.start..token.span.end].split('\n').enumerate()
+        {
+            if i > 0
+            {
+                line += 1;
+                line_start = part_start;
+            }
+            let character = utf16_length(&code[line_start..part_start]);
+            let length = utf16_length(part);
+            if length > 0
+            {
+                let delta_character = if line == previous.0 {character - previous.1} else {character};
+                data.extend_from_slice(&[(line - previous.0) as u64, delta_character as u64, length as u64, kind, modifiers]);
+                previous = (line, character);
+            }
+            part_start += part.len() + 1;
+        }
+        scanned = token.span.end;
+    }
+    return data;
+}
+
+fn utf16_length(text: &str) -> usize
+{
+    text.chars().map(|c| c.len_utf16()).sum()
+}
+
+fn location(uri: &str, code: &str, span: Span) -> Value
+{
+    json!({"uri": uri, "range": range(code, span)})
+}
+
+fn range(code: &str, span: Span) -> Value
+{
+    json!({"start": position_at(code, span.start), "end": position_at(code, span.end)})
+}
+
+//Positions count lines from 0 and characters in UTF-16 code units
+pub fn position_at(code: &str, offset: usize) -> Value
+{
+    let offset = offset.min(code.len());
+    let line_start = code[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = code[..line_start].matches('\n').count();
+    json!({"line": line, "character": utf16_length(&code[line_start..offset])})
+}
+
+//The byte offset of a position. Positions past the end of a line are at its end
+pub fn offset_at(code: &str, position: &Value) -> usize
+{
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+
+    let mut line_start = 0;
+    for _ in 0..line
+    {
+        match code[line_start..].find('\n')
+        {
+            Some(i) => line_start += i + 1,
+            None => return code.len()
+        }
+    }
+    let mut units = 0;
+    for (i, c) in code[line_start..].char_indices()
+    {
+        if units >= character || c == '\n'
+        {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    return code.len();
+}
+
+#[cfg(test)]
+mod lsp_tests
+{
+    use std::io::Cursor;
+
+    use lsp::*;
+
+    fn frame(messages: &[Value]) -> Vec<u8>
+    {
+        let mut input = Vec::new();
+        for message in messages
+        {
+            write_message(&mut input, message).unwrap();
+        }
+        return input;
+    }
+
+    //Runs the server on a script of messages and returns the exit code and
+    //everything the server sent back
+    fn run_script(messages: &[Value]) -> (i32, Vec<Value>)
+    {
+        let mut input = Cursor::new(frame(messages));
+        let mut output = Vec::new();
+        let code = run(&mut input, &mut output);
+
+        let mut output = Cursor::new(output);
+        let mut replies = Vec::new();
+        while let Some(body) = read_message(&mut output).unwrap()
+        {
+            replies.push(serde_json::from_str(&body).unwrap());
+        }
+        return (code, replies);
+    }
+
+    fn request(id: i64, method: &str, params: Value) -> Value
+    {
+        json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+    }
+
+    fn notification(method: &str, params: Value) -> Value
+    {
+        json!({"jsonrpc": "2.0", "method": method, "params": params})
+    }
+
+    fn position(line: usize, character: usize) -> Value
+    {
+        json!({"textDocument": {"uri": URI}, "position": {"line": line, "character": character}})
+    }
+
+    fn reply(replies: &[Value], id: i64) -> &Value
+    {
+        &replies.iter().find(|r| r["id"] == json!(id)).unwrap()["result"]
+    }
+
+    const URI: &'static str = "file:///test.boa";
+
+    const CODE: &'static str = "\
+def Number add(Number a, Number b)
+{
+    return a + c;
+}
+def main()
+{
+    print(add(1, 2));
+}";
+
+    #[test]
+    fn positions()
+    {
+        let code = "a\n\"é😀\" b";
+        let b = code.find('b').unwrap();
+        assert_eq!(position_at(code, b), json!({"line": 1, "character": 6}));
+        assert_eq!(offset_at(code, &json!({"line": 1, "character": 6})), b);
+        assert_eq!(offset_at(code, &json!({"line": 0, "character": 10})), 1);
+        assert_eq!(offset_at(code, &json!({"line": 5, "character": 0})), code.len());
+    }
+
+    #[test]
+    fn scripted_session()
+    {
+        let (code, replies) = run_script(&[
+            request(1, "initialize", json!({"capabilities": {}})),
+            notification("initialized", json!({})),
+            notification("textDocument/didOpen", json!({
+                "textDocument": {"uri": URI, "languageId": "boa", "version": 1, "text": CODE}
+            })),
+            //c is replaced by b, then a is replaced by the longer name first
+            notification("textDocument/didChange", json!({
+                "textDocument": {"uri": URI, "version": 2},
+                "contentChanges": [
+                    {"range": {"start": {"line": 2, "character": 15}, "end": {"line": 2, "character": 16}}, "text": "b"},
+                    {"range": {"start": {"line": 2, "character": 11}, "end": {"line": 2, "character": 12}}, "text": "first"},
+                    {"range": {"start": {"line": 0, "character": 22}, "end": {"line": 0, "character": 23}}, "text": "first"},
+                ]
+            })),
+            request(2, "textDocument/hover", position(2, 13)),
+            request(3, "textDocument/definition", position(6, 11)),
+            request(4, "textDocument/references", json!({
+                "textDocument": {"uri": URI}, "position": {"line": 0, "character": 12},
+                "context": {"includeDeclaration": false}
+            })),
+            request(5, "textDocument/documentSymbol", json!({"textDocument": {"uri": URI}})),
+            request(6, "textDocument/completion", position(2, 11)),
+            request(7, "textDocument/formatting", json!({"textDocument": {"uri": URI}})),
+            request(8, "shutdown", Value::Null),
+            notification("exit", Value::Null),
+        ]);
+        assert_eq!(code, 0);
+
+        let capabilities = &reply(&replies, 1)["capabilities"];
+        assert_eq!(capabilities["textDocumentSync"]["change"], json!(2));
+        assert_eq!(capabilities["hoverProvider"], json!(true));
+
+        let diagnostics: Vec<&Value> = replies.iter()
+            .filter(|r| r["method"] == json!("textDocument/publishDiagnostics"))
+            .collect();
+        assert_eq!(diagnostics.len(), 2);
+        let first = &diagnostics[0]["params"]["diagnostics"][0];
+        assert_eq!(first["code"], json!("E0200"));
+        assert_eq!(first["range"], json!({"start": {"line": 2, "character": 15}, "end": {"line": 2, "character": 16}}));
+        assert_eq!(diagnostics[1]["params"]["version"], json!(2));
+        assert_eq!(diagnostics[1]["params"]["diagnostics"], json!([]));
+
+        assert_eq!(reply(&replies, 2)["contents"]["value"], json!("```boa\nNumber first\n```"));
+        assert_eq!(reply(&replies, 3), &json!({
+            "uri": URI,
+            "range": {"start": {"line": 0, "character": 11}, "end": {"line": 0, "character": 14}}
+        }));
+        assert_eq!(reply(&replies, 4), &json!([{
+            "uri": URI,
+            "range": {"start": {"line": 6, "character": 10}, "end": {"line": 6, "character": 13}}
+        }]));
+
+        let symbols = reply(&replies, 5).as_array().unwrap();
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols[0]["name"], json!("add"));
+        assert_eq!(symbols[0]["kind"], json!(12));
+
+        let labels: Vec<&Value> = reply(&replies, 6).as_array().unwrap().iter().map(|item| &item["label"]).collect();
+        assert!(labels.contains(&&json!("first")) && labels.contains(&&json!("b")));
+        assert!(labels.contains(&&json!("main")));
+
+        let unknown = replies.iter().find(|r| r["id"] == json!(7)).unwrap();
+        assert_eq!(unknown["error"]["code"], json!(METHOD_NOT_FOUND));
+    }
+
+    #[test]
+    fn semantic_tokens()
+    {
+        let mut server = Server::new();
+        server.handle(&notification("textDocument/didOpen", json!({
+            "textDocument": {"uri": URI, "version": 1, "text": "def f()\n{ /*a\nb*/ return; }"}
+        })));
+        let replies = server.handle(&request(1, "textDocument/semanticTokens/full", json!({"textDocument": {"uri": URI}})));
+        let keyword = 0;
+        let comment = 1;
+        let function = 6;
+        assert_eq!(replies[0]["result"]["data"], json!([
+            0, 0, 3, keyword, 0,
+            0, 4, 1, function, 1,
+            1, 2, 3, comment, 0,
+            1, 0, 3, comment, 0,
+            0, 4, 6, keyword, 0,
+        ]));
+    }
+
+    #[test]
+    fn exit_without_shutdown()
+    {
+        let (code, replies) = run_script(&[notification("exit", Value::Null)]);
+        assert_eq!(code, 1);
+        assert!(replies.is_empty());
+
+        let mut input = Cursor::new(b"Content-Length: 5\r\n\r\n{oops".to_vec());
+        let mut output = Vec::new();
+        assert_eq!(run(&mut input, &mut output), 1);
+        let body = read_message(&mut Cursor::new(output)).unwrap().unwrap();
+        let reply: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(reply["error"]["code"], json!(PARSE_ERROR));
+    }
+}
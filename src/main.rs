@@ -1,15 +1,18 @@
-#![allow(dead_code)]
-#![allow(unused_variables)]
+extern crate boa;
 
-extern crate regex;
+use std::env;
+use std::io;
+use std::process;
 
-mod variable;
-mod script_state;
-mod lexer;
-mod parser;
-mod token;
+use boa::limits::run_with_stack;
 
-pub fn main() 
+pub fn main()
 {
+    let args: Vec<String> = env::args().skip(1).collect();
+    //Scripts get a thread with a big stack, so that they can make as many calls as their limits allow
+    let exit_code = run_with_stack(move || {
+        let stdin = io::stdin();
+        boa::cli::main(&args, &mut stdin.lock(), &mut io::stdout(), &mut io::stderr())
+    });
+    process::exit(exit_code);
 }
-
@@ -0,0 +1,366 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/*
+ * The value of a Number in a script. Integers are kept exact as long as they fit in
+ * 64 bits. Fractional values, and integers that get too large, are floating point
+ * numbers. Both forms are the same Number type to the type checker
+ */
+#[derive(Clone,Copy,Debug)]
+pub enum Number
+{
+    Integer(i64),
+    Float(f64),
+}
+
+pub type NumberResult = Result<Number, String>;
+
+//2^63, the first float above the largest i64
+const I64_LIMIT: f64 = 9223372036854775808.0;
+
+//add, sub and the others return a NumberResult because they can fail, so they
+//can't implement the operator traits
+#[allow(clippy::should_implement_trait)]
+impl Number
+{
+    //Converts the lexeme of a number literal into its value. Integer literals
+    //that don't fit in 64 bits become floats
+    pub fn parse(lexeme: &str) -> Option<Number>
+    {
+        if let Some(hex) = lexeme.strip_prefix("0x")
+        {
+            return i64::from_str_radix(hex, 16).ok().map(Number::Integer);
+        }
+        if !lexeme.contains('.')
+        {
+            if let Ok(integer) = lexeme.parse()
+            {
+                return Some(Number::Integer(integer));
+            }
+        }
+        lexeme.parse().ok().and_then(|f| Number::float(f).ok())
+    }
+
+    //A float result of a calculation. Infinity and NaN are not numbers a script
+    //can work with so they are reported as errors
+    pub fn float(value: f64) -> NumberResult
+    {
+        if value.is_finite()
+        {
+            Ok(Number::Float(value))
+        }
+        else
+        {
+            Err(String::from("The result is too large to be represented as a Number"))
+        }
+    }
+
+    pub fn as_f64(&self) -> f64
+    {
+        match *self
+        {
+            Number::Integer(i) => i as f64,
+            Number::Float(f) => f,
+        }
+    }
+
+    //The value as an integer if it is a whole number that fits in 64 bits
+    pub fn as_i64(&self) -> Option<i64>
+    {
+        match *self
+        {
+            Number::Integer(i) => Some(i),
+            Number::Float(f) if f.fract() == 0.0 && (-I64_LIMIT..I64_LIMIT).contains(&f) => Some(f as i64),
+            Number::Float(_) => None,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool
+    {
+        self.as_f64() == 0.0
+    }
+
+    pub fn is_integer(&self) -> bool
+    {
+        match *self
+        {
+            Number::Integer(_) => true,
+            Number::Float(_) => false,
+        }
+    }
+
+    //Integer arithmetic is checked. Results that don't fit in 64 bits are
+    //calculated again using floats
+    pub fn add(self, other: Number) -> NumberResult
+    {
+        match (self, other)
+        {
+            (Number::Integer(a), Number::Integer(b)) => a.checked_add(b)
+                .map(Number::Integer)
+                .map_or_else(|| Number::float(a as f64 + b as f64), Ok),
+            (a, b) => Number::float(a.as_f64() + b.as_f64())
+        }
+    }
+
+    pub fn sub(self, other: Number) -> NumberResult
+    {
+        match (self, other)
+        {
+            (Number::Integer(a), Number::Integer(b)) => a.checked_sub(b)
+                .map(Number::Integer)
+                .map_or_else(|| Number::float(a as f64 - b as f64), Ok),
+            (a, b) => Number::float(a.as_f64() - b.as_f64())
+        }
+    }
+
+    pub fn mul(self, other: Number) -> NumberResult
+    {
+        match (self, other)
+        {
+            (Number::Integer(a), Number::Integer(b)) => a.checked_mul(b)
+                .map(Number::Integer)
+                .map_or_else(|| Number::float(a as f64 * b as f64), Ok),
+            (a, b) => Number::float(a.as_f64() * b.as_f64())
+        }
+    }
+
+    pub fn neg(self) -> NumberResult
+    {
+        match self
+        {
+            Number::Integer(a) => a.checked_neg()
+                .map(Number::Integer)
+                .map_or_else(|| Number::float(-(a as f64)), Ok),
+            Number::Float(a) => Ok(Number::Float(-a))
+        }
+    }
+
+    //Dividing two integers gives an integer if the division is exact and a
+    //float otherwise, so 6 / 3 is 2 and 7 / 2 is 3.5
+    pub fn div(self, other: Number) -> NumberResult
+    {
+        if other.is_zero()
+        {
+            return Err(String::from("Division by zero"));
+        }
+        match (self, other)
+        {
+            (Number::Integer(a), Number::Integer(b)) => match a.checked_rem(b)
+            {
+                Some(0) => Ok(Number::Integer(a / b)),
+                _ => Number::float(a as f64 / b as f64)
+            },
+            (a, b) => Number::float(a.as_f64() / b.as_f64())
+        }
+    }
+
+    //a // b divides and rounds down towards negative infinity
+    pub fn int_div(self, other: Number) -> NumberResult
+    {
+        if other.is_zero()
+        {
+            return Err(String::from("Division by zero"));
+        }
+        match (self, other)
+        {
+            (Number::Integer(a), Number::Integer(b)) => match a.checked_div(b)
+            {
+                Some(quotient) if a % b != 0 && (a % b < 0) != (b < 0) => Ok(Number::Integer(quotient - 1)),
+                Some(quotient) => Ok(Number::Integer(quotient)),
+                None => Number::float((a as f64 / b as f64).floor())
+            },
+            (a, b) => Number::float((a.as_f64() / b.as_f64()).floor())
+        }
+    }
+
+    //a % b is the remainder of a // b. It has the same sign as b, so that
+    //(a // b) * b + a % b == a
+    pub fn rem(self, other: Number) -> NumberResult
+    {
+        if other.is_zero()
+        {
+            return Err(String::from("Division by zero"));
+        }
+        match (self, other)
+        {
+            (Number::Integer(a), Number::Integer(b)) => {
+                //i64::MIN % -1 overflows but the remainder is 0
+                let remainder = a.checked_rem(b).unwrap_or(0);
+                if remainder != 0 && (remainder < 0) != (b < 0)
+                {
+                    Ok(Number::Integer(remainder + b))
+                }
+                else
+                {
+                    Ok(Number::Integer(remainder))
+                }
+            }
+            (a, b) => {
+                let (a, b) = (a.as_f64(), b.as_f64());
+                let remainder = a % b;
+                if remainder != 0.0 && (remainder < 0.0) != (b < 0.0)
+                {
+                    Number::float(remainder + b)
+                }
+                else
+                {
+                    Number::float(remainder)
+                }
+            }
+        }
+    }
+}
+
+//Compares an integer and a float exactly, without rounding the integer to a float first
+fn compare_mixed(integer: i64, float: f64) -> Ordering
+{
+    if float >= I64_LIMIT
+    {
+        return Ordering::Less;
+    }
+    if float < -I64_LIMIT
+    {
+        return Ordering::Greater;
+    }
+
+    let whole = float.trunc();
+    match integer.cmp(&(whole as i64))
+    {
+        Ordering::Equal => 0.0.partial_cmp(&(float - whole)).unwrap(),
+        ordering => ordering
+    }
+}
+
+impl PartialEq for Number
+{
+    fn eq(&self, other: &Number) -> bool
+    {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Number
+{
+    fn partial_cmp(&self, other: &Number) -> Option<Ordering>
+    {
+        match (*self, *other)
+        {
+            (Number::Integer(a), Number::Integer(b)) => Some(a.cmp(&b)),
+            (Number::Integer(a), Number::Float(b)) => Some(compare_mixed(a, b)),
+            (Number::Float(a), Number::Integer(b)) => Some(compare_mixed(b, a).reverse()),
+            (Number::Float(a), Number::Float(b)) => a.partial_cmp(&b),
+        }
+    }
+}
+
+//Integers are printed with all their digits. Floats are printed with as few digits as
+//are needed to read them back, in scientific notation if they are very large or small
+impl fmt::Display for Number
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self
+        {
+            Number::Integer(i) => write!(f, "{}", i),
+            Number::Float(x) if x != 0.0 && (x.abs() >= 1e16 || x.abs() < 1e-6) => write!(f, "{:e}", x),
+            Number::Float(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod number_tests
+{
+    use number::*;
+
+    fn int(i: i64) -> Number
+    {
+        Number::Integer(i)
+    }
+
+    #[test]
+    fn parsing()
+    {
+        assert!(Number::parse("15").unwrap().is_integer());
+        assert_eq!(Number::parse("15"), Some(int(15)));
+        assert_eq!(Number::parse("0xff"), Some(int(255)));
+        assert!(!Number::parse("1.0").unwrap().is_integer());
+        assert_eq!(Number::parse("0.5"), Some(Number::Float(0.5)));
+        assert_eq!(Number::parse("99999999999999999999"), Some(Number::Float(1e20)));
+    }
+
+    #[test]
+    fn exact_integers()
+    {
+        assert_eq!(int(9007199254740993).add(int(1)).unwrap().to_string(), "9007199254740994");
+        assert!(int(6).div(int(3)).unwrap().is_integer());
+        assert_eq!(int(7).div(int(2)), Ok(Number::Float(3.5)));
+
+        let sum = Number::parse("0.1").unwrap().add(Number::parse("0.2").unwrap()).unwrap();
+        assert!(sum != Number::Float(0.3));
+        assert_eq!(int(1).add(int(2)), Ok(int(3)));
+    }
+
+    #[test]
+    fn overflow_promotes_to_float()
+    {
+        let max = int(i64::MAX);
+        let sum = max.add(int(1)).unwrap();
+        assert!(!sum.is_integer());
+        assert_eq!(sum, Number::Float(9223372036854775808.0));
+        assert!(!max.mul(int(2)).unwrap().is_integer());
+        assert!(!int(i64::MIN).neg().unwrap().is_integer());
+        assert!(!int(i64::MIN).sub(int(1)).unwrap().is_integer());
+
+        assert_eq!(Number::Float(1e308).mul(int(10)), Err(String::from("The result is too large to be represented as a Number")));
+    }
+
+    #[test]
+    fn integer_division_and_modulo()
+    {
+        assert_eq!(int(7).int_div(int(2)), Ok(int(3)));
+        assert_eq!(int(-7).int_div(int(2)), Ok(int(-4)));
+        assert_eq!(int(7).int_div(int(-2)), Ok(int(-4)));
+        assert_eq!(int(7).rem(int(3)), Ok(int(1)));
+        assert_eq!(int(-7).rem(int(3)), Ok(int(2)));
+        assert_eq!(int(7).rem(int(-3)), Ok(int(-2)));
+        assert_eq!(int(i64::MIN).rem(int(-1)), Ok(int(0)));
+        assert_eq!(Number::Float(7.5).int_div(int(2)), Ok(Number::Float(3.0)));
+        assert_eq!(Number::Float(-7.5).rem(int(2)), Ok(Number::Float(0.5)));
+
+        for &(a, b) in &[(7, 2), (-7, 2), (7, -2), (-7, -2), (6, 3)]
+        {
+            let quotient = int(a).int_div(int(b)).unwrap();
+            let remainder = int(a).rem(int(b)).unwrap();
+            assert_eq!(quotient.mul(int(b)).unwrap().add(remainder), Ok(int(a)));
+        }
+
+        let error = Err(String::from("Division by zero"));
+        assert_eq!(int(1).div(int(0)), error);
+        assert_eq!(int(1).int_div(Number::Float(0.0)), error);
+        assert_eq!(int(1).rem(int(0)), error);
+    }
+
+    #[test]
+    fn mixed_comparisons()
+    {
+        assert_eq!(int(1), Number::Float(1.0));
+        assert!(int(1) < Number::Float(1.5));
+        assert!(Number::Float(-0.5) < int(0));
+        assert!(int(-1) < Number::Float(-0.5));
+        //Comparing as floats would round the integer and call these equal
+        assert!(int(9007199254740993) > Number::Float(9007199254740992.0));
+        assert!(int(i64::MAX) < Number::Float(9223372036854775808.0));
+    }
+
+    #[test]
+    fn printing()
+    {
+        assert_eq!(int(-42).to_string(), "-42");
+        assert_eq!(Number::Float(3.5).to_string(), "3.5");
+        assert_eq!(Number::Float(1.0).to_string(), "1");
+        assert_eq!(Number::Float(0.1).add(Number::Float(0.2)).unwrap().to_string(), "0.30000000000000004");
+        assert_eq!(Number::Float(1e20).to_string(), "1e20");
+        assert_eq!(Number::Float(0.0000001).to_string(), "1e-7");
+    }
+}
@@ -1,23 +1,1379 @@
-enum Experession {
-    WhileLoop,
-    ForLoop,
-    IfStatement,
-    FunctionCall,
-
-    Assignment,
-    Addition,
-    Subtraction,
-    Multiplication,
+use std::collections::HashMap;
+
+use ast::*;
+use diagnostic::Diagnostic;
+use error_codes;
+use token::{Token, TokenType, Span};
+
+//Error produced when the token list does not follow the grammar of the language.
+//Boxed because diagnostics are large and every parse function returns a Result
+pub type ParseError = Box<Diagnostic>;
+
+type ParseResult<T> = Result<T, ParseError>;
+
+//How deeply blocks and expressions may be nested. Every pass over the parse tree
+//recurses into nested code, so deeper code is an error instead of a crash. Code at
+//the limit fits on the 2 MB stack of a new thread in release builds, debug builds
+//need limits::run_with_stack
+pub const MAX_NESTING: usize = 256;
+//A chain of operators like 1 + 2 + 3 is written flat, so it doesn't count towards
+//MAX_NESTING. It is still a tree with a level for every operator though, which the
+//passes recurse through, so the levels of an expression are limited separately.
+//Expressions at this limit need limits::run_with_stack
+pub const MAX_EXPRESSION_DEPTH: usize = 10_000;
+
+/*
+ * Recursive descent parser that turns the tokens produced by the lexer into
+ * a parse tree
+ */
+pub struct Parser
+{
+    tokens: Vec<Token>,
+    position: usize,
+    next_id: NodeId,
+    //End of the last consumed token, used for building spans
+    previous_end: usize,
+    //The blocks and expressions that are being parsed around the current token
+    nesting: usize,
+    //How deeply nested the expressions that have been made so far are, and how
+    //many levels their trees have
+    depths: HashMap<NodeId, (usize, usize)>,
 }
 
-struct ParseTree
+pub fn parse_token_list(tokens: Vec<Token>) -> Result<Program, ParseError>
 {
-    
+    Parser::new(tokens).parse_program()
 }
 
-fn parse_token_list(tokens: Vec<Token>) ->  ParseTree 
+impl Parser
 {
-    
+    pub fn new(tokens: Vec<Token>) -> Parser
+    {
+        Parser {
+            //Whitespace and comments are only interesting to the lexer
+            tokens: tokens.into_iter().filter(|t| !t.is_trivia()).collect(),
+            position: 0,
+            next_id: 0,
+            previous_end: 0,
+            nesting: 0,
+            depths: HashMap::new(),
+        }
+    }
+
+    //Allows several parsers to hand out node ids that don't collide, for example
+    //when parsing more code into an existing program
+    pub fn with_first_id(tokens: Vec<Token>, first_id: NodeId) -> Parser
+    {
+        let mut result = Parser::new(tokens);
+        result.next_id = first_id;
+        return result;
+    }
+
+    pub fn next_id(&self) -> NodeId
+    {
+        self.next_id
+    }
+
+    pub fn parse_program(&mut self) -> ParseResult<Program>
+    {
+        let mut items = Vec::new();
+        while !self.at_end()
+        {
+            items.push(self.parse_item()?);
+        }
+        return Ok(Program{items: items});
+    }
+
+    pub fn parse_item(&mut self) -> ParseResult<Item>
+    {
+        if self.check_keyword("def")
+        {
+            let function = self.parse_function()?;
+            if function.operator.is_some()
+            {
+                return Err(Box::new(Diagnostic::error(error_codes::SYNTAX_ERROR,
+                    String::from("Operators can only be declared in implement blocks"), function.name_span)));
+            }
+            if let Some(receiver) = function.receiver
+            {
+                return Err(Box::new(Diagnostic::error(error_codes::SYNTAX_ERROR,
+                        String::from("Only functions in implement blocks can take self"), receiver)
+                    .with_note(String::from("self is the instance that a method is called on"))));
+            }
+            Ok(Item::Function(function))
+        }
+        else if self.check_keyword("type")
+        {
+            Ok(Item::Type(self.parse_type_decl()?))
+        }
+        else if self.check_keyword("implement")
+        {
+            Ok(Item::Implement(self.parse_implement()?))
+        }
+        else
+        {
+            Ok(Item::Statement(self.parse_statement()?))
+        }
+    }
+
+    //Parses an expression followed by the end of the input
+    pub fn parse_lone_expression(&mut self) -> ParseResult<Expr>
+    {
+        let expr = self.parse_expression()?;
+        if !self.at_end()
+        {
+            return Err(Box::new(self.error_here("Expected end of expression")));
+        }
+        return Ok(expr);
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    //                      Declarations
+    ////////////////////////////////////////////////////////////////////////////
+    fn parse_function(&mut self) -> ParseResult<FunctionDecl>
+    {
+        let start = self.expect_keyword("def")?.span();
+
+        //The return type is optional so the first thing we find is either the
+        //return type or the name of the function
+        let mut operator = None;
+        if self.check_keyword("operator")
+        {
+            //Operators have to return something, the type checker reports it
+            let (overloaded, span) = self.parse_operator()?;
+            operator = Some(overloaded);
+            return self.parse_function_rest(start, None, format!("operator {}", overloaded.symbol()), span, operator);
+        }
+        let first = self.parse_type_expr()?;
+        let (return_type, name, name_span) = if self.check(TokenType::OpenPar)
+        {
+            if !first.args.is_empty()
+            {
+                return Err(Box::new(Diagnostic::error(error_codes::SYNTAX_ERROR,
+                    String::from("Expected a function name after the return type"), first.span)));
+            }
+            (None, first.name, first.span)
+        }
+        else if self.check_keyword("operator")
+        {
+            let (overloaded, span) = self.parse_operator()?;
+            operator = Some(overloaded);
+            (Some(first), format!("operator {}", overloaded.symbol()), span)
+        }
+        else
+        {
+            let name = self.expect(TokenType::Identifier, "Expected a function name")?;
+            (Some(first), String::from(name.lexeme()), name.span())
+        };
+        self.parse_function_rest(start, return_type, name, name_span, operator)
+    }
+
+    //The parameters and body of a function, after its name
+    fn parse_function_rest(&mut self, start: Span, return_type: Option<TypeExpr>, name: String, name_span: Span,
+        mut operator: Option<Operator>) -> ParseResult<FunctionDecl>
+    {
+        self.expect(TokenType::OpenPar, "Expected ( after the function name")?;
+        //Methods take the instance they are called on as self before the other parameters
+        let receiver = if self.check_keyword("self") {Some(self.advance().span())} else {None};
+        let mut params = Vec::new();
+        if !self.check(TokenType::ClosePar) && (receiver.is_none() || self.eat(TokenType::Comma))
+        {
+            loop
+            {
+                let type_expr = self.parse_type_expr()?;
+                let name = self.expect(TokenType::Identifier, "Expected a parameter name")?;
+                params.push(Param{
+                    span: type_expr.span.to(name.span()),
+                    type_expr: type_expr,
+                    name: String::from(name.lexeme()),
+                });
+
+                if !self.eat(TokenType::Comma)
+                {
+                    break;
+                }
+            }
+        }
+        self.expect(TokenType::ClosePar, "Expected ) after the function parameters")?;
+        if operator == Some(Operator::Binary(BinaryOp::Sub)) && params.len() == 1
+        {
+            operator = Some(Operator::Negate);
+        }
+
+        let body = self.parse_block()?;
+
+        Ok(FunctionDecl {
+            name: name,
+            name_span: name_span,
+            return_type: return_type,
+            params: params,
+            span: start.to(body.span),
+            body: body,
+            visibility: Visibility::Public,
+            receiver: receiver,
+            operator: operator,
+        })
+    }
+
+    //operator followed by the operator that the function overloads
+    fn parse_operator(&mut self) -> ParseResult<(Operator, Span)>
+    {
+        let start = self.expect_keyword("operator")?.span();
+        if self.check_keyword("if")
+        {
+            return Ok((Operator::Condition, start.to(self.advance().span())));
+        }
+        if !self.check(TokenType::Operator)
+        {
+            return Err(Box::new(self.error_here("Expected the operator to overload")));
+        }
+        let token = self.advance();
+        let span = start.to(token.span());
+        match BinaryOp::from_operator(token.lexeme())
+        {
+            Some(BinaryOp::NotEqual) => Err(Box::new(Diagnostic::error(error_codes::SYNTAX_ERROR,
+                    String::from("The operator != can not be overloaded"), span)
+                .with_note(String::from("!= is always the opposite of ==, overload == instead")))),
+            Some(BinaryOp::And) | Some(BinaryOp::Or) => Err(Box::new(Diagnostic::error(error_codes::SYNTAX_ERROR,
+                    format!("The operator {} can not be overloaded", token.lexeme()), span)
+                .with_note(String::from("& and | use operator if of their operands")))),
+            Some(op) => Ok((Operator::Binary(op), span)),
+            None => Err(Box::new(Diagnostic::error(error_codes::SYNTAX_ERROR,
+                format!("The operator {} can not be overloaded", token.lexeme()), span)))
+        }
+    }
+
+    fn parse_type_decl(&mut self) -> ParseResult<TypeDecl>
+    {
+        let start = self.expect_keyword("type")?.span();
+        let name = self.expect(TokenType::Identifier, "Expected a type name")?;
+        self.expect(TokenType::OpenCurl, "Expected { after the type name")?;
+
+        let mut fields = Vec::new();
+        let mut visibility = Visibility::Public;
+        while !self.check(TokenType::CloseCurl)
+        {
+            if let Some(new_visibility) = self.parse_visibility()?
+            {
+                visibility = new_visibility;
+                continue;
+            }
+
+            let hidden = if self.check_keyword("hidden") {Some(self.advance().span())} else {None};
+            if let (Some(span), Visibility::Public) = (hidden, visibility)
+            {
+                return Err(Box::new(Diagnostic::error(error_codes::SYNTAX_ERROR, String::from("Only private fields can be hidden"), span)
+                    .with_note(String::from("hidden stops the getter and setter of a private field from being generated"))));
+            }
+            let type_expr = self.parse_type_expr()?;
+            let field_name = self.expect(TokenType::Identifier, "Expected a field name")?;
+            self.eat(TokenType::EndStatement);
+            fields.push(FieldDecl{
+                span: hidden.unwrap_or(type_expr.span).to(field_name.span()),
+                type_expr: type_expr,
+                name: String::from(field_name.lexeme()),
+                visibility: visibility,
+                hidden: hidden.is_some(),
+            });
+        }
+        let end = self.expect(TokenType::CloseCurl, "Expected } at the end of the type")?;
+
+        Ok(TypeDecl{
+            name: String::from(name.lexeme()),
+            name_span: name.span(),
+            fields: fields,
+            span: start.to(end.span()),
+        })
+    }
+
+    fn parse_implement(&mut self) -> ParseResult<ImplementBlock>
+    {
+        let start = self.expect_keyword("implement")?.span();
+        self.expect_keyword("for")?;
+        let name = self.expect(TokenType::Identifier, "Expected the name of a type")?;
+        self.expect(TokenType::OpenCurl, "Expected { after the type name")?;
+
+        let mut functions = Vec::new();
+        let mut visibility = Visibility::Public;
+        while !self.check(TokenType::CloseCurl)
+        {
+            if let Some(new_visibility) = self.parse_visibility()?
+            {
+                visibility = new_visibility;
+                continue;
+            }
+            if !self.check_keyword("def")
+            {
+                return Err(Box::new(self.error_here("Only functions can be declared in implement blocks")));
+            }
+            let mut function = self.parse_function()?;
+            function.visibility = visibility;
+            functions.push(function);
+        }
+        let end = self.expect(TokenType::CloseCurl, "Expected } at the end of the implement block")?;
+
+        Ok(ImplementBlock{
+            type_name: String::from(name.lexeme()),
+            type_span: name.span(),
+            functions: functions,
+            span: start.to(end.span()),
+        })
+    }
+
+    //Parses public: or private: which changes the visibility of the members that follow
+    fn parse_visibility(&mut self) -> ParseResult<Option<Visibility>>
+    {
+        if !self.check_keyword("public") && !self.check_keyword("private")
+        {
+            return Ok(None);
+        }
+        let keyword = self.advance();
+        self.expect(TokenType::Colon, "Expected : after the visibility")?;
+        Ok(Some(if keyword.lexeme() == "public" {Visibility::Public} else {Visibility::Private}))
+    }
+
+    //Types look like Name or Name<Arg1, Arg2>
+    fn parse_type_expr(&mut self) -> ParseResult<TypeExpr>
+    {
+        let name = self.expect(TokenType::Identifier, "Expected a type")?;
+        let mut args = Vec::new();
+        let mut span = name.span();
+        if self.check_operator("<")
+        {
+            self.advance();
+            loop
+            {
+                args.push(self.nested(|parser| parser.parse_type_expr())?);
+                if !self.eat(TokenType::Comma)
+                {
+                    break;
+                }
+            }
+            if !self.check_operator(">")
+            {
+                return Err(Box::new(self.error_here("Expected > at the end of the type arguments")));
+            }
+            span = span.to(self.advance().span());
+        }
+
+        Ok(TypeExpr{
+            name: String::from(name.lexeme()),
+            args: args,
+            span: span
+        })
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    //                      Statements
+    ////////////////////////////////////////////////////////////////////////////
+    fn parse_block(&mut self) -> ParseResult<Block>
+    {
+        self.nested(|parser| parser.parse_block_contents())
+    }
+
+    fn parse_block_contents(&mut self) -> ParseResult<Block>
+    {
+        let start = self.expect(TokenType::OpenCurl, "Expected {")?.span();
+        let mut statements = Vec::new();
+        while !self.check(TokenType::CloseCurl)
+        {
+            if self.at_end()
+            {
+                return Err(Box::new(self.error_here("Expected } at the end of the block")));
+            }
+            statements.push(self.parse_statement()?);
+        }
+        let end = self.advance().span();
+
+        Ok(Block{
+            statements: statements,
+            span: start.to(end)
+        })
+    }
+
+    pub fn parse_statement(&mut self) -> ParseResult<Statement>
+    {
+        let start = self.peek_span();
+        let kind = if self.check_keyword("let")
+        {
+            self.parse_let()?
+        }
+        else if self.check_keyword("return")
+        {
+            self.advance();
+            let value = if self.check(TokenType::EndStatement)
+            {
+                None
+            }
+            else
+            {
+                Some(self.parse_expression()?)
+            };
+            self.expect_end_statement()?;
+            StatementKind::Return(value)
+        }
+        else if self.check_keyword("if")
+        {
+            self.parse_if()?
+        }
+        else if self.check_keyword("while")
+        {
+            self.advance();
+            let condition = self.parse_expression()?;
+            let body = self.parse_block()?;
+            StatementKind::While{condition: condition, body: body}
+        }
+        else if self.check_keyword("for")
+        {
+            self.parse_for()?
+        }
+        else if self.check_keyword("foreach")
+        {
+            self.parse_foreach()?
+        }
+        else if self.check(TokenType::OpenCurl)
+        {
+            StatementKind::Block(self.parse_block()?)
+        }
+        else
+        {
+            self.parse_expression_statement()?
+        };
+
+        Ok(Statement{
+            kind: kind,
+            span: start.to(Span::new(self.previous_end, self.previous_end))
+        })
+    }
+
+    fn parse_let(&mut self) -> ParseResult<StatementKind>
+    {
+        self.expect_keyword("let")?;
+
+        //let <name> = ... or let <Type> <name> = ...
+        let type_expr = if self.check(TokenType::Identifier)
+            && (self.check_next(TokenType::Assignment) || self.check_next(TokenType::EndStatement))
+        {
+            None
+        }
+        else
+        {
+            Some(self.parse_type_expr()?)
+        };
+
+        let name = self.expect(TokenType::Identifier, "Expected a variable name")?;
+        let value = if self.eat(TokenType::Assignment)
+        {
+            Some(self.parse_expression()?)
+        }
+        else
+        {
+            None
+        };
+        self.expect_end_statement()?;
+
+        Ok(StatementKind::Let{
+            type_expr: type_expr,
+            name: String::from(name.lexeme()),
+            name_span: name.span(),
+            value: value
+        })
+    }
+
+    fn parse_if(&mut self) -> ParseResult<StatementKind>
+    {
+        self.expect_keyword("if")?;
+        let mut branches = Vec::new();
+        let condition = self.parse_expression()?;
+        branches.push((condition, self.parse_block()?));
+
+        let mut else_block = None;
+        loop
+        {
+            if self.check_keyword("elseif")
+            {
+                self.advance();
+                let condition = self.parse_expression()?;
+                branches.push((condition, self.parse_block()?));
+            }
+            else if self.check_keyword("else")
+            {
+                self.advance();
+                else_block = Some(self.parse_block()?);
+                break;
+            }
+            else
+            {
+                break;
+            }
+        }
+
+        Ok(StatementKind::If{branches: branches, else_block: else_block})
+    }
+
+    fn parse_for(&mut self) -> ParseResult<StatementKind>
+    {
+        self.expect_keyword("for")?;
+        let var = self.expect(TokenType::Identifier, "Expected a loop variable")?;
+        if self.check_keyword("in")
+        {
+            return Err(Box::new(self.error_here("Expected is")
+                .with_note(String::from("for counts from one number to another, foreach goes through a container"))));
+        }
+        self.expect_keyword("is")?;
+        let start = self.parse_expression()?;
+        self.expect_keyword("to")?;
+        let end = self.parse_expression()?;
+        let step = if self.check_keyword("step")
+        {
+            self.advance();
+            if self.check(TokenType::OpenCurl)
+            {
+                return Err(Box::new(self.error_here("Expected the amount to step after step")));
+            }
+            Some(self.parse_expression()?)
+        }
+        else
+        {
+            None
+        };
+        let body = self.parse_block()?;
+
+        Ok(StatementKind::For{
+            var: String::from(var.lexeme()),
+            var_span: var.span(),
+            start: start,
+            end: end,
+            step: step,
+            body: body
+        })
+    }
+
+    fn parse_foreach(&mut self) -> ParseResult<StatementKind>
+    {
+        self.expect_keyword("foreach")?;
+
+        let (type_expr, var) = self.parse_loop_variable()?;
+        let value = if self.eat(TokenType::Comma)
+        {
+            let (type_expr, name) = self.parse_loop_variable()?;
+            Some(LoopValue{
+                type_expr: type_expr,
+                name: String::from(name.lexeme()),
+                span: name.span()
+            })
+        }
+        else
+        {
+            None
+        };
+        self.expect_keyword("in")?;
+        let container = self.parse_expression()?;
+        let body = self.parse_block()?;
+
+        Ok(StatementKind::Foreach{
+            type_expr: type_expr,
+            var: String::from(var.lexeme()),
+            var_span: var.span(),
+            value: value,
+            container: container,
+            body: body
+        })
+    }
+
+    //A variable of a foreach loop with its optional type
+    fn parse_loop_variable(&mut self) -> ParseResult<(Option<TypeExpr>, Token)>
+    {
+        let type_expr = if self.check(TokenType::Identifier) && (self.check_next_keyword("in") || self.check_next(TokenType::Comma))
+        {
+            None
+        }
+        else
+        {
+            Some(self.parse_type_expr()?)
+        };
+        let var = self.expect(TokenType::Identifier, "Expected a loop variable")?;
+        Ok((type_expr, var))
+    }
+
+    fn parse_expression_statement(&mut self) -> ParseResult<StatementKind>
+    {
+        let expr = self.parse_expression()?;
+
+        let kind = if self.eat(TokenType::Assignment)
+        {
+            let value = self.parse_expression()?;
+            StatementKind::Assign{target: expr, op: None, value: value}
+        }
+        else if self.check_operator("+=") || self.check_operator("-=")
+            || self.check_operator("*=") || self.check_operator("/=") || self.check_operator("%=")
+        {
+            let op_token = self.advance();
+            let op = BinaryOp::from_operator(&op_token.lexeme()[..1]).unwrap();
+            let value = self.parse_expression()?;
+            StatementKind::Assign{target: expr, op: Some(op), value: value}
+        }
+        else if self.check_operator("++") || self.check_operator("--")
+        {
+            let op = if self.advance().lexeme() == "++" {BinaryOp::Add} else {BinaryOp::Sub};
+            StatementKind::Increment{target: expr, op: op}
+        }
+        else
+        {
+            StatementKind::Expr(expr)
+        };
+
+        self.expect_end_statement()?;
+        return Ok(kind);
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    //                      Expressions
+    ////////////////////////////////////////////////////////////////////////////
+    pub fn parse_expression(&mut self) -> ParseResult<Expr>
+    {
+        self.nested(|parser| parser.parse_binary(0))
+    }
+
+    //Precedence climbing over the binary operators
+    fn parse_binary(&mut self, min_precedence: u8) -> ParseResult<Expr>
+    {
+        let mut lhs = self.parse_unary()?;
+
+        loop
+        {
+            let op = match self.peek()
+            {
+                Some(token) if token.token_type() == TokenType::Operator =>
+                    match BinaryOp::from_operator(token.lexeme())
+                    {
+                        Some(op) => op,
+                        None => break
+                    },
+                _ => break
+            };
+            if op.precedence() <= min_precedence
+            {
+                break;
+            }
+            self.advance();
+
+            let rhs = self.parse_binary(op.precedence())?;
+            let span = lhs.span.to(rhs.span);
+            lhs = self.make_expr(ExprKind::Binary{op: op, lhs: Box::new(lhs), rhs: Box::new(rhs)}, span)?;
+        }
+
+        return Ok(lhs);
+    }
+
+    fn parse_unary(&mut self) -> ParseResult<Expr>
+    {
+        let op = if self.check_operator("-")
+        {
+            Some(UnaryOp::Negate)
+        }
+        else if self.check_operator("!")
+        {
+            Some(UnaryOp::Not)
+        }
+        else
+        {
+            None
+        };
+
+        match op
+        {
+            Some(op) => {
+                let start = self.advance().span();
+                let expr = self.nested(|parser| parser.parse_unary())?;
+                let span = start.to(expr.span);
+                self.make_expr(ExprKind::Unary{op: op, expr: Box::new(expr)}, span)
+            }
+            None => self.parse_postfix()
+        }
+    }
+
+    fn parse_postfix(&mut self) -> ParseResult<Expr>
+    {
+        let mut expr = self.parse_primary()?;
+
+        loop
+        {
+            if self.eat(TokenType::OpenPar)
+            {
+                let mut args = Vec::new();
+                if !self.check(TokenType::ClosePar)
+                {
+                    loop
+                    {
+                        args.push(self.parse_expression()?);
+                        if !self.eat(TokenType::Comma)
+                        {
+                            break;
+                        }
+                    }
+                }
+                let end = self.expect(TokenType::ClosePar, "Expected ) after the function arguments")?;
+                let span = expr.span.to(end.span());
+                expr = self.make_expr(ExprKind::Call{callee: Box::new(expr), args: args}, span)?;
+            }
+            else if self.eat(TokenType::Dot)
+            {
+                let name = self.expect(TokenType::Identifier, "Expected a member name after .")?;
+                let span = expr.span.to(name.span());
+                expr = self.make_expr(ExprKind::Member{
+                    object: Box::new(expr),
+                    name: String::from(name.lexeme()),
+                    name_span: name.span()
+                }, span)?;
+            }
+            else if self.eat(TokenType::OpenSq)
+            {
+                let index = self.parse_expression()?;
+                let end = self.expect(TokenType::CloseSq, "Expected ] after the index")?;
+                let span = expr.span.to(end.span());
+                expr = self.make_expr(ExprKind::Index{object: Box::new(expr), index: Box::new(index)}, span)?;
+            }
+            else
+            {
+                break;
+            }
+        }
+
+        return Ok(expr);
+    }
+
+    fn parse_primary(&mut self) -> ParseResult<Expr>
+    {
+        let token = match self.peek()
+        {
+            Some(token) => token.clone(),
+            None => return Err(Box::new(self.error_here("Expected an expression")))
+        };
+
+        match token.token_type()
+        {
+            TokenType::Number | TokenType::HexNumber => {
+                self.advance();
+                self.make_expr(ExprKind::Number(String::from(token.lexeme())), token.span())
+            }
+            TokenType::StringLiteral => {
+                self.advance();
+                let value = unescape_string(token.lexeme());
+                self.make_expr(ExprKind::String(value), token.span())
+            }
+            TokenType::Keyword if token.lexeme() == "true" || token.lexeme() == "false" => {
+                self.advance();
+                self.make_expr(ExprKind::Bool(token.lexeme() == "true"), token.span())
+            }
+            TokenType::Identifier => {
+                self.advance();
+                self.make_expr(ExprKind::Identifier(String::from(token.lexeme())), token.span())
+            }
+            //self is a parameter of methods, so it is used like any other name
+            TokenType::Keyword if token.lexeme() == "self" => {
+                self.advance();
+                self.make_expr(ExprKind::Identifier(String::from(token.lexeme())), token.span())
+            }
+            TokenType::OpenPar => {
+                self.advance();
+                let expr = self.parse_expression()?;
+                self.expect(TokenType::ClosePar, "Expected )")?;
+                Ok(expr)
+            }
+            TokenType::OpenSq => self.parse_container_literal(),
+            _ => Err(Box::new(self.error_here("Expected an expression")))
+        }
+    }
+
+    //[a, b, c] is a list and [a: b, c: d] is a dictionary. [] and [:] are the
+    //empty list and dictionary
+    fn parse_container_literal(&mut self) -> ParseResult<Expr>
+    {
+        let start = self.expect(TokenType::OpenSq, "Expected [")?.span();
+
+        if let Some(end) = self.eat_token(TokenType::CloseSq)
+        {
+            return self.make_expr(ExprKind::List(Vec::new()), start.to(end.span()));
+        }
+        if self.eat(TokenType::Colon)
+        {
+            let end = self.expect(TokenType::CloseSq, "Expected ] after [:")?;
+            return self.make_expr(ExprKind::Dictionary(Vec::new()), start.to(end.span()));
+        }
+
+        let first = self.parse_expression()?;
+        let kind = if self.eat(TokenType::Colon)
+        {
+            let mut entries = vec!((first, self.parse_expression()?));
+            while self.eat(TokenType::Comma)
+            {
+                let key = self.parse_expression()?;
+                self.expect(TokenType::Colon, "Expected : between the key and the value")?;
+                entries.push((key, self.parse_expression()?));
+            }
+            ExprKind::Dictionary(entries)
+        }
+        else
+        {
+            let mut elements = vec!(first);
+            while self.eat(TokenType::Comma)
+            {
+                elements.push(self.parse_expression()?);
+            }
+            ExprKind::List(elements)
+        };
+        let end = self.expect(TokenType::CloseSq, "Expected ] at the end of the list")?;
+
+        self.make_expr(kind, start.to(end.span()))
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    //                      Helpers
+    ////////////////////////////////////////////////////////////////////////////
+    fn make_expr(&mut self, kind: ExprKind, span: Span) -> ParseResult<Expr>
+    {
+        let nesting = match kind
+        {
+            //The left operand of a binary operator is at the same level as the operator
+            ExprKind::Binary{ref lhs, ref rhs, ..} => self.depths[&lhs.id].0.max(1 + self.depths[&rhs.id].0),
+            _ => 1 + kind.children().iter().map(|child| self.depths[&child.id].0).max().unwrap_or(0)
+        };
+        let levels = 1 + kind.children().iter().map(|child| self.depths[&child.id].1).max().unwrap_or(0);
+        if nesting > MAX_NESTING
+        {
+            return Err(Box::new(self.too_deep(span)));
+        }
+        if levels > MAX_EXPRESSION_DEPTH
+        {
+            return Err(Box::new(Diagnostic::error(error_codes::TOO_DEEPLY_NESTED,
+                    format!("The expression has more than {} levels", MAX_EXPRESSION_DEPTH), span)
+                .with_note(String::from("every operator in a chain like 1 + 2 + 3 is a level, split it up with variables"))));
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.depths.insert(id, (nesting, levels));
+        Ok(Expr{id: id, kind: kind, span: span})
+    }
+
+    //Runs parse one level deeper
+    fn nested<T, F>(&mut self, parse: F) -> ParseResult<T>
+        where F: FnOnce(&mut Parser) -> ParseResult<T>
+    {
+        if self.nesting >= MAX_NESTING
+        {
+            return Err(Box::new(self.too_deep(self.peek_span())));
+        }
+        self.nesting += 1;
+        let result = parse(self);
+        self.nesting -= 1;
+        return result;
+    }
+
+    fn too_deep(&self, span: Span) -> Diagnostic
+    {
+        Diagnostic::error(error_codes::TOO_DEEPLY_NESTED,
+            format!("The code is nested more than {} levels deep", MAX_NESTING), span)
+            .with_note(String::from("split it up with variables or functions"))
+    }
+
+    fn at_end(&self) -> bool
+    {
+        self.position >= self.tokens.len()
+    }
+
+    fn peek(&self) -> Option<&Token>
+    {
+        self.tokens.get(self.position)
+    }
+
+    fn peek_span(&self) -> Span
+    {
+        match self.peek()
+        {
+            Some(token) => token.span(),
+            None => Span::new(self.previous_end, self.previous_end)
+        }
+    }
+
+    fn advance(&mut self) -> Token
+    {
+        let token = self.tokens[self.position].clone();
+        self.position += 1;
+        self.previous_end = token.span().end;
+        return token;
+    }
+
+    fn check(&self, token_type: TokenType) -> bool
+    {
+        match self.peek()
+        {
+            Some(token) => token.token_type() == token_type,
+            None => false
+        }
+    }
+
+    fn check_next(&self, token_type: TokenType) -> bool
+    {
+        match self.tokens.get(self.position + 1)
+        {
+            Some(token) => token.token_type() == token_type,
+            None => false
+        }
+    }
+
+    fn check_keyword(&self, keyword: &str) -> bool
+    {
+        match self.peek()
+        {
+            Some(token) => token.token_type() == TokenType::Keyword && token.lexeme() == keyword,
+            None => false
+        }
+    }
+
+    fn check_next_keyword(&self, keyword: &str) -> bool
+    {
+        match self.tokens.get(self.position + 1)
+        {
+            Some(token) => token.token_type() == TokenType::Keyword && token.lexeme() == keyword,
+            None => false
+        }
+    }
+
+    fn check_operator(&self, operator: &str) -> bool
+    {
+        match self.peek()
+        {
+            Some(token) => token.token_type() == TokenType::Operator && token.lexeme() == operator,
+            None => false
+        }
+    }
+
+    fn eat(&mut self, token_type: TokenType) -> bool
+    {
+        self.eat_token(token_type).is_some()
+    }
+
+    fn eat_token(&mut self, token_type: TokenType) -> Option<Token>
+    {
+        if self.check(token_type)
+        {
+            Some(self.advance())
+        }
+        else
+        {
+            None
+        }
+    }
+
+    fn expect(&mut self, token_type: TokenType, message: &str) -> ParseResult<Token>
+    {
+        match self.eat_token(token_type)
+        {
+            Some(token) => Ok(token),
+            None => Err(Box::new(self.error_here(message)))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> ParseResult<Token>
+    {
+        if self.check_keyword(keyword)
+        {
+            Ok(self.advance())
+        }
+        else
+        {
+            Err(Box::new(self.error_here(&format!("Expected {}", keyword))))
+        }
+    }
+
+    fn expect_end_statement(&mut self) -> ParseResult<()>
+    {
+        if self.eat_token(TokenType::EndStatement).is_none()
+        {
+            let end = Span::new(self.previous_end, self.previous_end);
+            return Err(Box::new(self.error_here("Expected ; at the end of the statement")
+                .with_suggestion(String::from("add a ; after the statement"), end, String::from(";"))));
+        }
+        Ok(())
+    }
+
+    fn error_here(&self, message: &str) -> Diagnostic
+    {
+        let message = match self.peek()
+        {
+            Some(token) => format!("{}, found '{}'", message, token.lexeme()),
+            None => format!("{}, found the end of the code", message)
+        };
+        Diagnostic::error(error_codes::SYNTAX_ERROR, message, self.peek_span())
+    }
+}
+
+//Removes the quotes around a string literal and replaces escape sequences
+fn unescape_string(lexeme: &str) -> String
+{
+    let inner = &lexeme[1..lexeme.len() - 1];
+    let mut result = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next()
+    {
+        if c != '\\'
+        {
+            result.push(c);
+            continue;
+        }
+        match chars.next()
+        {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('0') => result.push('\0'),
+            Some(other) => result.push(other),
+            None => result.push('\\')
+        }
+    }
+    return result;
 }
 
+#[cfg(test)]
+mod parser_tests
+{
+    use ast::*;
+    use lexer::Lexer;
+    use limits::run_with_stack;
+    use parser::{parse_token_list, MAX_EXPRESSION_DEPTH, MAX_NESTING};
+
+    fn parse(code: &str) -> Program
+    {
+        let tokens = Lexer::new().tokenize(String::from(code));
+        parse_token_list(tokens).unwrap()
+    }
+
+    fn parse_error(code: &str) -> String
+    {
+        let tokens = Lexer::new().tokenize(String::from(code));
+        parse_token_list(tokens).unwrap_err().message
+    }
+
+    fn main_body(program: &Program) -> &Vec<Statement>
+    {
+        match program.items[0]
+        {
+            Item::Function(ref f) => &f.body.statements,
+            _ => panic!("Expected a function")
+        }
+    }
+
+    #[test]
+    fn function_declarations()
+    {
+        let program = parse("def main() {} def Number add(Number a, Number b) { return a + b; }");
+        assert_eq!(program.items.len(), 2);
+
+        match program.items[0]
+        {
+            Item::Function(ref f) => {
+                assert_eq!(f.name, "main");
+                assert_eq!(f.return_type, None);
+                assert!(f.params.is_empty());
+            }
+            _ => panic!("Expected a function")
+        }
+        match program.items[1]
+        {
+            Item::Function(ref f) => {
+                assert_eq!(f.name, "add");
+                assert_eq!(f.return_type.as_ref().unwrap().name, "Number");
+                assert_eq!(f.params.len(), 2);
+                assert_eq!(f.params[1].name, "b");
+            }
+            _ => panic!("Expected a function")
+        }
+
+        let program = parse("def List<Number> numbers() { return [1, 2]; }");
+        match program.items[0]
+        {
+            Item::Function(ref f) => {
+                let return_type = f.return_type.as_ref().unwrap();
+                assert_eq!(return_type.name, "List");
+                assert_eq!(return_type.args[0].name, "Number");
+            }
+            _ => panic!("Expected a function")
+        }
+    }
+
+    #[test]
+    fn operator_precedence()
+    {
+        let program = parse("def main() { let x = 1 + 2 * 3 == 7; }");
+        let value = match main_body(&program)[0].kind
+        {
+            StatementKind::Let{ref value, ..} => value.clone().unwrap(),
+            _ => panic!("Expected let")
+        };
+        match value.kind
+        {
+            ExprKind::Binary{op: BinaryOp::Equal, ref lhs, ..} => match lhs.kind
+            {
+                ExprKind::Binary{op: BinaryOp::Add, ref rhs, ..} => match rhs.kind
+                {
+                    ExprKind::Binary{op: BinaryOp::Mul, ..} => {}
+                    _ => panic!("* should bind tighter than +")
+                },
+                _ => panic!("+ should bind tighter than ==")
+            },
+            _ => panic!("== should be the outermost operator")
+        }
+    }
+
+    #[test]
+    fn statements()
+    {
+        let program = parse(r#"
+            def main()
+            {
+                let Number a = 5;
+                let b = "hello";
+                a += 2;
+                a++;
+                if a < 3 { a = 1; } elseif a > 10 { a = 2; } else { a = 3; }
+                while a < 100 { a = a * 2; }
+                for i is 0 to 10 step 2 { print(i); }
+                foreach x in [1, 2, 3] { print(x); }
+                foreach Number y in [1, 2, 3] { print(y); }
+                let d = ["a": 1, "b": 2];
+                let e = [:];
+                print(d["a"]);
+                return;
+            }
+        "#);
+        let body = main_body(&program);
+        assert_eq!(body.len(), 13);
+
+        match body[2].kind
+        {
+            StatementKind::Assign{op: Some(BinaryOp::Add), ..} => {}
+            _ => panic!("Expected +=")
+        }
+        match body[3].kind
+        {
+            StatementKind::Increment{op: BinaryOp::Add, ..} => {}
+            _ => panic!("Expected ++")
+        }
+        match body[4].kind
+        {
+            StatementKind::If{ref branches, ref else_block} => {
+                assert_eq!(branches.len(), 2);
+                assert!(else_block.is_some());
+            }
+            _ => panic!("Expected if")
+        }
+        match body[6].kind
+        {
+            StatementKind::For{ref var, ref step, ..} => {
+                assert_eq!(var, "i");
+                assert!(step.is_some());
+            }
+            _ => panic!("Expected for")
+        }
+        match body[8].kind
+        {
+            StatementKind::Foreach{ref type_expr, ..} => assert_eq!(type_expr.as_ref().unwrap().name, "Number"),
+            _ => panic!("Expected foreach")
+        }
+        match body[10].kind
+        {
+            StatementKind::Let{value: Some(Expr{kind: ExprKind::Dictionary(ref entries), ..}), ..} =>
+                assert!(entries.is_empty()),
+            _ => panic!("Expected an empty dictionary")
+        }
+    }
 
+    #[test]
+    fn foreach_keys_and_values()
+    {
+        let program = parse(r#"
+            def main()
+            {
+                foreach key, Number value in ["a": 1] {}
+                foreach String key, value in ["a": 1] {}
+            }
+        "#);
+        let body = main_body(&program);
+
+        match body[0].kind
+        {
+            StatementKind::Foreach{ref type_expr, ref var, value: Some(ref value), ..} => {
+                assert!(type_expr.is_none());
+                assert_eq!(var, "key");
+                assert_eq!(value.name, "value");
+                assert_eq!(value.type_expr.as_ref().unwrap().name, "Number");
+            }
+            _ => panic!("Expected foreach with a value")
+        }
+        match body[1].kind
+        {
+            StatementKind::Foreach{ref type_expr, value: Some(ref value), ..} => {
+                assert_eq!(type_expr.as_ref().unwrap().name, "String");
+                assert!(value.type_expr.is_none());
+            }
+            _ => panic!("Expected foreach with a value")
+        }
+    }
+
+    #[test]
+    fn operators()
+    {
+        let program = parse(r#"
+            implement for Vector
+            {
+                def Vector operator -(Vector a, Vector b) { return a; }
+                def Vector operator -(Vector a) { return a; }
+                def Bool operator if(Vector a) { return true; }
+                def operator <=(Vector a, Vector b) {}
+            }
+        "#);
+        let functions = match program.items[0]
+        {
+            Item::Implement(ref implement) => &implement.functions,
+            _ => panic!("Expected an implement block")
+        };
+        let operators: Vec<(&str, Option<Operator>)> = functions.iter().map(|f| (&f.name[..], f.operator)).collect();
+        assert_eq!(operators, vec!(
+            ("operator -", Some(Operator::Binary(BinaryOp::Sub))),
+            ("operator -", Some(Operator::Negate)),
+            ("operator if", Some(Operator::Condition)),
+            ("operator <=", Some(Operator::Binary(BinaryOp::LessEqual))),
+        ));
+        assert!(functions[3].return_type.is_none());
+
+        assert_eq!(parse_error("implement for A { def Bool operator !=(A a, A b) { return true; } }"), "The operator != can not be overloaded");
+        assert_eq!(parse_error("implement for A { def Bool operator &(A a, A b) { return true; } }"), "The operator & can not be overloaded");
+        assert_eq!(parse_error("implement for A { def A operator !(A a) { return a; } }"), "The operator ! can not be overloaded");
+        assert_eq!(parse_error("def A operator +(A a, A b) { return a; }"), "Operators can only be declared in implement blocks");
+    }
+
+    #[test]
+    fn receivers()
+    {
+        let program = parse(r#"
+            implement for Point
+            {
+                def Number sum(self) { return self.x + self.y; }
+                def move(self, Number x) { self.x = x; }
+                def Point origin() { return Point(0, 0); }
+            }
+        "#);
+        let functions = match program.items[0]
+        {
+            Item::Implement(ref implement) => &implement.functions,
+            _ => panic!("Expected an implement block")
+        };
+        let receivers: Vec<bool> = functions.iter().map(|f| f.receiver.is_some()).collect();
+        assert_eq!(receivers, vec!(true, true, false));
+        assert_eq!(functions[1].params.len(), 1);
+
+        assert_eq!(parse_error("def f(self) {}"), "Only functions in implement blocks can take self");
+        assert_eq!(parse_error("implement for A { def f(Number x, self) {} }"), "Expected a type, found 'self'");
+    }
+
+    #[test]
+    fn types_and_implement_blocks()
+    {
+        let program = parse(r#"
+            type Point
+            {
+                public:
+                Number x;
+                Number y;
+                private:
+                String name
+                hidden Number id;
+            }
+
+            implement for Point
+            {
+                def Number sum() { return 0; }
+                private:
+                def helper() {}
+                public:
+                def other() {}
+            }
+        "#);
+
+        match program.items[0]
+        {
+            Item::Type(ref t) => {
+                assert_eq!(t.name, "Point");
+                assert_eq!(t.fields.len(), 4);
+                assert_eq!(t.fields[0].visibility, Visibility::Public);
+                assert_eq!(t.fields[2].visibility, Visibility::Private);
+                let hidden: Vec<bool> = t.fields.iter().map(|f| f.hidden).collect();
+                assert_eq!(hidden, vec!(false, false, false, true));
+            }
+            _ => panic!("Expected a type")
+        }
+        match program.items[1]
+        {
+            Item::Implement(ref i) => {
+                assert_eq!(i.type_name, "Point");
+                assert_eq!(i.functions[0].name, "sum");
+                let visibilities: Vec<Visibility> = i.functions.iter().map(|f| f.visibility).collect();
+                assert_eq!(visibilities, vec!(Visibility::Public, Visibility::Private, Visibility::Public));
+            }
+            _ => panic!("Expected an implement block")
+        }
+    }
+
+    #[test]
+    fn string_escapes()
+    {
+        let program = parse(r#"def main() { let a = "a\"b\n"; }"#);
+        match main_body(&program)[0].kind
+        {
+            StatementKind::Let{value: Some(Expr{kind: ExprKind::String(ref s), ..}), ..} =>
+                assert_eq!(s, "a\"b\n"),
+            _ => panic!("Expected a string")
+        }
+    }
+
+    #[test]
+    fn errors()
+    {
+        assert_eq!(parse_error("def main() { let a = 5 }"), "Expected ; at the end of the statement, found '}'");
+        assert_eq!(parse_error("def main() { let a = 5;"), "Expected } at the end of the block, found the end of the code");
+        assert_eq!(parse_error("def main( {}"), "Expected a type, found '{'");
+        assert_eq!(parse_error("implement for A { let a = 5; }"), "Only functions can be declared in implement blocks, found 'let'");
+        assert_eq!(parse_error("def main() { for i in [1] {} }"), "Expected is, found 'in'");
+        assert_eq!(parse_error("def main() { for i is 0 to 1 step {} }"), "Expected the amount to step after step, found '{'");
+        assert_eq!(parse_error("def main() { for i is 0 step 1 {} }"), "Expected to, found 'step'");
+        assert_eq!(parse_error("type A { hidden Number x; }"), "Only private fields can be hidden");
+    }
+
+    #[test]
+    fn nesting_is_limited()
+    {
+        //Debug builds need more than the stack of a test thread to parse code at the limit
+        run_with_stack(|| {
+            let too_deep = format!("The code is nested more than {} levels deep", MAX_NESTING);
+            //Chains of operators are flat, only their length is limited
+            let chain = |terms: usize| format!("def main() {{ let a = {}; }}", vec!("1"; terms).join(" + "));
+            parse(&chain(MAX_NESTING * 4));
+            parse(&chain(MAX_EXPRESSION_DEPTH));
+            assert_eq!(parse_error(&chain(MAX_EXPRESSION_DEPTH + 2)), format!("The expression has more than {} levels", MAX_EXPRESSION_DEPTH));
+            parse(&format!("def main() {{ let a = {}; }}", vec!("1 * 2 - 3"; 1000).join(" + ")));
+            let sums = |times: usize| format!("def main() {{ let a = {}1{}; }}", "1 + (".repeat(times), ")".repeat(times));
+            parse(&sums(MAX_NESTING - 3));
+            assert_eq!(parse_error(&sums(MAX_NESTING)), too_deep);
+
+            let nested = |open: &str, close: &str, times: usize| format!("def main() {{ let a = {}1{}; }}", open.repeat(times), close.repeat(times));
+            parse(&nested("(", ")", MAX_NESTING - 3));
+            assert_eq!(parse_error(&nested("(", ")", 20_000)), too_deep);
+            assert_eq!(parse_error(&nested("[", "]", 20_000)), too_deep);
+            assert_eq!(parse_error(&nested("- ", "", 20_000)), too_deep);
+            assert_eq!(parse_error(&format!("def main() {{ let a = b{}; }}", "()".repeat(20_000))), too_deep);
+            assert_eq!(parse_error(&format!("def main() {{ {}{} }}", "{".repeat(20_000), "}".repeat(20_000))), too_deep);
+            assert_eq!(parse_error(&format!("def f(List<{}Number{} a) {{}}", "List<".repeat(20_000), ">".repeat(20_001))), too_deep);
+        });
+    }
+}
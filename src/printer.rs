@@ -0,0 +1,320 @@
+use ast::*;
+
+/*
+ * Prints the parse tree as an indented outline with one node on each line. Meant
+ * for people looking at what the parser made of their code, not for parsing again
+ */
+pub fn print_program(program: &Program) -> String
+{
+    let mut printer = Printer{output: String::new(), depth: 0};
+    for item in &program.items
+    {
+        printer.item(item);
+    }
+    return printer.output;
+}
+
+pub fn print_expr(expr: &Expr) -> String
+{
+    let mut printer = Printer{output: String::new(), depth: 0};
+    printer.expr(expr);
+    return printer.output;
+}
+
+//Types are shown the way they are written, for example List<Number>
+pub fn type_expr_string(type_expr: &TypeExpr) -> String
+{
+    if type_expr.args.is_empty()
+    {
+        return type_expr.name.clone();
+    }
+    let args: Vec<String> = type_expr.args.iter().map(type_expr_string).collect();
+    format!("{}<{}>", type_expr.name, args.join(", "))
+}
+
+struct Printer
+{
+    output: String,
+    depth: usize,
+}
+
+impl Printer
+{
+    fn line(&mut self, text: &str)
+    {
+        for _ in 0..self.depth
+        {
+            self.output.push_str("  ");
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    //Prints a line with the children indented below it
+    fn node<F: FnOnce(&mut Printer)>(&mut self, text: &str, children: F)
+    {
+        self.line(text);
+        self.depth += 1;
+        children(self);
+        self.depth -= 1;
+    }
+
+    fn item(&mut self, item: &Item)
+    {
+        match *item
+        {
+            Item::Function(ref function) => self.function(function),
+            Item::Type(ref decl) => {
+                self.node(&format!("Type {}", decl.name), |p| {
+                    for field in &decl.fields
+                    {
+                        p.line(&format!("Field {}{}{} {}", visibility(field.visibility), if field.hidden {"hidden "} else {""},
+                            type_expr_string(&field.type_expr), field.name));
+                    }
+                });
+            }
+            Item::Implement(ref implement) => {
+                self.node(&format!("Implement for {}", implement.type_name), |p| {
+                    for function in &implement.functions
+                    {
+                        p.function(function);
+                    }
+                });
+            }
+            Item::Statement(ref statement) => self.statement(statement),
+        }
+    }
+
+    fn function(&mut self, function: &FunctionDecl)
+    {
+        let receiver = function.receiver.map(|_| String::from("self"));
+        let params: Vec<String> = receiver.into_iter()
+            .chain(function.params.iter().map(|param| format!("{} {}", type_expr_string(&param.type_expr), param.name)))
+            .collect();
+        let return_type = match function.return_type
+        {
+            Some(ref return_type) => format!(" -> {}", type_expr_string(return_type)),
+            None => String::new()
+        };
+        let text = format!("Function {}{}({}){}", visibility(function.visibility), function.name,
+            params.join(", "), return_type);
+        self.node(&text, |p| p.statements(&function.body));
+    }
+
+    fn statements(&mut self, block: &Block)
+    {
+        for statement in &block.statements
+        {
+            self.statement(statement);
+        }
+    }
+
+    fn block(&mut self, text: &str, block: &Block)
+    {
+        self.node(text, |p| p.statements(block));
+    }
+
+    fn statement(&mut self, statement: &Statement)
+    {
+        match statement.kind
+        {
+            StatementKind::Let{ref type_expr, ref name, ref value, ..} => {
+                let text = match *type_expr
+                {
+                    Some(ref type_expr) => format!("Let {} {}", type_expr_string(type_expr), name),
+                    None => format!("Let {}", name)
+                };
+                self.node(&text, |p| if let Some(ref value) = *value { p.expr(value) });
+            }
+            StatementKind::Assign{ref target, op, ref value} => {
+                let text = match op
+                {
+                    Some(op) => format!("Assign {}=", op.symbol()),
+                    None => String::from("Assign =")
+                };
+                self.node(&text, |p| {
+                    p.expr(target);
+                    p.expr(value);
+                });
+            }
+            StatementKind::Increment{ref target, op} => {
+                let text = if op == BinaryOp::Add {"Increment ++"} else {"Increment --"};
+                self.node(text, |p| p.expr(target));
+            }
+            StatementKind::Expr(ref expr) => self.node("Expression", |p| p.expr(expr)),
+            StatementKind::Return(ref value) => {
+                self.node("Return", |p| if let Some(ref value) = *value { p.expr(value) });
+            }
+            StatementKind::If{ref branches, ref else_block} => {
+                self.node("If", |p| {
+                    for &(ref condition, ref body) in branches
+                    {
+                        p.node("Branch", |p| {
+                            p.expr(condition);
+                            p.block("Then", body);
+                        });
+                    }
+                    if let Some(ref else_block) = *else_block
+                    {
+                        p.block("Else", else_block);
+                    }
+                });
+            }
+            StatementKind::While{ref condition, ref body} => {
+                self.node("While", |p| {
+                    p.expr(condition);
+                    p.block("Do", body);
+                });
+            }
+            StatementKind::For{ref var, ref start, ref end, ref step, ref body, ..} => {
+                self.node(&format!("For {}", var), |p| {
+                    p.node("Is", |p| p.expr(start));
+                    p.node("To", |p| p.expr(end));
+                    if let Some(ref step) = *step
+                    {
+                        p.node("Step", |p| p.expr(step));
+                    }
+                    p.block("Do", body);
+                });
+            }
+            StatementKind::Foreach{ref type_expr, ref var, ref value, ref container, ref body, ..} => {
+                let mut text = match *type_expr
+                {
+                    Some(ref type_expr) => format!("Foreach {} {}", type_expr_string(type_expr), var),
+                    None => format!("Foreach {}", var)
+                };
+                match *value
+                {
+                    Some(LoopValue{type_expr: Some(ref type_expr), ref name, ..}) =>
+                        text.push_str(&format!(", {} {}", type_expr_string(type_expr), name)),
+                    Some(LoopValue{type_expr: None, ref name, ..}) => text.push_str(&format!(", {}", name)),
+                    None => {}
+                }
+                self.node(&text, |p| {
+                    p.node("In", |p| p.expr(container));
+                    p.block("Do", body);
+                });
+            }
+            StatementKind::Block(ref block) => self.block("Block", block),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr)
+    {
+        match expr.kind
+        {
+            ExprKind::Number(ref lexeme) => self.line(&format!("Number {}", lexeme)),
+            ExprKind::String(ref s) => self.line(&format!("String {:?}", s)),
+            ExprKind::Bool(b) => self.line(&format!("Bool {}", b)),
+            ExprKind::Identifier(ref name) => self.line(&format!("Identifier {}", name)),
+            ExprKind::List(ref elements) => {
+                self.node("List", |p| {
+                    for element in elements
+                    {
+                        p.expr(element);
+                    }
+                });
+            }
+            ExprKind::Dictionary(ref entries) => {
+                self.node("Dictionary", |p| {
+                    for &(ref key, ref value) in entries
+                    {
+                        p.node("Entry", |p| {
+                            p.expr(key);
+                            p.expr(value);
+                        });
+                    }
+                });
+            }
+            ExprKind::Unary{op, ref expr} => {
+                let text = if op == UnaryOp::Negate {"Unary -"} else {"Unary !"};
+                self.node(text, |p| p.expr(expr));
+            }
+            ExprKind::Binary{op, ref lhs, ref rhs} => {
+                self.node(&format!("Binary {}", op.symbol()), |p| {
+                    p.expr(lhs);
+                    p.expr(rhs);
+                });
+            }
+            ExprKind::Call{ref callee, ref args} => {
+                self.node("Call", |p| {
+                    p.expr(callee);
+                    for arg in args
+                    {
+                        p.expr(arg);
+                    }
+                });
+            }
+            ExprKind::Member{ref object, ref name, ..} => {
+                self.node(&format!("Member {}", name), |p| p.expr(object));
+            }
+            ExprKind::Index{ref object, ref index} => {
+                self.node("Index", |p| {
+                    p.expr(object);
+                    p.expr(index);
+                });
+            }
+        }
+    }
+}
+
+fn visibility(visibility: Visibility) -> &'static str
+{
+    match visibility
+    {
+        Visibility::Public => "",
+        Visibility::Private => "private ",
+    }
+}
+
+#[cfg(test)]
+mod printer_tests
+{
+    use lexer::Lexer;
+    use parser::parse_token_list;
+    use printer::*;
+
+    fn print_code(code: &str) -> String
+    {
+        print_program(&parse_token_list(Lexer::new().tokenize(String::from(code))).unwrap())
+    }
+
+    #[test]
+    fn printing()
+    {
+        let code = "def Number main(List<String> args)\n\
+                    {\n\
+                    \tlet Number a = -1 + 2 * 3;\n\
+                    \tif a > 2 { print(args[0]); } else { a++; }\n\
+                    \treturn a;\n\
+                    }";
+        let expected = "\
+Function main(List<String> args) -> Number
+  Let Number a
+    Binary +
+      Unary -
+        Number 1
+      Binary *
+        Number 2
+        Number 3
+  If
+    Branch
+      Binary >
+        Identifier a
+        Number 2
+      Then
+        Expression
+          Call
+            Identifier print
+            Index
+              Identifier args
+              Number 0
+    Else
+      Increment ++
+        Identifier a
+  Return
+    Identifier a
+";
+        assert_eq!(print_code(code), expected);
+    }
+}
@@ -0,0 +1,456 @@
+use std::io::{self, BufRead, Write};
+use std::mem;
+
+use ast::*;
+use builtins::Builtins;
+use diagnostic::{self, Diagnostic};
+use error::Error;
+use error_codes;
+use interpreter::Interpreter;
+use parser::{self, Parser};
+use printer;
+use script_state::ScriptState;
+use types::Type;
+use validation::ValidationOptions;
+use variable::Value;
+use {check, lex, parse, CheckedProgram};
+
+//The name of the code typed into the REPL in diagnostics
+pub const REPL_FILE: &'static str = "<repl>";
+
+const PROMPT: &'static str = "> ";
+const CONTINUATION_PROMPT: &'static str = "... ";
+
+const HELP: &'static str = "\
+Type code to run it. Functions and types can be declared as well.
+Input that is not complete, like an open { or string, continues on the next line.
+An empty line ends it anyway.
+
+Commands:
+    :type <expression>   Shows the type of the expression without running it
+    :ast <code>          Shows the parse tree of the code
+    :reset               Forgets everything that has been declared
+    :help                Shows this text
+    :quit                Leaves the REPL
+";
+
+/*
+ * Diagnostics together with the code that they point into
+ */
+#[derive(Clone,PartialEq,Debug)]
+pub struct ReplError
+{
+    pub diagnostics: Vec<Diagnostic>,
+    pub code: String,
+}
+
+impl ReplError
+{
+    pub fn render(&self) -> String
+    {
+        diagnostic::render_all(&self.diagnostics, REPL_FILE, &self.code)
+    }
+}
+
+/*
+ * The result of code typed into the REPL
+ */
+#[derive(Clone,PartialEq,Debug)]
+pub struct Evaluation
+{
+    //The value of the last statement if it was an expression
+    pub value: Value,
+    pub value_type: Type,
+    pub warnings: Vec<Diagnostic>,
+}
+
+/*
+ * An interactive session. Every input is checked as if it followed all the input
+ * that was run before it, so it can use the functions, types and variables that
+ * were declared earlier. Only the new code is run, against variables that are
+ * kept in a ScriptState between inputs
+ */
+pub struct Repl
+{
+    builtins: Builtins,
+    //Every input that has run, each ending with a line break
+    history: String,
+    state: ScriptState,
+}
+
+impl Repl
+{
+    pub fn new(builtins: Builtins) -> Repl
+    {
+        Repl {
+            builtins: builtins,
+            history: String::new(),
+            state: ScriptState::new(),
+        }
+    }
+
+    //Forgets everything that has been declared
+    pub fn reset(&mut self)
+    {
+        self.history.clear();
+        self.state = ScriptState::new();
+    }
+
+    //Checks and runs the input. Output of the code is written to output
+    pub fn eval(&mut self, input: &str, output: &mut dyn Write) -> Result<Evaluation, ReplError>
+    {
+        let (code, checked) = self.check_input(input)?;
+        let offset = self.history.len();
+        let statements: Vec<&Statement> = checked.program.items.iter()
+            .filter_map(|item| match *item
+            {
+                Item::Statement(ref statement) if statement.span.start >= offset => Some(statement),
+                _ => None
+            })
+            .collect();
+        let value_type = match statements.last().map(|statement| &statement.kind)
+        {
+            Some(&StatementKind::Expr(ref expr)) => checked.types.expr_types.get(&expr.id).cloned().unwrap_or(Type::Unit),
+            _ => Type::Unit
+        };
+
+        let mut interpreter = Interpreter::new(&checked.program, &self.builtins, output);
+        interpreter.set_call_targets(checked.types.call_targets.clone());
+        let result = interpreter.run_top_level(&mut self.state, &statements);
+        match result
+        {
+            Ok(value) => {
+                self.history = code;
+                Ok(Evaluation {
+                    value: value,
+                    value_type: value_type,
+                    warnings: checked.warnings.into_iter().filter(|d| d.span.start >= offset).collect(),
+                })
+            }
+            Err(e) => Err(ReplError{diagnostics: vec!(e.to_diagnostic()), code: code})
+        }
+    }
+
+    //The type that an expression would have, without running it
+    pub fn type_of(&self, expr: &str) -> Result<Type, ReplError>
+    {
+        let (_, checked) = self.check_input(&format!("{};", expr.trim_end().trim_end_matches(';')))?;
+        let offset = self.history.len();
+        let statement = checked.program.items.iter().rev()
+            .filter_map(|item| match *item { Item::Statement(ref s) if s.span.start >= offset => Some(s), _ => None })
+            .next();
+        match statement.map(|statement| &statement.kind)
+        {
+            Some(&StatementKind::Expr(ref expr)) => Ok(checked.types.expr_types.get(&expr.id).cloned().unwrap_or(Type::Unknown)),
+            _ => {
+                let span = statement.map(|s| s.span).unwrap_or_default();
+                let error = Diagnostic::error(error_codes::SYNTAX_ERROR, String::from("Expected an expression"), span);
+                Err(ReplError{diagnostics: vec!(error), code: format!("{}{}", self.history, expr)})
+            }
+        }
+    }
+
+    //Runs the REPL until the input ends or :quit is typed
+    pub fn run(&mut self, input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()>
+    {
+        let mut buffer = String::new();
+        loop
+        {
+            write!(output, "{}", if buffer.is_empty() {PROMPT} else {CONTINUATION_PROMPT})?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0
+            {
+                return Ok(());
+            }
+
+            if buffer.is_empty() && line.trim_start().starts_with(':')
+            {
+                if !self.command(line.trim(), output)?
+                {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            buffer.push_str(&line);
+            //An empty line ends input that is not complete so that a mistake
+            //doesn't keep asking for more
+            if !is_complete(&buffer) && !line.trim().is_empty()
+            {
+                continue;
+            }
+            let code = mem::take(&mut buffer);
+            if code.trim().is_empty()
+            {
+                continue;
+            }
+
+            match self.eval(code.trim_end(), output)
+            {
+                Ok(evaluation) => {
+                    if !evaluation.warnings.is_empty()
+                    {
+                        let code = self.history.clone();
+                        writeln!(output, "{}", diagnostic::render_all(&evaluation.warnings, REPL_FILE, &code))?;
+                    }
+                    if evaluation.value_type != Type::Unit
+                    {
+                        writeln!(output, "{} : {}", evaluation.value.debug_string(), evaluation.value_type)?;
+                    }
+                }
+                Err(e) => writeln!(output, "{}", e.render())?
+            }
+        }
+    }
+
+    //Runs a command. Returns false if the REPL should stop
+    fn command(&mut self, line: &str, output: &mut dyn Write) -> io::Result<bool>
+    {
+        let (command, argument) = match line.find(char::is_whitespace)
+        {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, "")
+        };
+        match command
+        {
+            ":type" => match self.type_of(argument)
+            {
+                Ok(value_type) => writeln!(output, "{}", value_type)?,
+                Err(e) => writeln!(output, "{}", e.render())?
+            },
+            ":ast" => match print_ast(argument)
+            {
+                Ok(tree) => write!(output, "{}", tree)?,
+                Err(e) => writeln!(output, "{}", e.render())?
+            },
+            ":reset" => {
+                self.reset();
+                writeln!(output, "Everything was forgotten")?;
+            }
+            ":help" => write!(output, "{}", HELP)?,
+            ":quit" | ":q" => return Ok(false),
+            other => writeln!(output, "Unknown command {}, :help lists the commands", other)?
+        }
+        return Ok(true);
+    }
+
+    //Lexes, parses and checks the input after the history. Returns the code that
+    //was checked. The ; at the end of a statement can be left out in the REPL
+    fn check_input(&self, input: &str) -> Result<(String, CheckedProgram), ReplError>
+    {
+        let code = format!("{}{}\n", self.history, input);
+        match self.check_code(&code)
+        {
+            Err(Error::Parse(e)) => {
+                let trimmed = input.trim_end();
+                if trimmed.ends_with(';') || trimmed.ends_with('}')
+                {
+                    return Err(ReplError{diagnostics: vec!(*e), code: code});
+                }
+                let with_semicolon = format!("{}{};\n", self.history, trimmed);
+                match self.check_code(&with_semicolon)
+                {
+                    Ok(checked) => Ok((with_semicolon, checked)),
+                    Err(Error::Parse(_)) => Err(ReplError{diagnostics: vec!(*e), code: code}),
+                    Err(other) => Err(self.error(other, with_semicolon))
+                }
+            }
+            Err(other) => Err(self.error(other, code)),
+            Ok(checked) => Ok((code, checked))
+        }
+    }
+
+    fn check_code(&self, code: &str) -> Result<CheckedProgram, Error>
+    {
+        lex(code)
+            .and_then(parse)
+            .and_then(|program| check(program, &self.builtins, &ValidationOptions::repl()))
+    }
+
+    //Earlier input has already been checked, so warnings about it are left out
+    fn error(&self, error: Error, code: String) -> ReplError
+    {
+        let offset = self.history.len();
+        let diagnostics = error.diagnostics().into_iter()
+            .filter(|d| d.is_error() || d.span.start >= offset)
+            .collect();
+        ReplError{diagnostics: diagnostics, code: code}
+    }
+}
+
+//Whether the code can be run as it is or is waiting for more lines, because a
+//bracket, string or comment has not been closed yet or because it stops in the
+//middle of something, like a function whose body starts on the next line
+pub fn is_complete(code: &str) -> bool
+{
+    let tokens = match lex(code)
+    {
+        Ok(tokens) => tokens,
+        Err(Error::Lex(ref e)) => return e.code != error_codes::UNTERMINATED_STRING && e.code != error_codes::UNTERMINATED_COMMENT,
+        Err(_) => return true
+    };
+    let depth: i64 = tokens.iter()
+        .map(|token| match token.lexeme()
+        {
+            "{" | "(" | "[" => 1,
+            "}" | ")" | "]" => -1,
+            _ => 0
+        })
+        .sum();
+    if depth != 0
+    {
+        return depth < 0;
+    }
+
+    //A missing ; at the end is allowed, anything else missing at the end means
+    //that there is more to come
+    let end = tokens.iter().rev().find(|token| !token.is_trivia()).map(|token| token.span().end).unwrap_or(0);
+    match parse(tokens)
+    {
+        Err(Error::Parse(ref e)) if e.span.start >= end => lex(&format!("{};", code)).and_then(parse).is_ok(),
+        _ => true
+    }
+}
+
+//The parse tree of an expression, or of statements and declarations if the code
+//is not a single expression
+pub fn print_ast(code: &str) -> Result<String, ReplError>
+{
+    let tokens = lex(code).map_err(|e| ReplError{diagnostics: e.diagnostics(), code: String::from(code)})?;
+    if let Ok(expr) = Parser::new(tokens.clone()).parse_lone_expression()
+    {
+        return Ok(printer::print_expr(&expr));
+    }
+    match parser::parse_token_list(tokens)
+    {
+        Ok(program) => Ok(printer::print_program(&program)),
+        Err(e) => {
+            let with_semicolon = format!("{};", code.trim_end());
+            match lex(&with_semicolon).and_then(parse)
+            {
+                Ok(program) => Ok(printer::print_program(&program)),
+                Err(_) => Err(ReplError{diagnostics: vec!(*e), code: String::from(code)})
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod repl_tests
+{
+    use std::io::Cursor;
+
+    use builtins::Builtins;
+    use repl::*;
+
+    fn session(input: &str) -> String
+    {
+        let mut repl = Repl::new(Builtins::new());
+        let mut output = Vec::new();
+        repl.run(&mut Cursor::new(input), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn state_is_kept()
+    {
+        let mut repl = Repl::new(Builtins::new());
+        let mut output = Vec::new();
+        let result = repl.eval("let a = 5", &mut output).unwrap();
+        assert_eq!(result.value_type, Type::Unit);
+        repl.eval("def Number double(Number x) { return x * 2; }", &mut output).unwrap();
+        repl.eval("a = double(a);", &mut output).unwrap();
+        let result = repl.eval("[a, 1]", &mut output).unwrap();
+        assert_eq!((result.value.debug_string(), result.value_type), (String::from("[10, 1]"), Type::list(Type::Number)));
+        repl.eval("print(\"a is \" + a)", &mut output).unwrap_err();
+        repl.eval("print(a)", &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "10\n");
+
+        assert_eq!(repl.type_of("double"), Ok(Type::Function(vec!(Type::Number), Box::new(Type::Number))));
+        assert_eq!(repl.type_of("a > 1;"), Ok(Type::Bool));
+
+        repl.reset();
+        let error = repl.eval("a", &mut Vec::new()).unwrap_err();
+        assert_eq!(error.diagnostics[0].message, "a is not declared");
+    }
+
+    #[test]
+    fn errors_leave_the_state_alone()
+    {
+        let mut repl = Repl::new(Builtins::new());
+        let mut output = Vec::new();
+        let error = repl.eval("let a = 1 / 0;", &mut output).unwrap_err();
+        assert_eq!(error.render(), "\
+error[E0500]: Division by zero
+ --> <repl>:1:9
+  |
+1 | let a = 1 / 0;
+  |         ^^^^^ in <top level>
+");
+        repl.eval("let b = true", &mut output).unwrap();
+        let error = repl.eval("let c = b + 1", &mut output).unwrap_err();
+        assert_eq!(error.diagnostics[0].code, "E0313");
+        assert_eq!(repl.eval("b", &mut output).unwrap().value, Value::Bool(true));
+        //Functions can't see the variables of the REPL
+        let error = repl.eval("def f() { print(b); }", &mut output).unwrap_err();
+        assert_eq!(error.diagnostics[0].message, "b is not declared");
+    }
+
+    #[test]
+    fn complete_input()
+    {
+        assert!(is_complete("let a = 1"));
+        assert!(is_complete("def f() { }"));
+        assert!(!is_complete("def f() {"));
+        assert!(!is_complete("let l = [1,\n2"));
+        assert!(!is_complete("let s = \"abc"));
+        assert!(!is_complete("/* comment"));
+        assert!(is_complete("let a = $"));
+        assert!(is_complete("}"));
+        assert!(!is_complete("def f()"));
+        assert!(!is_complete("let a = 1 +"));
+        assert!(is_complete("let a = 1 + 2"));
+        assert!(is_complete("let a = = 2"));
+    }
+
+    #[test]
+    fn sessions()
+    {
+        let output = session("\
+def Number add(Number a, Number b)
+{
+    return a + b;
+}
+add(1, 2)
+let s = \"two
+lines\"
+:type s
+:ast -1 + 2
+:nothing
+:reset
+:type add
+print(1)
+:quit
+print(2)
+");
+        let expected = "\
+> ... ... ... > 3 : Number
+> ... > String
+> Binary +
+  Unary -
+    Number 1
+  Number 2
+> Unknown command :nothing, :help lists the commands
+> Everything was forgotten
+> error[E0200]: add is not declared
+ --> <repl>:1:1
+  |
+1 | add;
+  | ^^^
+
+> 1
+> ";
+        assert_eq!(output, expected);
+    }
+}
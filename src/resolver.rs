@@ -0,0 +1,593 @@
+use std::collections::HashMap;
+
+use ast::*;
+use diagnostic::Diagnostic;
+use error_codes;
+use script_state::{ScopeKind, ScopeStack};
+use token::Span;
+
+pub type DeclId = usize;
+
+#[derive(Clone,Copy,Eq,PartialEq,Hash,Debug)]
+pub enum DeclKind
+{
+    //Functions provided by the interpreter rather than the script
+    Builtin,
+    Function,
+    //Types are called to make instances and name their static functions
+    Type,
+    Parameter,
+    Variable,
+    LoopVariable,
+}
+
+#[derive(Clone,Eq,PartialEq,Debug)]
+pub struct Declaration
+{
+    pub name: String,
+    pub kind: DeclKind,
+    //The name in the declaration. Builtins have an empty span
+    pub span: Span,
+}
+
+/*
+ * The result of resolving a program. Every identifier expression that refers to
+ * something that was declared is bound to that declaration
+ */
+#[derive(Clone,Debug,Default)]
+pub struct Resolution
+{
+    pub declarations: Vec<Declaration>,
+    pub bindings: HashMap<NodeId, DeclId>,
+    //Declarations by the span of their name, for passes that walk the parse tree
+    //and want to know what a let or a parameter declared
+    pub declared: HashMap<Span, DeclId>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Resolution
+{
+    pub fn declaration_of(&self, id: NodeId) -> Option<&Declaration>
+    {
+        self.bindings.get(&id).map(|decl| &self.declarations[*decl])
+    }
+
+    pub fn declared_at(&self, span: Span) -> Option<DeclId>
+    {
+        self.declared.get(&span).cloned()
+    }
+
+    pub fn has_errors(&self) -> bool
+    {
+        self.diagnostics.iter().any(|d| d.is_error())
+    }
+}
+
+//Resolves all names in the program. builtins are names of functions that are
+//available without being declared in the script
+pub fn resolve(program: &Program, builtins: &[&str]) -> Resolution
+{
+    let mut resolver = Resolver::new();
+    for name in builtins
+    {
+        resolver.declare_global(name, DeclKind::Builtin, Span::default());
+    }
+    resolver.resolve_program(program);
+    return resolver.finish();
+}
+
+/*
+ * Walks the parse tree with a stack of nested scopes to find out which declaration
+ * each name refers to
+ */
+pub struct Resolver
+{
+    scopes: ScopeStack<DeclId>,
+    result: Resolution,
+    //Variables declared further down in the blocks that are currently being
+    //resolved. Used to tell use-before-declaration apart from undeclared names
+    pending: Vec<Vec<(String, Span)>>,
+}
+
+impl Resolver
+{
+    pub fn new() -> Resolver
+    {
+        Resolver {
+            scopes: ScopeStack::new(),
+            result: Resolution::default(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn finish(self) -> Resolution
+    {
+        self.result
+    }
+
+    pub fn declare_global(&mut self, name: &str, kind: DeclKind, span: Span) -> DeclId
+    {
+        let id = self.add_declaration(name, kind, span);
+        if let Some(previous) = self.scopes.global_mut().add_variable(String::from(name), id)
+        {
+            let previous = self.result.declarations[previous].clone();
+            self.report_redeclaration(name, span, &previous);
+        }
+        return id;
+    }
+
+    pub fn resolve_program(&mut self, program: &Program)
+    {
+        //Functions can be called before they are declared so all of them are
+        //added to the global scope before anything else is resolved
+        let mut types = Vec::new();
+        for item in &program.items
+        {
+            match *item
+            {
+                Item::Function(ref function) => {
+                    self.declare_global(&function.name, DeclKind::Function, function.name_span);
+                }
+                //The type checker reports types that are declared twice
+                Item::Type(ref decl) if !types.contains(&&decl.name) => {
+                    types.push(&decl.name);
+                    self.declare_global(&decl.name, DeclKind::Type, decl.name_span);
+                }
+                Item::Type(_) => {}
+                Item::Implement(_) | Item::Statement(_) => {}
+            }
+        }
+
+        for item in &program.items
+        {
+            match *item
+            {
+                Item::Function(ref function) => self.resolve_function(function),
+                Item::Implement(ref implement) => {
+                    for function in &implement.functions
+                    {
+                        self.resolve_function(function);
+                    }
+                }
+                Item::Statement(_) | Item::Type(_) => {}
+            }
+        }
+
+        //Code outside functions runs in a scope of its own, functions can't see
+        //the variables it declares
+        let top_level: Vec<&Statement> = program.items.iter()
+            .filter_map(|item| match *item { Item::Statement(ref s) => Some(s), _ => None })
+            .collect();
+        self.scopes.push(ScopeKind::Block);
+        self.pending.push(collect_declarations(top_level.iter().cloned()));
+        for statement in top_level
+        {
+            self.resolve_statement(statement);
+        }
+        self.pending.pop();
+        self.scopes.pop();
+    }
+
+    fn resolve_function(&mut self, function: &FunctionDecl)
+    {
+        self.scopes.push(ScopeKind::Function);
+        if let Some(receiver) = function.receiver
+        {
+            self.declare("self", DeclKind::Parameter, receiver);
+        }
+        for param in &function.params
+        {
+            self.declare(&param.name, DeclKind::Parameter, param.span);
+        }
+        //The body shares the scope of the parameters
+        self.resolve_statements(&function.body.statements);
+        self.scopes.pop();
+    }
+
+    fn resolve_block(&mut self, block: &Block, kind: ScopeKind)
+    {
+        self.scopes.push(kind);
+        self.resolve_statements(&block.statements);
+        self.scopes.pop();
+    }
+
+    fn resolve_statements(&mut self, statements: &[Statement])
+    {
+        self.pending.push(collect_declarations(statements.iter()));
+        for statement in statements
+        {
+            self.resolve_statement(statement);
+        }
+        self.pending.pop();
+    }
+
+    pub fn resolve_statement(&mut self, statement: &Statement)
+    {
+        match statement.kind
+        {
+            StatementKind::Let{ref name, name_span, ref value, ..} => {
+                //The value is resolved first so that let a = a; refers to an outer a
+                if let Some(ref value) = *value
+                {
+                    self.resolve_expr(value);
+                }
+                if let Some(pending) = self.pending.last_mut()
+                {
+                    pending.retain(|&(ref pending_name, span)| !(pending_name == name && span == name_span));
+                }
+                self.declare(name, DeclKind::Variable, name_span);
+            }
+            StatementKind::Assign{ref target, ref value, ..} => {
+                self.resolve_expr(target);
+                self.resolve_expr(value);
+            }
+            StatementKind::Increment{ref target, ..} => self.resolve_expr(target),
+            StatementKind::Expr(ref expr) => self.resolve_expr(expr),
+            StatementKind::Return(ref value) => {
+                if let Some(ref value) = *value
+                {
+                    self.resolve_expr(value);
+                }
+            }
+            StatementKind::If{ref branches, ref else_block} => {
+                for (condition, block) in branches
+                {
+                    self.resolve_expr(condition);
+                    self.resolve_block(block, ScopeKind::Block);
+                }
+                if let Some(ref block) = *else_block
+                {
+                    self.resolve_block(block, ScopeKind::Block);
+                }
+            }
+            StatementKind::While{ref condition, ref body} => {
+                self.resolve_expr(condition);
+                self.resolve_block(body, ScopeKind::Loop);
+            }
+            StatementKind::For{ref var, var_span, ref start, ref end, ref step, ref body} => {
+                self.resolve_expr(start);
+                self.resolve_expr(end);
+                if let Some(ref step) = *step
+                {
+                    self.resolve_expr(step);
+                }
+                self.resolve_loop(&[(var, var_span)], body);
+            }
+            StatementKind::Foreach{ref var, var_span, ref value, ref container, ref body, ..} => {
+                self.resolve_expr(container);
+                match *value
+                {
+                    Some(ref value) => self.resolve_loop(&[(var, var_span), (&value.name, value.span)], body),
+                    None => self.resolve_loop(&[(var, var_span)], body)
+                }
+            }
+            StatementKind::Block(ref block) => self.resolve_block(block, ScopeKind::Block),
+        }
+    }
+
+    fn resolve_loop(&mut self, vars: &[(&str, Span)], body: &Block)
+    {
+        self.scopes.push(ScopeKind::Loop);
+        for &(var, var_span) in vars
+        {
+            self.declare(var, DeclKind::LoopVariable, var_span);
+        }
+        self.resolve_statements(&body.statements);
+        self.scopes.pop();
+    }
+
+    pub fn resolve_expr(&mut self, expr: &Expr)
+    {
+        match expr.kind
+        {
+            ExprKind::Number(_) | ExprKind::String(_) | ExprKind::Bool(_) => {}
+            ExprKind::Identifier(ref name) => self.resolve_name(expr.id, name, expr.span),
+            ExprKind::List(ref elements) => {
+                for element in elements
+                {
+                    self.resolve_expr(element);
+                }
+            }
+            ExprKind::Dictionary(ref entries) => {
+                for (key, value) in entries
+                {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            }
+            ExprKind::Unary{ref expr, ..} => self.resolve_expr(expr),
+            ExprKind::Binary{ref lhs, ref rhs, ..} => {
+                self.resolve_expr(lhs);
+                self.resolve_expr(rhs);
+            }
+            ExprKind::Call{ref callee, ref args} => {
+                self.resolve_expr(callee);
+                for arg in args
+                {
+                    self.resolve_expr(arg);
+                }
+            }
+            //Members are looked up in the type of the object which is the job of
+            //the type checker
+            ExprKind::Member{ref object, ..} => self.resolve_expr(object),
+            ExprKind::Index{ref object, ref index} => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+        }
+    }
+
+    fn resolve_name(&mut self, id: NodeId, name: &str, span: Span)
+    {
+        if let Some(&decl) = self.scopes.lookup(name)
+        {
+            self.result.bindings.insert(id, decl);
+            return;
+        }
+
+        let later = self.pending.iter().rev()
+            .filter_map(|pending| pending.iter().find(|(pending_name, _)| pending_name == name))
+            .next()
+            .map(|&(_, span)| span);
+        let diagnostic = match later
+        {
+            Some(declaration) => Diagnostic::error(error_codes::USED_BEFORE_DECLARATION,
+                    format!("{} is used before it is declared", name), span)
+                .with_label(declaration, format!("{} is declared here", name)),
+            None if name == "self" => Diagnostic::error(error_codes::NOT_DECLARED, String::from("self is not declared"), span)
+                .with_note(String::from("Only functions in implement blocks that take self as their first parameter have it")),
+            None => {
+                let diagnostic = Diagnostic::error(error_codes::NOT_DECLARED, format!("{} is not declared", name), span);
+                match self.similar_name(name)
+                {
+                    Some(similar) => diagnostic.with_suggestion(format!("a similar name exists: {}", similar), span, similar),
+                    None => diagnostic
+                }
+            }
+        };
+        self.result.diagnostics.push(diagnostic);
+    }
+
+    //The visible name that is closest to a misspelled one, if any is close enough.
+    //Replacing every letter of a name is no misspelling, so x doesn't suggest l
+    fn similar_name(&self, name: &str) -> Option<String>
+    {
+        let length = name.chars().count();
+        let max_distance = (length / 3).max(1);
+        self.scopes.visible_names()
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|&(distance, _)| distance <= max_distance && distance < length)
+            .min_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(b.1)))
+            .map(|(_, candidate)| candidate.clone())
+    }
+
+    fn declare(&mut self, name: &str, kind: DeclKind, span: Span) -> DeclId
+    {
+        if let Some(&previous) = self.scopes.innermost().get(name)
+        {
+            let previous = self.result.declarations[previous].clone();
+            self.report_redeclaration(name, span, &previous);
+        }
+        else if let Some(&previous) = self.scopes.lookup_outer(name)
+        {
+            let previous = &self.result.declarations[previous];
+            let mut diagnostic = Diagnostic::warning(error_codes::SHADOWED_DECLARATION,
+                format!("{} shadows an earlier declaration", name), span);
+            if previous.kind != DeclKind::Builtin
+            {
+                diagnostic = diagnostic.with_label(previous.span, format!("{} is first declared here", name));
+            }
+            self.result.diagnostics.push(diagnostic);
+        }
+
+        let id = self.add_declaration(name, kind, span);
+        self.scopes.add_variable(String::from(name), id);
+        return id;
+    }
+
+    fn add_declaration(&mut self, name: &str, kind: DeclKind, span: Span) -> DeclId
+    {
+        self.result.declarations.push(Declaration{
+            name: String::from(name),
+            kind: kind,
+            span: span
+        });
+        let id = self.result.declarations.len() - 1;
+        if kind != DeclKind::Builtin
+        {
+            self.result.declared.insert(span, id);
+        }
+        return id;
+    }
+
+    fn report_redeclaration(&mut self, name: &str, span: Span, previous: &Declaration)
+    {
+        let mut diagnostic = Diagnostic::error(error_codes::ALREADY_DECLARED, format!("{} is already declared", name), span);
+        if previous.kind != DeclKind::Builtin
+        {
+            diagnostic = diagnostic.with_label(previous.span, format!("{} is first declared here", name));
+        }
+        self.result.diagnostics.push(diagnostic);
+    }
+}
+
+//The number of characters that have to be inserted, removed, replaced or swapped
+//with their neighbour to turn one string into the other
+fn edit_distance(a: &str, b: &str) -> usize
+{
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows: Vec<Vec<usize>> = vec!((0..=b.len()).collect());
+    for i in 1..=a.len()
+    {
+        let mut row = vec!(i);
+        for j in 1..=b.len()
+        {
+            let replace = rows[i - 1][j - 1] + if a[i - 1] == b[j - 1] {0} else {1};
+            let mut distance = replace.min(rows[i - 1][j] + 1).min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1]
+            {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            row.push(distance);
+        }
+        rows.push(row);
+    }
+    return rows[a.len()][b.len()];
+}
+
+//Names of the variables declared directly in a list of statements
+fn collect_declarations<'a, I>(statements: I) -> Vec<(String, Span)>
+    where I: Iterator<Item = &'a Statement>
+{
+    statements.filter_map(|statement| match statement.kind
+        {
+            StatementKind::Let{ref name, name_span, ..} => Some((name.clone(), name_span)),
+            _ => None
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod resolver_tests
+{
+    use diagnostic::Severity;
+    use lexer::Lexer;
+    use parser::parse_token_list;
+    use resolver::*;
+    use token::Span;
+
+    fn resolve_code(code: &str) -> Resolution
+    {
+        let tokens = Lexer::new().tokenize(String::from(code));
+        let program = parse_token_list(tokens).unwrap();
+        resolve(&program, &["print"])
+    }
+
+    fn messages(resolution: &Resolution) -> Vec<(Severity, String)>
+    {
+        resolution.diagnostics.iter().map(|d| (d.severity, d.message.clone())).collect()
+    }
+
+    #[test]
+    fn binds_uses_to_declarations()
+    {
+        let code = "def main() { let a = 1; print(a); }";
+        let resolution = resolve_code(code);
+        assert!(resolution.diagnostics.is_empty());
+
+        let mut names: Vec<(String, DeclKind)> = resolution.bindings.values()
+            .map(|&decl| (resolution.declarations[decl].name.clone(), resolution.declarations[decl].kind))
+            .collect();
+        names.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(names, vec!(
+                (String::from("a"), DeclKind::Variable),
+                (String::from("print"), DeclKind::Builtin),
+            ));
+
+        let a = resolution.declarations.iter().find(|d| d.name == "a").unwrap();
+        assert_eq!(&code[a.span.start..a.span.end], "a");
+    }
+
+    #[test]
+    fn functions_can_be_used_before_their_declaration()
+    {
+        let resolution = resolve_code("def main() { helper(1); } def helper(Number x) { print(x); }");
+        assert!(resolution.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn undeclared_names()
+    {
+        let code = "def main() { print(b); }";
+        let resolution = resolve_code(code);
+        assert_eq!(messages(&resolution), vec!((Severity::Error, String::from("b is not declared"))));
+        assert_eq!(resolution.diagnostics[0].span, Span::new(19, 20));
+    }
+
+    #[test]
+    fn variables_are_not_visible_outside_their_block()
+    {
+        let resolution = resolve_code("def main() { if true { let a = 1; } print(a); }");
+        assert_eq!(messages(&resolution), vec!((Severity::Error, String::from("a is not declared"))));
+
+        let resolution = resolve_code("def main() { for i is 0 to 10 {} print(i); }");
+        assert_eq!(messages(&resolution), vec!((Severity::Error, String::from("i is not declared"))));
+
+        let resolution = resolve_code("def f(Number x) {} def main() { print(x); }");
+        assert_eq!(messages(&resolution), vec!((Severity::Error, String::from("x is not declared"))));
+    }
+
+    #[test]
+    fn self_and_types()
+    {
+        let resolution = resolve_code("type P { Number x; } implement for P { def f(self) { print(self.x); print(P(1)); } }");
+        assert!(resolution.diagnostics.is_empty());
+
+        let resolution = resolve_code("type P { Number x; } implement for P { def f() { print(self.x); } }");
+        assert_eq!(messages(&resolution), vec!((Severity::Error, String::from("self is not declared"))));
+    }
+
+    #[test]
+    fn use_before_declaration()
+    {
+        let code = "def main() { print(a); let a = 1; }";
+        let resolution = resolve_code(code);
+        assert_eq!(messages(&resolution), vec!((Severity::Error, String::from("a is used before it is declared"))));
+        let label = &resolution.diagnostics[0].labels[0];
+        assert_eq!(&code[label.span.start..label.span.end], "a");
+        assert_eq!(label.span.start, 27);
+
+        //Declared later in an enclosing block
+        let resolution = resolve_code("def main() { while true { a = 2; } let a = 1; }");
+        assert_eq!(messages(&resolution), vec!((Severity::Error, String::from("a is used before it is declared"))));
+
+        //The initialiser can't refer to the variable itself
+        let resolution = resolve_code("def main() { let a = a; }");
+        assert_eq!(messages(&resolution), vec!((Severity::Error, String::from("a is used before it is declared"))));
+    }
+
+    #[test]
+    fn shadowing_and_redeclaration()
+    {
+        let code = "def main() { let a = 1; if true { let a = 2; print(a); } }";
+        let resolution = resolve_code(code);
+        assert_eq!(messages(&resolution), vec!((Severity::Warning, String::from("a shadows an earlier declaration"))));
+        assert_eq!(resolution.diagnostics[0].labels[0].span, Span::new(17, 18));
+
+        let resolution = resolve_code("def f(Number x) { let x = 2; }");
+        assert_eq!(messages(&resolution), vec!((Severity::Error, String::from("x is already declared"))));
+
+        let resolution = resolve_code("def main() { let a = 1; let a = 2; }");
+        assert_eq!(messages(&resolution), vec!((Severity::Error, String::from("a is already declared"))));
+
+        let resolution = resolve_code("def f() {} def f() {}");
+        assert_eq!(messages(&resolution), vec!((Severity::Error, String::from("f is already declared"))));
+
+        //Loop variables live in the same scope as the body of the loop
+        let resolution = resolve_code("def main() { foreach a in [1] { let a = 2; } }");
+        assert_eq!(messages(&resolution), vec!((Severity::Error, String::from("a is already declared"))));
+    }
+
+    #[test]
+    fn similar_names_are_suggested()
+    {
+        let resolution = resolve_code("def main() { let count = 1; print(cuont); print(total); }");
+        assert_eq!(resolution.diagnostics.len(), 2);
+        let suggestion = &resolution.diagnostics[0].suggestions[0];
+        assert_eq!(suggestion.replacement, "count");
+        assert_eq!(suggestion.span, Span::new(34, 39));
+        assert!(resolution.diagnostics[1].suggestions.is_empty());
+
+        //One letter names have nothing in common with other one letter names
+        let resolution = resolve_code("def main() { let l = 1; print(x); let ab = 2; print(ac); }");
+        assert_eq!(resolution.diagnostics.len(), 2);
+        assert!(resolution.diagnostics[0].suggestions.is_empty());
+        assert_eq!(resolution.diagnostics[1].suggestions[0].replacement, "ab");
+
+        assert_eq!(edit_distance("count", "count"), 0);
+        assert_eq!(edit_distance("cuont", "count"), 1);
+        assert_eq!(edit_distance("cont", "count"), 1);
+        assert_eq!(edit_distance("total", "count"), 4);
+    }
+}
@@ -1,23 +1,241 @@
+
 use std::collections::HashMap;
 
-use variable::Variable;
+use token::Span;
+use types::Type;
+use variable::{Value, Variable};
+
+//What kind of code a scope belongs to. Loops and functions matter for things
+//like return and break which look past block scopes
+#[derive(Clone,Copy,Eq,PartialEq,Hash,Debug)]
+pub enum ScopeKind
+{
+    Global,
+    Function,
+    Block,
+    Loop,
+}
 
-struct Scope 
+/*
+ * A single level of nested variables, for example the body of a function or of an if
+ * statement. The contents are generic so that the passes before execution can keep
+ * information about declarations in the same structure that holds variables at runtime
+ */
+pub struct Scope<T = Variable>
 {
-    variables: HashMap<String, Variable>
+    kind: ScopeKind,
+    variables: HashMap<String, T>
 }
 
-impl Scope 
+impl<T> Scope<T>
 {
-    pub fn new() -> Scope 
+    pub fn new(kind: ScopeKind) -> Scope<T>
     {
         Scope {
+            kind: kind,
             variables: HashMap::new()
         }
     }
 
-    pub fn add_variable(var: Variable) 
+    pub fn kind(&self) -> ScopeKind
+    {
+        self.kind
+    }
+
+    //Adds a variable to the scope, returning the variable that was previously
+    //declared with the same name
+    pub fn add_variable(&mut self, name: String, var: T) -> Option<T>
+    {
+        self.variables.insert(name, var)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&T>
+    {
+        self.variables.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut T>
+    {
+        self.variables.get_mut(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String>
+    {
+        self.variables.keys()
+    }
+}
+
+/*
+ * Scopes nested inside each other, innermost last. Lookups go from the innermost
+ * scope outwards
+ */
+pub struct ScopeStack<T = Variable>
+{
+    scopes: Vec<Scope<T>>
+}
+
+impl<T> ScopeStack<T>
+{
+    pub fn new() -> ScopeStack<T>
+    {
+        ScopeStack::with_root(ScopeKind::Global)
+    }
+
+    //Creates a stack where the outermost scope is of the specified kind
+    pub fn with_root(kind: ScopeKind) -> ScopeStack<T>
+    {
+        ScopeStack {
+            scopes: vec!(Scope::new(kind))
+        }
+    }
+
+    pub fn push(&mut self, kind: ScopeKind)
+    {
+        self.scopes.push(Scope::new(kind));
+    }
+
+    pub fn pop(&mut self) -> Option<Scope<T>>
+    {
+        //The outermost scope lives as long as the stack
+        if self.scopes.len() == 1
+        {
+            return None;
+        }
+        self.scopes.pop()
+    }
+
+    pub fn depth(&self) -> usize
+    {
+        self.scopes.len()
+    }
+
+    pub fn innermost(&self) -> &Scope<T>
+    {
+        self.scopes.last().unwrap()
+    }
+
+    pub fn innermost_mut(&mut self) -> &mut Scope<T>
+    {
+        self.scopes.last_mut().unwrap()
+    }
+
+    pub fn global(&self) -> &Scope<T>
+    {
+        &self.scopes[0]
+    }
+
+    pub fn global_mut(&mut self) -> &mut Scope<T>
+    {
+        &mut self.scopes[0]
+    }
+
+    //Adds a variable to the innermost scope
+    pub fn add_variable(&mut self, name: String, var: T) -> Option<T>
+    {
+        self.innermost_mut().add_variable(name, var)
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&T>
+    {
+        self.scopes.iter().rev().filter_map(|scope| scope.get(name)).next()
+    }
+
+    pub fn lookup_mut(&mut self, name: &str) -> Option<&mut T>
+    {
+        self.scopes.iter_mut().rev().filter_map(|scope| scope.get_mut(name)).next()
+    }
+
+    //Looks for a variable in all scopes except the innermost one
+    pub fn lookup_outer(&self, name: &str) -> Option<&T>
+    {
+        let count = self.scopes.len() - 1;
+        self.scopes[..count].iter().rev().filter_map(|scope| scope.get(name)).next()
+    }
+
+    //Every name that a lookup can find, innermost scope first
+    pub fn visible_names(&self) -> impl Iterator<Item = &String>
+    {
+        self.scopes.iter().rev().flat_map(|scope| scope.names())
+    }
+}
+
+/*
+ * A call to a function that is currently running
+ */
+pub struct CallFrame
+{
+    pub function: String,
+    //Where the function was called from. Empty for the call to main
+    pub call_site: Span,
+    pub scopes: ScopeStack,
+}
+
+/*
+ * Everything that changes while a script runs
+ */
+pub struct ScriptState
+{
+    frames: Vec<CallFrame>,
+}
+
+impl ScriptState
+{
+    pub fn new() -> ScriptState
+    {
+        ScriptState {
+            frames: Vec::new()
+        }
+    }
+
+    pub fn push_frame(&mut self, function: String, call_site: Span)
+    {
+        self.frames.push(CallFrame{
+            function: function,
+            call_site: call_site,
+            scopes: ScopeStack::with_root(ScopeKind::Function)
+        });
+    }
+
+    pub fn pop_frame(&mut self) -> Option<CallFrame>
+    {
+        self.frames.pop()
+    }
+
+    //The function calls that are running, outermost first
+    pub fn frames(&self) -> &[CallFrame]
+    {
+        &self.frames
+    }
+
+    pub fn push_scope(&mut self, kind: ScopeKind)
+    {
+        self.current_frame_mut().scopes.push(kind);
+    }
+
+    pub fn pop_scope(&mut self)
+    {
+        self.current_frame_mut().scopes.pop();
+    }
+
+    //Declares a variable in the innermost scope of the running function
+    pub fn declare(&mut self, name: String, declared_type: Type, value: Value)
+    {
+        let variable = Variable::new(name.clone(), declared_type, value);
+        self.current_frame_mut().scopes.add_variable(name, variable);
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Variable>
+    {
+        self.frames.last().and_then(|frame| frame.scopes.lookup(name))
+    }
+
+    pub fn lookup_mut(&mut self, name: &str) -> Option<&mut Variable>
+    {
+        self.frames.last_mut().and_then(|frame| frame.scopes.lookup_mut(name))
+    }
+
+    fn current_frame_mut(&mut self) -> &mut CallFrame
     {
-        
+        self.frames.last_mut().expect("No function is running")
     }
 }
@@ -0,0 +1,525 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use builtins::{BuiltinFunction, NativeFunction};
+use convert::IntoNative;
+use interpreter::RuntimeError;
+use number::{Number, NumberResult};
+use token::Span;
+use types::Type;
+use variable::{Dictionary, Value};
+
+/*
+ * The standard library. Its functions are methods of the built in types, so they
+ * are called like s.split(",") or list.push(1). They are named Type.name in the
+ * same way as the functions of implement blocks and get the value before the . as
+ * their first argument
+ */
+
+//The methods that change the value they are called on. Like assigning to an
+//element, they can't be used on a container that a foreach loop goes through
+const CHANGING_METHODS: &'static [&'static str] = &[
+    "List.push", "List.pop", "List.insert", "List.remove", "List.sort",
+    "Dictionary.remove", "Dictionary.merge",
+];
+
+//The methods that call a function of the script. Builtins can't do that on their
+//own, so the engines run them through call_with_functions
+const FUNCTION_METHODS: &'static [&'static str] = &["List.sort", "List.map", "List.filter", "List.reduce"];
+
+pub fn changes_receiver(name: &str) -> bool
+{
+    CHANGING_METHODS.contains(&name)
+}
+
+pub fn takes_functions(name: &str) -> bool
+{
+    FUNCTION_METHODS.contains(&name)
+}
+
+fn method<Args, F: IntoNative<Args>>(name: &str, function: F) -> BuiltinFunction
+{
+    BuiltinFunction::native(name, F::params(), F::return_type(), function.into_native())
+}
+
+//A method whose signature uses type parameters, which can't be worked out from
+//the Rust types of the closure
+fn generic<F>(name: &str, params: Vec<Type>, return_type: Type, function: F) -> BuiltinFunction
+    where F: Fn(Vec<Value>) -> Result<Value, String> + 'static
+{
+    let native: NativeFunction = Rc::new(function);
+    BuiltinFunction::native(name, params, return_type, native)
+}
+
+//A method that calls a function of the script, see call_with_functions
+fn calling(name: &str, params: Vec<Type>, return_type: Type) -> BuiltinFunction
+{
+    BuiltinFunction{name: String::from(name), params: params, return_type: return_type, native: None, capability: None}
+}
+
+fn function(params: Vec<Type>, return_type: Type) -> Type
+{
+    Type::Function(params, Box::new(return_type))
+}
+
+//Every method of the standard library
+pub fn stdlib_functions() -> Vec<BuiltinFunction>
+{
+    let mut functions = number_methods();
+    functions.extend(string_methods());
+    functions.extend(list_methods());
+    functions.extend(dictionary_methods());
+    return functions;
+}
+
+////////////////////////////////////////////////////////////////////////////////
+//                      Numbers
+////////////////////////////////////////////////////////////////////////////////
+fn number_methods() -> Vec<BuiltinFunction>
+{
+    vec!(
+        method("Number.abs", |n: Number| if n < Number::Integer(0) {n.neg()} else {Ok(n)}),
+        method("Number.min", |n: Number, other: Number| if other < n {other} else {n}),
+        method("Number.max", |n: Number, other: Number| if other > n {other} else {n}),
+        method("Number.floor", |n: Number| rounded(n, f64::floor)),
+        method("Number.ceil", |n: Number| rounded(n, f64::ceil)),
+        //Halves are rounded away from 0
+        method("Number.round", |n: Number| rounded(n, f64::round)),
+        method("Number.sqrt", |n: f64| real("The square root", n, n.sqrt())),
+        method("Number.pow", pow),
+        method("Number.sin", |n: f64| n.sin()),
+        method("Number.cos", |n: f64| n.cos()),
+        method("Number.tan", |n: f64| real("The tangent", n, n.tan())),
+        method("Number.asin", |n: f64| real("The arcsine", n, n.asin())),
+        method("Number.acos", |n: f64| real("The arccosine", n, n.acos())),
+        method("Number.atan", |n: f64| n.atan()),
+        method("Number.to_string", |n: Number| n.to_string()),
+        method("Number.format", format),
+    )
+}
+
+//Whole numbers are already rounded. Floats become integers when they fit
+fn rounded(n: Number, round: fn(f64) -> f64) -> NumberResult
+{
+    match n
+    {
+        Number::Integer(_) => Ok(n),
+        Number::Float(f) => {
+            let whole = round(f);
+            match Number::Float(whole).as_i64()
+            {
+                Some(integer) => Ok(Number::Integer(integer)),
+                None => Number::float(whole)
+            }
+        }
+    }
+}
+
+//Results that are not a number, like the square root of -1
+fn real(what: &str, n: f64, result: f64) -> NumberResult
+{
+    if result.is_nan()
+    {
+        return Err(format!("{} of {} is not a Number", what, Number::Float(n)));
+    }
+    Number::float(result)
+}
+
+//Integers to a whole power that isn't negative stay exact as long as they fit
+fn pow(n: Number, exponent: Number) -> NumberResult
+{
+    if let (Number::Integer(base), Some(power)) = (n, exponent.as_i64())
+    {
+        if power >= 0 && power <= i64::from(u32::MAX)
+        {
+            if let Some(result) = base.checked_pow(power as u32)
+            {
+                return Ok(Number::Integer(result));
+            }
+        }
+    }
+    let result = n.as_f64().powf(exponent.as_f64());
+    if result.is_nan()
+    {
+        return Err(format!("{} to the power of {} is not a Number", n, exponent));
+    }
+    Number::float(result)
+}
+
+//The number with a fixed number of decimals
+fn format(n: Number, decimals: i64) -> Result<String, String>
+{
+    if !(0..=100).contains(&decimals)
+    {
+        return Err(format!("Numbers can be formatted with 0 to 100 decimals, not {}", decimals));
+    }
+    match n
+    {
+        //Integers are formatted exactly, even the ones a float can't hold
+        Number::Integer(integer) if decimals == 0 => Ok(integer.to_string()),
+        Number::Integer(integer) => Ok(format!("{}.{}", integer, "0".repeat(decimals as usize))),
+        Number::Float(float) => Ok(format!("{:.*}", decimals as usize, float))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+//                      Strings
+////////////////////////////////////////////////////////////////////////////////
+
+//Positions in strings count characters, not bytes
+fn string_methods() -> Vec<BuiltinFunction>
+{
+    vec!(
+        method("String.length", |s: String| s.chars().count() as i64),
+        //The characters from start up to but not including end
+        method("String.slice", |s: String, start: i64, end: i64| {
+            let length = s.chars().count() as i64;
+            if start < 0 || start > end || end > length
+            {
+                return Err(format!("The slice {} to {} is out of bounds for a length of {}", start, end, length));
+            }
+            Ok(s.chars().skip(start as usize).take((end - start) as usize).collect::<String>())
+        }),
+        method("String.split", |s: String, separator: String| {
+            if separator.is_empty()
+            {
+                return Err(String::from("Can not split at an empty separator"));
+            }
+            Ok(s.split(separator.as_str()).map(String::from).collect::<Vec<String>>())
+        }),
+        method("String.trim", |s: String| String::from(s.trim())),
+        //The position of the first occurrence, or -1 if there is none
+        method("String.find", |s: String, part: String| match s.find(part.as_str())
+        {
+            Some(byte) => s[..byte].chars().count() as i64,
+            None => -1
+        }),
+        method("String.replace", |s: String, from: String, to: String| {
+            if from.is_empty()
+            {
+                return Err(String::from("Can not replace an empty string"));
+            }
+            Ok(s.replace(from.as_str(), &to))
+        }),
+        method("String.upper", |s: String| s.to_uppercase()),
+        method("String.lower", |s: String| s.to_lowercase()),
+        //Numbers are written like they are in scripts, with an optional -
+        method("String.to_number", |s: String| parse_number(&s).ok_or_else(|| format!("\"{}\" is not a Number", s))),
+        method("String.is_number", |s: String| parse_number(&s).is_some()),
+    )
+}
+
+fn parse_number(s: &str) -> Option<Number>
+{
+    match s.strip_prefix('-')
+    {
+        Some(positive) if !positive.starts_with('-') => parse_number(positive).and_then(|n| n.neg().ok()),
+        Some(_) => None,
+        None if s.starts_with(|c: char| c.is_ascii_digit()) => Number::parse(s),
+        None => None
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+//                      Lists
+////////////////////////////////////////////////////////////////////////////////
+fn list_methods() -> Vec<BuiltinFunction>
+{
+    let t = || Type::parameter("T");
+    let u = || Type::parameter("U");
+    let list = |element: Type| Type::list(element);
+    vec!(
+        generic("List.length", vec!(list(t())), Type::Number,
+            |args| Ok(Value::integer(elements(&args[0])?.borrow().len() as i64))),
+        generic("List.push", vec!(list(t()), t()), Type::Unit, |args| {
+            elements(&args[0])?.borrow_mut().push(args[1].clone());
+            Ok(Value::Unit)
+        }),
+        generic("List.pop", vec!(list(t())), t(),
+            |args| elements(&args[0])?.borrow_mut().pop().ok_or_else(|| String::from("Can not pop from an empty list"))),
+        //Elements from index on move one place up. The index can be the length to add at the end
+        generic("List.insert", vec!(list(t()), Type::Number, t()), Type::Unit, |args| {
+            let elements = elements(&args[0])?;
+            let index = position(&args[1], elements.borrow().len() + 1)?;
+            elements.borrow_mut().insert(index, args[2].clone());
+            Ok(Value::Unit)
+        }),
+        generic("List.remove", vec!(list(t()), Type::Number), t(), |args| {
+            let elements = elements(&args[0])?;
+            let index = position(&args[1], elements.borrow().len())?;
+            let removed = elements.borrow_mut().remove(index);
+            Ok(removed)
+        }),
+        generic("List.contains", vec!(list(t()), t()), Type::Bool,
+            |args| Ok(Value::Bool(elements(&args[0])?.borrow().contains(&args[1])))),
+        generic("List.join", vec!(list(Type::String), Type::String), Type::String, |args| {
+            let parts: Vec<String> = elements(&args[0])?.borrow().iter().map(|e| e.to_string()).collect();
+            Ok(Value::String(parts.join(&args[1].to_string())))
+        }),
+
+        //Sorts the list in place. The function tells if its first argument goes
+        //before its second. Elements that are equal keep their order
+        calling("List.sort", vec!(list(t()), function(vec!(t(), t()), Type::Bool)), Type::Unit),
+        calling("List.map", vec!(list(t()), function(vec!(t()), u())), list(u())),
+        calling("List.filter", vec!(list(t()), function(vec!(t()), Type::Bool)), list(t())),
+        //Combines the elements from the first to the last, starting with initial
+        calling("List.reduce", vec!(list(t()), u(), function(vec!(u(), t()), u())), u()),
+    )
+}
+
+fn elements(value: &Value) -> Result<Rc<RefCell<Vec<Value>>>, String>
+{
+    match *value
+    {
+        Value::List(ref elements) => Ok(elements.clone()),
+        ref other => Err(format!("Expected a List but found {}", other.type_name()))
+    }
+}
+
+//A position in a list that has to be below end
+fn position(index: &Value, end: usize) -> Result<usize, String>
+{
+    let number = index.as_number()?;
+    match number.as_i64()
+    {
+        Some(i) if i >= 0 && (i as u64) < end as u64 => Ok(i as usize),
+        _ => Err(format!("Index {} is out of bounds for a length of {}", number, end))
+    }
+}
+
+//Runs a function value of the script with the arguments
+pub type FunctionCall<'a> = dyn FnMut(&Value, Vec<Value>) -> Result<Value, RuntimeError> + 'a;
+
+//Runs the methods that call functions of the script.
+//The engines make sure that the functions can't change the list
+pub fn call_with_functions(name: &str, args: Vec<Value>, call: &mut FunctionCall, call_site: Span) -> Result<Value, RuntimeError>
+{
+    let error = |message: String| RuntimeError::new(message, call_site);
+    let list = elements(&args[0]).map_err(error)?;
+    let values = list.borrow().clone();
+    let function = &args[1];
+    match name
+    {
+        "List.sort" => {
+            let sorted = merge_sort(values, &mut |a, b| call(function, vec!(a.clone(), b.clone()))?.as_bool().map_err(error))?;
+            *list.borrow_mut() = sorted;
+            Ok(Value::Unit)
+        }
+        "List.map" => {
+            let mut mapped = Vec::new();
+            for value in values
+            {
+                mapped.push(call(function, vec!(value))?);
+            }
+            Ok(Value::list(mapped))
+        }
+        "List.filter" => {
+            let mut kept = Vec::new();
+            for value in values
+            {
+                if call(function, vec!(value.clone()))?.as_bool().map_err(error)?
+                {
+                    kept.push(value);
+                }
+            }
+            Ok(Value::list(kept))
+        }
+        "List.reduce" => {
+            let function = &args[2];
+            let mut result = args[1].clone();
+            for value in values
+            {
+                result = call(function, vec!(result, value))?;
+            }
+            Ok(result)
+        }
+        _ => Err(error(format!("There is no function called {}", name)))
+    }
+}
+
+//A stable merge sort. Unlike the sorts of the standard library it works with a
+//function that contradicts itself, which only gives some order of the elements
+fn merge_sort(mut elements: Vec<Value>, less: &mut dyn FnMut(&Value, &Value) -> Result<bool, RuntimeError>)
+    -> Result<Vec<Value>, RuntimeError>
+{
+    if elements.len() <= 1
+    {
+        return Ok(elements);
+    }
+    let right = elements.split_off(elements.len() / 2);
+    let mut left = merge_sort(elements, less)?.into_iter().peekable();
+    let mut right = merge_sort(right, less)?.into_iter().peekable();
+
+    let mut merged = Vec::new();
+    //The right element only goes first if it is less, which keeps equal elements in order
+    while let (Some(l), Some(r)) = (left.peek(), right.peek())
+    {
+        let right_first = less(r, l)?;
+        merged.push(if right_first {right.next()} else {left.next()}.unwrap());
+    }
+    merged.extend(left);
+    merged.extend(right);
+    return Ok(merged);
+}
+
+////////////////////////////////////////////////////////////////////////////////
+//                      Dictionaries
+////////////////////////////////////////////////////////////////////////////////
+fn dictionary_methods() -> Vec<BuiltinFunction>
+{
+    let k = || Type::parameter("K");
+    let v = || Type::parameter("V");
+    let dictionary = || Type::dictionary(k(), v());
+    vec!(
+        generic("Dictionary.length", vec!(dictionary()), Type::Number,
+            |args| Ok(Value::integer(entries(&args[0])?.borrow().len() as i64))),
+        //Keys and values are in the order the keys were added
+        generic("Dictionary.keys", vec!(dictionary()), Type::list(k()),
+            |args| Ok(Value::list(entries(&args[0])?.borrow().keys()))),
+        generic("Dictionary.values", vec!(dictionary()), Type::list(v()),
+            |args| Ok(Value::list(entries(&args[0])?.borrow().values()))),
+        generic("Dictionary.has", vec!(dictionary(), k()), Type::Bool,
+            |args| Ok(Value::Bool(entries(&args[0])?.borrow().get(&args[1]).is_some()))),
+        //Removes the key and returns its value
+        generic("Dictionary.remove", vec!(dictionary(), k()), v(), |args| {
+            let removed = entries(&args[0])?.borrow_mut().remove(&args[1]);
+            removed.ok_or_else(|| format!("The key {} is not in the dictionary", args[1].debug_string()))
+        }),
+        //Adds the entries of the other dictionary, replacing the values of keys that are in both
+        generic("Dictionary.merge", vec!(dictionary(), dictionary()), Type::Unit, |args| {
+            let added = entries(&args[1])?.borrow().entries().to_vec();
+            let dictionary = entries(&args[0])?;
+            for (key, value) in added
+            {
+                dictionary.borrow_mut().insert(key, value);
+            }
+            Ok(Value::Unit)
+        }),
+    )
+}
+
+fn entries(value: &Value) -> Result<Rc<RefCell<Dictionary>>, String>
+{
+    match *value
+    {
+        Value::Dictionary(ref dictionary) => Ok(dictionary.clone()),
+        ref other => Err(format!("Expected a Dictionary but found {}", other.type_name()))
+    }
+}
+
+#[cfg(test)]
+mod stdlib_tests
+{
+    use builtins::Builtins;
+    use interpreter::RuntimeError;
+    use number::Number;
+    use stdlib::*;
+    use token::Span;
+    use variable::{Dictionary, Value};
+
+    fn call(name: &str, args: Vec<Value>) -> Result<Value, String>
+    {
+        Builtins::new().call(name, args, &mut Vec::new())
+    }
+
+    fn string(s: &str) -> Value
+    {
+        Value::String(String::from(s))
+    }
+
+    fn float(f: f64) -> Value
+    {
+        Value::Number(Number::Float(f))
+    }
+
+    fn numbers(numbers: &[i64]) -> Value
+    {
+        Value::list(numbers.iter().map(|&n| Value::integer(n)).collect())
+    }
+
+    #[test]
+    fn numbers_stay_exact()
+    {
+        assert_eq!(call("Number.abs", vec!(Value::integer(-3))), Ok(Value::integer(3)));
+        assert_eq!(call("Number.round", vec!(float(2.5))), Ok(Value::integer(3)));
+        assert_eq!(call("Number.round", vec!(float(-2.5))), Ok(Value::integer(-3)));
+        assert_eq!(call("Number.floor", vec!(float(-1.5))), Ok(Value::integer(-2)));
+        assert_eq!(call("Number.max", vec!(Value::integer(2), float(2.5))), Ok(float(2.5)));
+        assert_eq!(call("Number.pow", vec!(Value::integer(3), Value::integer(4))), Ok(Value::integer(81)));
+        assert_eq!(call("Number.pow", vec!(Value::integer(2), Value::integer(-1))), Ok(float(0.5)));
+        assert_eq!(call("Number.pow", vec!(Value::integer(2), Value::integer(64))), Ok(float(18446744073709551616.0)));
+        assert_eq!(call("Number.format", vec!(float(2.0 / 3.0), Value::integer(2))), Ok(string("0.67")));
+        assert_eq!(call("Number.format", vec!(Value::integer(9007199254740993), Value::integer(2))),
+            Ok(string("9007199254740993.00")));
+        assert_eq!(call("Number.format", vec!(Value::integer(-7), Value::integer(1))), Ok(string("-7.0")));
+        assert_eq!(call("Number.to_string", vec!(float(0.5))), Ok(string("0.5")));
+
+        assert_eq!(call("Number.sqrt", vec!(Value::integer(-4))), Err(String::from("The square root of -4 is not a Number")));
+        assert_eq!(call("Number.pow", vec!(Value::integer(-8), float(0.5))), Err(String::from("-8 to the power of 0.5 is not a Number")));
+        assert_eq!(call("Number.format", vec!(Value::integer(1), Value::integer(-1))),
+            Err(String::from("Numbers can be formatted with 0 to 100 decimals, not -1")));
+    }
+
+    #[test]
+    fn strings_count_characters()
+    {
+        assert_eq!(call("String.length", vec!(string("héllo"))), Ok(Value::integer(5)));
+        assert_eq!(call("String.slice", vec!(string("héllo"), Value::integer(1), Value::integer(3))), Ok(string("él")));
+        assert_eq!(call("String.find", vec!(string("héllo"), string("l"))), Ok(Value::integer(2)));
+        assert_eq!(call("String.find", vec!(string("hello"), string("x"))), Ok(Value::integer(-1)));
+        assert_eq!(call("String.split", vec!(string("a,,b"), string(","))), Ok(Value::list(vec!(string("a"), string(""), string("b")))));
+        assert_eq!(call("String.slice", vec!(string("abc"), Value::integer(2), Value::integer(4))),
+            Err(String::from("The slice 2 to 4 is out of bounds for a length of 3")));
+        assert!(call("String.split", vec!(string("abc"), string(""))).is_err());
+
+        assert_eq!(call("String.to_number", vec!(string("-12"))), Ok(Value::integer(-12)));
+        assert_eq!(call("String.to_number", vec!(string("2.5"))), Ok(float(2.5)));
+        assert_eq!(call("String.to_number", vec!(string(" 1"))), Err(String::from("\" 1\" is not a Number")));
+        assert_eq!(call("String.is_number", vec!(string("--1"))), Ok(Value::Bool(false)));
+        assert_eq!(call("String.is_number", vec!(string("inf"))), Ok(Value::Bool(false)));
+    }
+
+    #[test]
+    fn lists_and_dictionaries_are_changed_in_place()
+    {
+        let list = numbers(&[1, 2]);
+        call("List.insert", vec!(list.clone(), Value::integer(2), Value::integer(3))).unwrap();
+        assert_eq!(call("List.remove", vec!(list.clone(), Value::integer(0))), Ok(Value::integer(1)));
+        assert_eq!(list, numbers(&[2, 3]));
+        assert_eq!(call("List.insert", vec!(list.clone(), Value::integer(3), Value::integer(4))),
+            Err(String::from("Index 3 is out of bounds for a length of 3")));
+
+        let mut entries = Dictionary::new();
+        entries.insert(string("a"), Value::integer(1));
+        let dictionary = Value::dictionary(entries);
+        //Merging a dictionary into itself changes nothing
+        call("Dictionary.merge", vec!(dictionary.clone(), dictionary.clone())).unwrap();
+        assert_eq!(call("Dictionary.length", vec!(dictionary.clone())), Ok(Value::integer(1)));
+        assert_eq!(call("Dictionary.remove", vec!(dictionary.clone(), string("b"))),
+            Err(String::from("The key \"b\" is not in the dictionary")));
+        assert_eq!(call("Dictionary.remove", vec!(dictionary.clone(), string("a"))), Ok(Value::integer(1)));
+    }
+
+    #[test]
+    fn sorting_is_stable()
+    {
+        //Sorted by their tens only, so the order of the ones shows which elements moved
+        let list = numbers(&[31, 10, 22, 11, 30, 20]);
+        let mut by_tens = |_: &Value, args: Vec<Value>| -> Result<Value, RuntimeError> {
+            let tens = |value: &Value| value.as_number().unwrap().as_i64().unwrap() / 10;
+            Ok(Value::Bool(tens(&args[0]) < tens(&args[1])))
+        };
+        call_with_functions("List.sort", vec!(list.clone(), Value::Unit), &mut by_tens, Span::default()).unwrap();
+        assert_eq!(list, numbers(&[10, 11, 22, 20, 31, 30]));
+
+        //A function that contradicts itself still gives every element once
+        let mut always = |_: &Value, _: Vec<Value>| -> Result<Value, RuntimeError> { Ok(Value::Bool(true)) };
+        call_with_functions("List.sort", vec!(list.clone(), Value::Unit), &mut always, Span::default()).unwrap();
+        let mut sorted = list.iteration_values().unwrap();
+        sorted.sort_by(|a, b| a.as_number().unwrap().partial_cmp(&b.as_number().unwrap()).unwrap());
+        assert_eq!(Value::list(sorted), numbers(&[10, 11, 20, 22, 30, 31]));
+
+        assert_eq!(call("List.sort", vec!(list, Value::Unit)),
+            Err(String::from("List.sort calls functions of the script, so only a running script can call it")));
+    }
+}
@@ -22,25 +22,122 @@ pub enum TokenType {
     CloseCurl,
     OpenSq,
     CloseSq,
+    Comma,
+    Dot,
+    Colon,
+}
+
+/*
+   A region of the source code, stored as byte offsets into the code that was
+   passed to the lexer
+ */
+#[derive(Clone,Copy,Eq,PartialEq,Hash,Debug,Default)]
+pub struct Span
+{
+    pub start: usize,
+    pub end: usize,
+}
+impl Span
+{
+    pub fn new(start: usize, end: usize) -> Span
+    {
+        Span {
+            start: start,
+            end: end
+        }
+    }
+
+    //Returns the smallest span that covers both self and other
+    pub fn to(&self, other: Span) -> Span
+    {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    //Returns the 1-based line and column of the start of the span
+    pub fn line_column(&self, code: &str) -> (usize, usize)
+    {
+        let mut line = 1;
+        let mut column = 1;
+        for (offset, c) in code.char_indices()
+        {
+            if offset >= self.start
+            {
+                break;
+            }
+            if c == '\n'
+            {
+                line += 1;
+                column = 1;
+            }
+            else
+            {
+                column += 1;
+            }
+        }
+        return (line, column);
+    }
 }
 
 /*
    Represents a token of the language that is created by the lexer
    and parsed by the parser
  */
-#[derive(Clone,Eq,PartialEq,Debug)]
+#[derive(Clone,Debug)]
 pub struct Token
 {
     lexeme: String, //The 'contents' of the token
     token_type: TokenType, //The type of the token
+    span: Span, //Where in the code the token was found
 }
-impl Token 
+impl Token
 {
-    pub fn new(lexeme: String, token_type: TokenType) -> Token 
+    pub fn new(lexeme: String, token_type: TokenType) -> Token
+    {
+        Token {
+            lexeme: lexeme,
+            token_type: token_type,
+            span: Span::default()
+        }
+    }
+
+    pub fn with_span(lexeme: String, token_type: TokenType, span: Span) -> Token
     {
         Token {
             lexeme: lexeme,
-            token_type: token_type
+            token_type: token_type,
+            span: span
         }
     }
+
+    pub fn lexeme(&self) -> &str
+    {
+        &self.lexeme
+    }
+
+    pub fn token_type(&self) -> TokenType
+    {
+        self.token_type
+    }
+
+    pub fn span(&self) -> Span
+    {
+        self.span
+    }
+
+    //Whitespace and comments carry no meaning for the parser
+    pub fn is_trivia(&self) -> bool
+    {
+        matches!(self.token_type, TokenType::Whitespace | TokenType::LineComment | TokenType::BlockComment)
+    }
+}
+
+//Two tokens are the same token if they have the same contents, regardless of
+//where they were found
+impl PartialEq for Token
+{
+    fn eq(&self, other: &Token) -> bool
+    {
+        self.lexeme == other.lexeme && self.token_type == other.token_type
+    }
 }
+impl Eq for Token {}
@@ -0,0 +1,2014 @@
+use std::collections::HashMap;
+use std::slice;
+
+use ast::*;
+use builtins::{missing_capability_message, Builtins};
+use capabilities::Capability;
+use diagnostic::Diagnostic;
+use error_codes;
+use initialisation::check_constructor;
+use number::Number;
+use resolver::{DeclId, DeclKind, Resolution};
+use token::Span;
+use types::Type;
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct FunctionSignature
+{
+    pub params: Vec<Type>,
+    pub return_type: Type,
+    //The name of the function. Builtins have an empty span
+    pub span: Span,
+    pub visibility: Visibility,
+    //Methods are called on an instance, which they get as self. The params don't include it
+    pub receiver: bool,
+}
+
+impl FunctionSignature
+{
+    pub fn as_type(&self) -> Type
+    {
+        Type::Function(self.params.clone(), Box::new(self.return_type.clone()))
+    }
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct FieldInfo
+{
+    pub name: String,
+    pub field_type: Type,
+    pub visibility: Visibility,
+    pub span: Span,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct OperatorInfo
+{
+    //The type whose implement block the operator function is in
+    pub type_name: String,
+    pub operator: Operator,
+    pub signature: FunctionSignature,
+    //The name that the operator function is called by when the script runs
+    pub function: String,
+}
+
+/*
+ * What calls that the type checker resolves end up doing, by the id of the
+ * expression. Both engines use these instead of the built in operators and
+ * instead of looking for a method
+ */
+#[derive(Clone,Debug,Default)]
+pub struct CallTargets
+{
+    //The operator functions of unary and binary expressions, and of the targets
+    //of assignments like a += b
+    pub operators: HashMap<NodeId, String>,
+    //Values that are used as conditions, through their operator if
+    pub conditions: HashMap<NodeId, String>,
+    //The callees of calls to generated getters and setters
+    pub accessors: HashMap<NodeId, Accessor>,
+    //The callees of method calls and of calls of static functions
+    pub methods: HashMap<NodeId, MethodCall>,
+    //The callees of calls that make an instance of a type
+    pub constructors: HashMap<NodeId, ConstructorCall>,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct MethodCall
+{
+    //Functions of implement blocks are named Type.name
+    pub function: String,
+    //The object before the . is passed as self
+    pub receiver: bool,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub struct ConstructorCall
+{
+    pub type_name: String,
+    //All fields in the order they are declared
+    pub fields: Vec<String>,
+    //The constructor that gives the fields their values, or None when the
+    //arguments are the values of the fields
+    pub function: Option<String>,
+}
+
+//A getter or setter that is generated for a private field
+#[derive(Clone,PartialEq,Debug)]
+pub enum Accessor
+{
+    Get(String),
+    Set(String),
+}
+
+/*
+ * Everything the checker knows about a type declared with the type keyword
+ */
+#[derive(Clone,PartialEq,Debug)]
+pub struct TypeInfo
+{
+    pub name: String,
+    pub span: Span,
+    pub fields: Vec<FieldInfo>,
+    pub methods: HashMap<String, FunctionSignature>,
+    pub operators: Vec<OperatorInfo>,
+    //The methods that are generated getters and setters
+    pub accessors: HashMap<String, Accessor>,
+    //The def new(self, ...) of the type. Without one the type is made from a value for each field
+    pub constructor: Option<FunctionSignature>,
+}
+
+impl TypeInfo
+{
+    pub fn field(&self, name: &str) -> Option<&FieldInfo>
+    {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+#[derive(Clone,Debug,Default)]
+pub struct TypeCheckResult
+{
+    pub diagnostics: Vec<Diagnostic>,
+    //The type of every expression that was checked
+    pub expr_types: HashMap<NodeId, Type>,
+    //The type of every variable, parameter and loop variable
+    pub decl_types: HashMap<DeclId, Type>,
+    pub functions: HashMap<String, FunctionSignature>,
+    pub types: HashMap<String, TypeInfo>,
+    pub call_targets: CallTargets,
+}
+
+impl TypeCheckResult
+{
+    pub fn has_errors(&self) -> bool
+    {
+        self.diagnostics.iter().any(|d| d.is_error())
+    }
+}
+
+//Checks the types of a program that has been resolved
+pub fn check(program: &Program, resolution: &Resolution, builtins: &Builtins) -> TypeCheckResult
+{
+    let mut checker = TypeChecker::new(resolution, builtins);
+    checker.check_program(program);
+    return checker.result;
+}
+
+/*
+ * Walks the parse tree and makes sure that every value is used in a way that
+ * is valid for its type
+ */
+pub struct TypeChecker<'a>
+{
+    resolution: &'a Resolution,
+    result: TypeCheckResult,
+    //The return type of the function that is being checked
+    return_type: Option<Type>,
+    //The type whose implement block is being checked. Private members can only
+    //be used from there
+    current_type: Option<String>,
+    //Builtins that need a capability that the script was not given
+    missing_capabilities: HashMap<String, Capability>,
+    //The methods of the built in types by their Type.name. The value they are
+    //called on is their first parameter
+    builtin_methods: HashMap<String, FunctionSignature>,
+}
+
+impl<'a> TypeChecker<'a>
+{
+    pub fn new(resolution: &'a Resolution, builtins: &Builtins) -> TypeChecker<'a>
+    {
+        let mut result = TypeCheckResult::default();
+        let mut missing_capabilities = HashMap::new();
+        let mut builtin_methods = HashMap::new();
+        for builtin in builtins.functions()
+        {
+            if let Some(capability) = builtins.missing_capability(&builtin.name)
+            {
+                missing_capabilities.insert(builtin.name.clone(), capability);
+            }
+            let signature = FunctionSignature{
+                params: builtin.params.clone(),
+                return_type: builtin.return_type.clone(),
+                span: Span::default(),
+                visibility: Visibility::Public,
+                receiver: false
+            };
+            if builtin.is_method()
+            {
+                builtin_methods.insert(builtin.name.clone(), signature);
+            }
+            else
+            {
+                result.functions.insert(builtin.name.clone(), signature);
+            }
+        }
+
+        TypeChecker {
+            resolution: resolution,
+            result: result,
+            return_type: None,
+            current_type: None,
+            missing_capabilities: missing_capabilities,
+            builtin_methods: builtin_methods,
+        }
+    }
+
+    pub fn check_program(&mut self, program: &Program)
+    {
+        self.collect_declarations(program);
+
+        for item in &program.items
+        {
+            match *item
+            {
+                Item::Function(ref function) => self.check_function(function),
+                Item::Implement(ref implement) => {
+                    self.current_type = Some(implement.type_name.clone());
+                    for function in &implement.functions
+                    {
+                        self.check_function(function);
+                        if function.name == "new" && function.receiver.is_some()
+                        {
+                            self.check_constructor(&implement.type_name, function);
+                        }
+                    }
+                    self.current_type = None;
+                }
+                Item::Statement(ref statement) => {
+                    self.return_type = None;
+                    self.check_statement(statement);
+                }
+                Item::Type(_) => {}
+            }
+        }
+    }
+
+    //Finds the types and signatures of everything declared at the top level so
+    //that they can be used before their declaration
+    fn collect_declarations(&mut self, program: &Program)
+    {
+        //Types are added before their fields are checked so that fields can refer
+        //to any type, including the one they are in
+        for item in &program.items
+        {
+            if let Item::Type(ref decl) = *item
+            {
+                if let Some(previous) = self.result.types.get(&decl.name).map(|t| t.span)
+                {
+                    self.error_with_label(error_codes::DUPLICATE_MEMBER, format!("The type {} is already declared", decl.name), decl.name_span,
+                        previous, String::from("first declared here"));
+                    continue;
+                }
+                self.result.types.insert(decl.name.clone(), TypeInfo{
+                    name: decl.name.clone(),
+                    span: decl.name_span,
+                    fields: Vec::new(),
+                    methods: HashMap::new(),
+                    operators: Vec::new(),
+                    accessors: HashMap::new(),
+                    constructor: None
+                });
+            }
+        }
+
+        for item in &program.items
+        {
+            match *item
+            {
+                Item::Type(ref decl) => {
+                    let mut fields: Vec<FieldInfo> = Vec::new();
+                    for field in &decl.fields
+                    {
+                        let field_type = self.resolve_type(&field.type_expr);
+                        if let Some(previous) = fields.iter().find(|f| f.name == field.name).map(|f| f.span)
+                        {
+                            self.error_with_label(error_codes::DUPLICATE_MEMBER, format!("The field {} is already declared", field.name), field.span,
+                                previous, String::from("first declared here"));
+                            continue;
+                        }
+                        fields.push(FieldInfo{
+                            name: field.name.clone(),
+                            field_type: field_type,
+                            visibility: field.visibility,
+                            span: field.span,
+                        });
+                    }
+                    if let Some(info) = self.result.types.get_mut(&decl.name)
+                    {
+                        if info.span == decl.name_span
+                        {
+                            info.fields = fields;
+                        }
+                    }
+                }
+                Item::Function(ref function) => {
+                    let signature = self.signature(function);
+                    //Duplicate functions are reported by the resolver
+                    if !self.result.functions.contains_key(&function.name)
+                    {
+                        self.result.functions.insert(function.name.clone(), signature);
+                    }
+                }
+                Item::Implement(ref implement) => {
+                    if !self.result.types.contains_key(&implement.type_name)
+                    {
+                        self.error(error_codes::UNKNOWN_TYPE, format!("Unknown type {}", implement.type_name), implement.type_span);
+                        continue;
+                    }
+                    for function in &implement.functions
+                    {
+                        let signature = self.signature(function);
+                        if let Some(operator) = function.operator
+                        {
+                            self.declare_operator(implement, function, operator, signature);
+                            continue;
+                        }
+                        if function.name == "new"
+                        {
+                            self.declare_constructor(implement, function, signature);
+                            continue;
+                        }
+                        let info = self.result.types.get_mut(&implement.type_name).unwrap();
+                        let previous = info.methods.get(&function.name).map(|m| m.span);
+                        match previous
+                        {
+                            Some(previous) => {
+                                let message = format!("The method {} is already implemented for {}", function.name, implement.type_name);
+                                self.error_with_label(error_codes::DUPLICATE_MEMBER, message, function.name_span, previous, String::from("first implemented here"));
+                            }
+                            None => {
+                                info.methods.insert(function.name.clone(), signature);
+                            }
+                        }
+                    }
+                }
+                Item::Statement(_) => {}
+            }
+        }
+
+        //Accessors come last so that the methods they conflict with are known
+        for item in &program.items
+        {
+            if let Item::Type(ref decl) = *item
+            {
+                self.generate_accessors(decl);
+            }
+        }
+    }
+
+    //Private fields get a get_<field>() and a set_<field>(value) method unless they
+    //are hidden. Methods with the same names have to be written for hidden fields.
+    //Fields, methods and accessors share one namespace so none of them can have the
+    //name of another
+    fn generate_accessors(&mut self, decl: &TypeDecl)
+    {
+        let info = match self.result.types.get(&decl.name)
+        {
+            Some(info) if info.span == decl.name_span => info.clone(),
+            _ => return
+        };
+        for field in &info.fields
+        {
+            if let Some(method) = info.methods.get(&field.name)
+            {
+                self.error_with_label(error_codes::DUPLICATE_MEMBER, format!("The method {} of {} has the name of a field", field.name, decl.name),
+                    method.span, field.span, String::from("the field is declared here"));
+            }
+        }
+        for field in &decl.fields
+        {
+            let field_info = match info.field(&field.name)
+            {
+                Some(field_info) if field_info.span == field.span => field_info,
+                _ => continue
+            };
+            if field.visibility != Visibility::Private || field.hidden
+            {
+                continue;
+            }
+
+            let getter = FunctionSignature{
+                params: Vec::new(),
+                return_type: field_info.field_type.clone(),
+                span: field.span,
+                visibility: Visibility::Public,
+                receiver: true
+            };
+            let setter = FunctionSignature{
+                params: vec!(field_info.field_type.clone()),
+                return_type: Type::Unit,
+                span: field.span,
+                visibility: Visibility::Public,
+                receiver: true
+            };
+            let accessors = [
+                ("getter", format!("get_{}", field.name), getter, Accessor::Get(field.name.clone())),
+                ("setter", format!("set_{}", field.name), setter, Accessor::Set(field.name.clone())),
+            ];
+            for (what, name, signature, accessor) in accessors
+            {
+                if let Some(method) = info.methods.get(&name)
+                {
+                    let diagnostic = Diagnostic::error(error_codes::DUPLICATE_MEMBER,
+                            format!("The method {} of {} has the name of the {} of the private field {}", name, decl.name, what, field.name), method.span)
+                        .with_label(field.span, format!("the {} is generated for this field", what))
+                        .with_note(format!("Declare the field as hidden {} to write its getter and setter yourself", field.name));
+                    self.result.diagnostics.push(diagnostic);
+                    continue;
+                }
+                if let Some(other) = info.field(&name)
+                {
+                    let diagnostic = Diagnostic::error(error_codes::DUPLICATE_MEMBER,
+                            format!("The field {} of {} has the name of the {} of the private field {}", name, decl.name, what, field.name), other.span)
+                        .with_label(field.span, format!("the {} is generated for this field", what))
+                        .with_note(format!("Declare the field as hidden {} to write its getter and setter yourself", field.name));
+                    self.result.diagnostics.push(diagnostic);
+                    continue;
+                }
+                let info = self.result.types.get_mut(&decl.name).unwrap();
+                info.methods.insert(name.clone(), signature);
+                info.accessors.insert(name, accessor);
+            }
+        }
+    }
+
+    //Constructors take the instance that is being made as self and give its fields
+    //their values instead of returning it
+    fn declare_constructor(&mut self, implement: &ImplementBlock, function: &FunctionDecl, signature: FunctionSignature)
+    {
+        let type_name = &implement.type_name;
+        if !signature.receiver
+        {
+            self.result.diagnostics.push(Diagnostic::error(error_codes::INVALID_CONSTRUCTOR,
+                    format!("The constructor of {} has to take self", type_name), function.name_span)
+                .with_note(String::from("self is the new instance, write def new(self, ...)")));
+            return;
+        }
+        if let Some(ref return_type) = function.return_type
+        {
+            self.error(error_codes::INVALID_CONSTRUCTOR, format!("The constructor of {} can not return a value", type_name), return_type.span);
+            return;
+        }
+        let info = self.result.types.get_mut(type_name).unwrap();
+        match info.constructor
+        {
+            Some(ref previous) => {
+                let previous = previous.span;
+                self.error_with_label(error_codes::DUPLICATE_MEMBER, format!("The constructor of {} is already implemented", type_name),
+                    function.name_span, previous, String::from("first implemented here"));
+            }
+            None => info.constructor = Some(signature)
+        }
+    }
+
+    //Checks that an operator function can be used for its operator and that no
+    //other operator function takes the same operands
+    fn declare_operator(&mut self, implement: &ImplementBlock, function: &FunctionDecl, operator: Operator, signature: FunctionSignature)
+    {
+        let name = &function.name;
+        let span = function.name_span;
+        if let Some(receiver) = function.receiver
+        {
+            self.error(error_codes::INVALID_OPERATOR, format!("{} can not take self, its operands are its parameters", name), receiver);
+            return;
+        }
+        if signature.params.len() != operator.operands()
+        {
+            let plural = if operator.operands() == 1 {""} else {"s"};
+            self.error(error_codes::INVALID_OPERATOR, format!("{} takes {} parameter{}, not {}",
+                name, operator.operands(), plural, signature.params.len()), span);
+            return;
+        }
+        let own_type = Type::User(implement.type_name.clone());
+        if !signature.params.contains(&own_type)
+        {
+            self.error(error_codes::INVALID_OPERATOR, format!("{} for {} needs a parameter of type {}",
+                name, implement.type_name, implement.type_name), span);
+            return;
+        }
+        let returns_bool = match operator
+        {
+            Operator::Binary(op) => op.is_comparison(),
+            Operator::Negate => false,
+            Operator::Condition => true,
+        };
+        if returns_bool && signature.return_type != Type::Bool && !signature.return_type.is_unknown()
+        {
+            self.error(error_codes::INVALID_OPERATOR, format!("{} has to return Bool", name), span);
+            return;
+        }
+        if signature.return_type == Type::Unit
+        {
+            self.error(error_codes::INVALID_OPERATOR, format!("{} has to return a value", name), span);
+            return;
+        }
+
+        let previous = self.result.types.values()
+            .flat_map(|info| info.operators.iter())
+            .find(|other| other.operator == operator && other.signature.params == signature.params)
+            .map(|other| other.signature.span);
+        if let Some(previous) = previous
+        {
+            let operands: Vec<String> = signature.params.iter().map(|param| param.to_string()).collect();
+            self.error_with_label(error_codes::DUPLICATE_MEMBER, format!("{} is already implemented for {}", name, operands.join(" and ")),
+                span, previous, String::from("first implemented here"));
+            return;
+        }
+        self.result.types.get_mut(&implement.type_name).unwrap().operators.push(OperatorInfo{
+            type_name: implement.type_name.clone(),
+            operator: operator,
+            signature: signature,
+            function: implement.function_name(function)
+        });
+    }
+
+    fn signature(&mut self, function: &FunctionDecl) -> FunctionSignature
+    {
+        let params = function.params.iter().map(|param| self.resolve_type(&param.type_expr)).collect();
+        let return_type = match function.return_type
+        {
+            Some(ref type_expr) => self.resolve_type(type_expr),
+            None => Type::Unit
+        };
+
+        FunctionSignature{
+            params: params,
+            return_type: return_type,
+            span: function.name_span,
+            visibility: function.visibility,
+            receiver: function.receiver.is_some()
+        }
+    }
+
+    pub fn resolve_type(&mut self, type_expr: &TypeExpr) -> Type
+    {
+        let args: Vec<Type> = type_expr.args.iter().map(|arg| self.resolve_type(arg)).collect();
+
+        match Type::builtin(&type_expr.name, args)
+        {
+            Some(Ok(result)) => {
+                if let Type::Dictionary(ref key, _) = result
+                {
+                    self.check_key_type(key, type_expr.args[0].span);
+                }
+                result
+            }
+            Some(Err(expected)) => {
+                let message = match expected
+                {
+                    0 => format!("{} does not take type arguments", type_expr.name),
+                    1 => format!("{} expects 1 type argument", type_expr.name),
+                    n => format!("{} expects {} type arguments", type_expr.name, n)
+                };
+                self.error(error_codes::WRONG_TYPE_ARGUMENTS, message, type_expr.span);
+                Type::Unknown
+            }
+            None if self.result.types.contains_key(&type_expr.name) => {
+                if !type_expr.args.is_empty()
+                {
+                    self.error(error_codes::WRONG_TYPE_ARGUMENTS, format!("{} does not take type arguments", type_expr.name), type_expr.span);
+                }
+                Type::User(type_expr.name.clone())
+            }
+            None => {
+                self.error(error_codes::UNKNOWN_TYPE, format!("Unknown type {}", type_expr.name), type_expr.span);
+                Type::Unknown
+            }
+        }
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    //                      Functions and statements
+    ////////////////////////////////////////////////////////////////////////////
+    fn check_function(&mut self, function: &FunctionDecl)
+    {
+        let signature = self.signature_quiet(function);
+        if let (Some(receiver), Some(type_name)) = (function.receiver, self.current_type.clone())
+        {
+            self.set_declared_type(receiver, Type::User(type_name));
+        }
+        for (param, param_type) in function.params.iter().zip(signature.params.iter())
+        {
+            self.set_declared_type(param.span, param_type.clone());
+        }
+
+        self.return_type = Some(signature.return_type.clone());
+        self.check_statements(&function.body.statements);
+
+        if signature.return_type != Type::Unit && !block_returns(&function.body)
+        {
+            self.error(error_codes::MISSING_RETURN, format!("{} does not return a value on every path", function.name), function.name_span);
+        }
+    }
+
+    fn check_constructor(&mut self, type_name: &str, constructor: &FunctionDecl)
+    {
+        let fields: Vec<String> = match self.result.types.get(type_name)
+        {
+            Some(info) => info.fields.iter().map(|field| field.name.clone()).collect(),
+            None => return
+        };
+        let diagnostics = check_constructor(type_name, &fields, constructor);
+        self.result.diagnostics.extend(diagnostics);
+    }
+
+    //The signature of a function without reporting errors in its types again
+    fn signature_quiet(&mut self, function: &FunctionDecl) -> FunctionSignature
+    {
+        let diagnostic_count = self.result.diagnostics.len();
+        let signature = self.signature(function);
+        self.result.diagnostics.truncate(diagnostic_count);
+        return signature;
+    }
+
+    fn check_statements(&mut self, statements: &[Statement])
+    {
+        for statement in statements
+        {
+            self.check_statement(statement);
+        }
+    }
+
+    pub fn check_statement(&mut self, statement: &Statement)
+    {
+        match statement.kind
+        {
+            StatementKind::Let{ref type_expr, ref name, name_span, ref value} => {
+                let declared = type_expr.as_ref().map(|t| self.resolve_type(t));
+                let value_type = value.as_ref().map(|v| (self.check_expr(v), v.span));
+
+                let variable_type = match (declared, value_type)
+                {
+                    (Some(declared), Some((value_type, span))) => {
+                        self.expect_type(&declared, &value_type, span);
+                        declared
+                    }
+                    (Some(declared), None) => declared,
+                    (None, Some((value_type, span))) => self.infer_variable_type(name, value_type, span),
+                    (None, None) => {
+                        self.error(error_codes::CANNOT_INFER, format!("The type of {} can not be inferred without a value, add a type", name), name_span);
+                        Type::Unknown
+                    }
+                };
+                self.set_declared_type(name_span, variable_type);
+            }
+            StatementKind::Assign{ref target, op, ref value} => {
+                let target_type = self.check_assignable(target);
+                let value_type = self.check_expr(value);
+                let value_type = match op
+                {
+                    Some(op) => self.binary_result(op, &target_type, &value_type, target.id, statement.span),
+                    None => value_type
+                };
+                self.expect_type(&target_type, &value_type, value.span);
+            }
+            StatementKind::Increment{ref target, ..} => {
+                let target_type = self.check_assignable(target);
+                self.expect_type(&Type::Number, &target_type, target.span);
+            }
+            StatementKind::Expr(ref expr) => {
+                self.check_expr(expr);
+            }
+            StatementKind::Return(ref value) => self.check_return(value.as_ref(), statement.span),
+            StatementKind::If{ref branches, ref else_block} => {
+                for &(ref condition, ref block) in branches
+                {
+                    self.check_condition(condition);
+                    self.check_statements(&block.statements);
+                }
+                if let Some(ref block) = *else_block
+                {
+                    self.check_statements(&block.statements);
+                }
+            }
+            StatementKind::While{ref condition, ref body} => {
+                self.check_condition(condition);
+                self.check_statements(&body.statements);
+            }
+            StatementKind::For{var_span, ref start, ref end, ref step, ref body, ..} => {
+                for bound in [Some(start), Some(end), step.as_ref()].iter().filter_map(|b| *b)
+                {
+                    let bound_type = self.check_expr(bound);
+                    self.expect_type(&Type::Number, &bound_type, bound.span);
+                }
+                //Steps that are only known when the loop starts are checked then
+                if let Some(step) = step.as_ref().filter(|step| constant_number(step).is_some_and(|n| n.is_zero()))
+                {
+                    self.error(error_codes::ZERO_STEP, String::from("The step of a for loop can not be 0"), step.span);
+                }
+                self.set_declared_type(var_span, Type::Number);
+                self.check_statements(&body.statements);
+            }
+            StatementKind::Foreach{ref type_expr, ref var, var_span, ref value, ref container, ref body} => {
+                let container_type = self.check_expr(container);
+                let (element_type, value_type) = match *value
+                {
+                    Some(_) => {
+                        let (key_type, value_type) = self.entry_types(&container_type, container.span);
+                        (key_type, Some(value_type))
+                    }
+                    None => (self.element_type(&container_type, container.span), None)
+                };
+                self.declare_loop_variable(type_expr, var, var_span, element_type);
+                if let (&Some(ref value), Some(value_type)) = (value, value_type)
+                {
+                    self.declare_loop_variable(&value.type_expr, &value.name, value.span, value_type);
+                }
+                self.check_statements(&body.statements);
+            }
+            StatementKind::Block(ref block) => self.check_statements(&block.statements),
+        }
+    }
+
+    //The type of a variable declared without a type, based on the type of
+    //the value it is initialised with
+    fn infer_variable_type(&mut self, name: &str, value_type: Type, span: Span) -> Type
+    {
+        if value_type == Type::Unit
+        {
+            self.error(error_codes::CANNOT_INFER, format!("The type of {} can not be inferred from something that has no value", name), span);
+            return Type::Unknown;
+        }
+        if value_type.contains_infer()
+        {
+            self.error(error_codes::CANNOT_INFER, format!("The type of {} can not be inferred from {}, add a type", name, value_type), span);
+            return Type::Unknown;
+        }
+        return value_type;
+    }
+
+    fn check_return(&mut self, value: Option<&Expr>, span: Span)
+    {
+        let value_type = value.map(|v| (self.check_expr(v), v.span));
+        let return_type = match self.return_type.clone()
+        {
+            Some(return_type) => return_type,
+            None => {
+                self.error(error_codes::INVALID_RETURN, String::from("return can only be used inside functions"), span);
+                return;
+            }
+        };
+
+        match value_type
+        {
+            Some((_, value_span)) if return_type == Type::Unit =>
+                self.error(error_codes::INVALID_RETURN, String::from("This function does not return a value"), value_span),
+            Some((value_type, value_span)) => self.expect_type(&return_type, &value_type, value_span),
+            None if return_type != Type::Unit =>
+                self.error(error_codes::INVALID_RETURN, format!("Expected a return value of type {}", return_type), span),
+            None => {}
+        }
+    }
+
+    fn check_condition(&mut self, condition: &Expr)
+    {
+        let condition_type = self.check_expr(condition);
+        let condition_type = self.condition_type(condition, condition_type);
+        self.expect_type(&Type::Bool, &condition_type, condition.span);
+    }
+
+    //The type of the elements that foreach goes through
+    fn declare_loop_variable(&mut self, type_expr: &Option<TypeExpr>, var: &str, var_span: Span, element_type: Type)
+    {
+        let var_type = match *type_expr
+        {
+            Some(ref type_expr) => {
+                let declared = self.resolve_type(type_expr);
+                self.expect_type(&declared, &element_type, type_expr.span);
+                declared
+            }
+            None => self.infer_variable_type(var, element_type, var_span)
+        };
+        self.set_declared_type(var_span, var_type);
+    }
+
+    //The type that foreach goes through for a container. User types are
+    //iterated through what their iterate(self) method returns
+    fn iterated_type(&mut self, container_type: &Type, span: Span) -> Type
+    {
+        let mut current = container_type.clone();
+        let mut seen = Vec::new();
+        while let Type::User(type_name) = current
+        {
+            let iterate = match self.result.types.get(&type_name)
+            {
+                Some(info) => info.methods.get("iterate").cloned(),
+                None => return Type::Unknown
+            };
+            let iterate = match iterate
+            {
+                Some(iterate) => iterate,
+                None => {
+                    self.result.diagnostics.push(Diagnostic::error(error_codes::NOT_ITERABLE,
+                            format!("Cannot iterate over a value of type {}", type_name), span)
+                        .with_note(format!("{} can be iterated by adding a def iterate(self) to an implement block for it", type_name)));
+                    return Type::Unknown;
+                }
+            };
+            self.check_visibility(&type_name, "method", "iterate", iterate.visibility, iterate.span, span);
+            if !iterate.receiver || !iterate.params.is_empty()
+            {
+                self.error_with_label(error_codes::NOT_ITERABLE, format!("Cannot iterate over a value of type {}", type_name), span,
+                    iterate.span, String::from("foreach needs an iterate method that only takes self"));
+                return Type::Unknown;
+            }
+            if seen.contains(&type_name)
+            {
+                self.error_with_label(error_codes::NOT_ITERABLE, format!("Cannot iterate over a value of type {}", type_name), span,
+                    iterate.span, format!("iterate leads back to {}", type_name));
+                return Type::Unknown;
+            }
+            seen.push(type_name);
+            current = iterate.return_type;
+        }
+        return current;
+    }
+
+    fn element_type(&mut self, container_type: &Type, span: Span) -> Type
+    {
+        match self.iterated_type(container_type, span)
+        {
+            Type::List(ref element) => (**element).clone(),
+            Type::Dictionary(ref key, _) => (**key).clone(),
+            Type::String => Type::String,
+            Type::Unknown => Type::Unknown,
+            ref other => {
+                self.error(error_codes::NOT_ITERABLE, format!("Cannot iterate over a value of type {}", other), span);
+                Type::Unknown
+            }
+        }
+    }
+
+    //The types of the key and the value in foreach key, value in container
+    fn entry_types(&mut self, container_type: &Type, span: Span) -> (Type, Type)
+    {
+        match self.iterated_type(container_type, span)
+        {
+            Type::Dictionary(ref key, ref value) => ((**key).clone(), (**value).clone()),
+            Type::Unknown => (Type::Unknown, Type::Unknown),
+            ref other => {
+                self.error(error_codes::NOT_ITERABLE, format!("Only dictionaries have keys and values to iterate over, not {}", other), span);
+                (Type::Unknown, Type::Unknown)
+            }
+        }
+    }
+
+    //Checks the left hand side of an assignment
+    fn check_assignable(&mut self, target: &Expr) -> Type
+    {
+        match target.kind
+        {
+            ExprKind::Identifier(ref name) => {
+                let is_function = match self.resolution.declaration_of(target.id)
+                {
+                    Some(decl) => decl.kind == DeclKind::Function || decl.kind == DeclKind::Builtin,
+                    None => false
+                };
+                if is_function
+                {
+                    self.error(error_codes::INVALID_ASSIGNMENT, format!("Cannot assign to the function {}", name), target.span);
+                    return Type::Unknown;
+                }
+                self.check_expr(target)
+            }
+            ExprKind::Index{ref object, ..} => {
+                let result = self.check_expr(target);
+                //Strings are copied around so changing a character in place is not possible
+                if self.result.expr_types.get(&object.id) == Some(&Type::String)
+                {
+                    self.error(error_codes::INVALID_ASSIGNMENT, String::from("The characters of a String can not be assigned to"), target.span);
+                }
+                result
+            }
+            ExprKind::Member{..} => self.check_expr(target),
+            _ => {
+                self.check_expr(target);
+                self.error(error_codes::INVALID_ASSIGNMENT, String::from("Only variables, members and elements can be assigned to"), target.span);
+                Type::Unknown
+            }
+        }
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    //                      Expressions
+    ////////////////////////////////////////////////////////////////////////////
+    pub fn check_expr(&mut self, expr: &Expr) -> Type
+    {
+        let result = self.check_expr_kind(expr);
+        self.result.expr_types.insert(expr.id, result.clone());
+        return result;
+    }
+
+    fn check_expr_kind(&mut self, expr: &Expr) -> Type
+    {
+        match expr.kind
+        {
+            ExprKind::Number(_) => Type::Number,
+            ExprKind::String(_) => Type::String,
+            ExprKind::Bool(_) => Type::Bool,
+            ExprKind::Identifier(ref name) => self.identifier_type(expr.id, name, expr.span),
+            ExprKind::List(ref elements) => {
+                let mut element_type = Type::Infer;
+                for element in elements
+                {
+                    let current = self.check_expr(element);
+                    element_type = self.unify(element_type, current, element.span, "List elements");
+                }
+                Type::list(element_type)
+            }
+            ExprKind::Dictionary(ref entries) => {
+                let mut key_type = Type::Infer;
+                let mut value_type = Type::Infer;
+                for &(ref key, ref value) in entries
+                {
+                    let current = self.check_expr(key);
+                    key_type = self.unify(key_type, current, key.span, "Dictionary keys");
+                    let current = self.check_expr(value);
+                    value_type = self.unify(value_type, current, value.span, "Dictionary values");
+                }
+                if let Some(&(ref key, _)) = entries.first()
+                {
+                    self.check_key_type(&key_type, key.span);
+                }
+                Type::dictionary(key_type, value_type)
+            }
+            ExprKind::Unary{op, expr: ref operand_expr} => {
+                let operand = self.check_expr(operand_expr);
+                if op == UnaryOp::Negate
+                {
+                    match self.find_operator(Operator::Negate, slice::from_ref(&operand), expr.span)
+                    {
+                        Overload::Found(overload) => {
+                            self.result.call_targets.operators.insert(expr.id, overload.function);
+                            return overload.signature.return_type;
+                        }
+                        Overload::Ambiguous => return Type::Unknown,
+                        Overload::Missing => {}
+                    }
+                }
+                let operand = if op == UnaryOp::Not {self.condition_type(operand_expr, operand)} else {operand};
+                let expr = operand_expr;
+                let expected = match op
+                {
+                    UnaryOp::Negate => Type::Number,
+                    UnaryOp::Not => Type::Bool,
+                };
+                self.expect_type(&expected, &operand, expr.span);
+                expected
+            }
+            ExprKind::Binary{op, ref lhs, ref rhs} => {
+                let mut lhs_type = self.check_expr(lhs);
+                let mut rhs_type = self.check_expr(rhs);
+                if op == BinaryOp::And || op == BinaryOp::Or
+                {
+                    lhs_type = self.condition_type(lhs, lhs_type);
+                    rhs_type = self.condition_type(rhs, rhs_type);
+                }
+                self.binary_result(op, &lhs_type, &rhs_type, expr.id, expr.span)
+            }
+            ExprKind::Call{ref callee, ref args} => self.check_call(callee, args, expr.span),
+            ExprKind::Member{ref object, ref name, name_span} => {
+                let object_type = self.check_expr(object);
+                self.member_type(&object_type, name, name_span)
+            }
+            ExprKind::Index{ref object, ref index} => {
+                let object_type = self.check_expr(object);
+                let index_type = self.check_expr(index);
+                match object_type
+                {
+                    Type::List(element) => {
+                        self.expect_type(&Type::Number, &index_type, index.span);
+                        *element
+                    }
+                    Type::Dictionary(key, value) => {
+                        self.expect_type(&key, &index_type, index.span);
+                        *value
+                    }
+                    Type::String => {
+                        self.expect_type(&Type::Number, &index_type, index.span);
+                        Type::String
+                    }
+                    Type::Unknown => Type::Unknown,
+                    other => {
+                        self.error(error_codes::NOT_INDEXABLE, format!("Cannot index into a value of type {}", other), object.span);
+                        Type::Unknown
+                    }
+                }
+            }
+        }
+    }
+
+    fn identifier_type(&mut self, id: NodeId, name: &str, span: Span) -> Type
+    {
+        let decl = match self.resolution.bindings.get(&id)
+        {
+            Some(&decl) => decl,
+            //The resolver has already reported this
+            None => return Type::Unknown
+        };
+
+        let kind = self.resolution.declarations[decl].kind;
+        //Referring to the function is rejected as well as calling it, since a
+        //reference could be called later
+        if kind == DeclKind::Builtin
+        {
+            if let Some(&capability) = self.missing_capabilities.get(name)
+            {
+                self.error(error_codes::MISSING_CAPABILITY, missing_capability_message(name, capability), span);
+            }
+        }
+
+        match kind
+        {
+            DeclKind::Function | DeclKind::Builtin => match self.result.functions.get(name)
+            {
+                Some(signature) => signature.as_type(),
+                None => Type::Unknown
+            },
+            DeclKind::Type => {
+                self.result.diagnostics.push(Diagnostic::error(error_codes::TYPE_AS_VALUE,
+                        format!("The type {} is not a value", name), span)
+                    .with_note(format!("Instances are made with {}(...) and static functions are called with {}.name(...)", name, name)));
+                Type::Unknown
+            }
+            _ => self.result.decl_types.get(&decl).cloned().unwrap_or(Type::Unknown)
+        }
+    }
+
+    fn check_call(&mut self, callee: &Expr, args: &[Expr], span: Span) -> Type
+    {
+        if let Some(type_name) = self.type_name_of(callee)
+        {
+            return self.check_construction(callee, &type_name, args, span);
+        }
+        //Methods are not values so they are handled separately
+        if let ExprKind::Member{ref object, ref name, name_span} = callee.kind
+        {
+            if let Some(type_name) = self.type_name_of(object)
+            {
+                return self.check_static_call(callee, &type_name, name, name_span, args, span);
+            }
+            let object_type = self.check_expr(object);
+            let method = match object_type
+            {
+                Type::User(ref type_name) => self.result.types.get(type_name)
+                    .and_then(|info| info.methods.get(name))
+                    .cloned(),
+                _ => None
+            };
+            if let Some(method) = method
+            {
+                if let Type::User(ref type_name) = object_type
+                {
+                    self.check_visibility(type_name, "method", name, method.visibility, method.span, name_span);
+                    if !method.receiver
+                    {
+                        self.result.diagnostics.push(Diagnostic::error(error_codes::INVALID_METHOD_CALL,
+                                format!("{} is a static function of {} and isn't called on an instance", name, type_name), name_span)
+                            .with_note(format!("Call it as {}.{}(...)", type_name, name)));
+                    }
+                    else if let Some(accessor) = self.result.types[type_name].accessors.get(name).cloned()
+                    {
+                        self.result.call_targets.accessors.insert(callee.id, accessor);
+                    }
+                    else
+                    {
+                        self.result.call_targets.methods.insert(callee.id, MethodCall{
+                            function: format!("{}.{}", type_name, name),
+                            receiver: true
+                        });
+                    }
+                }
+                self.result.expr_types.insert(callee.id, method.as_type());
+                return self.check_arguments(name, &method.params, &method.return_type, args, span);
+            }
+            if let Some(function) = self.builtin_method(&object_type, name)
+            {
+                return self.check_builtin_method(callee, object, &object_type, function, args, span);
+            }
+            let member_type = self.member_type(&object_type, name, name_span);
+            self.result.expr_types.insert(callee.id, member_type.clone());
+            return self.check_value_call(&member_type, name, args, callee.span, span);
+        }
+
+        let callee_type = self.check_expr(callee);
+        let name = match callee.kind
+        {
+            ExprKind::Identifier(ref name) => name.clone(),
+            _ => String::from("This function")
+        };
+        self.check_value_call(&callee_type, &name, args, callee.span, span)
+    }
+
+    //The Type.name of a method of a built in type
+    fn builtin_method(&self, object_type: &Type, name: &str) -> Option<String>
+    {
+        let function = format!("{}.{}", object_type.builtin_name()?, name);
+        if self.builtin_methods.contains_key(&function) {Some(function)} else {None}
+    }
+
+    //Methods of built in types get the object as their first argument. Their
+    //signatures can have type parameters, which the object decides first
+    fn check_builtin_method(&mut self, callee: &Expr, object: &Expr, object_type: &Type, function: String, args: &[Expr], span: Span) -> Type
+    {
+        let method = self.builtin_methods[&function].clone();
+        let mut bindings = HashMap::new();
+        method.params[0].bind(object_type, &mut bindings);
+        self.expect_type(&method.params[0].substitute(&bindings), object_type, object.span);
+
+        let params = &method.params[1..];
+        let signature = Type::Function(params.to_vec(), Box::new(method.return_type.clone()));
+        self.result.expr_types.insert(callee.id, signature.substitute(&bindings));
+        self.result.call_targets.methods.insert(callee.id, MethodCall{function: function, receiver: true});
+        let name = match callee.kind
+        {
+            ExprKind::Member{ref name, ..} => name.clone(),
+            _ => String::new()
+        };
+        self.check_bound_arguments(&name, params, &method.return_type, args, span, bindings)
+    }
+
+    //The type that an identifier names, if it names one
+    fn type_name_of(&self, expr: &Expr) -> Option<String>
+    {
+        match expr.kind
+        {
+            ExprKind::Identifier(ref name) => match self.resolution.declaration_of(expr.id)
+            {
+                Some(decl) if decl.kind == DeclKind::Type => Some(name.clone()),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    //Type(...) makes an instance, either with the constructor of the type or from a
+    //value for each field
+    fn check_construction(&mut self, callee: &Expr, type_name: &str, args: &[Expr], span: Span) -> Type
+    {
+        let info = match self.result.types.get(type_name)
+        {
+            Some(info) => info.clone(),
+            None => return self.check_value_call(&Type::Unknown, type_name, args, callee.span, span)
+        };
+        let instance_type = Type::User(String::from(type_name));
+        self.result.expr_types.insert(callee.id, instance_type.clone());
+        let params: Vec<Type> = match info.constructor
+        {
+            Some(ref constructor) => {
+                self.check_visibility(type_name, "constructor", "new", constructor.visibility, constructor.span, callee.span);
+                constructor.params.clone()
+            }
+            None => info.fields.iter().map(|field| field.field_type.clone()).collect()
+        };
+        self.result.call_targets.constructors.insert(callee.id, ConstructorCall{
+            type_name: String::from(type_name),
+            fields: info.fields.iter().map(|field| field.name.clone()).collect(),
+            function: info.constructor.map(|_| format!("{}.new", type_name))
+        });
+        self.check_arguments(type_name, &params, &instance_type, args, span)
+    }
+
+    //Type.name(...) calls a function of an implement block that doesn't take self
+    fn check_static_call(&mut self, callee: &Expr, type_name: &str, name: &str, name_span: Span, args: &[Expr], span: Span) -> Type
+    {
+        let info = match self.result.types.get(type_name)
+        {
+            Some(info) => info.clone(),
+            None => return self.check_value_call(&Type::Unknown, name, args, callee.span, span)
+        };
+        let function = match info.methods.get(name)
+        {
+            Some(function) => function,
+            None => {
+                let diagnostic = if name == "new" && info.constructor.is_some()
+                {
+                    Diagnostic::error(error_codes::INVALID_METHOD_CALL, format!("The constructor of {} is called through the type", type_name), name_span)
+                        .with_note(format!("Call it as {}(...)", type_name))
+                }
+                else
+                {
+                    Diagnostic::error(error_codes::NO_MEMBER, format!("{} has no member {}", type_name, name), name_span)
+                };
+                self.result.diagnostics.push(diagnostic);
+                return self.check_value_call(&Type::Unknown, name, args, callee.span, span);
+            }
+        };
+        self.check_visibility(type_name, "method", name, function.visibility, function.span, name_span);
+        if function.receiver
+        {
+            self.result.diagnostics.push(Diagnostic::error(error_codes::INVALID_METHOD_CALL,
+                    format!("{} is a method of {} and has to be called on an instance", name, type_name), name_span)
+                .with_label(function.span, String::from("it takes self"))
+                .with_note(format!("Call it as instance.{}(...) on an instance of {}", name, type_name)));
+        }
+        else
+        {
+            self.result.call_targets.methods.insert(callee.id, MethodCall{
+                function: format!("{}.{}", type_name, name),
+                receiver: false
+            });
+        }
+        self.result.expr_types.insert(callee.id, function.as_type());
+        self.check_arguments(name, &function.params, &function.return_type, args, span)
+    }
+
+    fn check_value_call(&mut self, callee_type: &Type, name: &str, args: &[Expr], callee_span: Span, span: Span) -> Type
+    {
+        match *callee_type
+        {
+            Type::Function(ref params, ref return_type) =>
+                self.check_arguments(name, params, return_type, args, span),
+            Type::Unknown => {
+                for arg in args
+                {
+                    self.check_expr(arg);
+                }
+                Type::Unknown
+            }
+            ref other => {
+                for arg in args
+                {
+                    self.check_expr(arg);
+                }
+                self.error(error_codes::NOT_CALLABLE, format!("A value of type {} can not be called", other), callee_span);
+                Type::Unknown
+            }
+        }
+    }
+
+    fn check_arguments(&mut self, name: &str, params: &[Type], return_type: &Type, args: &[Expr], span: Span) -> Type
+    {
+        self.check_bound_arguments(name, params, return_type, args, span, HashMap::new())
+    }
+
+    //The type parameters of builtins, like the T of List<T>, are bound to the types
+    //of the arguments in their place. Every other use of them then has to match
+    fn check_bound_arguments(&mut self, name: &str, params: &[Type], return_type: &Type, args: &[Expr], span: Span,
+        mut bindings: HashMap<String, Type>) -> Type
+    {
+        let arg_types: Vec<(Type, Span)> = args.iter().map(|arg| (self.check_expr(arg), arg.span)).collect();
+
+        if params.len() != args.len()
+        {
+            let plural = if params.len() == 1 {""} else {"s"};
+            let given = if args.len() == 1 {"was"} else {"were"};
+            self.error(error_codes::WRONG_ARGUMENT_COUNT, format!("{} expects {} argument{} but {} {} given", name, params.len(), plural, args.len(), given), span);
+        }
+        else
+        {
+            for (param, &(ref arg_type, _)) in params.iter().zip(arg_types.iter())
+            {
+                param.bind(arg_type, &mut bindings);
+            }
+            for (param, &(ref arg_type, arg_span)) in params.iter().zip(arg_types.iter())
+            {
+                self.expect_type(&param.substitute(&bindings), arg_type, arg_span);
+            }
+        }
+        return return_type.substitute(&bindings);
+    }
+
+    fn member_type(&mut self, object_type: &Type, name: &str, span: Span) -> Type
+    {
+        match *object_type
+        {
+            Type::User(ref type_name) => {
+                let info = match self.result.types.get(type_name)
+                {
+                    Some(info) => info,
+                    None => return Type::Unknown
+                };
+                if let Some(field) = info.field(name).cloned()
+                {
+                    self.check_visibility(type_name, "field", name, field.visibility, field.span, span);
+                    return field.field_type;
+                }
+                let message = if info.methods.contains_key(name)
+                {
+                    format!("The method {} has to be called", name)
+                }
+                else
+                {
+                    format!("{} has no member {}", type_name, name)
+                };
+                self.error(error_codes::NO_MEMBER, message, span);
+                Type::Unknown
+            }
+            Type::Unknown => Type::Unknown,
+            ref other => {
+                let message = if self.builtin_method(other, name).is_some()
+                {
+                    format!("The method {} has to be called", name)
+                }
+                else
+                {
+                    format!("{} has no member {}", other, name)
+                };
+                self.error(error_codes::NO_MEMBER, message, span);
+                Type::Unknown
+            }
+        }
+    }
+
+    //The type of lhs op rhs
+    fn binary_result(&mut self, op: BinaryOp, lhs: &Type, rhs: &Type, id: NodeId, span: Span) -> Type
+    {
+        //!= is the opposite of == so it calls the same operator function
+        let overloaded = if op == BinaryOp::NotEqual {BinaryOp::Equal} else {op};
+        if op != BinaryOp::And && op != BinaryOp::Or
+        {
+            match self.find_operator(Operator::Binary(overloaded), &[lhs.clone(), rhs.clone()], span)
+            {
+                Overload::Found(overload) => {
+                    self.result.call_targets.operators.insert(id, overload.function);
+                    return if op == BinaryOp::NotEqual {Type::Bool} else {overload.signature.return_type};
+                }
+                Overload::Ambiguous => return Type::Unknown,
+                Overload::Missing => {}
+            }
+        }
+
+        let result = match op
+        {
+            BinaryOp::Add => match (lhs, rhs)
+            {
+                (&Type::Number, &Type::Number) => Some(Type::Number),
+                (&Type::String, &Type::String) => Some(Type::String),
+                _ => None
+            },
+            BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::IntDiv | BinaryOp::Rem => match (lhs, rhs)
+            {
+                (&Type::Number, &Type::Number) => Some(Type::Number),
+                _ => None
+            },
+            BinaryOp::Less | BinaryOp::LessEqual | BinaryOp::Greater | BinaryOp::GreaterEqual => match (lhs, rhs)
+            {
+                (&Type::Number, &Type::Number) | (&Type::String, &Type::String) => Some(Type::Bool),
+                _ => None
+            },
+            BinaryOp::Equal | BinaryOp::NotEqual =>
+                if lhs.accepts(rhs) || rhs.accepts(lhs) {Some(Type::Bool)} else {None},
+            BinaryOp::And | BinaryOp::Or => match (lhs, rhs)
+            {
+                (&Type::Bool, &Type::Bool) => Some(Type::Bool),
+                _ => None
+            },
+        };
+
+        match result
+        {
+            Some(result) => result,
+            None if lhs.is_unknown() || rhs.is_unknown() => match op
+            {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::IntDiv | BinaryOp::Rem => Type::Unknown,
+                _ => Type::Bool
+            },
+            None => {
+                let mut diagnostic = Diagnostic::error(error_codes::INVALID_OPERANDS,
+                    format!("The operator {} can not be applied to {} and {}", op.symbol(), lhs, rhs), span);
+                if let Some(note) = operator_note(Operator::Binary(overloaded), &[lhs, rhs])
+                {
+                    diagnostic = diagnostic.with_note(note);
+                }
+                self.result.diagnostics.push(diagnostic);
+                Type::Unknown
+            }
+        }
+    }
+
+    //The operator function that an operator uses for operands of these types. It
+    //can be in the implement block of any of the types
+    fn find_operator(&mut self, operator: Operator, operands: &[Type], span: Span) -> Overload
+    {
+        if operands.iter().any(|operand| operand.is_unknown())
+        {
+            return Overload::Missing;
+        }
+        let mut candidates: Vec<OperatorInfo> = Vec::new();
+        for operand in operands
+        {
+            let overloads = match *operand
+            {
+                Type::User(ref type_name) => self.result.types.get(type_name).map(|info| &info.operators),
+                _ => None
+            };
+            for overload in overloads.into_iter().flatten()
+            {
+                let accepted = overload.signature.params.iter().zip(operands).all(|(param, operand)| param.accepts(operand));
+                if overload.operator == operator && accepted && !candidates.contains(overload)
+                {
+                    candidates.push(overload.clone());
+                }
+            }
+        }
+
+        match candidates.len()
+        {
+            0 => Overload::Missing,
+            1 => {
+                let overload = candidates.pop().unwrap();
+                self.check_visibility(&overload.type_name, "operator", operator.symbol(), overload.signature.visibility, overload.signature.span, span);
+                Overload::Found(overload)
+            }
+            _ => {
+                let operands: Vec<String> = operands.iter().map(|operand| operand.to_string()).collect();
+                let mut diagnostic = Diagnostic::error(error_codes::AMBIGUOUS_OPERATOR,
+                    format!("The operator {} is ambiguous for {}", operator.symbol(), operands.join(" and ")), span);
+                for candidate in &candidates
+                {
+                    diagnostic = diagnostic.with_label(candidate.signature.span, format!("this operator function of {} fits", candidate.type_name));
+                }
+                self.result.diagnostics.push(diagnostic.with_note(String::from("Give the operands types so that only one operator function fits")));
+                Overload::Ambiguous
+            }
+        }
+    }
+
+    //The type of a value that is used as a condition. Values of types that
+    //implement operator if are converted to Bool by it
+    fn condition_type(&mut self, condition: &Expr, condition_type: Type) -> Type
+    {
+        if let Type::User(_) = condition_type
+        {
+            match self.find_operator(Operator::Condition, slice::from_ref(&condition_type), condition.span)
+            {
+                Overload::Found(overload) => {
+                    self.result.call_targets.conditions.insert(condition.id, overload.function);
+                    return Type::Bool;
+                }
+                Overload::Ambiguous => return Type::Unknown,
+                Overload::Missing => {}
+            }
+        }
+        return condition_type;
+    }
+
+    //Private members can only be used in the implement blocks of their own type
+    fn check_visibility(&mut self, type_name: &str, what: &str, name: &str, visibility: Visibility, declaration: Span, span: Span)
+    {
+        if visibility == Visibility::Private && self.current_type.as_deref() != Some(type_name)
+        {
+            self.error_with_label(error_codes::PRIVATE_MEMBER, format!("The {} {} of {} is private", what, name, type_name), span,
+                declaration, format!("{} is declared private here", name));
+        }
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    //                      Helpers
+    ////////////////////////////////////////////////////////////////////////////
+
+    //Combines the types of elements that should all have the same type
+    fn unify(&mut self, current: Type, new: Type, span: Span, what: &str) -> Type
+    {
+        match current.merge(&new)
+        {
+            Some(result) => result,
+            None => {
+                self.error(error_codes::TYPE_MISMATCH, format!("{} must have the same type, expected {} but found {}", what, current, new), span);
+                current
+            }
+        }
+    }
+
+    fn check_key_type(&mut self, key_type: &Type, span: Span)
+    {
+        if !key_type.is_key()
+        {
+            self.result.diagnostics.push(Diagnostic::error(error_codes::INVALID_KEY_TYPE,
+                    format!("{} can not be a dictionary key because its values can change", key_type), span)
+                .with_note(String::from("Keys can be Numbers, Strings, Bools or functions")));
+        }
+    }
+
+    fn set_declared_type(&mut self, span: Span, declared_type: Type)
+    {
+        if let Some(decl) = self.resolution.declared_at(span)
+        {
+            self.result.decl_types.insert(decl, declared_type);
+        }
+    }
+
+    fn expect_type(&mut self, expected: &Type, found: &Type, span: Span)
+    {
+        if !expected.accepts(found)
+        {
+            self.error(error_codes::TYPE_MISMATCH, format!("Expected {} but found {}", expected, found), span);
+        }
+    }
+
+    fn error(&mut self, code: &'static str, message: String, span: Span)
+    {
+        self.result.diagnostics.push(Diagnostic::error(code, message, span));
+    }
+
+    fn error_with_label(&mut self, code: &'static str, message: String, span: Span, label_span: Span, label: String)
+    {
+        self.result.diagnostics.push(Diagnostic::error(code, message, span).with_label(label_span, label));
+    }
+}
+
+//What find_operator found for an operator
+enum Overload
+{
+    Found(OperatorInfo),
+    //There is no operator function so the built in operator is used
+    Missing,
+    //More than one operator function fits, which has been reported
+    Ambiguous,
+}
+
+//Tells how to make an operator work for user types, if any of the operands is one
+fn operator_note(operator: Operator, operands: &[&Type]) -> Option<String>
+{
+    let type_name = operands.iter().filter_map(|operand| match **operand
+    {
+        Type::User(ref type_name) => Some(type_name),
+        _ => None
+    }).next()?;
+    let params: Vec<String> = operands.iter().enumerate().map(|(i, operand)| format!("{} {}", operand, ["a", "b"][i])).collect();
+    Some(format!("The operator can be implemented with def <type> operator {}({}) in the implement block of {}",
+        operator.symbol(), params.join(", "), type_name))
+}
+
+//The value of an expression that is made of number literals only, or None if
+//the code has to run to know it
+fn constant_number(expr: &Expr) -> Option<Number>
+{
+    match expr.kind
+    {
+        ExprKind::Number(ref lexeme) => Number::parse(lexeme),
+        ExprKind::Unary{op: UnaryOp::Negate, ref expr} => constant_number(expr)?.neg().ok(),
+        ExprKind::Binary{op, ref lhs, ref rhs} => {
+            let (lhs, rhs) = (constant_number(lhs)?, constant_number(rhs)?);
+            match op
+            {
+                BinaryOp::Add => lhs.add(rhs).ok(),
+                BinaryOp::Sub => lhs.sub(rhs).ok(),
+                BinaryOp::Mul => lhs.mul(rhs).ok(),
+                BinaryOp::Div => lhs.div(rhs).ok(),
+                BinaryOp::IntDiv => lhs.int_div(rhs).ok(),
+                BinaryOp::Rem => lhs.rem(rhs).ok(),
+                _ => None
+            }
+        }
+        _ => None
+    }
+}
+
+//Returns true if the block is guaranteed to run a return statement
+fn block_returns(block: &Block) -> bool
+{
+    block.statements.iter().any(statement_returns)
+}
+
+fn statement_returns(statement: &Statement) -> bool
+{
+    match statement.kind
+    {
+        StatementKind::Return(_) => true,
+        StatementKind::If{ref branches, else_block: Some(ref else_block)} =>
+            branches.iter().all(|&(_, ref block)| block_returns(block)) && block_returns(else_block),
+        StatementKind::Block(ref block) => block_returns(block),
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod type_checker_tests
+{
+    use builtins::Builtins;
+    use capabilities::{Capabilities, Capability};
+    use lexer::Lexer;
+    use parser::parse_token_list;
+    use resolver::resolve;
+    use type_checker::*;
+
+    fn check_code(code: &str) -> TypeCheckResult
+    {
+        let tokens = Lexer::new().tokenize(String::from(code));
+        let program = parse_token_list(tokens).unwrap();
+        let builtins = Builtins::new();
+        let resolution = resolve(&program, &builtins.names());
+        assert!(!resolution.has_errors(), "{:?}", resolution.diagnostics);
+        check(&program, &resolution, &builtins)
+    }
+
+    fn errors(code: &str) -> Vec<String>
+    {
+        check_code(code).diagnostics.into_iter().map(|d| d.message).collect()
+    }
+
+    fn assert_ok(code: &str)
+    {
+        assert_eq!(errors(code), Vec::<String>::new());
+    }
+
+    fn assert_error(code: &str, message: &str)
+    {
+        assert_eq!(errors(code), vec!(String::from(message)));
+    }
+
+    #[test]
+    fn valid_programs()
+    {
+        assert_ok(r#"
+            def Number add(Number a, Number b) { return a + b; }
+            def String greet(String name) { return "Hello " + name; }
+            def main()
+            {
+                let Number a = add(1, 2);
+                let String s = greet("you");
+                let List<Number> l = [1, 2, 3];
+                let Dictionary<String, Number> d = ["a": 1];
+                let Bool b = a < 5 & s != "me";
+                a += l[0] * d["a"];
+                a++;
+                if b { print(a); } elseif !b { print(s); } else { print(l); }
+                while a > 0 { a = a - 1; }
+                for i is 0 to a step 2 { print(i * 2); }
+                foreach Number x in l { print(x); }
+                foreach String key in d { print(key); }
+            }
+        "#);
+    }
+
+    #[test]
+    fn operator_mismatches()
+    {
+        let code = r#"def main() { let Number a = "a" - 1; }"#;
+        let result = check_code(code);
+        assert_eq!(result.diagnostics.len(), 1);
+        assert_eq!(result.diagnostics[0].message, "The operator - can not be applied to String and Number");
+        let span = result.diagnostics[0].span;
+        assert_eq!(&code[span.start..span.end], r#""a" - 1"#);
+
+        assert_error("def main() { let Bool a = 1 & true; }", "The operator & can not be applied to Number and Bool");
+        assert_error("def main() { let Bool a = [1] < [2]; }", "The operator < can not be applied to List<Number> and List<Number>");
+        assert_error("def main() { let Bool a = 1 == \"1\"; }", "The operator == can not be applied to Number and String");
+        assert_error("def main() { let Number a = -\"a\"; }", "Expected Number but found String");
+    }
+
+    #[test]
+    fn declaration_mismatches()
+    {
+        assert_error(r#"def main() { let Number a = "a"; }"#, "Expected Number but found String");
+        assert_error(r#"def main() { let List<String> a = [1, 2]; }"#, "Expected List<String> but found List<Number>");
+        assert_error(r#"def main() { let a = [1, "a"]; }"#, "List elements must have the same type, expected Number but found String");
+        assert_error(r#"def main() { let Number a = 1; a = "b"; }"#, "Expected Number but found String");
+        assert_error(r#"def main() { let String a = "a"; a++; }"#, "Expected Number but found String");
+        assert_error(r#"def main() { let Strin a = "a"; }"#, "Unknown type Strin");
+        assert_error(r#"def main() { let List a = [1]; }"#, "List expects 1 type argument");
+        assert_error(r#"def main() { let Number<String> a = 1; }"#, "Number does not take type arguments");
+        assert_error(r#"def main() { main = 5; }"#, "Cannot assign to the function main");
+        assert_error(r#"def main() { let s = "ab"; s[0] = "c"; }"#, "The characters of a String can not be assigned to");
+    }
+
+    #[test]
+    fn function_calls()
+    {
+        let functions = "def Number add(Number a, Number b) { return a + b; } ";
+        assert_error(&format!("{} def main() {{ add(1); }}", functions), "add expects 2 arguments but 1 was given");
+        assert_error(&format!("{} def main() {{ add(1, 2, 3); }}", functions), "add expects 2 arguments but 3 were given");
+        assert_error(&format!("{} def main() {{ add(1, \"2\"); }}", functions), "Expected Number but found String");
+        assert_error(&format!("{} def main() {{ let String s = add(1, 2); }}", functions), "Expected String but found Number");
+        assert_error("def main() { let Number a = 5; a(); }", "A value of type Number can not be called");
+
+        //A function that takes less than the one it replaces could be called with arguments it can't handle
+        assert_error("def f(Number n) { print(n + 1); } def main() { let g = print; g = f; g(\"a\"); }",
+            "Expected def nothing(any type) but found def nothing(Number)");
+        assert_ok("def f(Number n) { print(n + 1); } def main() { let g = f; g = print; g(1); }");
+    }
+
+    #[test]
+    fn return_types()
+    {
+        assert_error(r#"def Number f() { return "a"; }"#, "Expected Number but found String");
+        assert_error(r#"def Number f() { return; }"#, "Expected a return value of type Number");
+        assert_error(r#"def f() { return 5; }"#, "This function does not return a value");
+        assert_error(r#"def Number f() { if true { return 5; } }"#, "f does not return a value on every path");
+        assert_ok(r#"def Number f() { if true { return 5; } else { return 6; } }"#);
+        assert_ok(r#"def Number f() { while true {} return 1; }"#);
+        assert_error(r#"def main() { let Number a = print(5); }"#, "Expected Number but found nothing");
+    }
+
+    #[test]
+    fn inference()
+    {
+        let code = r#"
+            def List<String> names() { return ["a"]; }
+            def main()
+            {
+                let a = 5;
+                let b = "s" + "t";
+                let c = names();
+                let d = c[0];
+                let e = [[1], []];
+                let f = ["a": [:], "b": [1: true]];
+                let g = names;
+                foreach x in e { let Number y = x[0]; }
+                foreach key in f { let String k = key; }
+            }
+        "#;
+        let result = check_code(code);
+        assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+
+        let mut types: Vec<String> = result.decl_types.values().map(|t| format!("{}", t)).collect();
+        types.sort();
+        assert_eq!(types, vec!(
+                "Dictionary<String, Dictionary<Number, Bool>>",
+                "List<List<Number>>",
+                "List<Number>",
+                "List<String>",
+                "Number",
+                "Number",
+                "String",
+                "String",
+                "String",
+                "String",
+                "def List<String>()",
+            ));
+
+        assert_error("def main() { let a = 5; a = \"b\"; }", "Expected Number but found String");
+        assert_error("def main() { let a = [1]; let String b = a[0]; }", "Expected String but found Number");
+    }
+
+    #[test]
+    fn inference_failures()
+    {
+        assert_error("def main() { let a = []; }", "The type of a can not be inferred from List<_>, add a type");
+        assert_error("def main() { let a = [[]]; }", "The type of a can not be inferred from List<List<_>>, add a type");
+        assert_error("def main() { let a = [:]; }", "The type of a can not be inferred from Dictionary<_, _>, add a type");
+        assert_error("def main() { let a; }", "The type of a can not be inferred without a value, add a type");
+        assert_error("def main() { let a = print(1); }", "The type of a can not be inferred from something that has no value");
+        assert_error("def main() { foreach x in [] {} }", "The type of x can not be inferred from _, add a type");
+
+        //Empty containers are fine where the type is known
+        assert_ok("def f(List<Number> l) {} def main() { let List<Number> a = []; a = []; f([]); foreach Number x in [] {} }");
+
+        //Errors in the value are not reported again
+        assert_error("def main() { let a = 1 + \"a\"; let b = a; }", "The operator + can not be applied to Number and String");
+    }
+
+    #[test]
+    fn conditions_and_loops()
+    {
+        assert_error("def main() { if 1 {} }", "Expected Bool but found Number");
+        assert_error("def main() { while \"a\" {} }", "Expected Bool but found String");
+        assert_error("def main() { for i is 0 to \"10\" {} }", "Expected Number but found String");
+        assert_error("def main() { for i is 0 to 10 step 0 {} }", "The step of a for loop can not be 0");
+        assert_error("def main() { for i is 0 to 10 step -(0.5 - 1 / 2) {} }", "The step of a for loop can not be 0");
+        assert_ok("def main() { for i is 10 to 0 step -0.5 {} let s = 0; for i is 0 to 10 step s {} for i is 0 to 1 step 1 // 2 + 1 {} }");
+        assert_error("def main() { foreach x in 5 {} }", "Cannot iterate over a value of type Number");
+        assert_error("def main() { foreach String x in [1, 2] {} }", "Expected String but found Number");
+
+        assert_ok("def main() { foreach String k, Number v in [\"a\": 1] {} foreach k, v in [\"a\": [1]] { v[0] = 2; } }");
+        assert_error("def main() { foreach k, v in [1, 2] {} }", "Only dictionaries have keys and values to iterate over, not List<Number>");
+        assert_error("def main() { foreach k, String v in [\"a\": 1] {} }", "Expected String but found Number");
+    }
+
+    #[test]
+    fn iterable_user_types()
+    {
+        let numbers = "type Numbers { Number unused; } implement for Numbers { def List<Number> iterate(self) { return [1]; } } ";
+        assert_ok(&format!("{} def f(Numbers n) {{ foreach Number x in n {{}} }}", numbers));
+        assert_ok(&format!("{} type Outer {{ Number unused; }} implement for Outer {{ def Numbers iterate(self) {{ return Numbers(0); }} }} \
+            def f(Outer o) {{ foreach x in o {{ let y = x + 1; }} }}", numbers));
+        assert_error(&format!("{} def f(Numbers n) {{ foreach String x in n {{}} }}", numbers), "Expected String but found Number");
+
+        assert_error("type Empty { Number unused; } def f(Empty e) { foreach x in e {} }", "Cannot iterate over a value of type Empty");
+        assert_error("type Odd { Number unused; } implement for Odd { def List<Number> iterate(self, Number n) { return [n]; } } \
+            def f(Odd o) { foreach x in o {} }", "Cannot iterate over a value of type Odd");
+        assert_error("type Odd { Number unused; } implement for Odd { def List<Number> iterate() { return [1]; } } \
+            def f(Odd o) { foreach x in o {} }", "Cannot iterate over a value of type Odd");
+        assert_error("type Hidden { Number unused; } implement for Hidden { private: def List<Number> iterate(self) { return [1]; } } \
+            def f(Hidden h) { foreach x in h {} }", "The method iterate of Hidden is private");
+        assert_error("type Loop { Number unused; } implement for Loop { def Loop iterate(self) { return Loop(0); } } \
+            def f(Loop l) { foreach x in l {} }", "Cannot iterate over a value of type Loop");
+    }
+
+    #[test]
+    fn operators()
+    {
+        let vector = "type Vector { Number x; } implement for Vector { def Vector operator +(Vector a, Vector b) { return a; } \
+            def Vector operator *(Vector a, Number b) { return a; } def Vector operator -(Vector a) { return a; } \
+            def Bool operator ==(Vector a, Vector b) { return a.x == b.x; } def Bool operator <(Vector a, Vector b) { return true; } \
+            def Bool operator if(Vector a) { return a.x != 0; } } ";
+        assert_ok(&format!("{} def Vector f(Vector a, Vector b) {{ let c = a + b * 2; c += a; return -c; }}", vector));
+        assert_ok(&format!("{} def f(Vector a, Vector b) {{ let Bool c = a < b & a != b; if a | !b {{}} while a {{}} }}", vector));
+        let checked = check_code(&format!("{} def f(Vector a, Vector b) {{ if a != b {{}} }}", vector));
+        assert_eq!(checked.call_targets.operators.values().collect::<Vec<_>>(), vec!("Vector.operator ==(Vector, Vector)"));
+
+        assert_error(&format!("{} def f(Vector a) {{ let b = a - a; }}", vector), "The operator - can not be applied to Vector and Vector");
+        assert_error(&format!("{} def f(Vector a) {{ let b = 2 * a; }}", vector), "The operator * can not be applied to Number and Vector");
+        assert_error(&format!("{} def f(Vector a) {{ let Number b = a + a; }}", vector), "Expected Number but found Vector");
+        assert_error("type Hidden { Number x; } implement for Hidden { private: def Bool operator if(Hidden h) { return true; } } \
+            def f(Hidden h) { if h {} }", "The operator if of Hidden is private");
+        assert_error("type V { Number x; } implement for V { def V operator +(V a, List<Number> b) { return a; } \
+            def V operator +(V a, List<String> b) { return a; } } def f(V v) { let w = v + []; }",
+            "The operator + is ambiguous for V and List<_>");
+        assert_ok("type V { Number x; } implement for V { def V operator +(V a, List<Number> b) { return a; } \
+            def V operator +(V a, List<String> b) { return a; } } def f(V v) { let List<Number> l = []; let w = v + l; }");
+
+        assert_error("type V { Number x; } implement for V { def V operator +(V a) { return a; } }", "operator + takes 2 parameters, not 1");
+        assert_error("type V { Number x; } implement for V { def Number operator *(Number a, Number b) { return a; } }",
+            "operator * for V needs a parameter of type V");
+        assert_error("type V { Number x; } implement for V { def Number operator <(V a, V b) { return 1; } }", "operator < has to return Bool");
+        assert_error("type V { Number x; } implement for V { def operator -(V a) {} }", "operator - has to return a value");
+        assert_error("type V { Number x; } type W { Number x; } implement for V { def V operator +(V a, W b) { return a; } } \
+            implement for W { def V operator +(V a, W b) { return a; } }", "operator + is already implemented for V and W");
+    }
+
+    #[test]
+    fn accessors()
+    {
+        let account = "type Account { String owner; private: Number balance; hidden Number pin; } ";
+        let checked = check_code(&format!("{} def f(Account a) {{ a.set_balance(a.get_balance() + 1); }}", account));
+        assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+        let mut accessors: Vec<&Accessor> = checked.call_targets.accessors.values().collect();
+        accessors.sort_by_key(|accessor| format!("{:?}", accessor));
+        assert_eq!(accessors, vec!(&Accessor::Get(String::from("balance")), &Accessor::Set(String::from("balance"))));
+
+        assert_error(&format!("{} def f(Account a) {{ let String s = a.get_balance(); }}", account), "Expected String but found Number");
+        assert_error(&format!("{} def f(Account a) {{ a.set_balance(\"a\"); }}", account), "Expected Number but found String");
+        assert_error(&format!("{} def f(Account a) {{ print(a.get_pin()); }}", account), "Account has no member get_pin");
+        assert_error(&format!("{} def f(Account a) {{ print(a.get_owner()); }}", account), "Account has no member get_owner");
+        //Hidden fields can have accessors that are written by hand
+        assert_ok(&format!("{} implement for Account {{ def Number get_pin() {{ return 0; }} }}", account));
+        assert_error(&format!("{} implement for Account {{ def Number get_balance() {{ return 0; }} }}", account),
+            "The method get_balance of Account has the name of the getter of the private field balance");
+        assert_error("type A { Number set_x; private: Number x; }", "The field set_x of A has the name of the setter of the private field x");
+        assert_error("type A { private: Number get_x; Number x; }", "The field get_x of A has the name of the getter of the private field x");
+        assert_ok("type A { Number get_x; private: hidden Number x; }");
+        assert_error("type A { Number size; } implement for A { def Number size(self) { return 1; } }", "The method size of A has the name of a field");
+        assert_error("type A { private: Number x; } implement for A { def x(self) {} }", "The method x of A has the name of a field");
+    }
+
+    #[test]
+    fn user_types()
+    {
+        let point = "type Point { Number x; Number y; } implement for Point { def Number sum(self, Number offset) { return offset; } } ";
+        assert_ok(&format!("{} def Number f(Point p) {{ p.x = 5; return p.x + p.sum(p.y); }}", point));
+        assert_error(&format!("{} def f(Point p) {{ p.x = \"a\"; }}", point), "Expected Number but found String");
+        assert_error(&format!("{} def f(Point p) {{ print(p.z); }}", point), "Point has no member z");
+        assert_error(&format!("{} def f(Point p) {{ print(p.sum); }}", point), "The method sum has to be called");
+        assert_error(&format!("{} def f(Point p) {{ p.sum(\"a\"); }}", point), "Expected Number but found String");
+        assert_error(&format!("{} def f(Point p) {{ let Number n = p; }}", point), "Expected Number but found Point");
+        assert_error("type A { Number x; Number x; }", "The field x is already declared");
+        assert_error("type A { } type A { }", "The type A is already declared");
+        assert_error("implement for B { def f() {} }", "Unknown type B");
+        assert_ok("type Node { Number value; List<Node> children; }");
+    }
+
+    #[test]
+    fn dictionary_keys()
+    {
+        assert_ok("def add(Number a, Number b) {} def f(Dictionary<Bool, List<Number>> d) { let e = [add: 1, add: 2]; }");
+        assert_error("def f(Dictionary<List<Number>, Number> d) {}", "List<Number> can not be a dictionary key because its values can change");
+        assert_error("def f(List<Dictionary<Dictionary<String, Number>, Number>> l) {}",
+            "Dictionary<String, Number> can not be a dictionary key because its values can change");
+        assert_error("type P { Number x; } def f() { let d = [P(1): 1]; }", "P can not be a dictionary key because its values can change");
+        assert_error("def f() { let d = [[1]: 1, [2]: 2]; }", "List<Number> can not be a dictionary key because its values can change");
+    }
+
+    #[test]
+    fn private_members()
+    {
+        let account = r#"
+            type Account
+            {
+                public:
+                String owner;
+                private:
+                Number balance;
+            }
+            implement for Account
+            {
+                def Number total(self, Account other) { return other.balance + other.checked(other); }
+                private:
+                def Number checked(self, Account other) { other.balance = 0; return other.balance; }
+            }
+        "#;
+        assert_ok(&format!("{} def String f(Account a) {{ return a.owner; }}", account));
+        assert_ok(&format!("{} def Number f(Account a) {{ return a.total(a); }}", account));
+
+        let code = format!("{} def Number f(Account a) {{ return a.balance; }}", account);
+        let result = check_code(&code);
+        assert_eq!(result.diagnostics.len(), 1);
+        let diagnostic = &result.diagnostics[0];
+        assert_eq!(diagnostic.message, "The field balance of Account is private");
+        assert_eq!(&code[diagnostic.span.start..diagnostic.span.end], "balance");
+        assert!(diagnostic.span.start > account.len());
+        assert_eq!(diagnostic.labels[0].message, "balance is declared private here");
+        assert_eq!(&code[diagnostic.labels[0].span.start..diagnostic.labels[0].span.end], "Number balance");
+
+        assert_error(&format!("{} def f(Account a) {{ a.balance = 5; }}", account),
+            "The field balance of Account is private");
+        assert_error(&format!("{} def f(Account a) {{ a.checked(a); }}", account),
+            "The method checked of Account is private");
+
+        //Other types can't see private members either
+        assert_error(&format!("{} type Bank {{ }} implement for Bank {{ def f(Account a) {{ print(a.balance); }} }}", account),
+            "The field balance of Account is private");
+    }
+
+    #[test]
+    fn methods()
+    {
+        let point = "type Point { Number x; Number y; } implement for Point { def Number sum(self) { return self.x + self.y; } \
+            def Point origin() { return Point(0, 0); } def move(self, Number x) { self.x = x; } } ";
+        assert_ok(&format!("{} def Number f() {{ let p = Point.origin(); p.move(2); return p.sum(); }}", point));
+        let checked = check_code(&format!("{} def f(Point p) {{ p.move(1); }}", point));
+        assert_eq!(checked.call_targets.methods.values().collect::<Vec<_>>(),
+            vec!(&MethodCall{function: String::from("Point.move"), receiver: true}));
+
+        assert_error(&format!("{} def f() {{ Point.sum(); }}", point), "sum is a method of Point and has to be called on an instance");
+        assert_error(&format!("{} def f(Point p) {{ p.origin(); }}", point),
+            "origin is a static function of Point and isn't called on an instance");
+        assert_error(&format!("{} def f() {{ Point.size(); }}", point), "Point has no member size");
+        assert_error(&format!("{} def f() {{ let p = Point; }}", point), "The type Point is not a value");
+        assert_error("type A { Number x; } implement for A { def f(self) { self.x = \"a\"; } }", "Expected Number but found String");
+    }
+
+    #[test]
+    fn constructors()
+    {
+        assert_ok("type Point { Number x; Number y; } def Point f() { return Point(1, 2); }");
+        assert_error("type Point { Number x; Number y; } def f() { let p = Point(1); }", "Point expects 2 arguments but 1 was given");
+        assert_error("type Point { Number x; Number y; } def f() { let p = Point(1, \"a\"); }", "Expected Number but found String");
+
+        let counter = "type Counter { Number count; String name; } implement for Counter \
+            { def new(self, String name) { self.name = name; self.count = 0; } } ";
+        assert_ok(&format!("{} def Counter f() {{ return Counter(\"a\"); }}", counter));
+        let checked = check_code(&format!("{} def f() {{ let c = Counter(\"a\"); }}", counter));
+        assert_eq!(checked.call_targets.constructors.values().collect::<Vec<_>>(), vec!(&ConstructorCall{
+            type_name: String::from("Counter"),
+            fields: vec!(String::from("count"), String::from("name")),
+            function: Some(String::from("Counter.new")),
+        }));
+        assert_error(&format!("{} def f() {{ let c = Counter(0, \"a\"); }}", counter), "Counter expects 1 argument but 2 were given");
+        assert_error(&format!("{} def f() {{ Counter.new(\"a\"); }}", counter), "The constructor of Counter is called through the type");
+
+        assert_error("type A { Number x; } implement for A { def new(Number x) {} }", "The constructor of A has to take self");
+        assert_error("type A { Number x; } implement for A { def Number new(self) { self.x = 1; return 1; } }",
+            "The constructor of A can not return a value");
+
+        //Every field needs a value on every path before the constructor finishes
+        let fields = "type A { Number x; Number y; } implement for A ";
+        assert_ok(&format!("{} {{ def new(self, Bool b) {{ if b {{ self.x = 1; }} else {{ self.x = 2; }} self.y = self.x; }} }}", fields));
+        assert_error(&format!("{} {{ def new(self) {{ self.x = 1; }} }}", fields),
+            "The constructor of A finishes without giving a value to the field y");
+        assert_error(&format!("{} {{ def new(self, Bool b) {{ if b {{ self.x = 1; }} self.y = self.x; self.x = 2; }} }}", fields),
+            "self.x is used before it has a value");
+        assert_error(&format!("{} {{ def new(self, Bool b) {{ self.x = 1; if b {{ return; }} self.y = 2; }} }}", fields),
+            "The constructor of A returns without giving a value to the field y");
+        assert_error(&format!("{} {{ def new(self) {{ while true {{ self.x = 1; self.y = 2; }} }} }}", fields),
+            "The constructor of A finishes without giving a value to the fields x, y");
+        assert_error(&format!("{} {{ def new(self) {{ self.x = 1; print(self); self.y = 2; }} }}", fields),
+            "self is used before all of its fields have a value");
+    }
+
+    #[test]
+    fn builtin_methods()
+    {
+        assert_ok("def Number f(String s) { let parts = s.trim().split(\",\"); parts.push(\"x\"); return parts.length() + s.find(\"a\"); }");
+        assert_ok("def Bool less(Number a, Number b) { return a < b; } def String text(Number n) { return n.to_string(); } \
+            def String f(List<Number> l) { l.sort(less); return l.map(text).join(\", \"); }");
+        assert_ok("def Number f(Dictionary<String, Number> d) { d.merge(d); return d.keys().length() + d.remove(\"a\").abs(); }");
+        let checked = check_code("def f(List<Number> l) { l.push(1); }");
+        assert_eq!(checked.call_targets.methods.values().collect::<Vec<_>>(),
+            vec!(&MethodCall{function: String::from("List.push"), receiver: true}));
+
+        //The type parameters are bound by the receiver and then hold for the arguments
+        assert_error("def f(List<Number> l) { l.push(\"a\"); }", "Expected Number but found String");
+        assert_error("def Number add(Number a, Number b) { return a + b; } def f(List<Number> l) { let s = l.reduce(\"\", add); }",
+            "Expected def String(String, Number) but found def Number(Number, Number)");
+        assert_error("def f(List<Number> l) { let s = l.join(\",\"); }", "Expected List<String> but found List<Number>");
+        assert_error("def f(String s) { let n = s.length; }", "The method length has to be called");
+        assert_error("def f(String s) { let n = s.size(); }", "String has no member size");
+    }
+
+    #[test]
+    fn capabilities()
+    {
+        assert_error("def main() { print(read_file(\"secret\")); }",
+            "read_file needs the filesystem capability, which this script was not given");
+        //Taking a reference is not allowed either, since it could be called later
+        assert_error("def main() { let f = clock; }", "clock needs the clock capability, which this script was not given");
+        //Local names that hide a host function are fine
+        assert_ok("def main() { let random = 4; print(random); }");
+
+        let code = "def main() { print(random_int(1, 6)); write_file(\"a\", \"b\"); }";
+        let program = parse_token_list(Lexer::new().tokenize(String::from(code))).unwrap();
+        let mut builtins = Builtins::new();
+        builtins.set_capabilities(Capabilities::none().with(Capability::Random));
+        let resolution = resolve(&program, &builtins.names());
+        let messages: Vec<String> = check(&program, &resolution, &builtins).diagnostics.into_iter()
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(messages, vec!(String::from("write_file needs the filesystem capability, which this script was not given")));
+    }
+}
+
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use ast::TypeExpr;
+
+/*
+ * The static type of a value in a script
+ */
+#[derive(Clone,Eq,PartialEq,Hash,Debug)]
+pub enum Type
+{
+    Number,
+    String,
+    Bool,
+    //The type of functions that don't return anything
+    Unit,
+    List(Box<Type>),
+    Dictionary(Box<Type>, Box<Type>),
+    //A type declared with the type keyword
+    User(String),
+    Function(Vec<Type>, Box<Type>),
+
+    //Accepts values of every type. Only used for the parameters of builtin functions
+    Any,
+    //A type parameter of a builtin function, like the T of List<T>.push(T). It
+    //stands for the same type everywhere in the signature, which the arguments decide
+    Parameter(String),
+    //The element type of an empty container literal which has to be found out
+    //from where the container is used
+    Infer,
+    //The type of something that could not be checked, for example because of an
+    //earlier error. Compatible with everything to avoid reporting the same error twice
+    Unknown,
+}
+
+impl Type
+{
+    pub fn list(element: Type) -> Type
+    {
+        Type::List(Box::new(element))
+    }
+
+    pub fn dictionary(key: Type, value: Type) -> Type
+    {
+        Type::Dictionary(Box::new(key), Box::new(value))
+    }
+
+    //Returns the built in type with the specified name and number of type arguments
+    pub fn builtin(name: &str, args: Vec<Type>) -> Option<Result<Type, usize>>
+    {
+        let expected_args = match name
+        {
+            "Number" | "String" | "Bool" => 0,
+            "List" | "list" => 1,
+            "Dictionary" | "dictionary" => 2,
+            _ => return None
+        };
+        if args.len() != expected_args
+        {
+            return Some(Err(expected_args));
+        }
+
+        let mut args = args.into_iter();
+        let result = match name
+        {
+            "Number" => Type::Number,
+            "String" => Type::String,
+            "Bool" => Type::Bool,
+            "List" | "list" => Type::list(args.next().unwrap()),
+            _ => {
+                let key = args.next().unwrap();
+                Type::dictionary(key, args.next().unwrap())
+            }
+        };
+        return Some(Ok(result));
+    }
+
+    //Converts a type as it is written in the code without checking it. Names that
+    //are not built in types are taken to be user types
+    pub fn from_type_expr(type_expr: &TypeExpr) -> Type
+    {
+        let args = type_expr.args.iter().map(Type::from_type_expr).collect();
+        match Type::builtin(&type_expr.name, args)
+        {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Type::Unknown,
+            None => Type::User(type_expr.name.clone())
+        }
+    }
+
+    pub fn parameter(name: &str) -> Type
+    {
+        Type::Parameter(String::from(name))
+    }
+
+    //The name that the methods of a built in type are declared under, like List
+    //for every List<T>
+    pub fn builtin_name(&self) -> Option<&'static str>
+    {
+        match *self
+        {
+            Type::Number => Some("Number"),
+            Type::String => Some("String"),
+            Type::Bool => Some("Bool"),
+            Type::List(_) => Some("List"),
+            Type::Dictionary(_, _) => Some("Dictionary"),
+            _ => None
+        }
+    }
+
+    pub fn is_unknown(&self) -> bool
+    {
+        *self == Type::Unknown
+    }
+
+    //Returns true if part of the type could not be inferred
+    pub fn contains_infer(&self) -> bool
+    {
+        match *self
+        {
+            Type::Infer => true,
+            Type::List(ref element) => element.contains_infer(),
+            Type::Dictionary(ref key, ref value) => key.contains_infer() || value.contains_infer(),
+            Type::Function(ref params, ref return_type) =>
+                params.iter().any(|p| p.contains_infer()) || return_type.contains_infer(),
+            _ => false
+        }
+    }
+
+    //Lists, dictionaries and objects can't be dictionary keys. They can change after
+    //they are added, which would leave their entry under a key that no longer matches
+    pub fn is_key(&self) -> bool
+    {
+        !matches!(*self, Type::List(_) | Type::Dictionary(_, _) | Type::User(_))
+    }
+
+    //Combines two types that should be the same into the most specific one, for
+    //example List<_> and List<Number> into List<Number>. Returns None if the
+    //types are different
+    pub fn merge(&self, other: &Type) -> Option<Type>
+    {
+        match (self, other)
+        {
+            (&Type::Infer, _) | (&Type::Unknown, _) => Some(other.clone()),
+            (_, &Type::Infer) | (_, &Type::Unknown) => Some(self.clone()),
+            (&Type::List(ref a), &Type::List(ref b)) => a.merge(b).map(Type::list),
+            (&Type::Dictionary(ref ak, ref av), &Type::Dictionary(ref bk, ref bv)) =>
+                match (ak.merge(bk), av.merge(bv))
+                {
+                    (Some(key), Some(value)) => Some(Type::dictionary(key, value)),
+                    _ => None
+                },
+            _ if self == other => Some(self.clone()),
+            _ => None
+        }
+    }
+
+    //Binds the type parameters in self to the types in the same place in other,
+    //for example the T of List<T> to Number when other is List<Number>. A parameter
+    //that is already bound keeps the most specific of both types
+    pub fn bind(&self, other: &Type, bindings: &mut HashMap<String, Type>)
+    {
+        match (self, other)
+        {
+            (&Type::Parameter(ref name), _) => {
+                let bound = match bindings.get(name)
+                {
+                    Some(bound) => bound.merge(other).unwrap_or_else(|| bound.clone()),
+                    None => other.clone()
+                };
+                bindings.insert(name.clone(), bound);
+            }
+            (&Type::List(ref a), &Type::List(ref b)) => a.bind(b, bindings),
+            (&Type::Dictionary(ref ak, ref av), &Type::Dictionary(ref bk, ref bv)) => {
+                ak.bind(bk, bindings);
+                av.bind(bv, bindings);
+            }
+            (&Type::Function(ref ap, ref ar), &Type::Function(ref bp, ref br)) => {
+                for (a, b) in ap.iter().zip(bp.iter())
+                {
+                    a.bind(b, bindings);
+                }
+                ar.bind(br, bindings);
+            }
+            _ => {}
+        }
+    }
+
+    //Replaces the type parameters with the types they are bound to. Parameters that
+    //no argument decided are unknown
+    pub fn substitute(&self, bindings: &HashMap<String, Type>) -> Type
+    {
+        match *self
+        {
+            Type::Parameter(ref name) => bindings.get(name).cloned().unwrap_or(Type::Unknown),
+            Type::List(ref element) => Type::list(element.substitute(bindings)),
+            Type::Dictionary(ref key, ref value) => Type::dictionary(key.substitute(bindings), value.substitute(bindings)),
+            Type::Function(ref params, ref return_type) =>
+                Type::Function(params.iter().map(|p| p.substitute(bindings)).collect(), Box::new(return_type.substitute(bindings))),
+            ref other => other.clone()
+        }
+    }
+
+    //Returns true if a value of type other can be used where self is expected.
+    //A function can be used in place of another if it takes at least everything
+    //the other takes, so parameters are compared the other way around
+    pub fn accepts(&self, other: &Type) -> bool
+    {
+        match (self, other)
+        {
+            (&Type::Any, _) | (&Type::Unknown, _) | (_, &Type::Unknown) => true,
+            (&Type::Infer, _) | (_, &Type::Infer) => true,
+            (&Type::List(ref a), &Type::List(ref b)) => a.accepts(b),
+            (&Type::Dictionary(ref ak, ref av), &Type::Dictionary(ref bk, ref bv)) => ak.accepts(bk) && av.accepts(bv),
+            (&Type::Function(ref ap, ref ar), &Type::Function(ref bp, ref br)) =>
+                ap.len() == bp.len() && ap.iter().zip(bp.iter()).all(|(a, b)| b.accepts(a)) && ar.accepts(br),
+            _ => self == other
+        }
+    }
+}
+
+impl fmt::Display for Type
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match *self
+        {
+            Type::Number => write!(f, "Number"),
+            Type::String => write!(f, "String"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Unit => write!(f, "nothing"),
+            Type::List(ref element) => write!(f, "List<{}>", element),
+            Type::Dictionary(ref key, ref value) => write!(f, "Dictionary<{}, {}>", key, value),
+            Type::User(ref name) => write!(f, "{}", name),
+            Type::Function(ref params, ref return_type) => {
+                write!(f, "def {}(", return_type)?;
+                for (i, param) in params.iter().enumerate()
+                {
+                    if i != 0
+                    {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, ")")
+            }
+            Type::Any => write!(f, "any type"),
+            Type::Parameter(ref name) => write!(f, "{}", name),
+            Type::Infer => write!(f, "_"),
+            Type::Unknown => write!(f, "unknown"),
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use ast::*;
+use diagnostic::Diagnostic;
+use error_codes;
+use token::Span;
+
+/*
+ * Which of the rules about the structure of a script are enforced
+ */
+#[derive(Clone,Copy,Eq,PartialEq,Debug)]
+pub struct ValidationOptions
+{
+    //Scripts that are run need a main() function. Libraries only provide
+    //functions for other code to call
+    pub require_main: bool,
+    pub allow_top_level_code: bool,
+}
+
+impl ValidationOptions
+{
+    pub fn script() -> ValidationOptions
+    {
+        ValidationOptions {
+            require_main: true,
+            allow_top_level_code: false
+        }
+    }
+
+    pub fn library() -> ValidationOptions
+    {
+        ValidationOptions {
+            require_main: false,
+            allow_top_level_code: false
+        }
+    }
+
+    //Code typed into the REPL runs as soon as it has been checked
+    pub fn repl() -> ValidationOptions
+    {
+        ValidationOptions {
+            require_main: false,
+            allow_top_level_code: true
+        }
+    }
+}
+
+impl Default for ValidationOptions
+{
+    fn default() -> ValidationOptions
+    {
+        ValidationOptions::script()
+    }
+}
+
+//Checks that the program follows the rules about what is allowed where:
+//no code outside functions and a main() function to run
+pub fn validate(program: &Program, options: &ValidationOptions) -> Vec<Diagnostic>
+{
+    let mut diagnostics = Vec::new();
+
+    if !options.allow_top_level_code
+    {
+        for item in &program.items
+        {
+            if let Item::Statement(ref statement) = *item
+            {
+                diagnostics.push(Diagnostic::error(error_codes::TOP_LEVEL_CODE, String::from("Code is not allowed outside functions"), statement.span));
+            }
+        }
+    }
+
+    let main = program.items.iter()
+        .filter_map(|item| match *item { Item::Function(ref f) if f.name == "main" => Some(f), _ => None })
+        .next();
+    match main
+    {
+        Some(main) => check_main_signature(main, &mut diagnostics),
+        None if options.require_main => {
+            diagnostics.push(Diagnostic::error(error_codes::MISSING_MAIN, String::from("The script has no main() function to run"), Span::default()));
+        }
+        None => {}
+    }
+
+    return diagnostics;
+}
+
+//main can either take no parameters or the command line arguments as a
+//List<String>. It can return a Number which is used as the exit code
+fn check_main_signature(main: &FunctionDecl, diagnostics: &mut Vec<Diagnostic>)
+{
+    let params_ok = match main.params.len()
+    {
+        0 => true,
+        1 => is_string_list(&main.params[0].type_expr),
+        _ => false
+    };
+    if !params_ok
+    {
+        let span = main.params.iter().fold(main.params[0].span, |span, param| span.to(param.span));
+        diagnostics.push(Diagnostic::error(error_codes::INVALID_MAIN,
+                String::from("main must take no parameters or a single List<String>"), span));
+    }
+
+    if let Some(ref return_type) = main.return_type
+    {
+        if return_type.name != "Number" || !return_type.args.is_empty()
+        {
+            diagnostics.push(Diagnostic::error(error_codes::INVALID_MAIN,
+                    String::from("main can only return a Number"), return_type.span));
+        }
+    }
+}
+
+fn is_string_list(type_expr: &TypeExpr) -> bool
+{
+    (type_expr.name == "List" || type_expr.name == "list")
+        && type_expr.args.len() == 1
+        && type_expr.args[0].name == "String"
+        && type_expr.args[0].args.is_empty()
+}
+
+#[cfg(test)]
+mod validation_tests
+{
+    use lexer::Lexer;
+    use parser::parse_token_list;
+    use validation::*;
+
+    fn validate_code(code: &str, options: ValidationOptions) -> Vec<(String, Span)>
+    {
+        let tokens = Lexer::new().tokenize(String::from(code));
+        let program = parse_token_list(tokens).unwrap();
+        validate(&program, &options).into_iter().map(|d| (d.message, d.span)).collect()
+    }
+
+    fn messages(code: &str, options: ValidationOptions) -> Vec<String>
+    {
+        validate_code(code, options).into_iter().map(|(message, _)| message).collect()
+    }
+
+    #[test]
+    fn valid_main_functions()
+    {
+        assert!(messages("def main() {}", ValidationOptions::script()).is_empty());
+        assert!(messages("def main(List<String> args) {}", ValidationOptions::script()).is_empty());
+        assert!(messages("def Number main() { return 0; }", ValidationOptions::script()).is_empty());
+    }
+
+    #[test]
+    fn missing_main()
+    {
+        assert_eq!(messages("def helper() {}", ValidationOptions::script()),
+            vec!("The script has no main() function to run"));
+        assert!(messages("def helper() {}", ValidationOptions::library()).is_empty());
+    }
+
+    #[test]
+    fn wrong_main_signature()
+    {
+        let code = "def main(Number a, Number b) {}";
+        let errors = validate_code(code, ValidationOptions::script());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, "main must take no parameters or a single List<String>");
+        assert_eq!(&code[errors[0].1.start..errors[0].1.end], "Number a, Number b");
+
+        assert_eq!(messages("def main(List<Number> args) {}", ValidationOptions::script()),
+            vec!("main must take no parameters or a single List<String>"));
+        assert_eq!(messages("def String main() { return \"\"; }", ValidationOptions::script()),
+            vec!("main can only return a Number"));
+
+        //The signature is checked in libraries too
+        assert_eq!(messages("def String main() { return \"\"; }", ValidationOptions::library()),
+            vec!("main can only return a Number"));
+    }
+
+    #[test]
+    fn top_level_code()
+    {
+        let code = "let a = 5;\ndef main() {}\nprint(a);";
+        let errors = validate_code(code, ValidationOptions::script());
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].0, "Code is not allowed outside functions");
+        assert_eq!(&code[errors[0].1.start..errors[0].1.end], "let a = 5;");
+        assert_eq!(&code[errors[1].1.start..errors[1].1.end], "print(a);");
+
+        assert_eq!(messages("let a = 5;", ValidationOptions::library()),
+            vec!("Code is not allowed outside functions"));
+
+        let options = ValidationOptions{require_main: false, allow_top_level_code: true};
+        assert!(messages("let a = 5; print(a);", options).is_empty());
+    }
+}
@@ -1,4 +1,748 @@
-pub struct Variable 
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+use ast::{BinaryOp, UnaryOp};
+use number::{Number, NumberResult};
+use types::Type;
+
+/*
+ * A value that a script works with while it runs.
+ *
+ * Numbers, strings, bools and function references are copied when they are assigned
+ * or passed to a function. Lists, dictionaries and objects are shared: assigning one
+ * to another variable or passing it to a function gives access to the same container,
+ * so changes made through one name are visible through all of them
+ */
+#[derive(Clone,Debug)]
+pub enum Value
 {
-    name: String
+    Number(Number),
+    String(String),
+    Bool(bool),
+    List(Rc<RefCell<Vec<Value>>>),
+    Dictionary(Rc<RefCell<Dictionary>>),
+    //An instance of a type declared with the type keyword
+    Object(Rc<RefCell<Object>>),
+    Function(Rc<FunctionRef>),
+    //The result of functions that don't return anything
+    Unit,
+}
+
+/*
+ * A dictionary that keeps its entries in the order they were inserted. Keys are found
+ * through the hashes of their values, which the type checker keeps to values that can't
+ * change after they are added
+ */
+#[derive(Clone,PartialEq,Debug,Default)]
+pub struct Dictionary
+{
+    entries: Vec<(Value, Value)>,
+    //The positions in entries of the keys with each hash
+    positions: HashMap<u64, Vec<usize>>,
+}
+
+impl Dictionary
+{
+    pub fn new() -> Dictionary
+    {
+        Dictionary::default()
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value>
+    {
+        let position = self.position(key)?;
+        Some(&self.entries[position].1)
+    }
+
+    //Sets the value of a key, adding the key if it is new
+    pub fn insert(&mut self, key: Value, value: Value)
+    {
+        match self.position(&key)
+        {
+            Some(position) => self.entries[position].1 = value,
+            None => {
+                self.positions.entry(key.key_hash()).or_default().push(self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &Value) -> Option<Value>
+    {
+        let position = self.position(key)?;
+        let hash = key.key_hash();
+        let same_hash = self.positions.get_mut(&hash).unwrap();
+        same_hash.retain(|&p| p != position);
+        if same_hash.is_empty()
+        {
+            self.positions.remove(&hash);
+        }
+        //The entries after the removed one move down to keep their order
+        for same_hash in self.positions.values_mut()
+        {
+            for p in same_hash.iter_mut().filter(|p| **p > position)
+            {
+                *p -= 1;
+            }
+        }
+        Some(self.entries.remove(position).1)
+    }
+
+    pub fn keys(&self) -> Vec<Value>
+    {
+        self.entries.iter().map(|&(ref key, _)| key.clone()).collect()
+    }
+
+    pub fn values(&self) -> Vec<Value>
+    {
+        self.entries.iter().map(|&(_, ref value)| value.clone()).collect()
+    }
+
+    pub fn entries(&self) -> &[(Value, Value)]
+    {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.entries.is_empty()
+    }
+
+    fn position(&self, key: &Value) -> Option<usize>
+    {
+        let same_hash = self.positions.get(&key.key_hash())?;
+        same_hash.iter().cloned().find(|&position| self.entries[position].0 == *key)
+    }
+}
+
+/*
+ * An instance of a user defined type. Fields are kept in the order they were declared
+ */
+#[derive(Clone,PartialEq,Debug)]
+pub struct Object
+{
+    pub type_name: String,
+    pub fields: Vec<(String, Value)>,
+}
+
+/*
+ * A reference to a function declared in the script or provided by the interpreter
+ */
+#[derive(Clone,PartialEq,Debug)]
+pub struct FunctionRef
+{
+    pub name: String,
+    pub params: Vec<Type>,
+    pub return_type: Type,
+}
+
+impl Value
+{
+    pub fn integer(value: i64) -> Value
+    {
+        Value::Number(Number::Integer(value))
+    }
+
+    pub fn list(elements: Vec<Value>) -> Value
+    {
+        Value::List(Rc::new(RefCell::new(elements)))
+    }
+
+    pub fn dictionary(dictionary: Dictionary) -> Value
+    {
+        Value::Dictionary(Rc::new(RefCell::new(dictionary)))
+    }
+
+    pub fn object(type_name: String, fields: Vec<(String, Value)>) -> Value
+    {
+        Value::Object(Rc::new(RefCell::new(Object{type_name: type_name, fields: fields})))
+    }
+
+    pub fn function(name: String, params: Vec<Type>, return_type: Type) -> Value
+    {
+        Value::Function(Rc::new(FunctionRef{name: name, params: params, return_type: return_type}))
+    }
+
+    pub fn type_name(&self) -> String
+    {
+        match *self
+        {
+            Value::Number(_) => String::from("Number"),
+            Value::String(_) => String::from("String"),
+            Value::Bool(_) => String::from("Bool"),
+            Value::List(_) => String::from("List"),
+            Value::Dictionary(_) => String::from("Dictionary"),
+            Value::Object(ref object) => object.borrow().type_name.clone(),
+            Value::Function(_) => String::from("function"),
+            Value::Unit => String::from("nothing"),
+        }
+    }
+
+    //The type of the value as far as it can be seen from the value itself. The
+    //elements of empty containers have the type Infer
+    pub fn type_of(&self) -> Type
+    {
+        match *self
+        {
+            Value::Number(_) => Type::Number,
+            Value::String(_) => Type::String,
+            Value::Bool(_) => Type::Bool,
+            Value::List(ref elements) => Type::list(elements.borrow().first().map(|e| e.type_of()).unwrap_or(Type::Infer)),
+            Value::Dictionary(ref dictionary) => match dictionary.borrow().entries().first()
+            {
+                Some(&(ref key, ref value)) => Type::dictionary(key.type_of(), value.type_of()),
+                None => Type::dictionary(Type::Infer, Type::Infer)
+            },
+            Value::Object(ref object) => Type::User(object.borrow().type_name.clone()),
+            Value::Function(ref function) => Type::Function(function.params.clone(), Box::new(function.return_type.clone())),
+            Value::Unit => Type::Unit,
+        }
+    }
+
+    //Returns true if the value can be used where a value of the type is expected.
+    //Unlike type_of this looks at every element, for values made outside of scripts
+    //which the type checker hasn't seen
+    pub fn has_type(&self, expected: &Type) -> bool
+    {
+        self.matches_type(expected, &mut Vec::new())
+    }
+
+    //checking holds the containers that are being checked, reaching one again
+    //doesn't find anything new
+    fn matches_type(&self, expected: &Type, checking: &mut Vec<usize>) -> bool
+    {
+        if let Some(address) = self.address()
+        {
+            if checking.contains(&address)
+            {
+                return true;
+            }
+            checking.push(address);
+        }
+        match (expected, self)
+        {
+            (&Type::Any, _) | (&Type::Unknown, _) | (&Type::Infer, _) | (&Type::Parameter(_), _) => true,
+            (&Type::Number, &Value::Number(number)) => number.as_f64().is_finite(),
+            (&Type::String, &Value::String(_)) | (&Type::Bool, &Value::Bool(_)) | (&Type::Unit, &Value::Unit) => true,
+            (&Type::List(ref element), &Value::List(ref elements)) =>
+                elements.borrow().iter().all(|e| e.matches_type(element, checking)),
+            (&Type::Dictionary(ref key, ref value), &Value::Dictionary(ref dictionary)) => dictionary.borrow().entries().iter()
+                .all(|&(ref k, ref v)| k.matches_type(key, checking) && v.matches_type(value, checking)),
+            (&Type::User(ref name), &Value::Object(ref object)) => object.borrow().type_name == *name,
+            (&Type::Function(_, _), &Value::Function(_)) => expected.accepts(&self.type_of()),
+            _ => false
+        }
+    }
+
+    //Returns true if both values are the same shared container or object. Values
+    //that are copied are never the same
+    pub fn is_same(&self, other: &Value) -> bool
+    {
+        match (self, other)
+        {
+            (&Value::List(ref a), &Value::List(ref b)) => Rc::ptr_eq(a, b),
+            (&Value::Dictionary(ref a), &Value::Dictionary(ref b)) => Rc::ptr_eq(a, b),
+            (&Value::Object(ref a), &Value::Object(ref b)) => Rc::ptr_eq(a, b),
+            _ => false
+        }
+    }
+
+    //A hash that is the same for equal values, used to find the keys of dictionaries.
+    //Containers and objects only hash their type because their contents can change
+    fn key_hash(&self) -> u64
+    {
+        let mut hasher = DefaultHasher::new();
+        match *self
+        {
+            //Whole floats hash like the integers they are equal to
+            Value::Number(number) => match number.as_i64()
+            {
+                Some(integer) => integer.hash(&mut hasher),
+                None => number.as_f64().to_bits().hash(&mut hasher)
+            },
+            Value::String(ref string) => string.hash(&mut hasher),
+            Value::Bool(b) => b.hash(&mut hasher),
+            Value::Function(ref function) => function.name.hash(&mut hasher),
+            ref other => other.type_name().hash(&mut hasher)
+        }
+        return hasher.finish();
+    }
+
+    //The address of a shared container or object, which tells them apart. Copied
+    //values don't have one
+    fn address(&self) -> Option<usize>
+    {
+        match *self
+        {
+            Value::List(ref elements) => Some(Rc::as_ptr(elements) as usize),
+            Value::Dictionary(ref dictionary) => Some(Rc::as_ptr(dictionary) as usize),
+            Value::Object(ref object) => Some(Rc::as_ptr(object) as usize),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool, String>
+    {
+        match *self
+        {
+            Value::Bool(b) => Ok(b),
+            ref other => Err(format!("Expected a Bool but found {}", other.type_name()))
+        }
+    }
+
+    pub fn as_number(&self) -> Result<Number, String>
+    {
+        match *self
+        {
+            Value::Number(n) => Ok(n),
+            ref other => Err(format!("Expected a Number but found {}", other.type_name()))
+        }
+    }
+
+    pub fn unary(&self, op: UnaryOp) -> Result<Value, String>
+    {
+        match op
+        {
+            UnaryOp::Negate => Ok(Value::Number(self.as_number()?.neg()?)),
+            UnaryOp::Not => Ok(Value::Bool(!self.as_bool()?)),
+        }
+    }
+
+    //Applies a binary operator. And and Or are only here for completeness, the
+    //interpreter short circuits them
+    pub fn binary(&self, op: BinaryOp, other: &Value) -> Result<Value, String>
+    {
+        match (op, self, other)
+        {
+            (BinaryOp::Add, &Value::Number(a), &Value::Number(b)) => number(a.add(b)),
+            (BinaryOp::Add, &Value::String(ref a), &Value::String(ref b)) => Ok(Value::String(format!("{}{}", a, b))),
+            (BinaryOp::Sub, &Value::Number(a), &Value::Number(b)) => number(a.sub(b)),
+            (BinaryOp::Mul, &Value::Number(a), &Value::Number(b)) => number(a.mul(b)),
+            (BinaryOp::Div, &Value::Number(a), &Value::Number(b)) => number(a.div(b)),
+            (BinaryOp::IntDiv, &Value::Number(a), &Value::Number(b)) => number(a.int_div(b)),
+            (BinaryOp::Rem, &Value::Number(a), &Value::Number(b)) => number(a.rem(b)),
+
+            (BinaryOp::Equal, a, b) => Ok(Value::Bool(a == b)),
+            (BinaryOp::NotEqual, a, b) => Ok(Value::Bool(a != b)),
+
+            (BinaryOp::Less, &Value::Number(a), &Value::Number(b)) => Ok(Value::Bool(a < b)),
+            (BinaryOp::LessEqual, &Value::Number(a), &Value::Number(b)) => Ok(Value::Bool(a <= b)),
+            (BinaryOp::Greater, &Value::Number(a), &Value::Number(b)) => Ok(Value::Bool(a > b)),
+            (BinaryOp::GreaterEqual, &Value::Number(a), &Value::Number(b)) => Ok(Value::Bool(a >= b)),
+            (BinaryOp::Less, &Value::String(ref a), &Value::String(ref b)) => Ok(Value::Bool(a < b)),
+            (BinaryOp::LessEqual, &Value::String(ref a), &Value::String(ref b)) => Ok(Value::Bool(a <= b)),
+            (BinaryOp::Greater, &Value::String(ref a), &Value::String(ref b)) => Ok(Value::Bool(a > b)),
+            (BinaryOp::GreaterEqual, &Value::String(ref a), &Value::String(ref b)) => Ok(Value::Bool(a >= b)),
+
+            (BinaryOp::And, &Value::Bool(a), &Value::Bool(b)) => Ok(Value::Bool(a && b)),
+            (BinaryOp::Or, &Value::Bool(a), &Value::Bool(b)) => Ok(Value::Bool(a || b)),
+
+            (op, a, b) => Err(format!("The operator {} can not be applied to {} and {}", op.symbol(), a.type_name(), b.type_name()))
+        }
+    }
+
+    //Returns the element at index in a list, dictionary or string
+    pub fn index(&self, index: &Value) -> Result<Value, String>
+    {
+        match *self
+        {
+            Value::List(ref elements) => {
+                let elements = elements.borrow();
+                let i = list_index(index, elements.len())?;
+                Ok(elements[i].clone())
+            }
+            Value::Dictionary(ref dictionary) => match dictionary.borrow().get(index)
+            {
+                Some(value) => Ok(value.clone()),
+                None => Err(format!("The key {} is not in the dictionary", index.debug_string()))
+            },
+            Value::String(ref s) => {
+                let chars: Vec<char> = s.chars().collect();
+                let i = list_index(index, chars.len())?;
+                Ok(Value::String(chars[i].to_string()))
+            }
+            ref other => Err(format!("Cannot index into a value of type {}", other.type_name()))
+        }
+    }
+
+    //Changes the element at index. Assigning to a key that is not in a dictionary
+    //adds it
+    pub fn set_index(&self, index: &Value, value: Value) -> Result<(), String>
+    {
+        match *self
+        {
+            Value::List(ref elements) => {
+                let mut elements = elements.borrow_mut();
+                let i = list_index(index, elements.len())?;
+                elements[i] = value;
+                Ok(())
+            }
+            Value::Dictionary(ref dictionary) => {
+                dictionary.borrow_mut().insert(index.clone(), value);
+                Ok(())
+            }
+            ref other => Err(format!("Cannot assign to an element of a value of type {}", other.type_name()))
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Result<Value, String>
+    {
+        match *self
+        {
+            Value::Object(ref object) => object.borrow().fields.iter()
+                .find(|&&(ref field, _)| field == name)
+                .map(|&(_, ref value)| value.clone())
+                .ok_or_else(|| format!("{} has no member {}", self.type_name(), name)),
+            ref other => Err(format!("{} has no member {}", other.type_name(), name))
+        }
+    }
+
+    pub fn set_field(&self, name: &str, value: Value) -> Result<(), String>
+    {
+        match *self
+        {
+            Value::Object(ref object) => {
+                let mut object = object.borrow_mut();
+                match object.fields.iter_mut().find(|&&mut (ref field, _)| field == name)
+                {
+                    Some(field) => {
+                        field.1 = value;
+                        Ok(())
+                    }
+                    None => Err(format!("{} has no member {}", object.type_name, name))
+                }
+            }
+            ref other => Err(format!("{} has no member {}", other.type_name(), name))
+        }
+    }
+
+    //The values that foreach goes through
+    pub fn iteration_values(&self) -> Result<Vec<Value>, String>
+    {
+        match *self
+        {
+            Value::List(ref elements) => Ok(elements.borrow().clone()),
+            Value::Dictionary(ref dictionary) => Ok(dictionary.borrow().keys()),
+            Value::String(ref s) => Ok(s.chars().map(|c| Value::String(c.to_string())).collect()),
+            ref other => Err(format!("Cannot iterate over a value of type {}", other.type_name()))
+        }
+    }
+
+    //The keys and values that foreach key, value goes through
+    pub fn iteration_entries(&self) -> Result<Vec<(Value, Value)>, String>
+    {
+        match *self
+        {
+            Value::Dictionary(ref dictionary) => Ok(dictionary.borrow().entries().to_vec()),
+            ref other => Err(format!("Only dictionaries have keys and values to iterate over, not {}", other.type_name()))
+        }
+    }
+
+    //The error for changing a container while a foreach loop goes through it.
+    //The loop goes through a copy, so the change would not be seen by it
+    pub fn changed_while_iterating(&self) -> String
+    {
+        format!("The {} can not be changed while a foreach loop goes through it", self.type_name())
+    }
+
+    //Like to_string but strings are quoted, used for values inside containers
+    pub fn debug_string(&self) -> String
+    {
+        self.describe(true, &mut Vec::new())
+    }
+
+    //Writes the value, quoting strings if quoted is set. printing holds the containers
+    //and objects that are being written around this value, so one that contains itself
+    //is written as [...] or Type {...} where it is reached again
+    fn describe(&self, quoted: bool, printing: &mut Vec<usize>) -> String
+    {
+        let address = self.address();
+        if let Some(address) = address
+        {
+            if printing.contains(&address)
+            {
+                return match *self
+                {
+                    Value::Object(ref object) => format!("{} {{...}}", object.borrow().type_name),
+                    _ => String::from("[...]")
+                };
+            }
+            printing.push(address);
+        }
+        let description = match *self
+        {
+            Value::Number(n) => n.to_string(),
+            Value::String(ref s) if quoted => format!("{:?}", s),
+            Value::String(ref s) => s.clone(),
+            Value::Bool(b) => b.to_string(),
+            Value::List(ref elements) => {
+                let elements: Vec<String> = elements.borrow().iter().map(|e| e.describe(true, printing)).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Value::Dictionary(ref dictionary) if dictionary.borrow().is_empty() => String::from("[:]"),
+            Value::Dictionary(ref dictionary) => {
+                let entries: Vec<String> = dictionary.borrow().entries().iter()
+                    .map(|&(ref key, ref value)| format!("{}: {}", key.describe(true, printing), value.describe(true, printing)))
+                    .collect();
+                format!("[{}]", entries.join(", "))
+            }
+            Value::Object(ref object) => {
+                let object = object.borrow();
+                let fields: Vec<String> = object.fields.iter()
+                    .map(|&(ref name, ref value)| format!("{}: {}", name, value.describe(true, printing)))
+                    .collect();
+                format!("{} {{{}}}", object.type_name, fields.join(", "))
+            }
+            Value::Function(ref function) => format!("<function {}>", function.name),
+            Value::Unit => String::from("nothing"),
+        };
+        if address.is_some()
+        {
+            printing.pop();
+        }
+        return description;
+    }
+
+    //Compares the contents of the values. comparing holds the pairs of containers and
+    //objects that have been compared so far. A pair that is reached again counts as
+    //equal: if it isn't, the comparison that is still going on finds the difference
+    fn equals(&self, other: &Value, comparing: &mut Vec<(usize, usize)>) -> bool
+    {
+        if let (Some(a), Some(b)) = (self.address(), other.address())
+        {
+            if a == b || comparing.contains(&(a, b))
+            {
+                return true;
+            }
+            comparing.push((a, b));
+        }
+        match (self, other)
+        {
+            (&Value::Number(a), &Value::Number(b)) => a == b,
+            (&Value::String(ref a), &Value::String(ref b)) => a == b,
+            (&Value::Bool(a), &Value::Bool(b)) => a == b,
+            (&Value::List(ref a), &Value::List(ref b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.equals(y, comparing))
+            }
+            //Dictionaries are equal if they have the same keys with equal values, in any order
+            (&Value::Dictionary(ref a), &Value::Dictionary(ref b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len() && a.entries().iter()
+                    .all(|&(ref key, ref value)| b.get(key).is_some_and(|other| value.equals(other, comparing)))
+            }
+            (&Value::Object(ref a), &Value::Object(ref b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.type_name == b.type_name && a.fields.len() == b.fields.len() && a.fields.iter().zip(b.fields.iter())
+                    .all(|(&(ref n1, ref v1), &(ref n2, ref v2))| n1 == n2 && v1.equals(v2, comparing))
+            }
+            (&Value::Function(ref a), &Value::Function(ref b)) => a.name == b.name,
+            (&Value::Unit, &Value::Unit) => true,
+            _ => false
+        }
+    }
+}
+
+//Containers and objects are equal if their contents are equal, even if they
+//are not the same container
+impl PartialEq for Value
+{
+    fn eq(&self, other: &Value) -> bool
+    {
+        self.equals(other, &mut Vec::new())
+    }
+}
+
+fn number(result: NumberResult) -> Result<Value, String>
+{
+    result.map(Value::Number)
+}
+
+fn list_index(index: &Value, len: usize) -> Result<usize, String>
+{
+    let number = index.as_number()?;
+    match number.as_i64()
+    {
+        Some(i) if i >= 0 && (i as u64) < len as u64 => Ok(i as usize),
+        _ => Err(format!("Index {} is out of bounds for a length of {}", number, len))
+    }
+}
+
+impl fmt::Display for Value
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "{}", self.describe(false, &mut Vec::new()))
+    }
+}
+
+/*
+ * A named value in a scope together with the type it was declared with
+ */
+pub struct Variable
+{
+    pub name: String,
+    pub declared_type: Type,
+    pub value: Value,
+}
+
+impl Variable
+{
+    pub fn new(name: String, declared_type: Type, value: Value) -> Variable
+    {
+        Variable {
+            name: name,
+            declared_type: declared_type,
+            value: value
+        }
+    }
+}
+
+#[cfg(test)]
+mod value_tests
+{
+    use number::Number;
+    use types::Type;
+    use variable::*;
+
+    #[test]
+    fn copied_values()
+    {
+        let a = Value::String(String::from("a"));
+        let mut b = a.clone();
+        if let Value::String(ref mut s) = b
+        {
+            s.push('b');
+        }
+        assert_eq!(a, Value::String(String::from("a")));
+        assert!(!a.is_same(&b));
+        assert!(!Value::integer(1).is_same(&Value::integer(1)));
+    }
+
+    #[test]
+    fn shared_containers()
+    {
+        let list = Value::list(vec!(Value::integer(1)));
+        let alias = list.clone();
+        alias.set_index(&Value::integer(0), Value::integer(5)).unwrap();
+        assert_eq!(list.index(&Value::integer(0)).unwrap(), Value::integer(5));
+        assert!(list.is_same(&alias));
+
+        let dictionary = Value::dictionary(Dictionary::new());
+        let alias = dictionary.clone();
+        alias.set_index(&Value::String(String::from("k")), Value::Bool(true)).unwrap();
+        assert_eq!(dictionary.index(&Value::String(String::from("k"))).unwrap(), Value::Bool(true));
+
+        let object = Value::object(String::from("Point"), vec!((String::from("x"), Value::integer(1))));
+        let alias = object.clone();
+        alias.set_field("x", Value::integer(2)).unwrap();
+        assert_eq!(object.field("x").unwrap(), Value::integer(2));
+        assert_eq!(object.set_field("y", Value::Unit), Err(String::from("Point has no member y")));
+    }
+
+    #[test]
+    fn equality_compares_contents()
+    {
+        let a = Value::list(vec!(Value::integer(1), Value::String(String::from("x"))));
+        let b = Value::list(vec!(Value::integer(1), Value::String(String::from("x"))));
+        assert_eq!(a, b);
+        assert!(!a.is_same(&b));
+        assert!(Value::list(Vec::new()) != Value::dictionary(Dictionary::new()));
+
+        let dictionary = |entries: &[(i64, i64)]| {
+            let mut dictionary = Dictionary::new();
+            for &(key, value) in entries
+            {
+                dictionary.insert(Value::integer(key), Value::integer(value));
+            }
+            Value::dictionary(dictionary)
+        };
+        assert_eq!(dictionary(&[(1, 2), (3, 4)]), dictionary(&[(3, 4), (1, 2)]));
+        assert!(dictionary(&[(1, 2), (3, 4)]) != dictionary(&[(1, 2), (3, 5)]));
+        assert!(dictionary(&[(1, 2), (3, 4)]) != dictionary(&[(1, 2), (5, 4)]));
+    }
+
+    #[test]
+    fn dictionaries_find_keys_by_value()
+    {
+        let string = |s: &str| Value::String(String::from(s));
+        let mut dictionary = Dictionary::new();
+        for i in 0..1000
+        {
+            dictionary.insert(Value::integer(i), Value::integer(i * 2));
+        }
+        dictionary.insert(string("a"), Value::integer(1));
+        dictionary.insert(Value::Number(Number::Float(5.0)), Value::integer(0));
+        assert_eq!(dictionary.len(), 1001);
+        assert_eq!(dictionary.get(&Value::integer(5)), Some(&Value::integer(0)));
+        assert_eq!(dictionary.get(&Value::Number(Number::Float(999.0))), Some(&Value::integer(1998)));
+        assert_eq!(dictionary.get(&Value::Number(Number::Float(0.5))), None);
+        assert_eq!(dictionary.get(&string("a")), Some(&Value::integer(1)));
+
+        assert_eq!(dictionary.remove(&Value::integer(3)), Some(Value::integer(6)));
+        assert_eq!(dictionary.remove(&Value::integer(3)), None);
+        assert_eq!(dictionary.get(&Value::integer(4)), Some(&Value::integer(8)));
+        assert_eq!(dictionary.get(&string("a")), Some(&Value::integer(1)));
+        dictionary.insert(Value::integer(3), Value::integer(-1));
+        assert_eq!(dictionary.keys()[..4], [Value::integer(0), Value::integer(1), Value::integer(2), Value::integer(4)]);
+        assert_eq!(dictionary.entries().last(), Some(&(Value::integer(3), Value::integer(-1))));
+    }
+
+    #[test]
+    fn values_that_contain_themselves()
+    {
+        let node = |name: &str| Value::object(String::from("N"), vec!(
+            (String::from("name"), Value::String(String::from(name))),
+            (String::from("kids"), Value::list(Vec::new()))));
+        let (a, b) = (node("n"), node("n"));
+        a.set_field("kids", Value::list(vec!(b.clone()))).unwrap();
+        b.set_field("kids", Value::list(vec!(a.clone()))).unwrap();
+        assert_eq!(format!("{}", a), "N {name: \"n\", kids: [N {name: \"n\", kids: [N {...}]}]}");
+        assert_eq!(a, b);
+
+        let c = node("n");
+        c.set_field("kids", Value::list(vec!(node("m")))).unwrap();
+        assert!(a != c);
+
+        let list = Value::list(Vec::new());
+        if let Value::List(ref elements) = list
+        {
+            elements.borrow_mut().push(list.clone());
+        }
+        assert_eq!(format!("{}", list), "[[...]]");
+        let other = Value::list(Vec::new());
+        if let Value::List(ref elements) = other
+        {
+            elements.borrow_mut().push(other.clone());
+        }
+        assert_eq!(other, list);
+    }
+
+    #[test]
+    fn types_and_printing()
+    {
+        let mut dictionary = Dictionary::new();
+        dictionary.insert(Value::String(String::from("a")), Value::list(vec!(Value::Number(Number::Float(1.5)))));
+        let value = Value::dictionary(dictionary);
+        assert_eq!(value.type_of(), Type::dictionary(Type::String, Type::list(Type::Number)));
+        assert_eq!(format!("{}", value), "[\"a\": [1.5]]");
+        assert_eq!(Value::list(Vec::new()).type_of(), Type::list(Type::Infer));
+
+        let object = Value::object(String::from("P"), vec!((String::from("name"), Value::String(String::from("n")))));
+        assert_eq!(format!("{}", object), "P {name: \"n\"}");
+        assert_eq!(object.type_of(), Type::User(String::from("P")));
+
+        let function = Value::function(String::from("f"), vec!(Type::Number), Type::Unit);
+        assert_eq!(format!("{}", function), "<function f>");
+        assert_eq!(format!("{}", function.type_of()), "def nothing(Number)");
+    }
 }
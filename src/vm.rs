@@ -0,0 +1,1030 @@
+use std::io::Write;
+
+use builtins::Builtins;
+use bytecode::{CompiledProgram, Instruction};
+use interpreter::{for_running, for_value, iterated_value, main_args, BuiltinCall, RuntimeError, TraceFrame};
+use limits::{allocation_size, Budget, Limits};
+use number::Number;
+use stdlib;
+use token::Span;
+use variable::{Dictionary, Value};
+
+type RunResult<T> = Result<T, RuntimeError>;
+
+//A call to a compiled function that is currently running
+struct Frame
+{
+    function: usize,
+    //The position of the next instruction
+    ip: usize,
+    locals: Vec<Value>,
+    //The height of the stack when the function was called
+    base: usize,
+    //Where the function was called from. Empty for the call to main
+    call_site: Span,
+}
+
+/*
+ * Runs compiled programs on a stack of values. Behaves exactly like the interpreter,
+ * including the errors it reports and where it reports them. Fuel is used up by
+ * Step instructions, which are where the interpreter uses it
+ */
+pub struct Vm<'a>
+{
+    program: &'a CompiledProgram,
+    builtins: &'a Builtins,
+    frames: Vec<Frame>,
+    stack: Vec<Value>,
+    //The containers that running foreach loops go through, with the number of
+    //frames there were when the loop started
+    iterating: Vec<(usize, Value)>,
+    budget: Budget,
+    output: &'a mut dyn Write,
+}
+
+impl<'a> Vm<'a>
+{
+    pub fn new(program: &'a CompiledProgram, builtins: &'a Builtins, output: &'a mut dyn Write) -> Vm<'a>
+    {
+        Vm {
+            program: program,
+            builtins: builtins,
+            frames: Vec::new(),
+            stack: Vec::new(),
+            iterating: Vec::new(),
+            budget: Budget::new(Limits::default()),
+            output: output,
+        }
+    }
+
+    //Runs the main function of the script. args are passed on to main if it
+    //takes the command line arguments
+    pub fn run_main(&mut self, args: Vec<String>) -> RunResult<Value>
+    {
+        let args = main_args(self.program.function("main").map(|main| main.params), args)?;
+        self.call_function("main", args, Span::default())
+    }
+
+    //Limits what the script can do. The time limit starts counting when this is called
+    pub fn set_limits(&mut self, limits: Limits)
+    {
+        self.budget = Budget::new(limits);
+    }
+
+    pub fn call_function(&mut self, name: &str, args: Vec<Value>, call_site: Span) -> RunResult<Value>
+    {
+        let depth = self.frames.len();
+        let height = self.stack.len();
+        if let Some(result) = self.call(name, args, call_site)?
+        {
+            return Ok(result);
+        }
+
+        let result = self.run(depth).map_err(|error| self.add_trace(error));
+        if result.is_err()
+        {
+            self.frames.truncate(depth);
+            self.stack.truncate(height);
+            self.iterating.retain(|&(frames, _)| frames <= depth);
+        }
+        return result;
+    }
+
+    //Starts a call. Builtin functions run straight away and their result is returned,
+    //compiled functions get a frame that run executes
+    fn call(&mut self, name: &str, args: Vec<Value>, call_site: Span) -> RunResult<Option<Value>>
+    {
+        let index = match self.program.function_index(name)
+        {
+            Some(index) => index,
+            None => return self.call_builtin(name, args, call_site).map(Some)
+        };
+
+        let function = &self.program.functions[index];
+        if function.params != args.len()
+        {
+            return Err(RuntimeError::new(format!("{} expects {} arguments but {} were given",
+                name, function.params, args.len()), call_site));
+        }
+        self.budget.enter_call(self.frames.len(), call_site)?;
+
+        let mut locals = args;
+        locals.resize(function.locals, Value::Unit);
+        self.frames.push(Frame {
+            function: index,
+            ip: 0,
+            locals: locals,
+            base: self.stack.len(),
+            call_site: call_site,
+        });
+        return Ok(None);
+    }
+
+    //Runs a builtin in the same way as the interpreter
+    fn call_builtin(&mut self, name: &str, args: Vec<Value>, call_site: Span) -> RunResult<Value>
+    {
+        let iterating = self.iterating.iter().map(|&(_, ref iterated)| iterated);
+        let call = BuiltinCall::start(name, &args, iterating, &mut self.budget, call_site)?;
+        let result = if stdlib::takes_functions(name)
+        {
+            self.call_with_functions(name, args, call_site)?
+        }
+        else
+        {
+            self.builtins.call(name, args, self.output).map_err(|e| RuntimeError::new(e, call_site))?
+        };
+        call.finish(&result, &mut self.budget)?;
+        return Ok(result);
+    }
+
+    //The list counts as iterated by the frame that called the builtin, so the
+    //functions it calls can't change it
+    fn call_with_functions(&mut self, name: &str, args: Vec<Value>, call_site: Span) -> RunResult<Value>
+    {
+        self.iterating.push((self.frames.len(), args[0].clone()));
+        let result = stdlib::call_with_functions(name, args, &mut |function, args| match *function
+            {
+                Value::Function(ref function) => self.call_function(&function.name, args, call_site),
+                ref other => Err(RuntimeError::new(format!("A value of type {} can not be called", other.type_name()), call_site))
+            }, call_site);
+        self.iterating.pop();
+        return result;
+    }
+
+    //Executes instructions until the frame at depth returns
+    fn run(&mut self, depth: usize) -> RunResult<Value>
+    {
+        let program = self.program;
+        loop
+        {
+            let (instruction, span, function) = {
+                let frame = self.frames.last_mut().expect("No function is running");
+                let chunk = &program.functions[frame.function].chunk;
+                let position = frame.ip;
+                frame.ip += 1;
+                (chunk.code[position], chunk.spans[position], frame.function)
+            };
+            let constants = &program.functions[function].chunk.constants;
+            let error = |message: String| RuntimeError::new(message, span);
+
+            match instruction
+            {
+                Instruction::Constant(index) => {
+                    let value = constants[index].clone();
+                    self.budget.allocate(&value, span)?;
+                    self.stack.push(value);
+                }
+                Instruction::LoadLocal(slot) => {
+                    let value = self.frame().locals[slot].clone();
+                    self.stack.push(value);
+                }
+                Instruction::StoreLocal(slot) => {
+                    let value = self.pop();
+                    self.frame_mut().locals[slot] = value;
+                }
+                Instruction::Pop => {
+                    self.pop();
+                }
+                Instruction::Dup => {
+                    let value = self.peek().clone();
+                    self.stack.push(value);
+                }
+                Instruction::Dup2 => {
+                    let start = self.stack.len() - 2;
+                    let values = self.stack[start..].to_vec();
+                    self.stack.extend(values);
+                }
+
+                Instruction::Unary(op) => {
+                    let value = self.pop();
+                    let result = value.unary(op).map_err(error)?;
+                    self.stack.push(result);
+                }
+                Instruction::Binary(op) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    let result = lhs.binary(op, &rhs).map_err(error)?;
+                    self.budget.allocate(&result, span)?;
+                    self.stack.push(result);
+                }
+                Instruction::CheckNumber => {
+                    self.peek().as_number().map_err(error)?;
+                }
+
+                Instruction::List(count) => {
+                    let start = self.stack.len() - count;
+                    let list = Value::list(self.stack.split_off(start));
+                    self.budget.allocate(&list, span)?;
+                    self.stack.push(list);
+                }
+                Instruction::Dictionary(count) => {
+                    let start = self.stack.len() - count * 2;
+                    let mut values = self.stack.split_off(start).into_iter();
+                    let mut dictionary = Dictionary::new();
+                    while let (Some(key), Some(value)) = (values.next(), values.next())
+                    {
+                        dictionary.insert(key, value);
+                    }
+                    let dictionary = Value::dictionary(dictionary);
+                    self.budget.allocate(&dictionary, span)?;
+                    self.stack.push(dictionary);
+                }
+                Instruction::Index => {
+                    let index = self.pop();
+                    let container = self.pop();
+                    let element = container.index(&index).map_err(error)?;
+                    if let Value::String(_) = container
+                    {
+                        self.budget.allocate(&element, span)?;
+                    }
+                    self.stack.push(element);
+                }
+                Instruction::SetIndex => {
+                    let value = self.pop();
+                    let index = self.pop();
+                    let container = self.pop();
+                    if self.iterating.iter().any(|&(_, ref iterated)| iterated.is_same(&container))
+                    {
+                        return Err(error(container.changed_while_iterating()));
+                    }
+                    let old_size = allocation_size(&container);
+                    container.set_index(&index, value).map_err(error)?;
+                    self.budget.grow(&container, old_size, span)?;
+                }
+                Instruction::Field(name) => {
+                    let object = self.pop();
+                    let field = object.field(&constant_string(&constants[name])).map_err(error)?;
+                    self.stack.push(field);
+                }
+                Instruction::SetField(name) => {
+                    let value = self.pop();
+                    let object = self.pop();
+                    object.set_field(&constant_string(&constants[name]), value).map_err(error)?;
+                }
+
+                Instruction::CheckCallable => match *self.peek()
+                {
+                    Value::Function(_) => {}
+                    ref other => return Err(error(format!("A value of type {} can not be called", other.type_name())))
+                },
+                Instruction::Call(count) => {
+                    let start = self.stack.len() - count;
+                    let args = self.stack.split_off(start);
+                    let function = match self.pop()
+                    {
+                        Value::Function(function) => function,
+                        other => return Err(error(format!("A value of type {} can not be called", other.type_name())))
+                    };
+                    if let Some(result) = self.call(&function.name, args, span)?
+                    {
+                        self.stack.push(result);
+                    }
+                }
+                Instruction::CallNamed{function, args} => {
+                    let start = self.stack.len() - args;
+                    let args = self.stack.split_off(start);
+                    if let Some(result) = self.call(&constant_string(&constants[function]), args, span)?
+                    {
+                        self.stack.push(result);
+                    }
+                }
+                Instruction::Object{prototype, values} => {
+                    let object = self.new_object(&constants[prototype], values);
+                    self.budget.allocate(&object, span)?;
+                    self.stack.push(object);
+                }
+                Instruction::Return => {
+                    let value = self.pop();
+                    let frame = self.frames.pop().expect("No function is running");
+                    self.stack.truncate(frame.base);
+                    let frames = self.frames.len();
+                    self.iterating.retain(|&(started, _)| started <= frames);
+                    if self.frames.len() == depth
+                    {
+                        return Ok(value);
+                    }
+                    self.stack.push(value);
+                }
+
+                Instruction::Jump(target) => self.frame_mut().ip = target,
+                Instruction::JumpIfFalse(target) => {
+                    if !self.pop().as_bool().map_err(error)?
+                    {
+                        self.frame_mut().ip = target;
+                    }
+                }
+                Instruction::JumpIfTrue(target) => {
+                    if self.pop().as_bool().map_err(error)?
+                    {
+                        self.frame_mut().ip = target;
+                    }
+                }
+
+                Instruction::CheckStep => {
+                    if self.peek().as_number().map_err(error)?.is_zero()
+                    {
+                        return Err(error(String::from("The step of a for loop can not be 0")));
+                    }
+                }
+                Instruction::ForNext{start, end, step, pass, exit} => {
+                    let (start, end, step, count) = {
+                        let locals = &self.frame().locals;
+                        (local_number(&locals[start]), local_number(&locals[end]), local_number(&locals[step]), local_number(&locals[pass]))
+                    };
+                    let count = count.as_i64().unwrap();
+                    let value = for_value(start, step, count).map_err(error)?;
+                    if for_running(value, end, step)
+                    {
+                        self.frame_mut().locals[pass] = Value::integer(count + 1);
+                        self.stack.push(Value::Number(value));
+                    }
+                    else
+                    {
+                        self.frame_mut().ip = exit;
+                    }
+                }
+                Instruction::IterStart{pairs} => {
+                    let container = self.pop();
+                    let container = iterated_value(container, &mut |iterate, object| self.call_function(iterate, vec!(object), span))?;
+                    let values = if pairs
+                    {
+                        container.iteration_entries()
+                            .map(|entries| entries.into_iter().flat_map(|(key, value)| vec!(key, value)).collect())
+                    }
+                    else
+                    {
+                        container.iteration_values()
+                    }.map_err(error)?;
+                    self.iterating.push((self.frames.len(), container));
+                    self.stack.push(Value::list(values));
+                }
+                Instruction::IterNext{values, position, exit, pairs} => {
+                    let count = if pairs {2} else {1};
+                    let next = {
+                        let locals = &self.frame().locals;
+                        let index = local_number(&locals[position]).as_i64().unwrap() as usize;
+                        match locals[values]
+                        {
+                            Value::List(ref values) => values.borrow().get(index..index + count).map(|values| values.to_vec()),
+                            _ => None
+                        }
+                    };
+                    match next
+                    {
+                        Some(next) => {
+                            let frame = self.frame_mut();
+                            let index = local_number(&frame.locals[position]).as_i64().unwrap();
+                            frame.locals[position] = Value::integer(index + count as i64);
+                            self.stack.extend(next);
+                        }
+                        None => self.frame_mut().ip = exit
+                    }
+                }
+                Instruction::IterEnd => {
+                    self.iterating.pop();
+                }
+
+                Instruction::Step => self.budget.step(span)?,
+
+                Instruction::Fail(message) => return Err(error(constant_string(&constants[message]))),
+            }
+        }
+    }
+
+    //A copy of an object constant, either with the values on the top of the stack
+    //or with the nothing of the constant
+    fn new_object(&mut self, prototype: &Value, values: bool) -> Value
+    {
+        let prototype = match *prototype
+        {
+            Value::Object(ref object) => object.borrow(),
+            ref other => panic!("Expected an object constant but found {}", other)
+        };
+        let mut fields = prototype.fields.clone();
+        if values
+        {
+            let start = self.stack.len() - fields.len();
+            for (field, value) in fields.iter_mut().zip(self.stack.split_off(start))
+            {
+                field.1 = value;
+            }
+        }
+        return Value::object(prototype.type_name.clone(), fields);
+    }
+
+    //Records the calls that were running when the error happened, in the same way
+    //as the interpreter
+    fn add_trace(&self, mut error: RuntimeError) -> RuntimeError
+    {
+        if error.trace.is_empty()
+        {
+            error.trace = self.frames.iter().rev()
+                .map(|frame| TraceFrame {
+                    function: self.program.functions[frame.function].name.clone(),
+                    call_site: frame.call_site
+                })
+                .collect();
+        }
+        return error;
+    }
+
+    fn frame(&self) -> &Frame
+    {
+        self.frames.last().expect("No function is running")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame
+    {
+        self.frames.last_mut().expect("No function is running")
+    }
+
+    fn pop(&mut self) -> Value
+    {
+        self.stack.pop().expect("The stack is empty")
+    }
+
+    fn peek(&self) -> &Value
+    {
+        self.stack.last().expect("The stack is empty")
+    }
+}
+
+fn constant_string(value: &Value) -> String
+{
+    match *value
+    {
+        Value::String(ref s) => s.clone(),
+        ref other => panic!("Expected a string constant but found {}", other)
+    }
+}
+
+//Loop counters are always numbers, the instructions that store them check that
+fn local_number(value: &Value) -> Number
+{
+    value.as_number().expect("A loop counter is not a Number")
+}
+
+#[cfg(test)]
+mod vm_tests
+{
+    use std::time::{Duration, Instant};
+
+    use builtins::Builtins;
+    use bytecode::Instruction;
+    use compiler::compile;
+    use interpreter::Interpreter;
+    use lexer::Lexer;
+    use limits::{run_with_stack, Limit};
+    use parser::{parse_token_list, MAX_EXPRESSION_DEPTH};
+    use resolver::resolve;
+    use type_checker::{check, CallTargets};
+    use validation::{validate, ValidationOptions};
+    use vm::*;
+
+    //Scripts that both engines run. Errors are included since the engines have to
+    //report the same errors at the same place
+    const SCRIPTS: &'static [&'static str] = &[
+        "def main() { print(1 + 2 * 3); print((1 + 2) * 3); print(7 / 2); print(-4 - 1); print(!true); }",
+        "def main() { print(\"a\" + \"b\"); print(0xff); print(1 < 2 & 2 != 3); print(false | 1 > 2); }",
+        "def main() { print(7 // 2); print(-7 % 3); print(0.1 + 0.2); print(9223372036854775807 + 1); }",
+        "def main() { print([1, 2]); print([\"a\": [\"b\"]]); print([:]); print(\"abc\"[1]); }",
+        "def main() { let d = [\"a\": 1, \"b\": 2]; print(d == [\"b\": 2, \"a\": 1]); print([d].contains([\"b\": 2, \"a\": 1])); print(d == [\"b\": 2]); }",
+        r#"
+            def Number fib(Number n) { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); }
+            def greet(String name) { print("Hello " + name); }
+            def main() { print(fib(15)); greet("Boa"); }
+        "#,
+        r#"
+            def Number add(Number a, Number b) { return a + b; }
+            def main() { let f = add; print(f(1, 2)); let p = print; p(f); }
+        "#,
+        r#"
+            def String classify(Number n)
+            {
+                if n < 0 { return "negative"; }
+                elseif n == 0 { return "zero"; }
+                else { return "positive"; }
+            }
+            def main() { print(classify(-1)); print(classify(0)); print(classify(3)); }
+        "#,
+        r#"
+            def main()
+            {
+                let i = 0;
+                while i < 3 { i++; }
+                print(i);
+                let total = 0;
+                for j is 1 to 10 { total += j; }
+                print(total);
+                for j is 10 to 0 step -5 { print(j); }
+                for j is 0 to 1 step 0.5 { print(j); }
+                foreach x in [1, 2, 3] { total -= x; }
+                print(total);
+                foreach c in "ab" { print(c); }
+                foreach key in ["x": 1, "y": 2] { print(key); }
+                if true { let shadow = 1; print(shadow); }
+                let shadow = 2;
+                print(shadow);
+            }
+        "#,
+        r#"
+            def Number find(List<Number> l, Number target)
+            {
+                let i = 0;
+                foreach x in l { if x == target { return i; } i++; }
+                return -1;
+            }
+            def main() { print(find([4, 5, 6], 6)); print(find([4], 1)); }
+        "#,
+        r#"
+            def main()
+            {
+                let l = [[1, 2], [3]];
+                l[0][1] = 7;
+                l[1][0] += 1;
+                print(l);
+                let d = ["a": 1];
+                d["b"] = 2;
+                d["a"]++;
+                d["b"] *= 3;
+                print(d);
+                print(d["b"] + l[0][0]);
+            }
+        "#,
+        r#"
+            def change(List<Number> l, Dictionary<String, Number> d, Number n, String s)
+            {
+                l[0] = 5; d["a"] = 5; n = 5; s = "changed";
+            }
+            def main()
+            {
+                let l = [1]; let d = ["a": 1]; let n = 1; let s = "s";
+                change(l, d, n, s);
+                print(l); print(d); print(n); print(s);
+                let a = [[1], [2]]; let b = a; b[1] = [3]; print(a);
+            }
+        "#,
+        r#"
+            def Number next(List<Number> counter) { counter[0] += 1; return counter[0]; }
+            def main()
+            {
+                let counter = [0];
+                let l = [10, 20, 30];
+                l[next(counter)] += next(counter);
+                print(l);
+                print(counter);
+                print(next(counter) == 3 | next(counter) == 100);
+                print(counter);
+                print(false & next(counter) == 5);
+                print(counter);
+            }
+        "#,
+        r#"
+            def main()
+            {
+                for i is 1 to 3
+                {
+                    let a = i * 10;
+                    foreach j in [1, 2] { let b = a + j; print(b); }
+                }
+                let a = "after";
+                print(a);
+            }
+        "#,
+        r#"
+            def Number first(List<Number> l) { foreach x in l { return x; } return 0; }
+            def main()
+            {
+                let d = ["a": 1, "b": 2];
+                foreach k, v in d { print(k); print(v); }
+                foreach String k, Number v in d { d = [k: v]; }
+                print(d);
+                let l = [1, 2];
+                let copy = [0, 0];
+                foreach x in l { copy[x - 1] = x * 10; }
+                print(first(l));
+                l[0] = 3;
+                print(l);
+                print(copy);
+            }
+        "#,
+        "def Number main(List<String> args) { print(args); return 3; }",
+        "def main() { let a = 0; print(1 / a); }",
+        "def main() { let l = [1]; print(l[1]); }",
+        "def main() { let l = [1]; l[-1] = 2; }",
+        "def main() { let d = [\"a\": 1]; print(d[\"b\"]); }",
+        "def main() { let d = [\"a\": 1]; d[\"b\"] += 1; }",
+        "def main() { let s = 0; for i is 0 to 1 step s {} }",
+        "def main() { let l = [1, 2]; foreach x in l { l[0] = x; } }",
+        "def add(Dictionary<String, Number> d) { d[\"c\"] = 3; } def main() { let d = [\"a\": 1]; foreach k, v in d { add(d); } }",
+        "def main() { print(1 % 0); }",
+        "def main() { let l = [1]; l.pop(); l.pop(); }",
+        "def main() { let l = [1]; foreach x in l { l.push(x); } }",
+        "def List<Number> grow(List<Number> l, Number n) { l.push(n); return l; } def main() { let l = [1]; l.reduce(l, grow); }",
+        "def main() { print(\"1.5x\".to_number()); }",
+        "def inner(Number n) { print(n); print(1 / (n - 2)); } def main() { inner(3); inner(2); }",
+        "def Number down(Number n) { return down(n + 1); } def main() { print(down(0)); }",
+    ];
+
+    //The result of running a script and what it printed
+    type Outcome = (Result<Value, RuntimeError>, String);
+
+    fn run_both(code: &str) -> (Outcome, Outcome)
+    {
+        run_both_with_limits(code, Limits::default())
+    }
+
+    fn run_both_with_limits(code: &str, limits: Limits) -> (Outcome, Outcome)
+    {
+        let tokens = Lexer::new().tokenize(String::from(code));
+        let program = parse_token_list(tokens).unwrap();
+        let builtins = Builtins::new();
+        let resolution = resolve(&program, &builtins.names());
+        assert!(!resolution.has_errors(), "{:?}", resolution.diagnostics);
+        let checked = check(&program, &resolution, &builtins);
+        assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+        assert!(validate(&program, &ValidationOptions::script()).is_empty());
+        let args = vec!(String::from("a"), String::from("b"));
+
+        let mut interpreter_output = Vec::new();
+        let interpreter_result = {
+            let mut interpreter = Interpreter::new(&program, &builtins, &mut interpreter_output);
+            interpreter.set_call_targets(checked.call_targets.clone());
+            interpreter.set_limits(limits);
+            interpreter.run_main(args.clone())
+        };
+
+        let compiled = compile(&program, &checked.call_targets, &builtins);
+        let mut vm_output = Vec::new();
+        let vm_result = {
+            let mut vm = Vm::new(&compiled, &builtins, &mut vm_output);
+            vm.set_limits(limits);
+            vm.run_main(args)
+        };
+
+        ((interpreter_result, String::from_utf8(interpreter_output).unwrap()),
+         (vm_result, String::from_utf8(vm_output).unwrap()))
+    }
+
+    #[test]
+    fn same_behaviour_as_interpreter()
+    {
+        //The interpreter needs the stack to reach the call depth that the VM stops at
+        run_with_stack(|| for code in SCRIPTS
+        {
+            let (interpreted, compiled) = run_both(code);
+            assert_eq!(interpreted, compiled, "The engines disagree on {}", code);
+        });
+    }
+
+    #[test]
+    fn errors_match_interpreter()
+    {
+        //Make sure the scripts that are supposed to fail actually do
+        let failures = run_with_stack(|| SCRIPTS.iter().filter(|code| run_both(code).1 .0.is_err()).count());
+        assert_eq!(failures, 15);
+
+        let code = "def main() { let d = [\"a\": 1]; d[\"b\"] += 1; }";
+        let error = run_both(code).1 .0.unwrap_err();
+        assert_eq!(error.message, "The key \"b\" is not in the dictionary");
+        assert_eq!(&code[error.span.start..error.span.end], "d[\"b\"]");
+    }
+
+    //The rules of for var is start to end step amount, each with a loop that
+    //shows it and what the loop prints. Both engines have to follow them
+    const FOR_LOOPS: &'static [(&'static str, &'static str)] = &[
+        //The end is included and the step defaults to 1
+        ("for i is 1 to 3 { print(i); }", "1\n2\n3\n"),
+        //The loop runs once if the start is the end
+        ("for i is 2 to 2 { print(i); }", "2\n"),
+        //The default step never counts down, the loop doesn't run
+        ("for i is 3 to 1 { print(i); }", ""),
+        //Steps don't have to land on the end
+        ("for i is 0 to 10 step 4 { print(i); }", "0\n4\n8\n"),
+        //Negative steps count down to the end, which is included
+        ("for i is 3 to 1 step -1 { print(i); }", "3\n2\n1\n"),
+        ("for i is 1 to 3 step -1 { print(i); }", ""),
+        //Fractional steps and bounds. The variable is start + n * step so the
+        //rounding errors of 0.1 don't add up and 1 is reached
+        ("for i is 0 to 1 step 0.25 { print(i); }", "0\n0.25\n0.5\n0.75\n1\n"),
+        ("let n = 0; for i is 0 to 1 step 0.1 { n++; } print(n);", "11\n"),
+        ("for i is 0.5 to 2 { print(i); }", "0.5\n1.5\n"),
+        ("for i is 1 to -0.5 step -0.5 { print(i); }", "1\n0.5\n0\n-0.5\n"),
+        //The start, end and step are worked out once, in that order, before the loop
+        ("let l = [3]; for i is 1 to l[0] step l[0] - 2 { l[0] = 1; print(i); }", "1\n2\n3\n"),
+        //Changing the variable only changes it for the rest of the pass
+        ("for i is 1 to 3 { i = i * 10; print(i); }", "10\n20\n30\n"),
+        //A step that is 0 when the loop starts is an error
+        ("let s = 0; for i is 0 to 1 step s { print(i); }", ""),
+    ];
+
+    #[test]
+    fn for_loop_semantics()
+    {
+        for &(body, expected) in FOR_LOOPS
+        {
+            let code = format!("def main() {{ {} }}", body);
+            let (interpreted, compiled) = run_both(&code);
+            assert_eq!(interpreted, compiled, "The engines disagree on {}", body);
+            assert_eq!(compiled.1, expected, "{}", body);
+        }
+        let error = run_both("def main() { let s = 0; for i is 0 to 1 step s {} }").1 .0.unwrap_err();
+        assert_eq!(error.message, "The step of a for loop can not be 0");
+    }
+
+    #[test]
+    fn user_types_are_iterable()
+    {
+        let code = r#"
+            type Numbers { Number unused; }
+            implement for Numbers { def List<Number> iterate(self) { return [1, 2]; } }
+            def show(Numbers numbers) { foreach n in numbers { print(n); } }
+        "#;
+        let program = parse_token_list(Lexer::new().tokenize(String::from(code))).unwrap();
+        let builtins = Builtins::new();
+        let resolution = resolve(&program, &builtins.names());
+        let checked = check(&program, &resolution, &builtins);
+        assert!(!checked.has_errors());
+        let numbers = Value::object(String::from("Numbers"), vec!((String::from("unused"), Value::integer(0))));
+
+        let mut interpreter_output = Vec::new();
+        Interpreter::new(&program, &builtins, &mut interpreter_output)
+            .call_function("show", vec!(numbers.clone()), Span::default()).unwrap();
+        let compiled = compile(&program, &checked.call_targets, &builtins);
+        let mut vm_output = Vec::new();
+        Vm::new(&compiled, &builtins, &mut vm_output).call_function("show", vec!(numbers), Span::default()).unwrap();
+
+        assert_eq!(String::from_utf8(interpreter_output).unwrap(), "1\n2\n");
+        assert_eq!(String::from_utf8(vm_output).unwrap(), "1\n2\n");
+    }
+
+    #[test]
+    fn operator_functions()
+    {
+        let code = r#"
+            type Vector { Number x; Number y; }
+            implement for Vector
+            {
+                def Vector operator +(Vector a, Vector b) { a.x += b.x; return a; }
+                def Number operator *(Vector a, Vector b) { return a.x * b.x + a.y * b.y; }
+                def Number operator -(Vector a) { return -a.x; }
+                def Bool operator ==(Vector a, Vector b) { return a.x == b.x; }
+                def Bool operator <(Vector a, Vector b) { return a.x < b.x; }
+                def Bool operator if(Vector a) { return a.x != 0; }
+            }
+            def show(Vector a, Vector b)
+            {
+                print(a * b);
+                print(-a);
+                print(a == b);
+                print(a != b);
+                print(a < b);
+                if a & !b { print("not b"); }
+                if a | !b { print("a"); }
+                a += b;
+                while b { b.x -= 1; }
+                print(a.x);
+            }
+        "#;
+        let program = parse_token_list(Lexer::new().tokenize(String::from(code))).unwrap();
+        let builtins = Builtins::new();
+        let resolution = resolve(&program, &builtins.names());
+        let checked = check(&program, &resolution, &builtins);
+        assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+        let vector = |x: i64, y: i64| Value::object(String::from("Vector"),
+            vec!((String::from("x"), Value::integer(x)), (String::from("y"), Value::integer(y))));
+
+        let mut interpreter_output = Vec::new();
+        {
+            let mut interpreter = Interpreter::new(&program, &builtins, &mut interpreter_output);
+            interpreter.set_call_targets(checked.call_targets.clone());
+            interpreter.call_function("show", vec!(vector(1, 2), vector(3, 4)), Span::default()).unwrap();
+        }
+        let compiled = compile(&program, &checked.call_targets, &builtins);
+        let mut vm_output = Vec::new();
+        Vm::new(&compiled, &builtins, &mut vm_output).call_function("show", vec!(vector(1, 2), vector(3, 4)), Span::default()).unwrap();
+
+        let expected = "11\n-1\nfalse\ntrue\ntrue\na\n4\n";
+        assert_eq!(String::from_utf8(interpreter_output).unwrap(), expected);
+        assert_eq!(String::from_utf8(vm_output).unwrap(), expected);
+    }
+
+    #[test]
+    fn accessors()
+    {
+        let code = r#"
+            type Account { private: Number balance; }
+            def deposit(Account a, Number amount) { a.set_balance(a.get_balance() + amount); print(a.get_balance()); }
+        "#;
+        let program = parse_token_list(Lexer::new().tokenize(String::from(code))).unwrap();
+        let builtins = Builtins::new();
+        let resolution = resolve(&program, &builtins.names());
+        let checked = check(&program, &resolution, &builtins);
+        assert!(!checked.has_errors(), "{:?}", checked.diagnostics);
+        let account = || Value::object(String::from("Account"), vec!((String::from("balance"), Value::integer(5))));
+
+        let mut interpreter_output = Vec::new();
+        {
+            let mut interpreter = Interpreter::new(&program, &builtins, &mut interpreter_output);
+            interpreter.set_call_targets(checked.call_targets.clone());
+            interpreter.call_function("deposit", vec!(account(), Value::integer(2)), Span::default()).unwrap();
+        }
+        let compiled = compile(&program, &checked.call_targets, &builtins);
+        let mut vm_output = Vec::new();
+        Vm::new(&compiled, &builtins, &mut vm_output).call_function("deposit", vec!(account(), Value::integer(2)), Span::default()).unwrap();
+
+        assert_eq!(String::from_utf8(interpreter_output).unwrap(), "7\n");
+        assert_eq!(String::from_utf8(vm_output).unwrap(), "7\n");
+    }
+
+    #[test]
+    fn methods_and_constructors()
+    {
+        let code = r#"
+            type Counter { Number count; String name; }
+            implement for Counter
+            {
+                def new(self, String name)
+                {
+                    self.name = name;
+                    if name == "" { self.count = 1; } else { self.count = 0; }
+                }
+                def add(self, Number amount) { self.count += amount; }
+                def String show(self) { print(self.count); return self.name; }
+                def Counter named(String name) { return Counter(name); }
+            }
+            type Point { Number x; Number y; }
+            implement for Point { def Number sum(self) { return self.x + self.y; } }
+            def main()
+            {
+                let c = Counter.named("a");
+                c.add(3);
+                c.add(4);
+                print(c.show());
+                print(Counter("").show());
+                let p = Point(1, 2);
+                print(p.sum());
+            }
+        "#;
+        let (interpreted, compiled) = run_both(code);
+        assert_eq!(interpreted, compiled);
+        assert_eq!(interpreted.1, "7\na\n1\n\n3\n");
+    }
+
+    #[test]
+    fn standard_library()
+    {
+        let code = r#"
+            def Bool longer(String a, String b) { return a.length() > b.length(); }
+            def Bool even(Number n) { return n % 2 == 0; }
+            def Number square(Number n) { return n * n; }
+            def Number add(Number total, Number n) { return total + n; }
+            def main()
+            {
+                let words = " one,three,two ".trim().split(",");
+                words.sort(longer);
+                print(words.join(" "));
+                let numbers = [3, 1, 4, 1, 5];
+                numbers.insert(0, 9);
+                print(numbers.filter(even).map(square));
+                print(numbers.reduce(0, add));
+                print(numbers.contains(5));
+                print("Hello".upper().replace("L", "_").slice(1, 4));
+                print((2).pow(10).max((2.5).round()));
+                print((1 / 3).format(3));
+                let d = ["a": 1];
+                d.merge(["b": 2, "a": 3]);
+                print(d.remove("a") + d.values().length());
+                print(d.has("a"));
+            }
+        "#;
+        let (interpreted, compiled) = run_both(code);
+        assert_eq!(interpreted, compiled);
+        assert_eq!(interpreted.1, "three one two\n[16]\n23\ntrue\nE__\n1024\n0.333\n4\nfalse\n");
+    }
+
+    #[test]
+    fn values_that_contain_themselves()
+    {
+        let code = r#"
+            type N { public: List<N> kids; }
+            def main()
+            {
+                let a = N([]);
+                let b = N([]);
+                a.kids.push(b);
+                b.kids.push(a);
+                print(a);
+                print(a == b);
+                let c = N([]);
+                c.kids.push(N([]));
+                print(a == c);
+            }
+        "#;
+        let (interpreted, compiled) = run_both(code);
+        assert_eq!(interpreted, compiled);
+        assert_eq!(interpreted.1, "N {kids: [N {kids: [N {...}]}]}\ntrue\nfalse\n");
+    }
+
+    #[test]
+    fn deep_expressions()
+    {
+        //Code as deep as the parser allows is checked and run without running out of stack
+        run_with_stack(|| {
+            let sum = vec!("n"; 250).join(" + ");
+            //The call to print is a level as well
+            let long = vec!("1"; MAX_EXPRESSION_DEPTH - 1).join(" + ");
+            let (interpreted, compiled) = run_both(&format!("def main() {{ print({}); }}", long));
+            assert_eq!(interpreted, compiled);
+            assert_eq!(interpreted.1, format!("{}\n", MAX_EXPRESSION_DEPTH - 1));
+
+            let code = format!("def Number f(Number n) {{ return {}; }} def main() {{ print(f({}1{})); }}",
+                sum, "(".repeat(200), ")".repeat(200));
+            let (interpreted, compiled) = run_both(&code);
+            assert_eq!(interpreted, compiled);
+            assert_eq!(interpreted.1, "250\n");
+        });
+    }
+
+    #[test]
+    fn locals_are_slots()
+    {
+        let code = "def main() { let a = 1; { let b = a; } let c = a; }";
+        let program = parse_token_list(Lexer::new().tokenize(String::from(code))).unwrap();
+        let compiled = compile(&program, &CallTargets::default(), &Builtins::new());
+        let main = compiled.function("main").unwrap();
+
+        //b's scope has ended when c is declared so they share a slot
+        assert_eq!(main.locals, 2);
+        let stores: Vec<Instruction> = main.chunk.code.iter().cloned()
+            .filter(|i| matches!(*i, Instruction::StoreLocal(_)))
+            .collect();
+        assert_eq!(stores, vec!(Instruction::StoreLocal(0), Instruction::StoreLocal(1), Instruction::StoreLocal(1)));
+        assert_eq!(main.chunk.constants.len(), 2);
+    }
+
+    #[test]
+    fn limits()
+    {
+        let limited = |code: &str, limits: Limits| {
+            let program = parse_token_list(Lexer::new().tokenize(String::from(code))).unwrap();
+            let builtins = Builtins::new();
+            let checked = check(&program, &resolve(&program, &builtins.names()), &builtins);
+            let compiled = compile(&program, &checked.call_targets, &builtins);
+            let mut output = Vec::new();
+            let mut vm = Vm::new(&compiled, &builtins, &mut output);
+            vm.set_limits(limits);
+            vm.run_main(Vec::new()).unwrap_err()
+        };
+
+        let error = limited("def main() { while true {} }", Limits{fuel: Some(10_000), ..Limits::unlimited()});
+        assert_eq!(error.limit, Some(Limit::Fuel));
+
+        let code = "def Number down(Number n) { return down(n + 1); } def main() { down(0); }";
+        let error = limited(code, Limits{max_call_depth: Some(10_000), ..Limits::unlimited()});
+        assert_eq!(error.message, "The maximum call depth of 10000 was exceeded");
+
+        let code = "def main() { let d = [0: 0]; let i = 1; while true { d[i] = i; i++; } }";
+        assert_eq!(limited(code, Limits{max_memory: Some(1 << 16), ..Limits::unlimited()}).limit, Some(Limit::Memory));
+
+        let error = limited("def main() { while true {} }", Limits{timeout: Some(Duration::from_millis(50)), ..Limits::unlimited()});
+        assert_eq!(error.limit, Some(Limit::Time));
+
+        let code = "def main() { let s = \"a\"; for i is 0 to 22 { s = s + s; } while true { s.find(\"b\"); } }";
+        let start = Instant::now();
+        let error = limited(code, Limits{timeout: Some(Duration::from_millis(100)), ..Limits::unlimited()});
+        assert_eq!(error.limit, Some(Limit::Time));
+        assert!(start.elapsed() < Duration::from_secs(2), "{:?}", start.elapsed());
+    }
+
+    #[test]
+    fn fuel_runs_out_in_the_same_place()
+    {
+        let code = "def main() { let n = 0; while n < 3 { n++; } }";
+        let (interpreted, compiled) = run_both_with_limits(code, Limits{fuel: Some(2), ..Limits::unlimited()});
+        let error = compiled.0.unwrap_err();
+        assert_eq!(error.limit, Some(Limit::Fuel));
+        assert_eq!(&code[error.span.start..error.span.end], "while n < 3 { n++; }");
+        assert_eq!(interpreted.0.unwrap_err(), error);
+
+        //Every amount of fuel runs out in a different place, statements, passes
+        //of every kind of loop and calls to builtins
+        let code = r#"
+            def Number twice(Number n) { return n * 2; }
+            def main()
+            {
+                let l = [1, 2];
+                foreach x in l { print(twice(x)); }
+                for i is 1 to 2 { l.push(i); }
+                let n = 0;
+                while n < 2 { n++; }
+                print(l.map(twice));
+            }
+        "#;
+        for fuel in 0..40
+        {
+            let (interpreted, compiled) = run_both_with_limits(code, Limits{fuel: Some(fuel), ..Limits::unlimited()});
+            assert_eq!(interpreted, compiled, "The engines disagree with {} fuel", fuel);
+        }
+    }
+}